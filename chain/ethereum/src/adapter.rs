@@ -14,7 +14,7 @@ use std::fmt;
 use std::marker::Unpin;
 use thiserror::Error;
 use tiny_keccak::keccak256;
-use web3::types::{Address, Log, H256};
+use web3::types::{Address, Log, H256, U256};
 
 use graph::prelude::*;
 use graph::{
@@ -58,17 +58,61 @@ pub enum EthereumContractCallError {
     Timeout,
 }
 
+#[derive(Error, Debug)]
+pub enum EthereumRpcError {
+    #[error("call error: {0}")]
+    Web3Error(web3::Error),
+    #[error("ethereum node took too long to perform call")]
+    Timeout,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
 enum LogFilterNode {
     Contract(Address),
     Event(EventSignature),
 }
 
+/// An event whose logs are only of interest if their indexed arguments
+/// (`topic1` to `topic3`) have one of the given values. A topic that is
+/// `None` matches any value.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct EventSignatureWithTopics {
+    /// The contract that emits the event, or `None` to match any contract.
+    pub address: Option<Address>,
+    pub signature: EventSignature,
+    pub topic1: Option<Vec<H256>>,
+    pub topic2: Option<Vec<H256>>,
+    pub topic3: Option<Vec<H256>>,
+}
+
+impl EventSignatureWithTopics {
+    /// Checks if `log` is emitted by the right contract, has the right event
+    /// signature and if its indexed arguments match the topic filters.
+    pub fn matches(&self, log: &Log) -> bool {
+        let topic_matches = |index: usize, topics: &Option<Vec<H256>>| match topics {
+            None => true,
+            Some(topics) => log
+                .topics
+                .get(index)
+                .map_or(false, |topic| topics.contains(topic)),
+        };
+
+        self.address.map_or(true, |address| address == log.address)
+            && log.topics.first() == Some(&self.signature)
+            && topic_matches(1, &self.topic1)
+            && topic_matches(2, &self.topic2)
+            && topic_matches(3, &self.topic3)
+    }
+}
+
 /// Corresponds to an `eth_getLogs` call.
 #[derive(Clone, Debug)]
 pub struct EthGetLogsFilter {
     pub contracts: Vec<Address>,
     pub event_signatures: Vec<EventSignature>,
+    pub topic1: Option<Vec<H256>>,
+    pub topic2: Option<Vec<H256>>,
+    pub topic3: Option<Vec<H256>>,
 }
 
 impl EthGetLogsFilter {
@@ -76,6 +120,9 @@ impl EthGetLogsFilter {
         EthGetLogsFilter {
             contracts: vec![address],
             event_signatures: vec![],
+            topic1: None,
+            topic2: None,
+            topic3: None,
         }
     }
 
@@ -83,6 +130,9 @@ impl EthGetLogsFilter {
         EthGetLogsFilter {
             contracts: vec![],
             event_signatures: vec![event],
+            topic1: None,
+            topic2: None,
+            topic3: None,
         }
     }
 }
@@ -157,22 +207,27 @@ impl bc::TriggerFilter<Chain> for TriggerFilter {
 
     fn to_firehose_filter(self) -> Vec<prost_types::Any> {
         let EthereumBlockFilter {
+            polling_intervals,
             contract_addresses: _contract_addresses,
             trigger_every_block,
         } = self.block.clone();
 
+        // Polling and once block handlers are evaluated against every block
+        // header, so they need all of them to be sent
+        let send_all_block_headers = trigger_every_block || !polling_intervals.is_empty();
+
         let log_filters: Vec<LogFilter> = self.log.into();
         let mut call_filters: Vec<CallToFilter> = self.call.into();
         call_filters.extend(Into::<Vec<CallToFilter>>::into(self.block));
 
-        if call_filters.is_empty() && log_filters.is_empty() && !trigger_every_block {
+        if call_filters.is_empty() && log_filters.is_empty() && !send_all_block_headers {
             return Vec::new();
         }
 
         let combined_filter = CombinedFilter {
             log_filters,
             call_filters,
-            send_all_block_headers: trigger_every_block,
+            send_all_block_headers,
         };
 
         vec![Any {
@@ -193,8 +248,16 @@ pub(crate) struct EthereumLogFilter {
     /// Event sigs with no associated address, matching on all addresses.
     /// Maps to a boolean representing if a trigger requires a transaction receipt.
     wildcard_events: HashMap<EventSignature, bool>,
+
+    /// Events that are filtered by their indexed arguments, with or without an associated
+    /// address. These are kept out of the graph since they can't be merged with other filters.
+    /// Maps to a boolean representing if a trigger requires a transaction receipt.
+    events_with_topic_filters: HashMap<EventSignatureWithTopics, bool>,
 }
 
+/// Firehose can only filter logs by contract and event signature. The topic filters of
+/// events are not sent along, and the logs in the blocks we receive are matched against
+/// them in `EthereumLogFilter::matches`.
 impl Into<Vec<LogFilter>> for EthereumLogFilter {
     fn into(self) -> Vec<LogFilter> {
         self.eth_get_logs_filters()
@@ -202,6 +265,7 @@ impl Into<Vec<LogFilter>> for EthereumLogFilter {
                 |EthGetLogsFilter {
                      contracts,
                      event_signatures,
+                     ..
                  }| LogFilter {
                     addresses: contracts
                         .iter()
@@ -234,6 +298,10 @@ impl EthereumLogFilter {
                     .all_edges()
                     .any(|(s, t, _)| (s == contract && t == event) || (t == contract && s == event))
                     || self.wildcard_events.contains_key(sig)
+                    || self
+                        .events_with_topic_filters
+                        .keys()
+                        .any(|event| event.matches(log))
             }
         }
     }
@@ -246,6 +314,18 @@ impl EthereumLogFilter {
     ) -> bool {
         if let Some(true) = self.wildcard_events.get(event_signature) {
             true
+        } else if self
+            .events_with_topic_filters
+            .iter()
+            .any(|(event, requires_receipt)| {
+                *requires_receipt
+                    && event.signature == *event_signature
+                    && event
+                        .address
+                        .map_or(true, |address| Some(&address) == contract_address)
+            })
+        {
+            true
         } else if let Some(address) = contract_address {
             let contract = LogFilterNode::Contract(*address);
             let event = LogFilterNode::Event(*event_signature);
@@ -264,6 +344,19 @@ impl EthereumLogFilter {
         for ds in iter {
             for event_handler in ds.mapping.event_handlers.iter() {
                 let event_sig = event_handler.topic0();
+                if event_handler.has_topic_filters() {
+                    this.events_with_topic_filters.insert(
+                        EventSignatureWithTopics {
+                            address: ds.address,
+                            signature: event_sig,
+                            topic1: event_handler.topic1.clone(),
+                            topic2: event_handler.topic2.clone(),
+                            topic3: event_handler.topic3.clone(),
+                        },
+                        event_handler.receipt,
+                    );
+                    continue;
+                }
                 match ds.address {
                     Some(contract) => {
                         this.contracts_and_events_graph.add_edge(
@@ -286,6 +379,19 @@ impl EthereumLogFilter {
         let mut this = EthereumLogFilter::default();
         for event_handler in &mapping.event_handlers {
             let signature = event_handler.topic0();
+            if event_handler.has_topic_filters() {
+                this.events_with_topic_filters.insert(
+                    EventSignatureWithTopics {
+                        address: None,
+                        signature,
+                        topic1: event_handler.topic1.clone(),
+                        topic2: event_handler.topic2.clone(),
+                        topic3: event_handler.topic3.clone(),
+                    },
+                    event_handler.receipt,
+                );
+                continue;
+            }
             this.wildcard_events
                 .insert(signature, event_handler.receipt);
         }
@@ -302,11 +408,14 @@ impl EthereumLogFilter {
         let EthereumLogFilter {
             contracts_and_events_graph,
             wildcard_events,
+            events_with_topic_filters,
         } = other;
         for (s, t, e) in contracts_and_events_graph.all_edges() {
             self.contracts_and_events_graph.add_edge(s, t, *e);
         }
         self.wildcard_events.extend(wildcard_events);
+        self.events_with_topic_filters
+            .extend(events_with_topic_filters);
     }
 
     /// An empty filter is one that never matches.
@@ -315,8 +424,11 @@ impl EthereumLogFilter {
         let EthereumLogFilter {
             contracts_and_events_graph,
             wildcard_events,
+            events_with_topic_filters,
         } = self;
-        contracts_and_events_graph.edge_count() == 0 && wildcard_events.is_empty()
+        contracts_and_events_graph.edge_count() == 0
+            && wildcard_events.is_empty()
+            && events_with_topic_filters.is_empty()
     }
 
     /// Filters for `eth_getLogs` calls. The filters will not return false positives. This attempts
@@ -330,6 +442,47 @@ impl EthereumLogFilter {
             .map(|(event, _)| EthGetLogsFilter::from_event(event))
             .collect_vec();
 
+        // Events with topic filters can only share a filter with events that have the same
+        // signature and topic filters, otherwise the topics of one event would restrict or
+        // widen the logs of another. Within such a group, the contracts are merged, and an
+        // event without an address makes the whole group match any contract.
+        let mut events_with_topics: HashMap<_, Option<Vec<Address>>> = HashMap::new();
+        for event in self.events_with_topic_filters.into_keys() {
+            let EventSignatureWithTopics {
+                address,
+                signature,
+                topic1,
+                topic2,
+                topic3,
+            } = event;
+            let contracts = events_with_topics
+                .entry((signature, topic1, topic2, topic3))
+                .or_insert_with(|| Some(vec![]));
+            match (contracts.as_mut(), address) {
+                (Some(contracts), Some(address)) => contracts.push(address),
+                (_, None) => *contracts = None,
+                (None, Some(_)) => {}
+            }
+        }
+        for ((signature, topic1, topic2, topic3), contracts) in events_with_topics {
+            let filter = |contracts: Vec<Address>| EthGetLogsFilter {
+                contracts,
+                event_signatures: vec![signature],
+                topic1: topic1.clone(),
+                topic2: topic2.clone(),
+                topic3: topic3.clone(),
+            };
+            match contracts {
+                None => filters.push(filter(vec![])),
+                Some(mut contracts) => {
+                    contracts.sort();
+                    for chunk in contracts.chunks(ENV_VARS.get_logs_max_contracts) {
+                        filters.push(filter(chunk.to_vec()));
+                    }
+                }
+            }
+        }
+
         // The current algorithm is to repeatedly find the maximum cardinality vertex and turn all
         // of its edges into a filter. This is nice because it is neutral between filtering by
         // contract or by events, if there are many events that appear on only one data source
@@ -583,6 +736,9 @@ impl From<&EthereumBlockFilter> for EthereumCallFilter {
 
 #[derive(Clone, Debug, Default)]
 pub(crate) struct EthereumBlockFilter {
+    /// The `(start_block, interval)` pairs of polling and once block handlers. An interval of 0
+    /// denotes a once handler, which only triggers at its start block.
+    pub polling_intervals: HashSet<(BlockNumber, i32)>,
     pub contract_addresses: HashSet<(BlockNumber, Address)>,
     pub trigger_every_block: bool,
 }
@@ -609,6 +765,7 @@ impl EthereumBlockFilter {
     /// which keeps track of deployed contracts and relevant addresses.
     pub fn from_mapping(mapping: &Mapping) -> Self {
         Self {
+            polling_intervals: HashSet::new(),
             contract_addresses: HashSet::new(),
             trigger_every_block: mapping.block_handlers.len() != 0,
         }
@@ -635,7 +792,21 @@ impl EthereumBlockFilter {
                     .into_iter()
                     .any(|block_handler| block_handler.filter.is_none());
 
+                let polling_intervals = data_source
+                    .mapping
+                    .block_handlers
+                    .iter()
+                    .filter_map(|block_handler| match block_handler.filter {
+                        Some(BlockHandlerFilter::Once) => Some((data_source.start_block, 0)),
+                        Some(BlockHandlerFilter::Polling { every }) => {
+                            Some((data_source.start_block, every.get() as i32))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+
                 filter_opt.extend(Self {
+                    polling_intervals,
                     trigger_every_block: has_block_handler_without_filter,
                     contract_addresses: if has_block_handler_with_call_filter {
                         vec![(
@@ -658,11 +829,13 @@ impl EthereumBlockFilter {
         };
 
         let EthereumBlockFilter {
+            polling_intervals,
             contract_addresses,
             trigger_every_block,
         } = other;
 
         self.trigger_every_block = self.trigger_every_block || trigger_every_block;
+        self.polling_intervals.extend(polling_intervals);
 
         for other in contract_addresses {
             let (other_start_block, other_address) = other;
@@ -688,6 +861,25 @@ impl EthereumBlockFilter {
         !self.contract_addresses.is_empty()
     }
 
+    /// Returns `true` if a polling block handler needs to run at `block_number`.
+    pub fn matches_polling_interval(&self, block_number: BlockNumber) -> bool {
+        self.polling_intervals
+            .iter()
+            .any(|(start_block, interval)| match interval {
+                0 => false,
+                interval => {
+                    block_number >= *start_block && (block_number - start_block) % interval == 0
+                }
+            })
+    }
+
+    /// Returns `true` if a once block handler needs to run at `block_number`.
+    pub fn matches_once(&self, block_number: BlockNumber) -> bool {
+        self.polling_intervals
+            .iter()
+            .any(|(start_block, interval)| *interval == 0 && block_number == *start_block)
+    }
+
     /// An empty filter is one that never matches.
     pub fn is_empty(&self) -> bool {
         // If we are triggering every block, we are of course not empty
@@ -695,6 +887,10 @@ impl EthereumBlockFilter {
             return false;
         }
 
+        if !self.polling_intervals.is_empty() {
+            return false;
+        }
+
         self.contract_addresses.is_empty()
     }
 
@@ -917,6 +1113,25 @@ pub trait EthereumAdapter: Send + Sync + 'static {
         call: EthereumContractCall,
         cache: Arc<dyn EthereumCallCache>,
     ) -> Box<dyn Future<Item = Vec<Token>, Error = EthereumContractCallError> + Send>;
+
+    /// Get the native balance of `address` at the block `block_ptr`.
+    async fn get_balance(
+        &self,
+        logger: &Logger,
+        address: Address,
+        block_ptr: BlockPtr,
+        cache: Arc<dyn EthereumCallCache>,
+    ) -> Result<U256, EthereumRpcError>;
+
+    /// Check whether `address` has code, i.e., is a contract, at the block
+    /// `block_ptr`.
+    async fn has_code(
+        &self,
+        logger: &Logger,
+        address: Address,
+        block_ptr: BlockPtr,
+        cache: Arc<dyn EthereumCallCache>,
+    ) -> Result<bool, EthereumRpcError>;
 }
 
 #[cfg(test)]
@@ -1028,6 +1243,7 @@ mod tests {
             log: EthereumLogFilter {
                 contracts_and_events_graph: GraphMap::new(),
                 wildcard_events: HashMap::new(),
+                events_with_topic_filters: HashMap::new(),
             },
             call: EthereumCallFilter {
                 contract_addresses_function_signatures: HashMap::from_iter(vec![
@@ -1038,6 +1254,7 @@ mod tests {
                 wildcard_signatures: HashSet::new(),
             },
             block: EthereumBlockFilter {
+                polling_intervals: HashSet::default(),
                 contract_addresses: HashSet::from_iter([
                     (100, address(1000)),
                     (200, address(2000)),
@@ -1152,12 +1369,14 @@ mod tests {
             log: EthereumLogFilter {
                 contracts_and_events_graph: GraphMap::new(),
                 wildcard_events: HashMap::new(),
+                events_with_topic_filters: HashMap::new(),
             },
             call: EthereumCallFilter {
                 contract_addresses_function_signatures: HashMap::new(),
                 wildcard_signatures: HashSet::new(),
             },
             block: EthereumBlockFilter {
+                polling_intervals: HashSet::default(),
                 contract_addresses: HashSet::new(),
                 trigger_every_block: true,
             },
@@ -1311,11 +1530,13 @@ mod tests {
     #[test]
     fn extending_ethereum_block_filter_no_found() {
         let mut base = EthereumBlockFilter {
+            polling_intervals: HashSet::default(),
             contract_addresses: HashSet::new(),
             trigger_every_block: false,
         };
 
         let extension = EthereumBlockFilter {
+            polling_intervals: HashSet::default(),
             contract_addresses: HashSet::from_iter(vec![(10, address(1))]),
             trigger_every_block: false,
         };
@@ -1331,11 +1552,13 @@ mod tests {
     #[test]
     fn extending_ethereum_block_filter_conflict_picks_lowest_block_from_ext() {
         let mut base = EthereumBlockFilter {
+            polling_intervals: HashSet::default(),
             contract_addresses: HashSet::from_iter(vec![(10, address(1))]),
             trigger_every_block: false,
         };
 
         let extension = EthereumBlockFilter {
+            polling_intervals: HashSet::default(),
             contract_addresses: HashSet::from_iter(vec![(2, address(1))]),
             trigger_every_block: false,
         };
@@ -1351,11 +1574,13 @@ mod tests {
     #[test]
     fn extending_ethereum_block_filter_conflict_picks_lowest_block_from_base() {
         let mut base = EthereumBlockFilter {
+            polling_intervals: HashSet::default(),
             contract_addresses: HashSet::from_iter(vec![(2, address(1))]),
             trigger_every_block: false,
         };
 
         let extension = EthereumBlockFilter {
+            polling_intervals: HashSet::default(),
             contract_addresses: HashSet::from_iter(vec![(10, address(1))]),
             trigger_every_block: false,
         };
@@ -1371,11 +1596,13 @@ mod tests {
     #[test]
     fn extending_ethereum_block_filter_every_block_in_ext() {
         let mut base = EthereumBlockFilter {
+            polling_intervals: HashSet::default(),
             contract_addresses: HashSet::default(),
             trigger_every_block: false,
         };
 
         let extension = EthereumBlockFilter {
+            polling_intervals: HashSet::default(),
             contract_addresses: HashSet::default(),
             trigger_every_block: true,
         };
@@ -1388,11 +1615,13 @@ mod tests {
     #[test]
     fn extending_ethereum_block_filter_every_block_in_base_and_merge_contract_addresses() {
         let mut base = EthereumBlockFilter {
+            polling_intervals: HashSet::default(),
             contract_addresses: HashSet::from_iter(vec![(10, address(2))]),
             trigger_every_block: true,
         };
 
         let extension = EthereumBlockFilter {
+            polling_intervals: HashSet::default(),
             contract_addresses: HashSet::from_iter(vec![]),
             trigger_every_block: false,
         };
@@ -1409,11 +1638,13 @@ mod tests {
     #[test]
     fn extending_ethereum_block_filter_every_block_in_ext_and_merge_contract_addresses() {
         let mut base = EthereumBlockFilter {
+            polling_intervals: HashSet::default(),
             contract_addresses: HashSet::from_iter(vec![(10, address(2))]),
             trigger_every_block: false,
         };
 
         let extension = EthereumBlockFilter {
+            polling_intervals: HashSet::default(),
             contract_addresses: HashSet::from_iter(vec![(10, address(1))]),
             trigger_every_block: true,
         };
@@ -1427,6 +1658,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn extending_ethereum_block_filter_merges_polling_intervals() {
+        let mut base = EthereumBlockFilter {
+            polling_intervals: HashSet::from_iter(vec![(10, 5)]),
+            contract_addresses: HashSet::default(),
+            trigger_every_block: false,
+        };
+
+        let extension = EthereumBlockFilter {
+            polling_intervals: HashSet::from_iter(vec![(20, 0)]),
+            contract_addresses: HashSet::default(),
+            trigger_every_block: false,
+        };
+
+        base.extend(extension);
+
+        assert_eq!(
+            HashSet::from_iter(vec![(10, 5), (20, 0)]),
+            base.polling_intervals,
+        );
+    }
+
+    #[test]
+    fn ethereum_block_filter_polling_intervals() {
+        let filter = EthereumBlockFilter {
+            polling_intervals: HashSet::from_iter(vec![(10, 5), (12, 0)]),
+            contract_addresses: HashSet::default(),
+            trigger_every_block: false,
+        };
+
+        assert!(!filter.is_empty());
+
+        assert!(!filter.matches_polling_interval(5));
+        assert!(filter.matches_polling_interval(10));
+        assert!(!filter.matches_polling_interval(12));
+        assert!(filter.matches_polling_interval(15));
+
+        assert!(!filter.matches_once(10));
+        assert!(filter.matches_once(12));
+        assert!(!filter.matches_once(17));
+    }
+
     #[test]
     fn extending_ethereum_call_filter() {
         let mut base = EthereumCallFilter {
@@ -1512,6 +1785,7 @@ fn complete_log_filter() {
             let logs_filters: Vec<_> = EthereumLogFilter {
                 contracts_and_events_graph,
                 wildcard_events: HashMap::new(),
+                events_with_topic_filters: HashMap::new(),
             }
             .eth_get_logs_filters()
             .collect();
@@ -1606,6 +1880,7 @@ fn log_filter_require_transacion_receipt_method() {
     let filter = EthereumLogFilter {
         contracts_and_events_graph,
         wildcard_events,
+        events_with_topic_filters: HashMap::new(),
     };
 
     // connected contracts and events graph
@@ -1637,3 +1912,105 @@ fn log_filter_require_transacion_receipt_method() {
     assert!(!filter.requires_transaction_receipt(&event_signature_b, Some(&alien_contract_address)));
     assert!(!filter.requires_transaction_receipt(&event_signature_c, Some(&alien_contract_address)));
 }
+
+#[test]
+fn log_filter_with_topic_filters() {
+    let contract = Address::from_low_u64_be(1);
+    let other_contract = Address::from_low_u64_be(2);
+    let event_signature = H256::from_low_u64_be(3);
+    let recipient = H256::from_low_u64_be(4);
+    let other_recipient = H256::from_low_u64_be(5);
+
+    let event = EventSignatureWithTopics {
+        address: Some(contract),
+        signature: event_signature,
+        topic1: None,
+        topic2: Some(vec![recipient]),
+        topic3: None,
+    };
+    let filter = EthereumLogFilter {
+        contracts_and_events_graph: GraphMap::new(),
+        wildcard_events: HashMap::new(),
+        events_with_topic_filters: [(event, true)].into_iter().collect(),
+    };
+
+    let log = |address: Address, topics: Vec<H256>| Log {
+        address,
+        topics,
+        data: web3::types::Bytes::default(),
+        block_hash: None,
+        block_number: None,
+        transaction_hash: None,
+        transaction_index: None,
+        log_index: None,
+        transaction_log_index: None,
+        log_type: None,
+        removed: None,
+    };
+
+    assert!(filter.matches(&log(
+        contract,
+        vec![event_signature, H256::zero(), recipient]
+    )));
+    assert!(!filter.matches(&log(
+        contract,
+        vec![event_signature, H256::zero(), other_recipient]
+    )));
+    assert!(!filter.matches(&log(
+        other_contract,
+        vec![event_signature, H256::zero(), recipient]
+    )));
+    assert!(!filter.matches(&log(contract, vec![event_signature, H256::zero()])));
+
+    assert!(filter.requires_transaction_receipt(&event_signature, Some(&contract)));
+    assert!(!filter.requires_transaction_receipt(&event_signature, Some(&other_contract)));
+
+    let logs_filters: Vec<_> = filter.eth_get_logs_filters().collect();
+    assert_eq!(1, logs_filters.len());
+    assert_eq!(vec![contract], logs_filters[0].contracts);
+    assert_eq!(vec![event_signature], logs_filters[0].event_signatures);
+    assert_eq!(None, logs_filters[0].topic1);
+    assert_eq!(Some(vec![recipient]), logs_filters[0].topic2);
+    assert_eq!(None, logs_filters[0].topic3);
+
+    // Events that share the signature and the topic filters are merged into
+    // one filter, events with other topic filters are not
+    let with_address = |address: Option<Address>, topic2: H256| EventSignatureWithTopics {
+        address,
+        signature: event_signature,
+        topic1: None,
+        topic2: Some(vec![topic2]),
+        topic3: None,
+    };
+    let filter = |events: Vec<EventSignatureWithTopics>| EthereumLogFilter {
+        contracts_and_events_graph: GraphMap::new(),
+        wildcard_events: HashMap::new(),
+        events_with_topic_filters: events.into_iter().map(|event| (event, false)).collect(),
+    };
+
+    let mut logs_filters: Vec<_> = filter(vec![
+        with_address(Some(other_contract), recipient),
+        with_address(Some(contract), recipient),
+        with_address(Some(contract), other_recipient),
+    ])
+    .eth_get_logs_filters()
+    .collect();
+    logs_filters.sort_by_key(|filter| filter.contracts.len());
+    assert_eq!(2, logs_filters.len());
+    assert_eq!(vec![contract], logs_filters[0].contracts);
+    assert_eq!(Some(vec![other_recipient]), logs_filters[0].topic2);
+    assert_eq!(vec![contract, other_contract], logs_filters[1].contracts);
+    assert_eq!(vec![event_signature], logs_filters[1].event_signatures);
+    assert_eq!(Some(vec![recipient]), logs_filters[1].topic2);
+
+    // An event without an address matches the logs of all contracts
+    let logs_filters: Vec<_> = filter(vec![
+        with_address(Some(contract), recipient),
+        with_address(None, recipient),
+    ])
+    .eth_get_logs_filters()
+    .collect();
+    assert_eq!(1, logs_filters.len());
+    assert!(logs_filters[0].contracts.is_empty());
+    assert_eq!(Some(vec![recipient]), logs_filters[0].topic2);
+}
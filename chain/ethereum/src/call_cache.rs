@@ -0,0 +1,200 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Error;
+use graph::blockchain::BlockPtr;
+use graph::components::store::CachedEthereumCall;
+use graph::prelude::{ethabi::Address, EthereumCallCache};
+
+/// The number of blocks for which the results of declared calls are kept
+/// in memory. Results are only needed from the time the calls for a block
+/// are prefetched until the handlers for the block have run, but the cache
+/// is shared by all subgraphs on a chain, and each of them can be at a
+/// different block.
+const DECLARED_CALLS_BLOCKS: usize = 256;
+
+type BlockCalls = HashMap<(Address, Vec<u8>), Vec<u8>>;
+
+/// An `EthereumCallCache` that keeps the results of calls that handlers
+/// declare in the manifest in memory, in front of the call cache in the
+/// chain store. Prefetched results are added with `insert_declared` before
+/// the handlers for a block run, so that `ethereum.call` finds them even
+/// before they have been written to the database.
+pub struct DeclaredCallCache {
+    store: Arc<dyn EthereumCallCache>,
+    blocks: Mutex<VecDeque<(BlockPtr, BlockCalls)>>,
+}
+
+impl DeclaredCallCache {
+    pub fn new(store: Arc<dyn EthereumCallCache>) -> Self {
+        Self {
+            store,
+            blocks: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Remember the `return_value` of the declared call `encoded_call` to
+    /// `contract_address` at `block`. This only changes what is held in
+    /// memory; the result still needs to be written to the store with
+    /// `set_call`.
+    pub fn insert_declared(
+        &self,
+        contract_address: Address,
+        encoded_call: Vec<u8>,
+        block: BlockPtr,
+        return_value: Vec<u8>,
+    ) {
+        let mut blocks = self.blocks.lock().unwrap();
+        let pos = match blocks.iter().position(|(ptr, _)| ptr == &block) {
+            Some(pos) => pos,
+            None => {
+                if blocks.len() == DECLARED_CALLS_BLOCKS {
+                    blocks.pop_front();
+                }
+                blocks.push_back((block, HashMap::new()));
+                blocks.len() - 1
+            }
+        };
+        blocks[pos]
+            .1
+            .insert((contract_address, encoded_call), return_value);
+    }
+
+    fn get_declared(
+        &self,
+        contract_address: Address,
+        encoded_call: &[u8],
+        block: &BlockPtr,
+    ) -> Option<Vec<u8>> {
+        let blocks = self.blocks.lock().unwrap();
+        blocks
+            .iter()
+            .find(|(ptr, _)| ptr == block)
+            .and_then(|(_, calls)| calls.get(&(contract_address, encoded_call.to_vec())))
+            .cloned()
+    }
+}
+
+impl EthereumCallCache for DeclaredCallCache {
+    fn get_call(
+        &self,
+        contract_address: Address,
+        encoded_call: &[u8],
+        block: BlockPtr,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        match self.get_declared(contract_address, encoded_call, &block) {
+            Some(return_value) => Ok(Some(return_value)),
+            None => self.store.get_call(contract_address, encoded_call, block),
+        }
+    }
+
+    /// The calls in the store, together with the declared calls for
+    /// `block` that are still held in memory
+    fn get_calls_in_block(&self, block: BlockPtr) -> Result<Vec<CachedEthereumCall>, Error> {
+        let mut calls = self.store.get_calls_in_block(block.clone())?;
+
+        let blocks = self.blocks.lock().unwrap();
+        let declared = match blocks.iter().find(|(ptr, _)| ptr == &block) {
+            Some((_, declared)) => declared,
+            None => return Ok(calls),
+        };
+
+        for ((contract_address, encoded_call), return_value) in declared {
+            let id = CachedEthereumCall::id(contract_address, encoded_call, &block);
+            match calls.iter_mut().find(|call| call.blake3_id == id) {
+                Some(call) => {
+                    call.call_data = Some(encoded_call.clone());
+                    call.declared = true;
+                }
+                None => calls.push(CachedEthereumCall {
+                    blake3_id: id.to_vec(),
+                    block_ptr: block.clone(),
+                    contract_address: *contract_address,
+                    return_value: return_value.clone(),
+                    call_data: Some(encoded_call.clone()),
+                    declared: true,
+                }),
+            }
+        }
+
+        Ok(calls)
+    }
+
+    fn set_call(
+        &self,
+        contract_address: Address,
+        encoded_call: &[u8],
+        block: BlockPtr,
+        return_value: &[u8],
+    ) -> Result<(), Error> {
+        self.store
+            .set_call(contract_address, encoded_call, block, return_value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::{web3::types::H256, BlockHash};
+
+    use super::*;
+
+    /// A call cache in the store that never has anything
+    struct EmptyCallCache;
+
+    impl EthereumCallCache for EmptyCallCache {
+        fn get_call(&self, _: Address, _: &[u8], _: BlockPtr) -> Result<Option<Vec<u8>>, Error> {
+            Ok(None)
+        }
+
+        fn get_calls_in_block(&self, _: BlockPtr) -> Result<Vec<CachedEthereumCall>, Error> {
+            Ok(vec![])
+        }
+
+        fn set_call(&self, _: Address, _: &[u8], _: BlockPtr, _: &[u8]) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn block(number: i32) -> BlockPtr {
+        BlockPtr::new(
+            BlockHash::from(H256::from_low_u64_be(number as u64)),
+            number,
+        )
+    }
+
+    #[test]
+    fn declared_calls_are_served_from_memory() {
+        let cache = DeclaredCallCache::new(Arc::new(EmptyCallCache));
+        let address = Address::from_low_u64_be(1);
+
+        cache.insert_declared(address, vec![1, 2], block(1), vec![3]);
+
+        assert_eq!(
+            Some(vec![3]),
+            cache.get_call(address, &[1, 2], block(1)).unwrap()
+        );
+        assert_eq!(None, cache.get_call(address, &[1, 2], block(2)).unwrap());
+
+        let calls = cache.get_calls_in_block(block(1)).unwrap();
+        assert_eq!(1, calls.len());
+        assert!(calls[0].declared);
+        assert_eq!(Some(vec![1, 2]), calls[0].call_data);
+        assert_eq!(
+            CachedEthereumCall::id(&address, &[1, 2], &block(1)).to_vec(),
+            calls[0].blake3_id
+        );
+    }
+
+    #[test]
+    fn only_recent_blocks_are_kept() {
+        let cache = DeclaredCallCache::new(Arc::new(EmptyCallCache));
+        let address = Address::from_low_u64_be(1);
+
+        for number in 0..=DECLARED_CALLS_BLOCKS as i32 {
+            cache.insert_declared(address, vec![], block(number), vec![]);
+        }
+
+        assert_eq!(None, cache.get_call(address, &[], block(0)).unwrap());
+        assert!(cache.get_call(address, &[], block(1)).unwrap().is_some());
+    }
+}
@@ -8,15 +8,18 @@ use graph::prelude::futures03::future::try_join;
 use graph::prelude::futures03::stream::FuturesOrdered;
 use graph::prelude::{Link, SubgraphManifestValidationError};
 use graph::slog::{o, trace};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::num::NonZeroU32;
 use std::str::FromStr;
 use std::{convert::TryFrom, sync::Arc};
 use tiny_keccak::{keccak256, Keccak};
 
 use graph::{
-    blockchain::{self, Blockchain},
+    blockchain::{self, BlockPtr, Blockchain},
     prelude::{
         async_trait,
-        ethabi::{Address, Contract, Event, Function, LogParam, ParamType, RawLog},
+        ethabi::{Address, Contract, Event, Function, LogParam, ParamType, RawLog, Token},
         info, serde_json, warn,
         web3::types::{Log, Transaction, H256},
         BlockNumber, CheapClone, DataSourceTemplateInfo, Deserialize, EthereumCall,
@@ -28,6 +31,7 @@ use graph::data::subgraph::{calls_host_fn, DataSourceContext, Source};
 
 use crate::chain::Chain;
 use crate::trigger::{EthereumBlockTriggerType, EthereumTrigger, MappingTrigger};
+use crate::EthereumContractCall;
 
 // The recommended kind is `ethereum`, `ethereum/contract` is accepted for backwards compatibility.
 const ETHEREUM_KINDS: &[&str] = &["ethereum/contract", "ethereum"];
@@ -43,6 +47,7 @@ pub struct DataSource {
     pub manifest_idx: u32,
     pub address: Option<Address>,
     pub start_block: BlockNumber,
+    pub end_block: Option<BlockNumber>,
     pub mapping: Mapping,
     pub context: Arc<Option<DataSourceContext>>,
     pub creation_block: Option<BlockNumber>,
@@ -58,6 +63,10 @@ impl blockchain::DataSource<Chain> for DataSource {
         self.start_block
     }
 
+    fn end_block(&self) -> Option<BlockNumber> {
+        self.end_block
+    }
+
     fn match_and_decode(
         &self,
         trigger: &<Chain as Blockchain>::TriggerData,
@@ -103,6 +112,7 @@ impl blockchain::DataSource<Chain> for DataSource {
             creation_block: _,
             contract_abi: _,
             start_block: _,
+            end_block: _,
         } = self;
 
         // mapping_request_sender, host_metrics, and (most of) host_exports are operational structs
@@ -167,6 +177,7 @@ impl blockchain::DataSource<Chain> for DataSource {
             manifest_idx,
             address,
             start_block: 0,
+            end_block: None,
             mapping: template.mapping.clone(),
             context: Arc::new(context),
             creation_block,
@@ -193,25 +204,72 @@ impl blockchain::DataSource<Chain> for DataSource {
         };
 
         // Validate that there are no more than one of each type of block_handler
-        let has_too_many_block_handlers = {
-            let mut non_filtered_block_handler_count = 0;
-            let mut call_filtered_block_handler_count = 0;
-            self.mapping
-                .block_handlers
-                .iter()
-                .for_each(|block_handler| {
-                    if block_handler.filter.is_none() {
-                        non_filtered_block_handler_count += 1
-                    } else {
-                        call_filtered_block_handler_count += 1
-                    }
-                });
-            non_filtered_block_handler_count > 1 || call_filtered_block_handler_count > 1
-        };
+        let mut non_filtered_block_handler_count = 0;
+        let mut call_filtered_block_handler_count = 0;
+        let mut once_filtered_block_handler_count = 0;
+        let mut polling_filtered_block_handler_count = 0;
+        self.mapping
+            .block_handlers
+            .iter()
+            .for_each(|block_handler| match block_handler.filter {
+                None => non_filtered_block_handler_count += 1,
+                Some(BlockHandlerFilter::Call) => call_filtered_block_handler_count += 1,
+                Some(BlockHandlerFilter::Once) => once_filtered_block_handler_count += 1,
+                Some(BlockHandlerFilter::Polling { .. }) => {
+                    polling_filtered_block_handler_count += 1
+                }
+            });
+        let has_too_many_block_handlers = non_filtered_block_handler_count > 1
+            || call_filtered_block_handler_count > 1
+            || once_filtered_block_handler_count > 1
+            || polling_filtered_block_handler_count > 1;
         if has_too_many_block_handlers {
             errors.push(anyhow!("data source has duplicated block handlers"));
         }
 
+        // Non-filtered and polling block handlers are both triggered by plain
+        // block triggers, so a data source can only have one of them
+        if non_filtered_block_handler_count > 0 && polling_filtered_block_handler_count > 0 {
+            errors.push(anyhow!(
+                "data source has both a non-filtered and a polling block handler"
+            ));
+        }
+
+        // Validate that topic filters of event handlers are not empty, since
+        // they would never match any event
+        for event_handler in &self.mapping.event_handlers {
+            for (i, topics) in [
+                &event_handler.topic1,
+                &event_handler.topic2,
+                &event_handler.topic3,
+            ]
+            .iter()
+            .enumerate()
+            {
+                if topics.as_ref().map_or(false, |topics| topics.is_empty()) {
+                    errors.push(anyhow!(
+                        "event handler `{}` has an empty `topic{}` filter",
+                        event_handler.handler,
+                        i + 1
+                    ));
+                }
+            }
+        }
+
+        // Validate that declared calls refer to known ABIs, functions and event parameters
+        for event_handler in &self.mapping.event_handlers {
+            for decl in event_handler.calls.iter() {
+                if let Err(e) = self.validate_call_decl(event_handler, decl) {
+                    errors.push(anyhow!(
+                        "declared call `{}` of event handler `{}` is invalid: {}",
+                        decl.label,
+                        event_handler.handler,
+                        e
+                    ));
+                }
+            }
+        }
+
         // Validate that event handlers don't require receipts for API versions lower than 0.0.7
         let api_version = self.api_version();
         if api_version < semver::Version::new(0, 0, 7) {
@@ -261,6 +319,7 @@ impl DataSource {
             manifest_idx,
             address: source.address,
             start_block: source.start_block,
+            end_block: source.end_block,
             mapping,
             context: Arc::new(context),
             creation_block,
@@ -268,6 +327,78 @@ impl DataSource {
         })
     }
 
+    /// The contract calls declared by the event handler that `trigger`
+    /// resolves to, if any.
+    pub(crate) fn declared_calls(
+        &self,
+        trigger: &EthereumTrigger,
+        block: &Arc<LightEthereumBlock>,
+        logger: &Logger,
+    ) -> Result<Vec<EthereumContractCall>, Error> {
+        if !self.mapping.has_declared_calls() {
+            return Ok(vec![]);
+        }
+
+        let trigger = match self.match_and_decode(trigger, block, logger)? {
+            Some(trigger) => trigger,
+            None => return Ok(vec![]),
+        };
+        let (log, params) = match &trigger.trigger {
+            MappingTrigger::Log { log, params, .. } => (log, params),
+            _ => return Ok(vec![]),
+        };
+
+        let handler = match self
+            .handlers_for_log(log)?
+            .into_iter()
+            .find(|handler| handler.handler == trigger.handler_name())
+        {
+            Some(handler) => handler,
+            None => return Ok(vec![]),
+        };
+
+        let block_ptr = block.block_ptr();
+        handler
+            .calls
+            .iter()
+            .map(|decl| {
+                decl.expr
+                    .resolve(&self.mapping.abis, log, params, &block_ptr)
+                    .with_context(|| format!("failed to resolve declared call `{}`", decl.label))
+            })
+            .collect()
+    }
+
+    fn validate_call_decl(
+        &self,
+        event_handler: &MappingEventHandler,
+        decl: &CallDecl,
+    ) -> Result<(), Error> {
+        let abi = self.mapping.find_abi(&decl.expr.abi)?;
+        let function = abi.contract.function(&decl.expr.func)?;
+        if function.inputs.len() != decl.expr.args.len() {
+            return Err(anyhow!(
+                "function `{}` takes {} arguments but {} were given",
+                decl.expr.func,
+                function.inputs.len(),
+                decl.expr.args.len()
+            ));
+        }
+
+        // Events that aren't in the ABI are reported elsewhere
+        if let Some(event) = self.contract_event_with_signature(&event_handler.event) {
+            for arg in std::iter::once(&decl.expr.address).chain(decl.expr.args.iter()) {
+                if let CallArg::Param(name) = arg {
+                    if !event.inputs.iter().any(|input| &input.name == name) {
+                        return Err(anyhow!("event has no parameter named `{}`", name));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     fn handlers_for_log(&self, log: &Log) -> Result<Vec<MappingEventHandler>, Error> {
         // Get signature from the log
         let topic0 = log.topics.get(0).context("Ethereum event has no topics")?;
@@ -276,7 +407,7 @@ impl DataSource {
             .mapping
             .event_handlers
             .iter()
-            .filter(|handler| *topic0 == handler.topic0())
+            .filter(|handler| *topic0 == handler.topic0() && handler.matches_topics(log))
             .cloned()
             .collect::<Vec<_>>();
 
@@ -308,13 +439,28 @@ impl DataSource {
     fn handler_for_block(
         &self,
         trigger_type: &EthereumBlockTriggerType,
+        block: BlockNumber,
     ) -> Option<MappingBlockHandler> {
         match trigger_type {
+            EthereumBlockTriggerType::Start => self
+                .mapping
+                .block_handlers
+                .iter()
+                .find(move |handler| {
+                    handler.filter == Some(BlockHandlerFilter::Once) && block == self.start_block
+                })
+                .cloned(),
             EthereumBlockTriggerType::Every => self
                 .mapping
                 .block_handlers
                 .iter()
-                .find(move |handler| handler.filter == None)
+                .find(move |handler| match handler.filter {
+                    None => true,
+                    Some(BlockHandlerFilter::Polling { every }) => {
+                        (block - self.start_block) % every.get() as BlockNumber == 0
+                    }
+                    Some(BlockHandlerFilter::Call) | Some(BlockHandlerFilter::Once) => false,
+                })
                 .cloned(),
             EthereumBlockTriggerType::WithCallTo(_address) => self
                 .mapping
@@ -468,6 +614,7 @@ impl DataSource {
 
             // Unfiltered block triggers match any data source address.
             EthereumTrigger::Block(_, EthereumBlockTriggerType::Every) => return true,
+            EthereumTrigger::Block(_, EthereumBlockTriggerType::Start) => return true,
         };
 
         ds_address == *trigger_address
@@ -491,7 +638,7 @@ impl DataSource {
 
         match trigger {
             EthereumTrigger::Block(_, trigger_type) => {
-                let handler = match self.handler_for_block(trigger_type) {
+                let handler = match self.handler_for_block(trigger_type, block.number()) {
                     Some(handler) => handler,
                     None => return Ok(None),
                 };
@@ -804,6 +951,7 @@ impl TryFrom<DataSourceTemplateInfo<Chain>> for DataSource {
             manifest_idx: template.manifest_idx,
             address: Some(address),
             start_block: 0,
+            end_block: None,
             mapping: template.mapping,
             context: Arc::new(context),
             creation_block: Some(creation_block),
@@ -911,7 +1059,21 @@ pub struct Mapping {
 
 impl Mapping {
     pub fn requires_archive(&self) -> anyhow::Result<bool> {
-        calls_host_fn(&self.runtime, "ethereum.call")
+        if self.has_declared_calls() {
+            return Ok(true);
+        }
+        for host_fn in ["ethereum.call", "ethereum.getBalance", "ethereum.hasCode"] {
+            if calls_host_fn(&self.runtime, host_fn)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn has_declared_calls(&self) -> bool {
+        self.event_handlers
+            .iter()
+            .any(|handler| !handler.calls.is_empty())
     }
 
     pub fn has_call_handler(&self) -> bool {
@@ -1034,6 +1196,11 @@ pub enum BlockHandlerFilter {
     // Call filter will trigger on all blocks where the data source contract
     // address has been called
     Call,
+    // Once filter will trigger only once, on the start block of the data source
+    Once,
+    // Polling filter will trigger on the start block of the data source and
+    // then every `every` blocks after that
+    Polling { every: NonZeroU32 },
 }
 
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
@@ -1046,9 +1213,14 @@ pub struct MappingCallHandler {
 pub struct MappingEventHandler {
     pub event: String,
     pub topic0: Option<H256>,
+    pub topic1: Option<Vec<H256>>,
+    pub topic2: Option<Vec<H256>>,
+    pub topic3: Option<Vec<H256>>,
     pub handler: String,
     #[serde(default)]
     pub receipt: bool,
+    #[serde(default)]
+    pub calls: CallDecls,
 }
 
 impl MappingEventHandler {
@@ -1056,6 +1228,194 @@ impl MappingEventHandler {
         self.topic0
             .unwrap_or_else(|| string_to_h256(&self.event.replace("indexed ", "")))
     }
+
+    /// Returns `true` if the handler only processes events whose indexed
+    /// arguments have specific values.
+    pub fn has_topic_filters(&self) -> bool {
+        self.topic1.is_some() || self.topic2.is_some() || self.topic3.is_some()
+    }
+
+    /// Checks if the indexed arguments of `log` match the `topic1` to
+    /// `topic3` filters of this handler. A missing filter matches anything.
+    pub fn matches_topics(&self, log: &Log) -> bool {
+        [&self.topic1, &self.topic2, &self.topic3]
+            .iter()
+            .enumerate()
+            .all(|(i, topics)| match topics {
+                None => true,
+                Some(topics) => log
+                    .topics
+                    .get(i + 1)
+                    .map_or(false, |topic| topics.contains(topic)),
+            })
+    }
+}
+
+/// The `eth_call`s that an event handler declares in the manifest so that
+/// they can be executed before the handler runs. In the manifest, they are a
+/// map from a label to a call like `ERC20[event.address].balanceOf(event.params.to)`.
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct CallDecls(Vec<CallDecl>);
+
+impl CallDecls {
+    pub fn iter(&self) -> impl Iterator<Item = &CallDecl> {
+        self.0.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<'de> Deserialize<'de> for CallDecls {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let decls: BTreeMap<String, String> = Deserialize::deserialize(deserializer)?;
+        decls
+            .into_iter()
+            .map(|(label, expr)| expr.parse().map(|expr| CallDecl { label, expr }))
+            .collect::<Result<Vec<_>, _>>()
+            .map(CallDecls)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct CallDecl {
+    pub label: String,
+    pub expr: CallExpr,
+}
+
+/// A call of `func` on the contract with ABI `abi` at `address`.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct CallExpr {
+    pub abi: String,
+    pub address: CallArg,
+    pub func: String,
+    pub args: Vec<CallArg>,
+}
+
+impl FromStr for CallExpr {
+    type Err = anyhow::Error;
+
+    /// Parses a call of the form `Abi[address].function(arg1, arg2)`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            anyhow!(
+                "invalid call `{}`, expected a call like `Contract[event.address].function(event.params.arg)`",
+                s
+            )
+        };
+
+        let (abi, rest) = s.split_once('[').ok_or_else(invalid)?;
+        let (address, rest) = rest.split_once("].").ok_or_else(invalid)?;
+        let (func, rest) = rest.split_once('(').ok_or_else(invalid)?;
+        let args = rest.trim_end().strip_suffix(')').ok_or_else(invalid)?;
+
+        let (abi, func) = (abi.trim(), func.trim());
+        if abi.is_empty() || func.is_empty() {
+            return Err(invalid());
+        }
+
+        let args = if args.trim().is_empty() {
+            vec![]
+        } else {
+            args.split(',')
+                .map(CallArg::from_str)
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        Ok(CallExpr {
+            abi: abi.to_owned(),
+            address: address.parse()?,
+            func: func.to_owned(),
+            args,
+        })
+    }
+}
+
+impl CallExpr {
+    /// Turns this expression into a contract call for the event `log`, whose
+    /// parameters have been decoded into `params`.
+    pub fn resolve(
+        &self,
+        abis: &[Arc<MappingABI>],
+        log: &Log,
+        params: &[LogParam],
+        block_ptr: &BlockPtr,
+    ) -> Result<EthereumContractCall, Error> {
+        let abi = abis
+            .iter()
+            .find(|abi| abi.name == self.abi)
+            .ok_or_else(|| anyhow!("unknown ABI `{}`", self.abi))?;
+        let function = abi.contract.function(&self.func)?.clone();
+
+        let address = match self.address.resolve(log, params)? {
+            Token::Address(address) => address,
+            token => return Err(anyhow!("`{}` is not an address: {}", self.address, token)),
+        };
+        let args = self
+            .args
+            .iter()
+            .map(|arg| arg.resolve(log, params))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(EthereumContractCall {
+            address,
+            block_ptr: block_ptr.cheap_clone(),
+            function,
+            args,
+        })
+    }
+}
+
+/// An argument of a declared call, taken from the event that triggered the handler.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub enum CallArg {
+    /// `event.address`, the address of the contract that emitted the event
+    Address,
+    /// `event.params.<name>`, one of the parameters of the event
+    Param(String),
+}
+
+impl FromStr for CallArg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.strip_prefix("event.params.") {
+            Some(name) if !name.is_empty() => Ok(CallArg::Param(name.to_owned())),
+            _ if s == "event.address" => Ok(CallArg::Address),
+            _ => Err(anyhow!(
+                "invalid call argument `{}`, expected `event.address` or `event.params.<name>`",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CallArg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CallArg::Address => write!(f, "event.address"),
+            CallArg::Param(name) => write!(f, "event.params.{}", name),
+        }
+    }
+}
+
+impl CallArg {
+    fn resolve(&self, log: &Log, params: &[LogParam]) -> Result<Token, Error> {
+        match self {
+            CallArg::Address => Ok(Token::Address(log.address)),
+            CallArg::Param(name) => params
+                .iter()
+                .find(|param| &param.name == name)
+                .map(|param| param.value.clone())
+                .ok_or_else(|| anyhow!("event has no parameter named `{}`", name)),
+        }
+    }
 }
 
 /// Hashes a string to a H256 hash.
@@ -89,6 +89,12 @@ pub struct EnvVars {
     /// Set by the flag `GRAPH_ETHEREUM_GENESIS_BLOCK_NUMBER`. The default value
     /// is 0.
     pub genesis_block_number: u64,
+    /// Maximum number of calls declared by event handlers that are run
+    /// concurrently when prefetching them for a block.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_ETHEREUM_DECLARED_CALLS_BATCH_SIZE`. The default value is 50.
+    pub declared_calls_batch_size: usize,
 }
 
 // This does not print any values avoid accidentally leaking any sensitive env vars
@@ -131,6 +137,7 @@ impl From<Inner> for EnvVars {
             cleanup_blocks: x.cleanup_blocks.0,
             target_triggers_per_block_range: x.target_triggers_per_block_range,
             genesis_block_number: x.genesis_block_number,
+            declared_calls_batch_size: x.declared_calls_batch_size,
         }
     }
 }
@@ -181,4 +188,6 @@ struct Inner {
     target_triggers_per_block_range: u64,
     #[envconfig(from = "GRAPH_ETHEREUM_GENESIS_BLOCK_NUMBER", default = "0")]
     genesis_block_number: u64,
+    #[envconfig(from = "GRAPH_ETHEREUM_DECLARED_CALLS_BATCH_SIZE", default = "50")]
+    declared_calls_batch_size: usize,
 }
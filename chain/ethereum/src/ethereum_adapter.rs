@@ -21,8 +21,9 @@ use graph::{
             self,
             types::{
                 Address, BlockId, BlockNumber as Web3BlockNumber, Bytes, CallRequest, Filter,
-                FilterBuilder, Log, Transaction, TransactionReceipt, H256,
+                FilterBuilder, Log, Transaction, TransactionReceipt, H256, U256,
             },
+            Transport as _,
         },
         BlockNumber, ChainStore, CheapClone, DynTryFuture, Error, EthereumCallCache, Logger,
         TimeoutError, TryFutureExt,
@@ -48,7 +49,7 @@ use crate::{
     adapter::{
         EthGetLogsFilter, EthereumAdapter as EthereumAdapterTrait, EthereumBlockFilter,
         EthereumCallFilter, EthereumContractCall, EthereumContractCallError, EthereumLogFilter,
-        ProviderEthRpcMetrics, SubgraphEthRpcMetrics,
+        EthereumRpcError, ProviderEthRpcMetrics, SubgraphEthRpcMetrics,
     },
     transport::Transport,
     trigger::{EthereumBlockTriggerType, EthereumTrigger},
@@ -252,7 +253,12 @@ impl EthereumAdapter {
                         .from_block(from.into())
                         .to_block(to.into())
                         .address(filter.contracts.clone())
-                        .topics(Some(filter.event_signatures.clone()), None, None, None)
+                        .topics(
+                            Some(filter.event_signatures.clone()),
+                            filter.topic1.clone(),
+                            filter.topic2.clone(),
+                            filter.topic3.clone(),
+                        )
                         .build();
 
                     // Request logs from client
@@ -416,7 +422,19 @@ impl EthereumAdapter {
         .boxed()
     }
 
-    fn call(
+    /// The block to use for requests about the state at `block_ptr`. We use the hash
+    /// so that we never get the state of a block on another fork, except for Ganache
+    /// which does not support requests by block hash.
+    /// See https://github.com/trufflesuite/ganache-cli/issues/973
+    fn block_id(&self, block_ptr: &BlockPtr) -> BlockId {
+        if !self.supports_eip_1898 {
+            BlockId::Number(block_ptr.number.into())
+        } else {
+            BlockId::Hash(block_ptr.hash_as_h256())
+        }
+    }
+
+    pub(crate) fn call(
         &self,
         logger: Logger,
         contract_address: Address,
@@ -424,14 +442,7 @@ impl EthereumAdapter {
         block_ptr: BlockPtr,
     ) -> impl Future<Item = Bytes, Error = EthereumContractCallError> + Send {
         let web3 = self.web3.clone();
-
-        // Ganache does not support calls by block hash.
-        // See https://github.com/trufflesuite/ganache-cli/issues/973
-        let block_id = if !self.supports_eip_1898 {
-            BlockId::Number(block_ptr.number.into())
-        } else {
-            BlockId::Hash(block_ptr.hash_as_h256())
-        };
+        let block_id = self.block_id(&block_ptr);
         let retry_log_message = format!("eth_call RPC call for block {}", block_ptr);
         retry(retry_log_message, &logger)
             .when(|result| match result {
@@ -807,6 +818,16 @@ impl EthereumAdapter {
         )
     }
 
+    /// Reorg safety: all `block_nums` must be final blocks.
+    pub(crate) fn block_ptrs_by_numbers(
+        &self,
+        logger: Logger,
+        block_nums: Vec<BlockNumber>,
+    ) -> Box<dyn Future<Item = Vec<BlockPtr>, Error = Error> + Send> {
+        debug!(&logger, "Requesting hashes for {} blocks", block_nums.len());
+        Box::new(self.load_block_ptrs_rpc(logger, block_nums).collect())
+    }
+
     pub async fn chain_id(&self) -> Result<u64, Error> {
         let logger = self.logger.clone();
         let web3 = self.web3.clone();
@@ -1263,6 +1284,93 @@ impl EthereumAdapterTrait for EthereumAdapter {
         )
     }
 
+    async fn get_balance(
+        &self,
+        logger: &Logger,
+        address: H160,
+        block_ptr: BlockPtr,
+        cache: Arc<dyn EthereumCallCache>,
+    ) -> Result<U256, EthereumRpcError> {
+        debug!(logger, "eth_getBalance";
+            "address" => hex::encode(&address),
+            "block" => block_ptr.to_string()
+        );
+
+        if let Some(balance) = get_cached(logger, &cache, address, BALANCE_CACHE_KEY, &block_ptr) {
+            return Ok(U256::from_big_endian(&balance));
+        }
+
+        let web3 = self.web3.clone();
+        let block_id = self.block_id(&block_ptr);
+        let retry_log_message = format!("eth_getBalance RPC call for block {}", block_ptr);
+        let balance: U256 = retry(retry_log_message, logger)
+            .limit(ENV_VARS.request_retries)
+            .timeout_secs(ENV_VARS.json_rpc_timeout.as_secs())
+            .run(move || {
+                let web3 = web3.cheap_clone();
+                async move { request_at_block(&web3, "eth_getBalance", address, block_id).await }
+            })
+            .await
+            .map_err(|e| e.into_inner().unwrap_or(EthereumRpcError::Timeout))?;
+
+        let mut bytes = [0u8; 32];
+        balance.to_big_endian(&mut bytes);
+        set_cached(
+            logger,
+            cache,
+            address,
+            BALANCE_CACHE_KEY,
+            block_ptr,
+            bytes.to_vec(),
+        );
+
+        Ok(balance)
+    }
+
+    async fn has_code(
+        &self,
+        logger: &Logger,
+        address: H160,
+        block_ptr: BlockPtr,
+        cache: Arc<dyn EthereumCallCache>,
+    ) -> Result<bool, EthereumRpcError> {
+        debug!(logger, "eth_getCode";
+            "address" => hex::encode(&address),
+            "block" => block_ptr.to_string()
+        );
+
+        if let Some(has_code) = get_cached(logger, &cache, address, HAS_CODE_CACHE_KEY, &block_ptr)
+        {
+            return Ok(has_code.first() == Some(&1));
+        }
+
+        let web3 = self.web3.clone();
+        let block_id = self.block_id(&block_ptr);
+        let retry_log_message = format!("eth_getCode RPC call for block {}", block_ptr);
+        let code: Bytes = retry(retry_log_message, logger)
+            .limit(ENV_VARS.request_retries)
+            .timeout_secs(ENV_VARS.json_rpc_timeout.as_secs())
+            .run(move || {
+                let web3 = web3.cheap_clone();
+                async move { request_at_block(&web3, "eth_getCode", address, block_id).await }
+            })
+            .await
+            .map_err(|e| e.into_inner().unwrap_or(EthereumRpcError::Timeout))?;
+
+        // Only whether there is code matters, there is no need to cache the code itself
+        let has_code = !code.0.is_empty();
+        set_cached(
+            logger,
+            cache,
+            address,
+            HAS_CODE_CACHE_KEY,
+            block_ptr,
+            vec![has_code as u8],
+        );
+
+        Ok(has_code)
+    }
+
     /// Load Ethereum blocks in bulk, returning results as they come back as a Stream.
     fn load_blocks(
         &self,
@@ -1313,6 +1421,66 @@ impl EthereumAdapterTrait for EthereumAdapter {
     }
 }
 
+/// Balances and whether an address has code are kept in the call cache with
+/// these keys in place of the encoded call. They can't collide with contract
+/// calls since those are always a 4 byte selector followed by 32 byte words.
+const BALANCE_CACHE_KEY: &[u8] = b"eth_getBalance";
+const HAS_CODE_CACHE_KEY: &[u8] = b"eth_hasCode";
+
+/// Make the JSON-RPC request `method` for `address` at `block_id`. The
+/// `web3` crate only accepts block numbers for `eth_getBalance` and
+/// `eth_getCode`, and we need to be able to pass a block hash.
+async fn request_at_block<T: serde::de::DeserializeOwned>(
+    web3: &Web3<Transport>,
+    method: &'static str,
+    address: H160,
+    block_id: BlockId,
+) -> Result<T, EthereumRpcError> {
+    let params = vec![
+        web3::helpers::serialize(&address),
+        web3::helpers::serialize(&block_id),
+    ];
+    let value = web3
+        .transport()
+        .execute(method, params)
+        .await
+        .map_err(EthereumRpcError::Web3Error)?;
+    json::from_value(value)
+        .map_err(|e| EthereumRpcError::Web3Error(web3::Error::Decoder(e.to_string())))
+}
+
+fn get_cached(
+    logger: &Logger,
+    cache: &Arc<dyn EthereumCallCache>,
+    address: H160,
+    key: &[u8],
+    block_ptr: &BlockPtr,
+) -> Option<Vec<u8>> {
+    cache
+        .get_call(address, key, block_ptr.clone())
+        .map_err(|e| error!(logger, "call cache get error"; "error" => e.to_string()))
+        .ok()
+        .flatten()
+}
+
+fn set_cached(
+    logger: &Logger,
+    cache: Arc<dyn EthereumCallCache>,
+    address: H160,
+    key: &'static [u8],
+    block_ptr: BlockPtr,
+    value: Vec<u8>,
+) {
+    let logger = logger.clone();
+
+    // Don't block handler execution on writing to the cache.
+    let _ = graph::spawn_blocking_allow_panic(move || {
+        cache
+            .set_call(address, key, block_ptr, &value)
+            .map_err(|e| error!(logger, "call cache set error"; "error" => e.to_string()))
+    });
+}
+
 /// Returns blocks with triggers, corresponding to the specified range and filters.
 /// If a block contains no triggers, there may be no corresponding item in the stream.
 /// However the `to` block will always be present, even if triggers are empty.
@@ -1374,11 +1542,12 @@ pub(crate) async fn blocks_with_triggers(
 
     // Scan for Blocks
     if filter.block.trigger_every_block {
+        let block_filter = filter.block.clone();
         let block_future = adapter
             .block_range_to_ptrs(logger.clone(), from, to)
             .map(move |ptrs| {
                 ptrs.into_iter()
-                    .map(|ptr| EthereumTrigger::Block(ptr, EthereumBlockTriggerType::Every))
+                    .flat_map(|ptr| plain_block_triggers(&block_filter, ptr))
                     .collect()
             })
             .compat()
@@ -1402,6 +1571,31 @@ pub(crate) async fn blocks_with_triggers(
         trigger_futs.push(block_future)
     }
 
+    // Scan for blocks that polling and once block handlers run on, unless
+    // they are already covered by the scan of every block above
+    if !filter.block.trigger_every_block && !filter.block.polling_intervals.is_empty() {
+        let block_filter = filter.block.clone();
+        let block_numbers: Vec<BlockNumber> = (from..=to)
+            .filter(|block_number| {
+                block_filter.matches_once(*block_number)
+                    || block_filter.matches_polling_interval(*block_number)
+            })
+            .collect();
+
+        if !block_numbers.is_empty() {
+            let block_future = adapter
+                .block_ptrs_by_numbers(logger.clone(), block_numbers)
+                .map(move |ptrs| {
+                    ptrs.into_iter()
+                        .flat_map(|ptr| plain_block_triggers(&block_filter, ptr))
+                        .collect()
+                })
+                .compat()
+                .boxed();
+            trigger_futs.push(block_future)
+        }
+    }
+
     // Get hash for "to" block
     let to_hash_fut = adapter
         .block_hash_by_block_number(&logger, to)
@@ -1589,7 +1783,6 @@ pub(crate) fn parse_block_triggers(
     }
 
     let block_ptr = BlockPtr::from(&block.ethereum_block);
-    let trigger_every_block = block_filter.trigger_every_block;
     let call_filter = EthereumCallFilter::from(block_filter);
     let block_ptr2 = block_ptr.cheap_clone();
     let mut triggers = match &block.calls {
@@ -1605,7 +1798,24 @@ pub(crate) fn parse_block_triggers(
             .collect::<Vec<EthereumTrigger>>(),
         None => vec![],
     };
-    if trigger_every_block {
+    triggers.append(&mut plain_block_triggers(block_filter, block_ptr));
+    triggers
+}
+
+/// Returns the triggers for block handlers that don't depend on the contents
+/// of the block: non-filtered, polling and once block handlers.
+fn plain_block_triggers(
+    block_filter: &EthereumBlockFilter,
+    block_ptr: BlockPtr,
+) -> Vec<EthereumTrigger> {
+    let mut triggers = Vec::new();
+    if block_filter.matches_once(block_ptr.number) {
+        triggers.push(EthereumTrigger::Block(
+            block_ptr.cheap_clone(),
+            EthereumBlockTriggerType::Start,
+        ));
+    }
+    if block_filter.trigger_every_block || block_filter.matches_polling_interval(block_ptr.number) {
         triggers.push(EthereumTrigger::Block(
             block_ptr,
             EthereumBlockTriggerType::Every,
@@ -2086,6 +2296,7 @@ mod tests {
             )],
             parse_block_triggers(
                 &EthereumBlockFilter {
+                    polling_intervals: HashSet::default(),
                     contract_addresses: HashSet::from_iter(vec![(10, address(1))]),
                     trigger_every_block: true,
                 },
@@ -2117,6 +2328,7 @@ mod tests {
             Vec::<EthereumTrigger>::new(),
             parse_block_triggers(
                 &EthereumBlockFilter {
+                    polling_intervals: HashSet::default(),
                     contract_addresses: HashSet::from_iter(vec![(1, address(1))]),
                     trigger_every_block: false,
                 },
@@ -2151,6 +2363,7 @@ mod tests {
             )],
             parse_block_triggers(
                 &EthereumBlockFilter {
+                    polling_intervals: HashSet::default(),
                     contract_addresses: HashSet::from_iter(vec![(1, address(4))]),
                     trigger_every_block: false,
                 },
@@ -2160,6 +2373,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_block_triggers_polling_and_once() {
+        let block = |number: u64| EthereumBlockWithCalls {
+            ethereum_block: EthereumBlock {
+                block: Arc::new(Block {
+                    hash: Some(hash(number as u8)),
+                    number: Some(U64::from(number)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            calls: Some(vec![]),
+        };
+
+        let filter = EthereumBlockFilter {
+            polling_intervals: HashSet::from_iter(vec![(10, 5), (10, 0)]),
+            contract_addresses: HashSet::new(),
+            trigger_every_block: false,
+        };
+
+        assert_eq!(
+            vec![
+                EthereumTrigger::Block(
+                    BlockPtr::from((hash(10), 10)),
+                    EthereumBlockTriggerType::Start
+                ),
+                EthereumTrigger::Block(
+                    BlockPtr::from((hash(10), 10)),
+                    EthereumBlockTriggerType::Every
+                )
+            ],
+            parse_block_triggers(&filter, &block(10)),
+            "start block should trigger both the once and the polling handlers"
+        );
+
+        assert_eq!(
+            Vec::<EthereumTrigger>::new(),
+            parse_block_triggers(&filter, &block(12)),
+            "block outside of the polling interval should not trigger"
+        );
+
+        assert_eq!(
+            vec![EthereumTrigger::Block(
+                BlockPtr::from((hash(15), 15)),
+                EthereumBlockTriggerType::Every
+            )],
+            parse_block_triggers(&filter, &block(15)),
+            "block on the polling interval should trigger the polling handler"
+        );
+    }
+
     fn address(id: u64) -> Address {
         Address::from_low_u64_be(id)
     }
@@ -1,4 +1,5 @@
 mod adapter;
+mod call_cache;
 mod capabilities;
 pub mod codec;
 mod data_source;
@@ -8,6 +9,7 @@ mod ingestor;
 pub mod runtime;
 mod transport;
 
+pub use self::call_cache::DeclaredCallCache;
 pub use self::capabilities::NodeCapabilities;
 pub use self::ethereum_adapter::EthereumAdapter;
 pub use self::runtime::RuntimeAdapter;
@@ -15,7 +17,10 @@ pub use self::transport::Transport;
 pub use env::ENV_VARS;
 
 // ETHDEP: These concrete types should probably not be exposed.
-pub use data_source::{DataSource, DataSourceTemplate, Mapping, MappingABI, TemplateSource};
+pub use data_source::{
+    BlockHandlerFilter, CallArg, CallExpr, DataSource, DataSourceTemplate, Mapping, MappingABI,
+    TemplateSource,
+};
 
 pub mod chain;
 
@@ -24,7 +29,7 @@ pub mod trigger;
 
 pub use crate::adapter::{
     EthereumAdapter as EthereumAdapterTrait, EthereumContractCall, EthereumContractCallError,
-    ProviderEthRpcMetrics, SubgraphEthRpcMetrics, TriggerFilter,
+    EthereumRpcError, ProviderEthRpcMetrics, SubgraphEthRpcMetrics, TriggerFilter,
 };
 pub use crate::chain::Chain;
 pub use crate::network::EthereumNetworks;
@@ -1,9 +1,12 @@
 use std::{sync::Arc, time::Instant};
 
+use crate::chain::BlockFinality;
 use crate::data_source::MappingABI;
+use crate::trigger::EthereumTrigger;
 use crate::{
     capabilities::NodeCapabilities, network::EthereumNetworkAdapters, Chain, DataSource,
-    EthereumAdapter, EthereumAdapterTrait, EthereumContractCall, EthereumContractCallError,
+    DeclaredCallCache, EthereumAdapter, EthereumAdapterTrait, EthereumContractCall,
+    EthereumContractCallError, ENV_VARS,
 };
 use anyhow::{Context, Error};
 use blockchain::HostFn;
@@ -13,14 +16,19 @@ use graph::{
     blockchain::{self, BlockPtr, HostFnCtx},
     cheap_clone::CheapClone,
     prelude::{
+        async_trait,
         ethabi::{self, Address, Token},
-        EthereumCallCache, Future01CompatExt,
+        futures03::{stream, StreamExt},
+        web3::types::Bytes,
+        BigInt, EthereumCallCache, Future01CompatExt,
     },
     runtime::{asc_get, asc_new, AscPtr, HostExportError},
     semver::Version,
-    slog::{info, trace, Logger},
+    slog::{debug, error, info, trace, warn, Logger},
+};
+use graph_runtime_wasm::asc_abi::class::{
+    AscBigInt, AscEnumArray, AscWrapped, EthereumValueKind, Uint8Array,
 };
-use graph_runtime_wasm::asc_abi::class::{AscEnumArray, EthereumValueKind};
 
 use super::abi::{AscUnresolvedContractCall, AscUnresolvedContractCall_0_0_4};
 
@@ -34,11 +42,17 @@ use super::abi::{AscUnresolvedContractCall, AscUnresolvedContractCall_0_0_4};
 // [1] - https://www.sciencedirect.com/science/article/abs/pii/S0166531620300900
 pub const ETHEREUM_CALL: Gas = Gas::new(5_000_000_000);
 
+// Getting the balance or the code of an address doesn't execute any EVM code, so these are
+// charged for roughly the cost of a round trip to the Ethereum node.
+pub const ETHEREUM_GET_BALANCE: Gas = Gas::new(100_000_000);
+pub const ETHEREUM_HAS_CODE: Gas = Gas::new(100_000_000);
+
 pub struct RuntimeAdapter {
     pub eth_adapters: Arc<EthereumNetworkAdapters>,
-    pub call_cache: Arc<dyn EthereumCallCache>,
+    pub call_cache: Arc<DeclaredCallCache>,
 }
 
+#[async_trait]
 impl blockchain::RuntimeAdapter<Chain> for RuntimeAdapter {
     fn host_fns(&self, ds: &DataSource) -> Result<Vec<HostFn>, Error> {
         let abis = ds.mapping.abis.clone();
@@ -51,15 +65,232 @@ impl blockchain::RuntimeAdapter<Chain> for RuntimeAdapter {
             })?
             .cheap_clone();
 
-        let ethereum_call = HostFn {
-            name: "ethereum.call",
+        let ethereum_call = {
+            let eth_adapter = eth_adapter.cheap_clone();
+            let call_cache = call_cache.cheap_clone();
+            HostFn {
+                name: "ethereum.call",
+                func: Arc::new(move |ctx, wasm_ptr| {
+                    ethereum_call(&eth_adapter, call_cache.cheap_clone(), ctx, wasm_ptr, &abis)
+                        .map(|ptr| ptr.wasm_ptr())
+                }),
+            }
+        };
+
+        let ethereum_get_balance = {
+            let eth_adapter = eth_adapter.cheap_clone();
+            let call_cache = call_cache.cheap_clone();
+            HostFn {
+                name: "ethereum.getBalance",
+                func: Arc::new(move |ctx, wasm_ptr| {
+                    ethereum_get_balance(&eth_adapter, call_cache.cheap_clone(), ctx, wasm_ptr)
+                        .map(|ptr| ptr.wasm_ptr())
+                }),
+            }
+        };
+
+        let ethereum_has_code = HostFn {
+            name: "ethereum.hasCode",
             func: Arc::new(move |ctx, wasm_ptr| {
-                ethereum_call(&eth_adapter, call_cache.cheap_clone(), ctx, wasm_ptr, &abis)
+                ethereum_has_code(&eth_adapter, call_cache.cheap_clone(), ctx, wasm_ptr)
                     .map(|ptr| ptr.wasm_ptr())
             }),
         };
 
-        Ok(vec![ethereum_call])
+        Ok(vec![ethereum_call, ethereum_get_balance, ethereum_has_code])
+    }
+
+    /// Runs the calls that event handlers declare in the manifest for the
+    /// triggers in `block`. The results are kept in memory in the call
+    /// cache by the time this returns, where `ethereum.call` will find them
+    /// when the handlers run.
+    async fn prefetch(
+        &self,
+        logger: &Logger,
+        block: &Arc<BlockFinality>,
+        triggers: &[EthereumTrigger],
+        data_sources: &[&DataSource],
+    ) {
+        let light_block = block.light_block();
+        let calls: Vec<_> = data_sources
+            .iter()
+            .filter(|ds| ds.mapping.has_declared_calls())
+            .flat_map(|ds| triggers.iter().map(move |trigger| (ds, trigger)))
+            .filter(|(_, trigger)| matches!(trigger, EthereumTrigger::Log(..)))
+            .flat_map(
+                |(ds, trigger)| match ds.declared_calls(trigger, light_block, logger) {
+                    Ok(calls) => calls,
+                    Err(e) => {
+                        warn!(logger, "Failed to prepare declared calls";
+                              "data_source" => &ds.name,
+                              "error" => format!("{:#}", e));
+                        vec![]
+                    }
+                },
+            )
+            .collect();
+
+        if calls.is_empty() {
+            return;
+        }
+
+        // Declared calls make a data source require an archive node
+        let eth_adapter = match self.eth_adapters.cheapest_with(&NodeCapabilities {
+            archive: true,
+            traces: false,
+        }) {
+            Ok(eth_adapter) => eth_adapter,
+            Err(e) => {
+                warn!(logger, "Failed to prefetch declared calls"; "error" => e.to_string());
+                return;
+            }
+        };
+
+        let start_time = Instant::now();
+        let count = calls.len();
+        stream::iter(
+            calls
+                .into_iter()
+                .map(|call| prefetch_call(&eth_adapter, logger, call, &self.call_cache)),
+        )
+        .buffer_unordered(ENV_VARS.declared_calls_batch_size)
+        .for_each(|result| async move {
+            // Reverts are expected and handled by the mapping; any other
+            // error is retried when the handler makes the call itself
+            match result {
+                Ok(_) | Err(EthereumContractCallError::Revert(_)) => {}
+                Err(e) => {
+                    debug!(logger, "Failed to prefetch declared call"; "error" => e.to_string())
+                }
+            }
+        })
+        .await;
+
+        trace!(logger, "Prefetched declared calls";
+               "count" => count,
+               "time" => format!("{}ms", start_time.elapsed().as_millis()));
+    }
+}
+
+/// Make the declared `call` and keep its raw result in memory in
+/// `call_cache`. The result is written to the store in the background.
+async fn prefetch_call(
+    eth_adapter: &EthereumAdapter,
+    logger: &Logger,
+    call: EthereumContractCall,
+    call_cache: &Arc<DeclaredCallCache>,
+) -> Result<(), EthereumContractCallError> {
+    let encoded_call = call
+        .function
+        .encode_input(&call.args)
+        .map_err(EthereumContractCallError::EncodingError)?;
+
+    let cached = call_cache
+        .get_call(call.address, &encoded_call, call.block_ptr.clone())
+        .map_err(|e| error!(logger, "call cache get error"; "error" => e.to_string()))
+        .ok()
+        .flatten();
+
+    let return_value = match cached {
+        Some(return_value) => return_value,
+        None => {
+            let return_value = eth_adapter
+                .call(
+                    logger.clone(),
+                    call.address,
+                    Bytes(encoded_call.clone()),
+                    call.block_ptr.clone(),
+                )
+                .compat()
+                .await?
+                .0;
+
+            // Don't hold up the handlers on writing to the store
+            let call_cache = call_cache.cheap_clone();
+            let logger = logger.clone();
+            let (address, encoded_call, block_ptr, return_value) = (
+                call.address,
+                encoded_call.clone(),
+                call.block_ptr.clone(),
+                return_value.clone(),
+            );
+            let _ = graph::spawn_blocking_allow_panic(move || {
+                call_cache
+                    .set_call(address, &encoded_call, block_ptr, &return_value)
+                    .map_err(|e| error!(logger, "call cache set error"; "error" => e.to_string()))
+            });
+
+            return_value
+        }
+    };
+
+    call_cache.insert_declared(call.address, encoded_call, call.block_ptr, return_value);
+    Ok(())
+}
+
+/// function ethereum.getBalance(address: Address): BigInt
+fn ethereum_get_balance(
+    eth_adapter: &EthereumAdapter,
+    call_cache: Arc<dyn EthereumCallCache>,
+    ctx: HostFnCtx<'_>,
+    wasm_ptr: u32,
+) -> Result<AscPtr<AscBigInt>, HostExportError> {
+    ctx.gas.consume_host_fn(ETHEREUM_GET_BALANCE)?;
+
+    let address: Address = asc_get::<_, Uint8Array, _>(ctx.heap, wasm_ptr.into(), &ctx.gas)?;
+    let result = graph::block_on(eth_adapter.get_balance(
+        &ctx.logger,
+        address,
+        ctx.block_ptr.cheap_clone(),
+        call_cache,
+    ));
+
+    match result {
+        Ok(balance) => {
+            let balance = BigInt::from_unsigned_u256(&balance);
+            Ok(asc_new(ctx.heap, &balance, &ctx.gas)?)
+        }
+
+        // Any error reported by the Ethereum node could be due to the block no longer being on
+        // the main chain, so retry on all of them.
+        Err(e) => Err(HostExportError::PossibleReorg(anyhow::anyhow!(
+            "Failed to get the balance of address {:?}: {}",
+            address,
+            e
+        ))),
+    }
+}
+
+/// function ethereum.hasCode(address: Address): boolean
+fn ethereum_has_code(
+    eth_adapter: &EthereumAdapter,
+    call_cache: Arc<dyn EthereumCallCache>,
+    ctx: HostFnCtx<'_>,
+    wasm_ptr: u32,
+) -> Result<AscPtr<AscWrapped<bool>>, HostExportError> {
+    ctx.gas.consume_host_fn(ETHEREUM_HAS_CODE)?;
+
+    let address: Address = asc_get::<_, Uint8Array, _>(ctx.heap, wasm_ptr.into(), &ctx.gas)?;
+    let result = graph::block_on(eth_adapter.has_code(
+        &ctx.logger,
+        address,
+        ctx.block_ptr.cheap_clone(),
+        call_cache,
+    ));
+
+    match result {
+        Ok(has_code) => {
+            let has_code = AscWrapped { inner: has_code };
+            Ok(asc_new(ctx.heap, &has_code, &ctx.gas)?)
+        }
+
+        // Any error reported by the Ethereum node could be due to the block no longer being on
+        // the main chain, so retry on all of them.
+        Err(e) => Err(HostExportError::PossibleReorg(anyhow::anyhow!(
+            "Failed to get the code of address {:?}: {}",
+            address,
+            e
+        ))),
     }
 }
 
@@ -224,3 +455,229 @@ pub struct UnresolvedContractCall {
 impl AscIndexId for AscUnresolvedContractCall {
     const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::SmartContractCall;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::mem::MaybeUninit;
+    use std::sync::{Arc, Mutex};
+
+    use graph::{
+        anyhow::{anyhow, Error},
+        blockchain::{BlockPtr, HostFnCtx},
+        components::store::CachedEthereumCall,
+        data::subgraph::API_VERSION_0_0_5,
+        prelude::{
+            ethabi::Address,
+            web3::types::{H256, U256},
+            BigInt, EthereumCallCache, Logger,
+        },
+        runtime::{
+            asc_get, asc_new, gas::GasCounter, AscHeap, AscPtr, DeterministicHostError,
+            IndexForAscTypeId,
+        },
+        semver::Version,
+        util::mem::init_slice,
+    };
+    use graph_runtime_wasm::asc_abi::class::{AscBigInt, AscWrapped, Uint8Array};
+
+    use crate::{adapter::ProviderEthRpcMetrics, transport::Transport, EthereumAdapter};
+
+    use super::{ethereum_get_balance, ethereum_has_code};
+
+    /// A call cache that only keeps its entries in memory
+    #[derive(Default)]
+    struct MemoryCallCache {
+        calls: Mutex<HashMap<(Address, Vec<u8>, BlockPtr), Vec<u8>>>,
+    }
+
+    impl EthereumCallCache for MemoryCallCache {
+        fn get_call(
+            &self,
+            contract_address: Address,
+            encoded_call: &[u8],
+            block: BlockPtr,
+        ) -> Result<Option<Vec<u8>>, Error> {
+            let key = (contract_address, encoded_call.to_vec(), block);
+            Ok(self.calls.lock().unwrap().get(&key).cloned())
+        }
+
+        fn get_calls_in_block(&self, block: BlockPtr) -> Result<Vec<CachedEthereumCall>, Error> {
+            let calls = self.calls.lock().unwrap();
+            Ok(calls
+                .iter()
+                .filter(|((_, _, call_block), _)| call_block == &block)
+                .map(
+                    |((contract_address, encoded_call, call_block), return_value)| {
+                        CachedEthereumCall {
+                            blake3_id: CachedEthereumCall::id(
+                                contract_address,
+                                encoded_call,
+                                call_block,
+                            )
+                            .to_vec(),
+                            block_ptr: call_block.clone(),
+                            contract_address: *contract_address,
+                            return_value: return_value.clone(),
+                            call_data: Some(encoded_call.clone()),
+                            declared: false,
+                        }
+                    },
+                )
+                .collect())
+        }
+
+        fn set_call(
+            &self,
+            contract_address: Address,
+            encoded_call: &[u8],
+            block: BlockPtr,
+            return_value: &[u8],
+        ) -> Result<(), Error> {
+            let key = (contract_address, encoded_call.to_vec(), block);
+            self.calls
+                .lock()
+                .unwrap()
+                .insert(key, return_value.to_vec());
+            Ok(())
+        }
+    }
+
+    struct BytesHeap {
+        memory: Vec<u8>,
+    }
+
+    impl AscHeap for BytesHeap {
+        fn raw_new(
+            &mut self,
+            bytes: &[u8],
+            _gas: &GasCounter,
+        ) -> Result<u32, DeterministicHostError> {
+            self.memory.extend_from_slice(bytes);
+            Ok((self.memory.len() - bytes.len()) as u32)
+        }
+
+        fn read<'a>(
+            &self,
+            offset: u32,
+            buffer: &'a mut [MaybeUninit<u8>],
+            _gas: &GasCounter,
+        ) -> Result<&'a mut [u8], DeterministicHostError> {
+            let start = offset as usize;
+            let src = self
+                .memory
+                .get(start..start + buffer.len())
+                .ok_or_else(|| anyhow!("read outside of allocated memory at {}", offset))?;
+            Ok(init_slice(src, buffer))
+        }
+
+        fn read_u32(&self, offset: u32, gas: &GasCounter) -> Result<u32, DeterministicHostError> {
+            let mut data = [MaybeUninit::<u8>::uninit(); 4];
+            let init = self.read(offset, &mut data, gas)?;
+            Ok(u32::from_le_bytes(init.try_into().unwrap()))
+        }
+
+        fn api_version(&self) -> Version {
+            API_VERSION_0_0_5.clone()
+        }
+
+        fn asc_type_id(
+            &mut self,
+            type_id_index: IndexForAscTypeId,
+        ) -> Result<u32, DeterministicHostError> {
+            Ok(type_id_index as u32)
+        }
+    }
+
+    /// Runs `test` with an adapter for an Ethereum node that can not be
+    /// reached, so that all requests have to be answered from the cache
+    fn run_test<F>(test: F)
+    where
+        F: FnOnce(&EthereumAdapter, BlockPtr),
+    {
+        let runtime = graph::prelude::tokio::runtime::Runtime::new().unwrap();
+        let url = "http://127.0.0.1:1";
+        let transport =
+            Transport::new_rpc(graph::url::Url::parse(url).unwrap(), Default::default());
+        let metrics = Arc::new(ProviderEthRpcMetrics::new(
+            test_store::METRICS_REGISTRY.clone(),
+        ));
+        let adapter = runtime.block_on(EthereumAdapter::new(
+            graph::log::discard(),
+            "unreachable".to_string(),
+            url,
+            transport,
+            metrics,
+            true,
+        ));
+
+        // The host functions block on the runtime, which they can only do
+        // outside of it
+        let _guard = runtime.enter();
+        test(&adapter, BlockPtr::from((H256::from_low_u64_be(7), 7)));
+    }
+
+    fn ctx<'a>(heap: &'a mut BytesHeap, block_ptr: &BlockPtr) -> HostFnCtx<'a> {
+        HostFnCtx {
+            logger: Logger::root(graph::slog::Discard, graph::slog::o!()),
+            block_ptr: block_ptr.clone(),
+            heap,
+            gas: GasCounter::new(),
+        }
+    }
+
+    fn address_ptr(heap: &mut BytesHeap, address: &Address) -> u32 {
+        // Keep the address away from the null pointer
+        heap.memory.resize(16, 0);
+        let ptr: AscPtr<Uint8Array> = asc_new(heap, address, &GasCounter::new()).unwrap();
+        ptr.wasm_ptr()
+    }
+
+    #[test]
+    fn get_balance_from_cache() {
+        run_test(|adapter, block_ptr| {
+            let address = Address::from_low_u64_be(1);
+            let cache = Arc::new(MemoryCallCache::default());
+            let mut balance = [0u8; 32];
+            U256::from(1_000_000).to_big_endian(&mut balance);
+            cache
+                .set_call(address, b"eth_getBalance", block_ptr.clone(), &balance)
+                .unwrap();
+
+            let mut heap = BytesHeap { memory: vec![] };
+            let wasm_ptr = address_ptr(&mut heap, &address);
+            let ptr = ethereum_get_balance(adapter, cache, ctx(&mut heap, &block_ptr), wasm_ptr)
+                .expect("the balance is in the cache");
+
+            let balance: BigInt =
+                asc_get::<_, AscBigInt, _>(&heap, ptr, &GasCounter::new()).unwrap();
+            assert_eq!(BigInt::from(1_000_000), balance);
+        })
+    }
+
+    #[test]
+    fn has_code_from_cache() {
+        run_test(|adapter, block_ptr| {
+            let contract = Address::from_low_u64_be(1);
+            let account = Address::from_low_u64_be(2);
+            let cache = Arc::new(MemoryCallCache::default());
+            cache
+                .set_call(contract, b"eth_hasCode", block_ptr.clone(), &[1])
+                .unwrap();
+            cache
+                .set_call(account, b"eth_hasCode", block_ptr.clone(), &[0])
+                .unwrap();
+
+            for (address, expected) in [(contract, true), (account, false)] {
+                let mut heap = BytesHeap { memory: vec![] };
+                let wasm_ptr = address_ptr(&mut heap, &address);
+                let ptr =
+                    ethereum_has_code(adapter, cache.clone(), ctx(&mut heap, &block_ptr), wasm_ptr)
+                        .expect("the answer is in the cache");
+
+                let has_code: AscWrapped<bool> = ptr.read_ptr(&heap, &GasCounter::new()).unwrap();
+                assert_eq!(expected, has_code.inner);
+            }
+        })
+    }
+}
@@ -2,14 +2,16 @@ use std::sync::Arc;
 
 use graph::{
     blockchain::{block_stream::BlockWithTriggers, BlockPtr},
+    data::subgraph::API_VERSION_0_0_5,
     prelude::{
         web3::types::{Address, Bytes, Log, H160, H256, U64},
-        EthereumCall,
+        EthereumCall, Link,
     },
 };
 
 use crate::{
     chain::BlockFinality,
+    data_source::Mapping,
     trigger::{EthereumBlockTriggerType, EthereumTrigger},
 };
 
@@ -95,3 +97,53 @@ fn test_trigger_ordering() {
         vec![log1, log2, call1, log3, call2, call4, call3, block2, block1]
     );
 }
+
+/// A wasm module that does nothing but import the functions `host_fns`
+fn wasm_importing(host_fns: &[&str]) -> Vec<u8> {
+    let mut imports = vec![host_fns.len() as u8];
+    for host_fn in host_fns {
+        for name in ["index", host_fn] {
+            imports.push(name.len() as u8);
+            imports.extend(name.as_bytes());
+        }
+        // A function of type 0
+        imports.extend([0x00, 0x00]);
+    }
+
+    let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+    // The type section with the single type `() -> ()`
+    wasm.extend([0x01, 0x04, 0x01, 0x60, 0x00, 0x00]);
+    wasm.push(0x02);
+    wasm.push(imports.len() as u8);
+    wasm.extend(imports);
+    wasm
+}
+
+#[test]
+fn test_requires_archive() {
+    let mapping = |host_fns: &[&str]| Mapping {
+        kind: "ethereum/events".to_string(),
+        api_version: API_VERSION_0_0_5.clone(),
+        language: "wasm/assemblyscript".to_string(),
+        entities: vec![],
+        abis: vec![],
+        block_handlers: vec![],
+        call_handlers: vec![],
+        event_handlers: vec![],
+        runtime: Arc::new(wasm_importing(host_fns)),
+        link: Link {
+            link: "link".to_string(),
+        },
+    };
+
+    assert!(!mapping(&["store.set", "log.log"])
+        .requires_archive()
+        .unwrap());
+    for host_fn in ["ethereum.call", "ethereum.getBalance", "ethereum.hasCode"] {
+        assert!(
+            mapping(&["store.set", host_fn]).requires_archive().unwrap(),
+            "`{}` requires an archive node",
+            host_fn
+        );
+    }
+}
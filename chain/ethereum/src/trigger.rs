@@ -244,6 +244,7 @@ impl Eq for EthereumTrigger {}
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum EthereumBlockTriggerType {
+    Start,
     Every,
     WithCallTo(Address),
 }
@@ -1,24 +1,25 @@
 use std::collections::HashMap;
+use std::num::NonZeroU32;
 use std::sync::Arc;
 use std::time::Duration;
 
 use graph::data::subgraph::schema::SubgraphError;
-use graph::data::subgraph::{SPEC_VERSION_0_0_4, SPEC_VERSION_0_0_7};
+use graph::data::subgraph::{IndexerHints, Prune, SPEC_VERSION_0_0_4, SPEC_VERSION_0_0_7};
 use graph::data_source::DataSourceTemplate;
 use graph::prelude::{
     anyhow, async_trait, serde_yaml, tokio, DeploymentHash, Entity, Link, Logger, SubgraphManifest,
     SubgraphManifestValidationError, UnvalidatedSubgraphManifest,
 };
 use graph::{
-    blockchain::NodeCapabilities as _,
+    blockchain::{DataSource as _, NodeCapabilities as _},
     components::{
         link_resolver::{JsonValueStream, LinkResolver as LinkResolverTrait},
-        store::EntityType,
+        store::{EntityType, BLOCK_NUMBER_MAX},
     },
     data::subgraph::SubgraphFeature,
 };
 
-use graph_chain_ethereum::{Chain, NodeCapabilities};
+use graph_chain_ethereum::{BlockHandlerFilter, CallArg, CallExpr, Chain, NodeCapabilities};
 use semver::Version;
 use test_store::LOGGER;
 
@@ -100,6 +101,8 @@ async fn resolve_unvalidated(text: &str) -> UnvalidatedSubgraphManifest<Chain> {
 
     resolver.add(id.as_str(), &text);
     resolver.add("/ipfs/Qmschema", &GQL_SCHEMA);
+    resolver.add("/ipfs/Qmabi", &ABI);
+    resolver.add("/ipfs/Qmmapping", &MAPPING_WITH_IPFS_FUNC_WASM);
 
     let resolver: Arc<dyn LinkResolverTrait> = Arc::new(resolver);
 
@@ -128,6 +131,46 @@ specVersion: 0.0.2
     assert!(manifest.graft.is_none());
 }
 
+#[tokio::test]
+async fn indexer_hints_manifest() {
+    const YAML: &str = "
+dataSources: []
+schema:
+  file:
+    /: /ipfs/Qmschema
+specVersion: 0.0.2
+indexerHints:
+  prune: PRUNE
+";
+
+    async fn prune(value: &str) -> Option<Prune> {
+        let yaml = YAML.replace("PRUNE", value);
+        resolve_manifest(&yaml, SPEC_VERSION_0_0_4)
+            .await
+            .indexer_hints
+            .prune
+    }
+
+    assert_eq!(Some(Prune::Auto), prune("auto").await);
+    assert_eq!(Some(Prune::Never), prune("never").await);
+    assert_eq!(Some(Prune::Blocks(100000)), prune("100000").await);
+
+    let manifest = resolve_manifest(&YAML.replace("PRUNE", "100000"), SPEC_VERSION_0_0_4).await;
+    assert_eq!(100000, manifest.indexer_hints.history_blocks());
+    let manifest = resolve_manifest(&YAML.replace("PRUNE", "never"), SPEC_VERSION_0_0_4).await;
+    assert_eq!(BLOCK_NUMBER_MAX, manifest.indexer_hints.history_blocks());
+
+    for invalid in ["0", "-5", "sometimes"] {
+        let yaml = YAML.replace("PRUNE", invalid);
+        let raw: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        assert!(
+            serde_yaml::from_value::<IndexerHints>(raw["indexerHints"].clone()).is_err(),
+            "prune: {} is not valid",
+            invalid
+        );
+    }
+}
+
 #[tokio::test]
 async fn ipfs_manifest() {
     let yaml = "
@@ -407,6 +450,237 @@ specVersion: 0.0.2
     assert_eq!(true, required_capabilities.traces);
 }
 
+#[tokio::test]
+async fn parse_polling_and_once_block_handlers() {
+    const YAML: &str = "
+dataSources:
+  - kind: ethereum/contract
+    name: Factory
+    network: mainnet
+    source:
+      address: \"0x0000000000000000000000000000000000000000\"
+      abi: Factory
+      startBlock: 9562480
+    mapping:
+      kind: ethereum/events
+      apiVersion: 0.0.4
+      language: wasm/assemblyscript
+      entities:
+        - TestEntity
+      file:
+        /: /ipfs/Qmmapping
+      abis:
+        - name: Factory
+          file:
+            /: /ipfs/Qmabi
+      blockHandlers:
+        - handler: initialize
+          filter:
+            kind: once
+        - handler: poll
+          filter:
+            kind: polling
+            every: 10
+schema:
+  file:
+    /: /ipfs/Qmschema
+specVersion: 0.0.2
+";
+
+    let manifest = resolve_manifest(YAML, SPEC_VERSION_0_0_4).await;
+    let data_source = manifest.data_sources[0].as_onchain().unwrap();
+    let filters = data_source
+        .mapping
+        .block_handlers
+        .iter()
+        .map(|handler| handler.filter.clone())
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        vec![
+            Some(BlockHandlerFilter::Once),
+            Some(BlockHandlerFilter::Polling {
+                every: NonZeroU32::new(10).unwrap()
+            })
+        ],
+        filters
+    );
+}
+
+#[tokio::test]
+async fn parse_declared_calls() {
+    const YAML: &str = "
+dataSources:
+  - kind: ethereum/contract
+    name: Factory
+    network: mainnet
+    source:
+      address: \"0x0000000000000000000000000000000000000000\"
+      abi: Factory
+      startBlock: 9562480
+    mapping:
+      kind: ethereum/events
+      apiVersion: 0.0.4
+      language: wasm/assemblyscript
+      entities:
+        - TestEntity
+      file:
+        /: /ipfs/Qmmapping
+      abis:
+        - name: Factory
+          file:
+            /: /ipfs/Qmabi
+      eventHandlers:
+        - event: Created(address,uint256)
+          handler: handleCreated
+          calls:
+            owner: Factory[event.address].get(event.params.id)
+schema:
+  file:
+    /: /ipfs/Qmschema
+specVersion: 0.0.2
+";
+
+    let manifest = resolve_manifest(YAML, SPEC_VERSION_0_0_4).await;
+    let data_source = manifest.data_sources[0].as_onchain().unwrap();
+    let handler = &data_source.mapping.event_handlers[0];
+    let calls = handler.calls.iter().collect::<Vec<_>>();
+
+    assert_eq!(1, calls.len());
+    assert_eq!("owner", calls[0].label);
+    assert_eq!(
+        CallExpr {
+            abi: "Factory".to_owned(),
+            address: CallArg::Address,
+            func: "get".to_owned(),
+            args: vec![CallArg::Param("id".to_owned())],
+        },
+        calls[0].expr
+    );
+    assert!(data_source.mapping.requires_archive().unwrap());
+}
+
+#[tokio::test]
+async fn parse_end_block() {
+    const YAML: &str = "
+dataSources:
+  - kind: ethereum/contract
+    name: Factory
+    network: mainnet
+    source:
+      abi: Factory
+      startBlock: 9562480
+      endBlock: 9562500
+    mapping:
+      kind: ethereum/events
+      apiVersion: 0.0.4
+      language: wasm/assemblyscript
+      entities:
+        - TestEntity
+      file:
+        /: /ipfs/Qmmapping
+      abis:
+        - name: Factory
+          file:
+            /: /ipfs/Qmabi
+      callHandlers:
+        - function: get(address)
+          handler: handleget
+  - kind: ethereum/contract
+    name: Pool
+    network: mainnet
+    source:
+      abi: Factory
+      startBlock: 9562480
+      endBlock: 9562600
+    mapping:
+      kind: ethereum/events
+      apiVersion: 0.0.4
+      language: wasm/assemblyscript
+      entities:
+        - TestEntity
+      file:
+        /: /ipfs/Qmmapping
+      abis:
+        - name: Factory
+          file:
+            /: /ipfs/Qmabi
+      callHandlers:
+        - function: get(address)
+          handler: handleget
+schema:
+  file:
+    /: /ipfs/Qmschema
+specVersion: 0.0.2
+";
+
+    let manifest = resolve_manifest(YAML, SPEC_VERSION_0_0_4).await;
+    let end_blocks: Vec<_> = manifest
+        .data_sources
+        .iter()
+        .map(|ds| ds.end_block())
+        .collect();
+
+    assert_eq!(vec![Some(9562500), Some(9562600)], end_blocks);
+    assert_eq!(Some(9562600), manifest.max_end_block());
+    assert!(manifest
+        .data_sources
+        .iter()
+        .all(|ds| ds.as_onchain().unwrap().has_expired(9562601)));
+}
+
+#[test]
+fn end_block_before_start_block_causes_validation_error() {
+    const YAML: &str = "
+dataSources:
+  - kind: ethereum/contract
+    name: Factory
+    network: mainnet
+    source:
+      address: \"0x0000000000000000000000000000000000000000\"
+      abi: Factory
+      startBlock: 100
+      endBlock: 99
+    mapping:
+      kind: ethereum/events
+      apiVersion: 0.0.4
+      language: wasm/assemblyscript
+      entities:
+        - TestEntity
+      file:
+        /: /ipfs/Qmmapping
+      abis:
+        - name: Factory
+          file:
+            /: /ipfs/Qmabi
+      callHandlers:
+        - function: get(address)
+          handler: handleget
+schema:
+  file:
+    /: /ipfs/Qmschema
+specVersion: 0.0.2
+";
+
+    test_store::run_test_sequentially(|store| async move {
+        let store = store.subgraph_store();
+        let unvalidated = resolve_unvalidated(YAML).await;
+        let error_msg = unvalidated
+            .validate(store.clone(), true)
+            .await
+            .expect_err("Validation must fail")
+            .into_iter()
+            .find(|e| matches!(e, SubgraphManifestValidationError::DataSourceValidation(..)))
+            .expect("There must be a data source validation error")
+            .to_string();
+
+        assert_eq!(
+            "data source Factory is invalid: `endBlock` (99) is lower than `startBlock` (100)",
+            error_msg
+        );
+    })
+}
+
 #[test]
 fn undeclared_grafting_feature_causes_feature_validation_error() {
     const YAML: &str = "
@@ -12,7 +12,12 @@ use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    let module_name = env::args().nth(1).unwrap();
+    let module_names: Vec<String> = env::args()
+        .nth(1)
+        .unwrap()
+        .split(',')
+        .map(str::to_string)
+        .collect();
 
     let token_env = env_var("SUBSTREAMS_API_TOKEN", "".to_string());
     let mut token: Option<String> = None;
@@ -54,9 +59,9 @@ async fn main() -> Result<(), Error> {
             firehose.clone(),
             None,
             None,
-            Arc::new(Mapper {}),
+            Arc::new(Mapper::new(module_names.clone(), None)),
             package.modules.clone(),
-            module_name.to_string(),
+            module_names,
             vec![12369621],
             vec![],
             logger.clone(),
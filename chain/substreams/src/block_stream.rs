@@ -7,6 +7,7 @@ use graph::{
             BlockStream, BlockStreamBuilder as BlockStreamBuilderTrait, FirehoseCursor,
         },
         substreams_block_stream::SubstreamsBlockStream,
+        Blockchain,
     },
     components::store::DeploymentLocator,
     data::subgraph::UnifiedMappingApiVersion,
@@ -35,13 +36,16 @@ impl BlockStreamBuilderTrait<Chain> for BlockStreamBuilder {
         deployment: DeploymentLocator,
         block_cursor: FirehoseCursor,
         _start_blocks: Vec<BlockNumber>,
-        _subgraph_current_block: Option<BlockPtr>,
+        subgraph_current_block: Option<BlockPtr>,
         filter: Arc<TriggerFilter>,
         _unified_api_version: UnifiedMappingApiVersion,
     ) -> Result<Box<dyn BlockStream<Chain>>> {
         let firehose_endpoint = chain.endpoints.random()?;
 
-        let mapper = Arc::new(Mapper {});
+        let mapper = Arc::new(
+            Mapper::new(filter.module_names.clone(), subgraph_current_block)
+                .with_chain_store(chain.chain_store()),
+        );
 
         let logger = chain
             .logger_factory
@@ -55,9 +59,11 @@ impl BlockStreamBuilderTrait<Chain> for BlockStreamBuilder {
             block_cursor.as_ref().clone(),
             mapper,
             filter.modules.clone(),
-            filter.module_name.clone(),
+            filter.module_names.clone(),
             filter.start_block.map(|x| vec![x]).unwrap_or(vec![]),
-            vec![],
+            // The substreams stop block is exclusive while the end block of a
+            // data source is the last block that should be processed.
+            filter.end_block.map(|x| vec![x + 1]).unwrap_or(vec![]),
             logger,
             chain.metrics_registry.clone(),
         )))
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 
 use anyhow::{anyhow, Error};
 use graph::{
@@ -7,6 +7,7 @@ use graph::{
     components::link_resolver::LinkResolver,
     prelude::{async_trait, BlockNumber, DataSourceTemplateInfo, Link},
     slog::Logger,
+    substreams::module,
 };
 
 use prost::Message;
@@ -21,6 +22,10 @@ const TEMPLATE_ERROR: &str = "Substreams do not support templates";
 
 const ALLOWED_MAPPING_KIND: [&'static str; 1] = ["substreams/graph-entities"];
 
+/// The value type that store modules used as outputs must have; their
+/// values are decoded as `EntityChange` messages.
+const STORE_VALUE_TYPE: &str = "proto:substreams.entity.v1.EntityChange";
+
 #[derive(Clone, Debug, PartialEq)]
 /// Represents the DataSource portion of the manifest once it has been parsed
 /// and the substream spkg has been downloaded + parsed.
@@ -51,6 +56,10 @@ impl blockchain::DataSource<Chain> for DataSource {
         self.initial_block.unwrap_or(0)
     }
 
+    fn end_block(&self) -> Option<BlockNumber> {
+        self.source.end_block
+    }
+
     fn name(&self) -> &str {
         &self.name
     }
@@ -122,6 +131,8 @@ impl blockchain::DataSource<Chain> for DataSource {
             ))
         }
 
+        errs.extend(self.source.validate());
+
         errs
     }
 
@@ -134,10 +145,42 @@ impl blockchain::DataSource<Chain> for DataSource {
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
-/// Module name comes from the manifest, package is the parsed spkg file.
+/// Module names come from the manifest, package is the parsed spkg file.
 pub struct Source {
-    pub module_name: String,
+    /// The output modules, their entity changes are merged in this order.
+    pub module_names: Vec<String>,
     pub package: graph::substreams::Package,
+    pub end_block: Option<BlockNumber>,
+}
+
+impl Source {
+    fn validate(&self) -> Vec<Error> {
+        let mut errs = vec![];
+
+        if self.module_names.is_empty() {
+            errs.push(anyhow!("at least one output module must be specified"));
+        }
+
+        let mut seen = HashSet::new();
+        for name in self.module_names.iter() {
+            if !seen.insert(name) {
+                errs.push(anyhow!("output module `{}` is listed more than once", name));
+            }
+        }
+
+        if let Some(modules) = &self.package.modules {
+            for name in self.module_names.iter() {
+                if !modules.modules.iter().any(|module| &module.name == name) {
+                    errs.push(anyhow!(
+                        "output module `{}` does not exist in the substreams package",
+                        name
+                    ));
+                }
+            }
+        }
+
+        errs
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -175,6 +218,8 @@ impl blockchain::UnresolvedDataSource<Chain> for UnresolvedDataSource {
         let content = resolver.cat(logger, &self.source.package.file).await?;
 
         let package = graph::substreams::Package::decode(content.as_ref())?;
+        let module_names = self.source.package.module_names();
+        check_store_value_types(&package, &module_names)?;
 
         let initial_block: Option<u64> = match package.modules {
             Some(ref modules) => modules.modules.iter().map(|x| x.initial_block).min(),
@@ -190,8 +235,9 @@ impl blockchain::UnresolvedDataSource<Chain> for UnresolvedDataSource {
             network: self.network,
             name: self.name,
             source: Source {
-                module_name: self.source.package.module_name,
+                module_names,
                 package,
+                end_block: self.source.end_block,
             },
             mapping: Mapping {
                 api_version: semver::Version::parse(&self.mapping.api_version)?,
@@ -203,21 +249,70 @@ impl blockchain::UnresolvedDataSource<Chain> for UnresolvedDataSource {
     }
 }
 
+/// Check that all store modules among the output modules `module_names`
+/// hold `EntityChange` values, since that is how the mapper decodes their
+/// deltas.
+fn check_store_value_types(
+    package: &graph::substreams::Package,
+    module_names: &[String],
+) -> Result<(), Error> {
+    let modules = match &package.modules {
+        Some(modules) => &modules.modules,
+        None => return Ok(()),
+    };
+
+    for module in modules
+        .iter()
+        .filter(|module| module_names.contains(&module.name))
+    {
+        if let Some(module::Kind::KindStore(store)) = &module.kind {
+            if store.value_type != STORE_VALUE_TYPE {
+                return Err(anyhow!(
+                    "output module `{}` is a store with value type `{}`, but stores used as outputs must have the value type `{}`",
+                    module.name,
+                    store.value_type,
+                    STORE_VALUE_TYPE
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Clone, Debug, Default, Hash, Eq, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 /// Source is a part of the manifest and this is needed for parsing.
 pub struct UnresolvedSource {
     package: UnresolvedPackage,
+    #[serde(default)]
+    end_block: Option<BlockNumber>,
 }
 
 #[derive(Clone, Debug, Default, Hash, Eq, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
-/// The unresolved Package section of the manifest.
+/// The unresolved Package section of the manifest. Either `moduleName` or
+/// `moduleNames` (or both) have to be set.
 pub struct UnresolvedPackage {
+    #[serde(default)]
     pub module_name: String,
+    #[serde(default)]
+    pub module_names: Vec<String>,
     pub file: Link,
 }
 
+impl UnresolvedPackage {
+    /// All output modules, `moduleName` first, followed by `moduleNames`.
+    fn module_names(&self) -> Vec<String> {
+        let mut names = Vec::with_capacity(self.module_names.len() + 1);
+        if !self.module_name.is_empty() {
+            names.push(self.module_name.clone());
+        }
+        names.extend(self.module_names.iter().cloned());
+        names
+    }
+}
+
 #[derive(Debug, Clone, Default, Deserialize)]
 /// This is necessary for the Blockchain trait associated types, substreams do not support
 /// data source templates so this is a noop and is not expected to be called.
@@ -263,8 +358,10 @@ mod test {
         components::link_resolver::LinkResolver,
         prelude::{async_trait, serde_yaml, JsonValueStream, Link},
         slog::{o, Discard, Logger},
+        substreams::{module, Module, Modules},
     };
 
+    use super::check_store_value_types;
     use crate::{DataSource, Mapping, UnresolvedDataSource, UnresolvedMapping, SUBSTREAMS_KIND};
 
     const EMPTY_PACKAGE: graph::substreams::Package = graph::substreams::Package {
@@ -285,10 +382,12 @@ mod test {
             source: crate::UnresolvedSource {
                 package: crate::UnresolvedPackage {
                     module_name: "output".into(),
+                    module_names: vec![],
                     file: Link {
                         link: "/ipfs/QmbHnhUFZa6qqqRyubUYhXntox1TCBxqryaBM1iNGqVJzT".into(),
                     },
                 },
+                end_block: None,
             },
             mapping: UnresolvedMapping {
                 api_version: "0.0.7".into(),
@@ -309,8 +408,9 @@ mod test {
             network: Some("mainnet".into()),
             name: "Uniswap".into(),
             source: crate::Source {
-                module_name: "output".into(),
+                module_names: vec!["output".into()],
                 package: EMPTY_PACKAGE,
+                end_block: None,
             },
             mapping: Mapping {
                 api_version: semver::Version::from_str("0.0.7").unwrap(),
@@ -344,14 +444,89 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn data_source_with_multiple_modules() {
+        let ds: UnresolvedDataSource = serde_yaml::from_str(MULTIPLE_MODULES_DATA_SOURCE).unwrap();
+        let link_resolver: Arc<dyn LinkResolver> = Arc::new(NoopLinkResolver {});
+        let logger = Logger::root(Discard, o!());
+        let ds: DataSource = ds.resolve(&link_resolver, &logger, 0).await.unwrap();
+        assert_eq!(
+            vec!["pools", "swaps", "store_tokens"],
+            ds.source.module_names
+        );
+        assert_eq!(Some(15_000_000), ds.source.end_block);
+    }
+
+    #[test]
+    fn data_source_module_validation() {
+        let mut ds = gen_data_source();
+
+        ds.source.module_names = vec![];
+        let errs: Vec<String> = ds.validate().into_iter().map(|e| e.to_string()).collect();
+        assert_eq!(errs, vec!["at least one output module must be specified"]);
+
+        ds.source.module_names = vec!["pools".into(), "swaps".into(), "pools".into()];
+        ds.source.package.modules = Some(Modules {
+            modules: vec![Module {
+                name: "pools".into(),
+                ..Default::default()
+            }],
+            binaries: vec![],
+        });
+        let errs: Vec<String> = ds.validate().into_iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errs,
+            vec![
+                "output module `pools` is listed more than once",
+                "output module `swaps` does not exist in the substreams package"
+            ]
+        );
+    }
+
+    #[test]
+    fn store_output_value_type() {
+        let store = |name: &str, value_type: &str| Module {
+            name: name.into(),
+            kind: Some(module::Kind::KindStore(module::KindStore {
+                value_type: value_type.into(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        let mut package = EMPTY_PACKAGE;
+        package.modules = Some(Modules {
+            modules: vec![
+                store("store_tokens", "proto:substreams.entity.v1.EntityChange"),
+                store("store_prices", "bigdecimal"),
+            ],
+            binaries: vec![],
+        });
+
+        let names = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(check_store_value_types(&package, &names(&["store_tokens"])).is_ok());
+        // Stores that are only used as inputs can have any value type
+        assert!(check_store_value_types(&package, &names(&["pools"])).is_ok());
+        let err = check_store_value_types(&package, &names(&["store_tokens", "store_prices"]))
+            .unwrap_err();
+        assert!(err.to_string().contains("`store_prices`"));
+    }
+
     fn gen_data_source() -> DataSource {
         DataSource {
             kind: SUBSTREAMS_KIND.into(),
             network: Some("mainnet".into()),
             name: "Uniswap".into(),
             source: crate::Source {
-                module_name: "".to_string(),
+                module_names: vec!["output".to_string()],
                 package: EMPTY_PACKAGE,
+                end_block: None,
             },
             mapping: Mapping {
                 api_version: semver::Version::from_str("0.0.7").unwrap(),
@@ -377,6 +552,24 @@ mod test {
           apiVersion: 0.0.7
     "#;
 
+    const MULTIPLE_MODULES_DATA_SOURCE: &str = r#"
+        kind: substreams
+        name: Uniswap
+        network: mainnet
+        source:
+          package:
+            moduleName: pools
+            moduleNames:
+              - swaps
+              - store_tokens
+            file:
+              /: /ipfs/QmbHnhUFZa6qqqRyubUYhXntox1TCBxqryaBM1iNGqVJzT
+          endBlock: 15000000
+        mapping:
+          kind: substreams/graph-entities
+          apiVersion: 0.0.7
+    "#;
+
     #[derive(Debug)]
     struct NoopLinkResolver {}
 
@@ -1,16 +1,94 @@
+use crate::codec::entity_change::Operation;
+use crate::codec::EntityChange;
 use crate::{Block, Chain, EntityChanges, TriggerData};
-use graph::blockchain::block_stream::SubstreamsError::{
-    MultipleModuleOutputError, UnexpectedStoreDeltaOutput,
-};
 use graph::blockchain::block_stream::{
     BlockStreamEvent, BlockWithTriggers, FirehoseCursor, SubstreamsError, SubstreamsMapper,
 };
-use graph::prelude::{async_trait, BlockHash, BlockNumber, BlockPtr, Logger};
+use graph::env::ENV_VARS;
+use graph::prelude::{async_trait, BlockHash, BlockNumber, BlockPtr, ChainStore, Logger};
 use graph::substreams::module_output::Data;
-use graph::substreams::{BlockScopedData, Clock, ForkStep};
+use graph::substreams::{store_delta, BlockScopedData, Clock, ForkStep, StoreDelta};
 use prost::Message;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+pub struct Mapper {
+    /// The output modules of the data source, in manifest order.
+    pub module_names: Vec<String>,
+    /// The hashes of the most recent blocks that were sent to the subgraph,
+    /// keyed by block number. An undo step only carries the clock of the
+    /// block that is undone, and we use this to find the hash of its parent.
+    recent_blocks: Mutex<BTreeMap<BlockNumber, BlockHash>>,
+    /// Used to look up the parent of an undone block that is not in
+    /// `recent_blocks`, which happens when the stream is restarted right
+    /// before the subgraph head is undone.
+    chain_store: Option<Arc<dyn ChainStore>>,
+}
+
+impl Mapper {
+    pub fn new(module_names: Vec<String>, subgraph_current_block: Option<BlockPtr>) -> Self {
+        let recent_blocks = subgraph_current_block
+            .into_iter()
+            .map(|ptr| (ptr.number, ptr.hash))
+            .collect();
+
+        Self {
+            module_names,
+            recent_blocks: Mutex::new(recent_blocks),
+            chain_store: None,
+        }
+    }
+
+    pub fn with_chain_store(self, chain_store: Arc<dyn ChainStore>) -> Self {
+        Self {
+            chain_store: Some(chain_store),
+            ..self
+        }
+    }
+
+    /// Remember the block `number` with `hash`. Blocks that are further
+    /// back than the reorg threshold can not be undone anymore and are
+    /// forgotten.
+    fn remember(&self, number: BlockNumber, hash: BlockHash) {
+        let mut recent_blocks = self.recent_blocks.lock().unwrap();
+        recent_blocks.insert(number, hash);
+
+        let keep = ENV_VARS.reorg_threshold.max(1) as usize + 1;
+        while recent_blocks.len() > keep {
+            let oldest = *recent_blocks.keys().next().unwrap();
+            recent_blocks.remove(&oldest);
+        }
+    }
+
+    /// Forget the block `number` and everything after it and return the
+    /// pointer to its parent, if we know it.
+    fn undo(&self, number: BlockNumber) -> Option<BlockPtr> {
+        let mut recent_blocks = self.recent_blocks.lock().unwrap();
+        recent_blocks.retain(|recent, _| *recent < number);
 
-pub struct Mapper {}
+        let parent = number.checked_sub(1)?;
+        recent_blocks
+            .get(&parent)
+            .map(|hash| BlockPtr::new(hash.clone(), parent))
+    }
+
+    /// Look up the parent of block `number` in the chain store. This only
+    /// succeeds if the chain store knows exactly one block at that height.
+    fn parent_from_chain_store(
+        &self,
+        number: BlockNumber,
+    ) -> Result<Option<BlockPtr>, SubstreamsError> {
+        let (chain_store, parent) = match (&self.chain_store, number.checked_sub(1)) {
+            (Some(chain_store), Some(parent)) => (chain_store, parent),
+            _ => return Ok(None),
+        };
+        let mut hashes = chain_store.block_hashes_by_block_number(parent)?;
+        if hashes.len() != 1 {
+            return Ok(None);
+        }
+        Ok(hashes.pop().map(|hash| BlockPtr::new(hash, parent)))
+    }
+}
 
 #[async_trait]
 impl SubstreamsMapper<Chain> for Mapper {
@@ -23,7 +101,7 @@ impl SubstreamsMapper<Chain> for Mapper {
             outputs,
             clock,
             step,
-            cursor: _,
+            cursor,
         } = block_scoped_data;
 
         let step = ForkStep::from_i32(*step).unwrap_or_else(|| {
@@ -33,18 +111,6 @@ impl SubstreamsMapper<Chain> for Mapper {
             )
         });
 
-        if outputs.len() == 0 {
-            return Ok(None);
-        }
-
-        if outputs.len() > 1 {
-            return Err(MultipleModuleOutputError);
-        }
-
-        //todo: handle step
-        let module_output = &block_scoped_data.outputs[0];
-        let cursor = &block_scoped_data.cursor;
-
         let clock = match clock {
             Some(clock) => clock,
             None => return Err(SubstreamsError::MissingClockError),
@@ -59,46 +125,446 @@ impl SubstreamsMapper<Chain> for Mapper {
         let hash: BlockHash = hash.as_str().try_into()?;
         let number: BlockNumber = *number as BlockNumber;
 
-        match module_output.data.as_ref() {
-            Some(Data::MapOutput(msg)) => {
-                let changes: EntityChanges = Message::decode(msg.value.as_slice())
-                    .map_err(SubstreamsError::DecodingError)?;
-
-                use ForkStep::*;
-                match step {
-                    StepIrreversible | StepNew => Ok(Some(BlockStreamEvent::ProcessBlock(
-                        // Even though the trigger processor for substreams doesn't care about TriggerData
-                        // there are a bunch of places in the runner that check if trigger data
-                        // empty and skip processing if so. This will prolly breakdown
-                        // close to head so we will need to improve things.
-
-                        // TODO(filipe): Fix once either trigger data can be empty
-                        // or we move the changes into trigger data.
-                        BlockWithTriggers::new(
-                            Block {
-                                hash,
-                                number,
-                                changes,
-                            },
-                            vec![TriggerData {}],
-                        ),
-                        FirehoseCursor::from(cursor.clone()),
-                    ))),
-                    StepUndo => {
-                        let parent_ptr = BlockPtr { hash, number };
-
-                        Ok(Some(BlockStreamEvent::Revert(
-                            parent_ptr,
-                            FirehoseCursor::from(cursor.clone()),
-                        )))
+        use ForkStep::*;
+        match step {
+            StepIrreversible | StepNew => self.remember(number, hash.clone()),
+            StepUndo => {
+                // The clock of an undo step points to the block that is being undone, which
+                // is the current subgraph head, so we need to revert to its parent. The undo
+                // step does not carry the parent hash, but we have seen the parent before
+                // unless the stream was restarted right at the block that is undone. In that
+                // case, we ask the chain store for the parent.
+                let parent_ptr = match self.undo(number) {
+                    Some(parent_ptr) => parent_ptr,
+                    None => self.parent_from_chain_store(number)?.ok_or_else(|| {
+                        SubstreamsError::UnknownParentError(BlockPtr::new(hash, number))
+                    })?,
+                };
+
+                return Ok(Some(BlockStreamEvent::Revert(
+                    parent_ptr,
+                    FirehoseCursor::from(cursor.clone()),
+                )));
+            }
+            StepUnknown => {
+                panic!("unknown step should not happen in the Firehose response")
+            }
+        }
+
+        if outputs.len() == 0 {
+            return Ok(None);
+        }
+
+        // Outputs are merged in the order in which the modules are listed in
+        // the data source so that the resulting entity changes are deterministic
+        // regardless of the order in which the server sends them.
+        let mut ordered_outputs = Vec::with_capacity(outputs.len());
+        for output in outputs {
+            let idx = self
+                .module_names
+                .iter()
+                .position(|name| name == &output.name)
+                .ok_or(SubstreamsError::ModuleOutputNotPresentOrUnexpected)?;
+            ordered_outputs.push((idx, output));
+        }
+        ordered_outputs.sort_by_key(|(idx, _)| *idx);
+
+        let mut changes = EntityChanges::default();
+        for (_, module_output) in ordered_outputs {
+            match module_output.data.as_ref() {
+                Some(Data::MapOutput(msg)) => {
+                    let module_changes: EntityChanges = Message::decode(msg.value.as_slice())
+                        .map_err(SubstreamsError::DecodingError)?;
+                    changes.entity_changes.extend(module_changes.entity_changes);
+                }
+                Some(Data::StoreDeltas(deltas)) => {
+                    for delta in deltas.deltas.iter() {
+                        changes
+                            .entity_changes
+                            .push(entity_change_from_delta(delta)?);
                     }
-                    StepUnknown => {
-                        panic!("unknown step should not happen in the Firehose response")
+                }
+                None => return Err(SubstreamsError::ModuleOutputNotPresentOrUnexpected),
+            }
+        }
+
+        Ok(Some(BlockStreamEvent::ProcessBlock(
+            // Even though the trigger processor for substreams doesn't care about TriggerData
+            // there are a bunch of places in the runner that check if trigger data
+            // empty and skip processing if so. This will prolly breakdown
+            // close to head so we will need to improve things.
+
+            // TODO(filipe): Fix once either trigger data can be empty
+            // or we move the changes into trigger data.
+            BlockWithTriggers::new(
+                Block {
+                    hash,
+                    number,
+                    changes,
+                },
+                vec![TriggerData {}],
+            ),
+            FirehoseCursor::from(cursor.clone()),
+        )))
+    }
+}
+
+/// Store modules used as outputs of a data source must store encoded `EntityChange`
+/// values. The operation of the delta takes precedence over the one in the value, and
+/// for deletions the value is taken from the old value since there is no new one.
+fn entity_change_from_delta(delta: &StoreDelta) -> Result<EntityChange, SubstreamsError> {
+    let operation = match delta.operation() {
+        store_delta::Operation::Unset => Operation::Unset,
+        store_delta::Operation::Create => Operation::Create,
+        store_delta::Operation::Update => Operation::Update,
+        store_delta::Operation::Delete => Operation::Delete,
+    };
+
+    let value = match operation {
+        Operation::Delete => &delta.old_value,
+        _ => &delta.new_value,
+    };
+
+    let mut change: EntityChange =
+        Message::decode(value.as_slice()).map_err(SubstreamsError::DecodingError)?;
+    change.set_operation(operation);
+
+    Ok(change)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use std::sync::Arc;
+
+    use graph::blockchain::block_stream::{BlockStreamEvent, SubstreamsMapper};
+    use graph::components::store::{BlockStore as _, EntityKey, WritableStore};
+    use graph::prelude::{
+        BlockHash, BlockPtr, DeploymentHash, Entity, EntityCache, EntityOperation,
+        StopwatchMetrics, SubgraphStore as _,
+    };
+    use graph::substreams::module_output::Data;
+    use graph::substreams::{
+        store_delta, BlockScopedData, Clock, ForkStep, ModuleOutput, StoreDelta, StoreDeltas,
+    };
+    use prost::Message;
+
+    use crate::codec::entity_change::Operation;
+    use crate::codec::{EntityChange, EntityChanges};
+    use crate::Chain;
+
+    use super::Mapper;
+
+    const SCHEMA: &str = "type Thing @entity { id: ID! }";
+
+    fn hash(number: u64, fork: u8) -> String {
+        let mut bytes = [0u8; 32];
+        bytes[0] = fork;
+        bytes[24..].copy_from_slice(&number.to_be_bytes());
+        hex::encode(bytes)
+    }
+
+    fn entity_change(id: &str) -> EntityChange {
+        EntityChange {
+            entity: "Thing".to_string(),
+            id: id.to_string(),
+            ordinal: 0,
+            operation: Operation::Create as i32,
+            fields: vec![],
+        }
+    }
+
+    fn map_output(name: &str, ids: &[&str]) -> ModuleOutput {
+        let changes = EntityChanges {
+            entity_changes: ids.iter().map(|id| entity_change(id)).collect(),
+        };
+
+        ModuleOutput {
+            name: name.to_string(),
+            logs: vec![],
+            logs_truncated: false,
+            data: Some(Data::MapOutput(prost_types::Any {
+                type_url: "type.googleapis.com/substreams.entity.v1.EntityChanges".to_string(),
+                value: changes.encode_to_vec(),
+            })),
+        }
+    }
+
+    fn block_scoped_data(step: ForkStep, number: u64, fork: u8, ids: &[&str]) -> BlockScopedData {
+        BlockScopedData {
+            outputs: vec![map_output("graph_out", ids)],
+            clock: Some(Clock {
+                id: hash(number, fork),
+                number,
+                timestamp: None,
+            }),
+            step: step as i32,
+            cursor: format!("cursor-{}-{}-{}", step as i32, number, fork),
+        }
+    }
+
+    fn block_ptr(number: u64, fork: u8) -> BlockPtr {
+        BlockPtr::new(
+            BlockHash::try_from(hash(number, fork).as_str()).unwrap(),
+            number as i32,
+        )
+    }
+
+    fn mapper(subgraph_current_block: Option<BlockPtr>) -> Mapper {
+        Mapper::new(vec!["graph_out".to_string()], subgraph_current_block)
+    }
+
+    /// Write the changes of `event` to `store` the same way the subgraph
+    /// runner does
+    async fn apply(
+        store: &Arc<dyn WritableStore>,
+        stopwatch: &StopwatchMetrics,
+        event: BlockStreamEvent<Chain>,
+    ) {
+        match event {
+            BlockStreamEvent::ProcessBlock(block, cursor) => {
+                let mut cache = EntityCache::new(Arc::new(store.clone()));
+                cache.append(
+                    block
+                        .block
+                        .changes
+                        .entity_changes
+                        .iter()
+                        .map(|change| EntityOperation::Set {
+                            key: EntityKey::data(change.entity.clone(), change.id.clone()),
+                            data: Entity::from(vec![("id", change.id.clone().into())]),
+                        })
+                        .collect(),
+                );
+                let mods = cache.as_modifications().unwrap().modifications;
+                store
+                    .transact_block_operations(
+                        block.ptr(),
+                        cursor,
+                        mods,
+                        stopwatch,
+                        vec![],
+                        vec![],
+                        vec![],
+                        vec![],
+                    )
+                    .await
+                    .unwrap();
+            }
+            BlockStreamEvent::Revert(ptr, cursor) => {
+                let head = store.block_ptr().unwrap();
+                assert!(
+                    ptr.number < head.number,
+                    "revert must move the head backwards, head: {}, revert to: {}",
+                    head,
+                    ptr
+                );
+                store.revert_block_operations(ptr, cursor).await.unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn undo_step_reverts_to_parent() {
+        test_store::run_test_sequentially(|store| async move {
+            let id = DeploymentHash::new("substreamsUndo").unwrap();
+            test_store::remove_subgraph(&id);
+            let deployment = test_store::create_test_subgraph(&id, SCHEMA).await;
+            let writable = store
+                .subgraph_store()
+                .writable(test_store::LOGGER.clone(), deployment.id)
+                .await
+                .unwrap();
+            let stopwatch = StopwatchMetrics::new(
+                graph::log::discard(),
+                deployment.hash.clone(),
+                "transact",
+                test_store::METRICS_REGISTRY.clone(),
+            );
+
+            let mapper = mapper(None);
+            let logger = graph::log::discard();
+
+            let undo = block_scoped_data(ForkStep::StepUndo, 2, 0, &["b"]);
+            let new_fork = block_scoped_data(ForkStep::StepNew, 2, 1, &["c"]);
+            let messages = vec![
+                block_scoped_data(ForkStep::StepNew, 1, 0, &["a"]),
+                block_scoped_data(ForkStep::StepNew, 2, 0, &["b"]),
+                undo.clone(),
+                new_fork.clone(),
+            ];
+
+            for message in messages.iter() {
+                let event = mapper
+                    .to_block_stream_event(&logger, message)
+                    .await
+                    .unwrap()
+                    .expect("every message should produce an event");
+
+                if message == &undo {
+                    match &event {
+                        BlockStreamEvent::Revert(ptr, _) => assert_eq!(&block_ptr(1, 0), ptr),
+                        BlockStreamEvent::ProcessBlock(_, _) => panic!("undo step must revert"),
                     }
                 }
+
+                apply(&writable, &stopwatch, event).await;
             }
-            Some(Data::StoreDeltas(_)) => Err(UnexpectedStoreDeltaOutput),
-            _ => Err(SubstreamsError::ModuleOutputNotPresentOrUnexpected),
+            writable.flush().await.unwrap();
+
+            let thing = |id: &str| {
+                writable
+                    .get(&EntityKey::data("Thing".to_string(), id.to_string()))
+                    .unwrap()
+            };
+            assert!(thing("a").is_some());
+            assert!(thing("b").is_none());
+            assert!(thing("c").is_some());
+
+            assert_eq!(Some(block_ptr(2, 1)), writable.block_ptr());
+            assert_eq!(new_fork.cursor, writable.block_cursor().to_string());
+        })
+    }
+
+    #[tokio::test]
+    async fn undo_step_without_outputs() {
+        let head = block_ptr(4, 0);
+        let mapper = mapper(Some(head.clone()));
+        let logger = graph::log::discard();
+
+        let new = block_scoped_data(ForkStep::StepNew, 5, 0, &["a"]);
+        mapper.to_block_stream_event(&logger, &new).await.unwrap();
+
+        let mut undo = block_scoped_data(ForkStep::StepUndo, 5, 0, &[]);
+        undo.outputs.clear();
+
+        let event = mapper.to_block_stream_event(&logger, &undo).await.unwrap();
+
+        match event {
+            Some(BlockStreamEvent::Revert(ptr, cursor)) => {
+                assert_eq!(head, ptr);
+                assert_eq!(undo.cursor, cursor.to_string());
+            }
+            _ => panic!("undo step must revert even without module outputs"),
         }
     }
+
+    #[tokio::test]
+    async fn undo_step_with_unknown_parent() {
+        // Right after a restart, we only know the subgraph head
+        let mapper = mapper(Some(block_ptr(4, 0)));
+        let logger = graph::log::discard();
+
+        let undo = block_scoped_data(ForkStep::StepUndo, 4, 0, &[]);
+        assert!(mapper.to_block_stream_event(&logger, &undo).await.is_err());
+    }
+
+    #[test]
+    fn undo_step_after_restart_at_head() {
+        test_store::run_test_sequentially(|store| async move {
+            use test_store::block_store::{self, BLOCK_ONE, BLOCK_TWO, GENESIS_BLOCK};
+
+            block_store::set_chain(
+                vec![&*GENESIS_BLOCK, &*BLOCK_ONE, &*BLOCK_TWO],
+                test_store::NETWORK_NAME,
+            );
+            let chain_store = store
+                .block_store()
+                .chain_store(test_store::NETWORK_NAME)
+                .unwrap();
+
+            // Right after a restart, we only know the subgraph head, and the
+            // first message undoes it
+            let head = BLOCK_TWO.block_ptr();
+            let mapper = mapper(Some(head.clone())).with_chain_store(chain_store);
+            let logger = graph::log::discard();
+
+            let mut undo = block_scoped_data(ForkStep::StepUndo, 2, 0, &[]);
+            undo.clock.as_mut().unwrap().id = BLOCK_TWO.hash.clone();
+
+            match mapper.to_block_stream_event(&logger, &undo).await.unwrap() {
+                Some(BlockStreamEvent::Revert(ptr, _)) => assert_eq!(BLOCK_ONE.block_ptr(), ptr),
+                _ => panic!("undo step must revert to the parent of the head"),
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn merges_outputs_in_module_order() {
+        let mapper = Mapper::new(
+            vec![
+                "pools".to_string(),
+                "swaps".to_string(),
+                "store_tokens".to_string(),
+            ],
+            None,
+        );
+        let logger = graph::log::discard();
+
+        let delta = |operation: store_delta::Operation, id: &str| {
+            let value = entity_change(id).encode_to_vec();
+            let (old_value, new_value) = match operation {
+                store_delta::Operation::Delete => (value, vec![]),
+                _ => (vec![], value),
+            };
+            StoreDelta {
+                operation: operation as i32,
+                ordinal: 0,
+                key: id.to_string(),
+                old_value,
+                new_value,
+            }
+        };
+
+        let mut data = block_scoped_data(ForkStep::StepNew, 1, 0, &[]);
+        // Outputs arrive in an order that differs from the manifest.
+        data.outputs = vec![
+            ModuleOutput {
+                name: "store_tokens".to_string(),
+                logs: vec![],
+                logs_truncated: false,
+                data: Some(Data::StoreDeltas(StoreDeltas {
+                    deltas: vec![
+                        delta(store_delta::Operation::Update, "token1"),
+                        delta(store_delta::Operation::Delete, "token2"),
+                    ],
+                })),
+            },
+            map_output("swaps", &["swap1", "swap2"]),
+            map_output("pools", &["pool1"]),
+        ];
+
+        let event = mapper
+            .to_block_stream_event(&logger, &data)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let block = match event {
+            BlockStreamEvent::ProcessBlock(block, _) => block.block,
+            BlockStreamEvent::Revert(_, _) => panic!("expected a block to process"),
+        };
+
+        let changes: Vec<_> = block
+            .changes
+            .entity_changes
+            .iter()
+            .map(|change| (change.id.as_str(), change.operation()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("pool1", Operation::Create),
+                ("swap1", Operation::Create),
+                ("swap2", Operation::Create),
+                ("token1", Operation::Update),
+                ("token2", Operation::Delete),
+            ],
+            changes
+        );
+
+        // Outputs of modules that are not part of the data source are rejected
+        data.outputs.push(map_output("unknown", &["x"]));
+        assert!(mapper.to_block_stream_event(&logger, &data).await.is_err());
+    }
 }
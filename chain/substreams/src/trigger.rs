@@ -48,8 +48,9 @@ impl ToAscPtr for TriggerData {
 #[derive(Debug, Clone, Default)]
 pub struct TriggerFilter {
     pub(crate) modules: Option<Modules>,
-    pub(crate) module_name: String,
+    pub(crate) module_names: Vec<String>,
     pub(crate) start_block: Option<BlockNumber>,
+    pub(crate) end_block: Option<BlockNumber>,
     pub(crate) data_sources_len: u8,
 }
 
@@ -67,8 +68,9 @@ impl blockchain::TriggerFilter<Chain> for TriggerFilter {
     ) {
         let Self {
             modules,
-            module_name,
+            module_names,
             start_block,
+            end_block,
             data_sources_len,
         } = self;
 
@@ -79,8 +81,9 @@ impl blockchain::TriggerFilter<Chain> for TriggerFilter {
         if let Some(ref ds) = data_sources.next() {
             *data_sources_len = 1;
             *modules = ds.source.package.modules.clone();
-            *module_name = ds.source.module_name.clone();
+            *module_names = ds.source.module_names.clone();
             *start_block = ds.initial_block;
+            *end_block = ds.source.end_block;
         }
     }
 
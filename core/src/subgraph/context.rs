@@ -14,8 +14,8 @@ use graph::{
     data_source::{offchain, DataSource, TriggerData},
     ipfs_client::CidFile,
     prelude::{
-        BlockNumber, BlockState, CancelGuard, DeploymentHash, MetricsRegistry, RuntimeHostBuilder,
-        SubgraphInstanceMetrics, TriggerProcessor,
+        BlockNumber, BlockState, CancelGuard, DeploymentHash, MetricsRegistry, RuntimeHost,
+        RuntimeHostBuilder, SubgraphInstanceMetrics, TriggerProcessor,
     },
     slog::Logger,
     tokio::sync::mpsc,
@@ -39,6 +39,8 @@ where
 {
     instance: SubgraphInstance<C, T>,
     pub instances: SharedInstanceKeepAliveMap,
+    /// The filter of the current block stream, rebuilt by the runner whenever the block stream
+    /// is (re)started.
     pub filter: C::TriggerFilter,
     pub offchain_monitor: OffchainMonitor,
     trigger_processor: Box<dyn TriggerProcessor<C, T>>,
@@ -48,14 +50,13 @@ impl<C: Blockchain, T: RuntimeHostBuilder<C>> IndexingContext<C, T> {
     pub fn new(
         instance: SubgraphInstance<C, T>,
         instances: SharedInstanceKeepAliveMap,
-        filter: C::TriggerFilter,
         offchain_monitor: OffchainMonitor,
         trigger_processor: Box<dyn TriggerProcessor<C, T>>,
     ) -> Self {
         Self {
             instance,
             instances,
-            filter,
+            filter: C::TriggerFilter::default(),
             offchain_monitor,
             trigger_processor,
         }
@@ -124,6 +125,31 @@ impl<C: Blockchain, T: RuntimeHostBuilder<C>> IndexingContext<C, T> {
         self.instance.revert_data_sources(reverted_block)
     }
 
+    /// The onchain data sources from the manifest.
+    pub fn static_data_sources(&self) -> impl Iterator<Item = &C::DataSource> + Clone {
+        self.instance
+            .static_data_sources()
+            .iter()
+            .filter_map(DataSource::as_onchain)
+    }
+
+    /// All onchain data sources, the ones from the manifest followed by the dynamic data
+    /// sources in the order in which they were created.
+    pub fn onchain_data_sources(&self) -> impl Iterator<Item = &C::DataSource> + Clone {
+        let dynamic_data_sources = self
+            .instance
+            .hosts()
+            .iter()
+            .filter(|host| host.creation_block_number().is_some())
+            .map(|host| host.data_source());
+
+        self.instance
+            .static_data_sources()
+            .iter()
+            .chain(dynamic_data_sources)
+            .filter_map(DataSource::as_onchain)
+    }
+
     pub fn add_dynamic_data_source(
         &mut self,
         logger: &Logger,
@@ -15,6 +15,9 @@ pub(crate) struct SubgraphInstance<C: Blockchain, T: RuntimeHostBuilder<C>> {
     templates: Arc<Vec<DataSourceTemplate<C>>>,
     host_metrics: Arc<HostMetrics>,
 
+    /// The data sources declared in the subgraph manifest.
+    static_data_sources: Arc<Vec<DataSource<C>>>,
+
     /// Runtime hosts, one for each data source mapping.
     ///
     /// The runtime hosts are created and added in the same order the
@@ -41,6 +44,14 @@ where
         let subgraph_id = manifest.id.clone();
         let network = manifest.network_name();
         let templates = Arc::new(manifest.templates);
+        let static_data_sources = Arc::new(
+            manifest
+                .data_sources
+                .iter()
+                .filter(|ds| ds.creation_block().is_none())
+                .cloned()
+                .collect(),
+        );
 
         let mut this = SubgraphInstance {
             host_builder,
@@ -50,6 +61,7 @@ where
             module_cache: HashMap::new(),
             templates,
             host_metrics,
+            static_data_sources,
         };
 
         // Create a new runtime host for each data source in the subgraph manifest;
@@ -166,4 +178,8 @@ where
     pub(super) fn hosts(&self) -> &[Arc<T::Host>] {
         &self.hosts
     }
+
+    pub(super) fn static_data_sources(&self) -> &[DataSource<C>] {
+        &self.static_data_sources
+    }
 }
@@ -15,6 +15,11 @@ pub struct IndexingInputs<C: Blockchain> {
     pub deployment: DeploymentLocator,
     pub features: BTreeSet<SubgraphFeature>,
     pub start_blocks: Vec<BlockNumber>,
+    /// The end blocks of all data sources, after which the trigger filter has to be rebuilt.
+    pub end_blocks: BTreeSet<BlockNumber>,
+    /// The block after which there is nothing left to index, see
+    /// `SubgraphManifest::max_end_block`.
+    pub max_end_block: Option<BlockNumber>,
     pub stop_block: Option<BlockNumber>,
     pub store: Arc<dyn WritableStore>,
     pub debug_fork: Option<Arc<dyn SubgraphFork>>,
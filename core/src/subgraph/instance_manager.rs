@@ -5,8 +5,8 @@ use crate::subgraph::loader::load_dynamic_data_sources;
 use crate::subgraph::runner::SubgraphRunner;
 use graph::blockchain::block_stream::BlockStreamMetrics;
 use graph::blockchain::Blockchain;
+use graph::blockchain::BlockchainKind;
 use graph::blockchain::NodeCapabilities;
-use graph::blockchain::{BlockchainKind, TriggerFilter};
 use graph::components::subgraph::ProofOfIndexingVersion;
 use graph::data::subgraph::{UnresolvedSubgraphManifest, SPEC_VERSION_0_0_6};
 use graph::prelude::{SubgraphInstanceManager as SubgraphInstanceManagerTrait, *};
@@ -227,13 +227,11 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
         // Dynamic data sources are loaded by appending them to the manifest.
         //
         // Refactor: Preferrably we'd avoid any mutation of the manifest.
-        let (manifest, static_data_sources) = {
+        let manifest = {
             let data_sources = load_dynamic_data_sources(store.clone(), logger.clone(), &manifest)
                 .await
                 .context("Failed to load dynamic data sources")?;
 
-            let static_data_sources = manifest.data_sources.clone();
-
             // Add dynamic data sources to the subgraph
             manifest.data_sources.extend(data_sources);
 
@@ -243,11 +241,14 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
                 manifest.data_sources.len()
             );
 
-            (manifest, static_data_sources)
+            manifest
         };
 
         let static_filters =
             self.static_filters || manifest.data_sources.len() >= ENV_VARS.static_filters_threshold;
+        if static_filters && !self.static_filters {
+            info!(logger, "forcing subgraph to use static filters.")
+        }
 
         let onchain_data_sources = manifest
             .data_sources
@@ -263,33 +264,9 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
             .with_context(|| format!("no chain configured for network {}", network))?
             .clone();
 
-        // if static_filters is enabled, build a minimal filter with the static data sources and
-        // add the necessary filters based on templates.
-        // if not enabled we just stick to the filter based on all the data sources.
-        // This specifically removes dynamic data sources based filters because these can be derived
-        // from templates AND this reduces the cost of egress traffic by making the payloads smaller.
-        let filter = if static_filters {
-            if !self.static_filters {
-                info!(logger, "forcing subgraph to use static filters.")
-            }
-
-            let onchain_data_sources = static_data_sources.iter().filter_map(|d| d.as_onchain());
-
-            let mut filter = C::TriggerFilter::from_data_sources(onchain_data_sources);
-
-            filter.extend_with_template(
-                manifest
-                    .templates
-                    .iter()
-                    .filter_map(|ds| ds.as_onchain())
-                    .cloned(),
-            );
-            filter
-        } else {
-            C::TriggerFilter::from_data_sources(onchain_data_sources.iter())
-        };
-
         let start_blocks = manifest.start_blocks();
+        let end_blocks = manifest.end_blocks();
+        let max_end_block = manifest.max_end_block();
 
         let templates = Arc::new(manifest.templates.clone());
 
@@ -371,6 +348,8 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
             deployment: deployment.clone(),
             features,
             start_blocks,
+            end_blocks,
+            max_end_block,
             stop_block,
             store,
             debug_fork,
@@ -385,13 +364,8 @@ impl<S: SubgraphStore> SubgraphInstanceManager<S> {
         };
 
         // The subgraph state tracks the state of the subgraph instance over time
-        let ctx = IndexingContext::new(
-            instance,
-            self.instances.cheap_clone(),
-            filter,
-            offchain_monitor,
-            tp,
-        );
+        let ctx =
+            IndexingContext::new(instance, self.instances.cheap_clone(), offchain_monitor, tp);
 
         let metrics = RunnerMetrics {
             subgraph: subgraph_metrics,
@@ -5,7 +5,7 @@ use crate::subgraph::state::IndexingState;
 use crate::subgraph::stream::new_block_stream;
 use atomic_refcell::AtomicRefCell;
 use graph::blockchain::block_stream::{BlockStreamEvent, BlockWithTriggers, FirehoseCursor};
-use graph::blockchain::{Block, Blockchain, TriggerFilter as _};
+use graph::blockchain::{Block, Blockchain, DataSource as _, TriggerFilter as _};
 use graph::components::store::{EmptyStore, EntityKey, StoredDynamicDataSource};
 use graph::components::{
     store::ModificationsAndCache,
@@ -56,6 +56,7 @@ where
                 should_try_unfail_non_deterministic: true,
                 synced: false,
                 skip_ptr_updates_timer: Instant::now(),
+                next_end_block: None,
                 backoff: ExponentialBackoff::new(
                     (MINUTE * 2).min(ENV_VARS.subgraph_error_retry_ceil),
                     ENV_VARS.subgraph_error_retry_ceil,
@@ -92,12 +93,22 @@ where
             }
         }
 
+        if self.has_reached_max_end_block(self.inputs.store.block_ptr()) {
+            self.mark_max_end_block_reached()?;
+            return Ok(());
+        }
+
         loop {
             debug!(self.logger, "Starting or restarting subgraph");
 
             let block_stream_canceler = CancelGuard::new();
             let block_stream_cancel_handle = block_stream_canceler.handle();
 
+            // The filter is rebuilt on every restart so that it covers data sources created
+            // since the last start and leaves out the ones that reached their end block.
+            self.ctx.filter = self.build_filter();
+            self.state.next_end_block = self.next_end_block();
+
             let mut block_stream =
                 new_block_stream(&self.inputs, &self.ctx.filter, &self.metrics.subgraph)
                     .await?
@@ -140,6 +151,76 @@ where
         }
     }
 
+    /// Builds the trigger filter for the block stream. Data sources that have reached their end
+    /// block at the current subgraph head can not match any later trigger and are left out.
+    fn build_filter(&self) -> C::TriggerFilter {
+        let current_block = self.inputs.store.block_ptr().map(|ptr| ptr.number);
+        let is_active = move |ds: &&C::DataSource| match (current_block, ds.end_block()) {
+            (Some(current_block), Some(end_block)) => current_block < end_block,
+            _ => true,
+        };
+
+        // If static filters are enabled, build a minimal filter with the static data sources and
+        // add the necessary filters based on templates. If not enabled we just stick to the filter
+        // based on all the data sources.
+        // This specifically removes dynamic data sources based filters because these can be derived
+        // from templates AND this reduces the cost of egress traffic by making the payloads smaller.
+        if self.inputs.static_filters {
+            let mut filter = C::TriggerFilter::from_data_sources(
+                self.ctx.static_data_sources().filter(is_active),
+            );
+
+            filter.extend_with_template(
+                self.inputs
+                    .templates
+                    .iter()
+                    .filter_map(|ds| ds.as_onchain())
+                    .cloned(),
+            );
+            filter
+        } else {
+            C::TriggerFilter::from_data_sources(self.ctx.onchain_data_sources().filter(is_active))
+        }
+    }
+
+    fn next_end_block(&self) -> Option<BlockNumber> {
+        let current_block = self.inputs.store.block_ptr().map(|ptr| ptr.number);
+        let first = current_block.map_or(0, |block| block + 1);
+        self.inputs.end_blocks.range(first..).next().cloned()
+    }
+
+    /// Whether `block_ptr` is at or past the end block of a data source in the current filter.
+    fn reaches_end_block(&self, block_ptr: &BlockPtr) -> bool {
+        self.state
+            .next_end_block
+            .map_or(false, |end_block| block_ptr.number >= end_block)
+    }
+
+    fn has_reached_max_end_block(&self, block_ptr: Option<BlockPtr>) -> bool {
+        match (self.inputs.max_end_block, block_ptr) {
+            (Some(max_end_block), Some(block_ptr)) => block_ptr.number >= max_end_block,
+            _ => false,
+        }
+    }
+
+    /// Once every data source has passed its end block there is nothing left to index, so the
+    /// subgraph is considered synced.
+    fn mark_max_end_block_reached(&mut self) -> Result<(), Error> {
+        info!(
+            self.logger,
+            "All data sources have reached their end block, stopping subgraph";
+            "end_block" => self.inputs.max_end_block,
+        );
+
+        if !self.state.synced {
+            self.inputs.store.deployment_synced()?;
+            self.state.synced = true;
+            self.metrics.stream.stopwatch.disable();
+        }
+
+        Ok(())
+    }
+
     /// Processes a block and returns the updated context and a boolean flag indicating
     /// whether new dynamic data sources have been added to the subgraph.
     async fn process_block(
@@ -179,6 +260,17 @@ where
         // Causality region for onchain triggers.
         let causality_region = CausalityRegion::from_network(&self.inputs.network);
 
+        // Give the chain a chance to fetch what the handlers for these
+        // triggers will need before running them one by one
+        if !triggers.is_empty() {
+            let data_sources: Vec<_> = self.ctx.onchain_data_sources().collect();
+            self.inputs
+                .chain
+                .runtime_adapter()
+                .prefetch(&logger, &block, &triggers, &data_sources)
+                .await;
+        }
+
         // Process events one after the other, passing in entity operations
         // collected previously to every new event being processed
         let mut block_state = match self
@@ -213,8 +305,10 @@ where
         };
 
         // If new data sources have been created, and static filters are not in use, it is necessary
-        // to restart the block stream with the new filters.
-        let needs_restart = block_state.has_created_data_sources() && !self.inputs.static_filters;
+        // to restart the block stream with the new filters. The same is true if a data source has
+        // reached its end block, so that its triggers are no longer streamed.
+        let needs_restart = (block_state.has_created_data_sources() && !self.inputs.static_filters)
+            || self.reaches_end_block(&block_ptr);
 
         // This loop will:
         // 1. Instantiate created data sources.
@@ -680,6 +774,7 @@ where
         if block.trigger_count() == 0
             && self.state.skip_ptr_updates_timer.elapsed() <= SKIP_PTR_UPDATES_THRESHOLD
             && !self.state.synced
+            && !self.reaches_end_block(&block_ptr)
             && !close_to_chain_head(
                 &block_ptr,
                 self.inputs.chain.chain_store().cached_head_ptr().await?,
@@ -752,6 +847,11 @@ where
                     }
                 }
 
+                if self.has_reached_max_end_block(Some(block_ptr.clone())) {
+                    self.mark_max_end_block_reached()?;
+                    return Ok(Action::Stop);
+                }
+
                 if matches!(action, Action::Restart) {
                     // Cancel the stream for real
                     self.ctx
@@ -1,6 +1,6 @@
 use graph::{
     components::store::EntityKey,
-    prelude::Entity,
+    prelude::{BlockNumber, Entity},
     util::{backoff::ExponentialBackoff, lfu_cache::LfuCache},
 };
 use std::time::Instant;
@@ -18,5 +18,8 @@ pub struct IndexingState {
     /// - The time THRESHOLD is passed
     /// - Or the subgraph has triggers for the block
     pub skip_ptr_updates_timer: Instant,
+    /// The lowest end block of the data sources in the current filter. Once the subgraph
+    /// reaches it, the block stream is restarted with a filter that leaves them out.
+    pub next_end_block: Option<BlockNumber>,
     pub entity_lfu_cache: LfuCache<EntityKey, Option<Entity>>,
 }
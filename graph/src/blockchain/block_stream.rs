@@ -311,14 +311,13 @@ pub enum SubstreamsError {
     #[error("unknown error")]
     UnknownError(#[from] anyhow::Error),
 
-    #[error("multiple module output error")]
-    MultipleModuleOutputError,
-
     #[error("module output was not available (none) or wrong data provided")]
     ModuleOutputNotPresentOrUnexpected,
 
-    #[error("unexpected store delta output")]
-    UnexpectedStoreDeltaOutput,
+    /// An undo step for a block whose parent neither the mapper nor the
+    /// chain store know
+    #[error("can not undo block {0} since its parent block is not known")]
+    UnknownParentError(BlockPtr),
 }
 
 #[derive(Debug)]
@@ -57,6 +57,10 @@ impl<C: Blockchain> DataSource<C> for MockDataSource {
         todo!()
     }
 
+    fn end_block(&self) -> Option<crate::components::store::BlockNumber> {
+        todo!()
+    }
+
     fn name(&self) -> &str {
         todo!()
     }
@@ -189,6 +189,9 @@ pub trait DataSource<C: Blockchain>:
 {
     fn address(&self) -> Option<&[u8]>;
     fn start_block(&self) -> BlockNumber;
+
+    /// The last block for which this data source processes triggers, if any.
+    fn end_block(&self) -> Option<BlockNumber>;
     fn name(&self) -> &str;
     fn kind(&self) -> &str;
     fn network(&self) -> Option<&str>;
@@ -226,6 +229,13 @@ pub trait DataSource<C: Blockchain>:
 
     /// Used as part of manifest validation. If there are no errors, return an empty vector.
     fn validate(&self) -> Vec<Error>;
+
+    /// Whether `block` lies beyond the end block of this data source, in which case no
+    /// triggers of that block should be processed by it.
+    fn has_expired(&self, block: BlockNumber) -> bool {
+        self.end_block()
+            .map_or(false, |end_block| block > end_block)
+    }
 }
 
 #[async_trait]
@@ -289,8 +299,22 @@ impl CheapClone for HostFn {
     }
 }
 
+#[async_trait]
 pub trait RuntimeAdapter<C: Blockchain>: Send + Sync {
     fn host_fns(&self, ds: &C::DataSource) -> Result<Vec<HostFn>, Error>;
+
+    /// Called with the triggers of a block before they are processed, so
+    /// that chains can fetch data that handlers are known to need ahead of
+    /// time. Failures must not be fatal since handlers are expected to fetch
+    /// anything that was not prefetched themselves.
+    async fn prefetch(
+        &self,
+        _logger: &Logger,
+        _block: &Arc<C::Block>,
+        _triggers: &[C::TriggerData],
+        _data_sources: &[&C::DataSource],
+    ) {
+    }
 }
 
 pub trait NodeCapabilities<C: Blockchain> {
@@ -121,7 +121,7 @@ where
         cursor: Option<String>,
         mapper: Arc<F>,
         modules: Option<Modules>,
-        module_name: String,
+        output_modules: Vec<String>,
         start_blocks: Vec<BlockNumber>,
         end_blocks: Vec<BlockNumber>,
         logger: Logger,
@@ -143,7 +143,7 @@ where
                 cursor,
                 mapper,
                 modules,
-                module_name,
+                output_modules,
                 manifest_start_block_num,
                 manifest_end_block_num,
                 subgraph_current_block,
@@ -159,7 +159,7 @@ fn stream_blocks<C: Blockchain, F: SubstreamsMapper<C>>(
     cursor: Option<String>,
     mapper: Arc<F>,
     modules: Option<Modules>,
-    module_name: String,
+    output_modules: Vec<String>,
     manifest_start_block_num: BlockNumber,
     manifest_end_block_num: BlockNumber,
     _subgraph_current_block: Option<BlockPtr>,
@@ -178,7 +178,7 @@ fn stream_blocks<C: Blockchain, F: SubstreamsMapper<C>>(
         fork_steps: vec![StepNew as i32, StepUndo as i32],
         irreversibility_condition: "".to_string(),
         modules,
-        output_modules: vec![module_name],
+        output_modules,
         ..Default::default()
     };
 
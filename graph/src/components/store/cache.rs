@@ -5,7 +5,8 @@ use std::sync::Arc;
 
 use crate::blockchain::BlockPtr;
 use crate::components::store::{
-    self as s, Entity, EntityKey, EntityOp, EntityOperation, EntityType,
+    self as s, DerivedEntityQuery, Entity, EntityKey, EntityOp, EntityOperation, EntityType,
+    LoadRelatedRequest,
 };
 use crate::data_source::DataSource;
 use crate::prelude::{Schema, ENV_VARS};
@@ -118,6 +119,66 @@ impl EntityCache {
         Ok(entity)
     }
 
+    /// Get the entity for `eref` only if it was created or changed in the
+    /// current block, without looking it up in the store. Returns `None` if
+    /// the entity was not touched by the current block or if it was removed
+    pub fn get_in_block(&self, eref: &EntityKey) -> Option<Entity> {
+        let mut entity = None;
+        if let Some(op) = self.updates.get(eref).cloned() {
+            entity = op.apply_to(entity)
+        }
+        if let Some(op) = self.handler_updates.get(eref).cloned() {
+            entity = op.apply_to(entity)
+        }
+        entity
+    }
+
+    /// Load the entities that make up the `@derivedFrom` field described
+    /// by `request`. Entities are loaded from the store and then updated
+    /// with the changes in this cache, so that entities that were created,
+    /// changed or removed by the current block are reflected in the result
+    pub fn load_related(
+        &mut self,
+        request: &LoadRelatedRequest,
+    ) -> Result<Vec<Entity>, anyhow::Error> {
+        let (entity_types, entity_field) = self
+            .schema
+            .derived_field(&request.entity_type, request.entity_field.as_str())?;
+
+        let mut related = Vec::new();
+        for entity_type in entity_types {
+            let query = DerivedEntityQuery {
+                entity_type,
+                entity_field: entity_field.as_str().into(),
+                value: request.entity_id.clone(),
+            };
+
+            let mut entities = self.store.get_derived(&query)?;
+
+            // Entities that have pending changes need to be looked at
+            // again since the changes might add them to or remove them
+            // from the result
+            let changed: Vec<_> = self
+                .updates
+                .keys()
+                .chain(self.handler_updates.keys())
+                .filter(|key| key.entity_type == query.entity_type)
+                .cloned()
+                .collect();
+            for key in changed {
+                entities.remove(&key);
+                if let Some(entity) = self.get(&key)? {
+                    if query.matches(&entity) {
+                        entities.insert(key, entity);
+                    }
+                }
+            }
+
+            related.extend(entities.into_values());
+        }
+        Ok(related)
+    }
+
     pub fn remove(&mut self, key: EntityKey) {
         self.entity_op(key, EntityOp::Remove);
     }
@@ -357,4 +418,29 @@ pub struct CachedEthereumCall {
 
     /// The encoded return value of this call.
     pub return_value: Vec<u8>,
+
+    /// The encoded call. It is only known for calls that are still held in
+    /// memory since the call cache only stores the hash of the call.
+    pub call_data: Option<Vec<u8>>,
+
+    /// Whether the call was declared in the manifest and prefetched before
+    /// the handlers for its block ran.
+    pub declared: bool,
+}
+
+impl CachedEthereumCall {
+    /// The id is the hashed encoded_call + contract_address + block hash to uniquely identify the call.
+    /// 256 bits of output, and therefore 128 bits of security against collisions, are needed since this
+    /// could be targeted by a birthday attack.
+    pub fn id(
+        contract_address: &ethabi::Address,
+        encoded_call: &[u8],
+        block: &BlockPtr,
+    ) -> [u8; 32] {
+        let mut hash = blake3::Hasher::new();
+        hash.update(encoded_call);
+        hash.update(contract_address.as_ref());
+        hash.update(block.hash_slice());
+        *hash.finalize().as_bytes()
+    }
 }
@@ -114,6 +114,49 @@ impl EntityKey {
     }
 }
 
+/// A request from a mapping to load the entities that make up the
+/// `@derivedFrom` field `entity_field` of the entity `entity_type[entity_id]`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadRelatedRequest {
+    /// Name of the entity type that has the derived field
+    pub entity_type: EntityType,
+    /// ID of the entity whose related entities should be loaded
+    pub entity_id: Word,
+    /// Name of the derived field
+    pub entity_field: Word,
+}
+
+/// A query for all entities of type `entity_type` whose attribute
+/// `entity_field` references the entity with id `value`, either directly
+/// or as one of the elements of a list
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DerivedEntityQuery {
+    /// Name of the entity type that holds the references
+    pub entity_type: EntityType,
+    /// Name of the attribute that holds the references
+    pub entity_field: Word,
+    /// The id of the referenced entity
+    pub value: Word,
+}
+
+impl DerivedEntityQuery {
+    /// Return `true` if `entity` references the entity with id `value`
+    pub fn matches(&self, entity: &Entity) -> bool {
+        fn refers_to(value: &Value, id: &str) -> bool {
+            match value {
+                Value::String(s) => s == id,
+                Value::Bytes(b) => b.to_string() == id,
+                Value::List(values) => values.iter().any(|value| refers_to(value, id)),
+                _ => false,
+            }
+        }
+
+        entity
+            .get(self.entity_field.as_str())
+            .map_or(false, |value| refers_to(value, self.value.as_str()))
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Child {
     pub attr: Attribute,
@@ -252,6 +295,15 @@ pub enum EntityOrder {
     Ascending(String, ValueType),
     /// Order descending by the given attribute. Use `id` as a tie-breaker
     Descending(String, ValueType),
+    /// Order ascending by an attribute of a child entity. Use `id` as a
+    /// tie-breaker
+    ChildAscending(EntityOrderByChild),
+    /// Order descending by an attribute of a child entity. Use `id` as a
+    /// tie-breaker
+    ChildDescending(EntityOrderByChild),
+    /// Order by several attributes in turn, each in its own direction. Use
+    /// `id` in the direction of the last attribute as a tie-breaker
+    Multi(Vec<(String, ValueType, OrderDirection)>),
     /// Order by the `id` of the entities
     Default,
     /// Do not order at all. This speeds up queries where we know that
@@ -259,6 +311,56 @@ pub enum EntityOrder {
     Unordered,
 }
 
+impl EntityOrder {
+    /// The attributes whose values, in this order, determine the position
+    /// of an entity in the order; `id` is always the last one. Return
+    /// `None` for orders that an `EntityCursor` can not be used with
+    pub fn cursor_attributes(&self) -> Option<Vec<&str>> {
+        let mut attrs = Vec::new();
+        match self {
+            EntityOrder::Ascending(attr, _) | EntityOrder::Descending(attr, _) => {
+                if attr != "id" {
+                    attrs.push(attr.as_str());
+                }
+            }
+            EntityOrder::Multi(keys) => {
+                // Keys after `id` can not change the order any more
+                attrs.extend(
+                    keys.iter()
+                        .map(|(attr, _, _)| attr.as_str())
+                        .take_while(|attr| *attr != "id"),
+                );
+            }
+            EntityOrder::Default => {}
+            EntityOrder::ChildAscending(_)
+            | EntityOrder::ChildDescending(_)
+            | EntityOrder::Unordered => return None,
+        }
+        attrs.push("id");
+        Some(attrs)
+    }
+}
+
+/// The direction of one attribute in an `EntityOrder::Multi`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrderDirection {
+    Ascending,
+    Descending,
+}
+
+/// Sorting by an attribute of a child entity that the entities we query
+/// reference through a single-valued, non-derived attribute
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityOrderByChild {
+    /// The attribute of the parent entity that holds the id of the child
+    pub join_attribute: Attribute,
+    /// The attribute of the child entity to sort by
+    pub sort_by_attribute: Attribute,
+    /// The possible types of the child; there is more than one if the
+    /// attribute references an interface
+    pub child_types: Vec<EntityType>,
+}
+
 /// How many entities to return, how many to skip etc.
 #[derive(Clone, Debug, PartialEq)]
 pub struct EntityRange {
@@ -267,6 +369,10 @@ pub struct EntityRange {
 
     /// How many entities to skip.
     pub skip: u32,
+
+    /// Only return entities on one side of a given entity in the order of
+    /// the query. `skip` is applied after the cursor
+    pub cursor: Option<EntityCursor>,
 }
 
 impl EntityRange {
@@ -275,6 +381,28 @@ impl EntityRange {
         Self {
             first: Some(n),
             skip: 0,
+            cursor: None,
+        }
+    }
+}
+
+/// A position in the order of a query, given by the values of the
+/// attributes from `EntityOrder::cursor_attributes` for some entity. That
+/// entity does not need to exist at the block of the query
+#[derive(Clone, Debug, PartialEq)]
+pub enum EntityCursor {
+    /// Only return entities that come after the position
+    After(Vec<Value>),
+    /// Only return entities that come before the position, still in the
+    /// order of the query. With `first`, the entities closest to the
+    /// position are returned
+    Before(Vec<Value>),
+}
+
+impl EntityCursor {
+    pub fn values(&self) -> &[Value] {
+        match self {
+            EntityCursor::After(values) | EntityCursor::Before(values) => values,
         }
     }
 }
@@ -512,6 +640,172 @@ impl EntityQuery {
     }
 }
 
+/// An aggregate function that can be computed over an attribute of all
+/// entities that match an `EntityAggregateQuery`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AggregateFunction {
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggregateFunction {
+    pub const ALL: [AggregateFunction; 4] = [
+        AggregateFunction::Sum,
+        AggregateFunction::Avg,
+        AggregateFunction::Min,
+        AggregateFunction::Max,
+    ];
+
+    /// The name of the function in SQL and in the GraphQL API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Avg => "avg",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
+        }
+    }
+
+    /// The type of the result of applying this function to an attribute of
+    /// type `value_type`, or `None` if the function can not be applied to
+    /// such attributes. Sums are widened so that they can not overflow
+    pub fn value_type(&self, value_type: ValueType) -> Option<ValueType> {
+        use AggregateFunction::*;
+        use ValueType::*;
+
+        match (self, value_type) {
+            (Sum, Int) => Some(Int8),
+            (Sum, Int8) | (Sum, BigInt) => Some(BigInt),
+            (Sum, BigDecimal) => Some(BigDecimal),
+            (Avg, Int) | (Avg, Int8) | (Avg, BigInt) | (Avg, BigDecimal) => Some(BigDecimal),
+            (Min, Int | Int8 | BigInt | BigDecimal | Timestamp)
+            | (Max, Int | Int8 | BigInt | BigDecimal | Timestamp) => Some(value_type),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AggregateFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A query for the number of entities of one type that match a filter, and
+/// for aggregates over some of their attributes
+#[derive(Clone, Debug)]
+pub struct EntityAggregateQuery {
+    /// ID of the subgraph.
+    pub subgraph_id: DeploymentHash,
+
+    /// The block height at which to execute the query
+    pub block: BlockNumber,
+
+    /// The entity type whose entities we aggregate over
+    pub entity_type: EntityType,
+
+    /// Filter to filter entities by.
+    pub filter: Option<EntityFilter>,
+
+    /// The aggregates to compute, as pairs of function and attribute name.
+    /// The number of matching entities is always computed
+    pub aggregates: Vec<(AggregateFunction, String)>,
+
+    /// Optional logger for anything related to this query
+    pub logger: Option<Logger>,
+
+    pub query_id: Option<String>,
+}
+
+impl EntityAggregateQuery {
+    pub fn new(subgraph_id: DeploymentHash, block: BlockNumber, entity_type: EntityType) -> Self {
+        EntityAggregateQuery {
+            subgraph_id,
+            block,
+            entity_type,
+            filter: None,
+            aggregates: vec![],
+            logger: None,
+            query_id: None,
+        }
+    }
+}
+
+/// The result of an `EntityAggregateQuery`
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityAggregates {
+    /// The number of entities that match the filter
+    pub count: i64,
+    /// The value of each aggregate, in the order in which they appear in
+    /// `EntityAggregateQuery.aggregates`. Aggregates over no entities, or
+    /// only over null values, are `Value::Null`
+    pub values: Vec<Value>,
+}
+
+/// A query for all versions of one entity that were valid at some block
+/// between `from` and `to`
+#[derive(Clone, Debug)]
+pub struct EntityHistoryQuery {
+    /// ID of the subgraph.
+    pub subgraph_id: DeploymentHash,
+
+    /// The type of the entity
+    pub entity_type: EntityType,
+
+    /// The id of the entity
+    pub id: String,
+
+    /// The first block of the interval. When `None`, the interval starts at
+    /// the earliest block for which the subgraph still has data
+    pub from: Option<BlockNumber>,
+
+    /// The last block of the interval (inclusive)
+    pub to: BlockNumber,
+
+    /// How many versions to return and how many to skip. Versions are
+    /// ordered by the block at which they became valid
+    pub range: EntityRange,
+
+    /// Optional logger for anything related to this query
+    pub logger: Option<Logger>,
+
+    pub query_id: Option<String>,
+}
+
+impl EntityHistoryQuery {
+    pub fn new(
+        subgraph_id: DeploymentHash,
+        entity_type: EntityType,
+        id: String,
+        to: BlockNumber,
+    ) -> Self {
+        EntityHistoryQuery {
+            subgraph_id,
+            entity_type,
+            id,
+            from: None,
+            to,
+            range: EntityRange::first(100),
+            logger: None,
+            query_id: None,
+        }
+    }
+}
+
+/// One version of an entity as returned by an `EntityHistoryQuery`
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityVersion {
+    /// The attributes of the entity in this version
+    pub entity: BTreeMap<Word, r::Value>,
+    /// The first block at which this version is valid
+    pub valid_from: BlockNumber,
+    /// The first block at which this version is no longer valid, or `None`
+    /// if it is the current version
+    pub valid_until: Option<BlockNumber>,
+}
+
 /// Operation types that lead to entity changes.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
@@ -1074,6 +1368,13 @@ impl ReadStore for EmptyStore {
         Ok(BTreeMap::new())
     }
 
+    fn get_derived(
+        &self,
+        _query: &DerivedEntityQuery,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        Ok(BTreeMap::new())
+    }
+
     fn input_schema(&self) -> Arc<Schema> {
         self.schema.cheap_clone()
     }
@@ -1090,6 +1391,119 @@ pub struct VersionStats {
     pub ratio: f64,
 }
 
+/// How pruning removes history from a table
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PruneStrategy {
+    /// Copy the rows that are kept into a new table and replace the
+    /// existing table with it. This works best when pruning removes most
+    /// of the table
+    Rebuild,
+    /// Delete the rows that are no longer needed from the existing table.
+    /// This works best when pruning removes only a small part of the table
+    Delete,
+}
+
+/// The parameters for pruning a deployment
+#[derive(Clone, Debug)]
+pub struct PruneRequest {
+    /// How many blocks of history to keep
+    pub history_blocks: BlockNumber,
+    /// The number of blocks that a reorg can revert
+    pub reorg_threshold: BlockNumber,
+    /// The earliest block for which queries will still work after pruning
+    pub earliest_block: BlockNumber,
+    /// Blocks before this one are final and will not be reverted
+    pub final_block: BlockNumber,
+    /// The earliest block the deployment has data for before pruning
+    pub first_block: BlockNumber,
+    /// The latest block the deployment has processed
+    pub latest_block: BlockNumber,
+    /// Tables from which pruning removes at least this fraction of the
+    /// rows are rebuilt
+    pub rebuild_threshold: f64,
+    /// Tables from which pruning removes at least this fraction of the
+    /// rows, but less than `rebuild_threshold`, are pruned by deleting
+    pub delete_threshold: f64,
+    /// Prune all tables that are worth pruning with this strategy instead
+    /// of choosing a strategy for each table based on `rebuild_threshold`
+    pub forced_strategy: Option<PruneStrategy>,
+}
+
+impl PruneRequest {
+    /// Set up pruning for a deployment that has data for the blocks from
+    /// `first_block` to `latest_block` so that it keeps `history_blocks`
+    /// blocks of history
+    pub fn new(
+        deployment: &DeploymentLocator,
+        history_blocks: BlockNumber,
+        reorg_threshold: BlockNumber,
+        first_block: BlockNumber,
+        latest_block: BlockNumber,
+    ) -> Result<Self, StoreError> {
+        if history_blocks <= reorg_threshold {
+            return Err(crate::constraint_violation!(
+                "the deployment {} needs to keep at least {} blocks of history \
+                 and can't be pruned to only {} blocks of history",
+                deployment,
+                reorg_threshold + 1,
+                history_blocks
+            ));
+        }
+        if latest_block <= history_blocks {
+            return Err(crate::constraint_violation!(
+                "the deployment {} has only processed up to block {} \
+                 and can't keep {} blocks of history",
+                deployment,
+                latest_block,
+                history_blocks
+            ));
+        }
+
+        Ok(Self {
+            history_blocks,
+            reorg_threshold,
+            earliest_block: latest_block - history_blocks,
+            final_block: latest_block - reorg_threshold,
+            first_block,
+            latest_block,
+            rebuild_threshold: ENV_VARS.store.rebuild_threshold,
+            delete_threshold: ENV_VARS.store.delete_threshold,
+            forced_strategy: None,
+        })
+    }
+
+    /// Decide how to prune a table with the given statistics, or return
+    /// `None` if the table is not worth pruning. We estimate how much of
+    /// the table pruning removes by assuming that entity versions are
+    /// spread evenly over the deployment's history, and that current
+    /// versions of entities are never removed
+    pub fn strategy(&self, stats: &VersionStats) -> Option<PruneStrategy> {
+        if self.earliest_block >= self.final_block {
+            return None;
+        }
+
+        let removal_ratio = self.history_pct() * (1.0 - stats.ratio);
+        if removal_ratio < self.delete_threshold {
+            None
+        } else if let Some(strategy) = self.forced_strategy {
+            Some(strategy)
+        } else if removal_ratio >= self.rebuild_threshold {
+            Some(PruneStrategy::Rebuild)
+        } else {
+            Some(PruneStrategy::Delete)
+        }
+    }
+
+    /// The fraction of the deployment's history that pruning removes
+    fn history_pct(&self) -> f64 {
+        if self.latest_block <= self.first_block || self.earliest_block <= self.first_block {
+            return 0.0;
+        }
+        (self.earliest_block - self.first_block) as f64
+            / (self.latest_block - self.first_block) as f64
+    }
+}
+
 /// Callbacks for `SubgraphStore.prune` so that callers can report progress
 /// of the pruning procedure to users
 #[allow(unused_variables)]
@@ -1108,5 +1522,9 @@ pub trait PruneReporter: Send + 'static {
     fn copy_nonfinal_finish(&mut self, table: &str, rows: usize) {}
     fn finish_switch(&mut self) {}
 
+    fn delete_start(&mut self, earliest_block: BlockNumber) {}
+    fn delete_batch(&mut self, table: &str, rows: usize, total_rows: usize, finished: bool) {}
+    fn delete_finish(&mut self) {}
+
     fn finish_prune(&mut self) {}
 }
@@ -176,6 +176,12 @@ pub trait ReadStore: Send + Sync + 'static {
         ids_for_type: BTreeMap<&EntityType, Vec<&str>>,
     ) -> Result<BTreeMap<EntityType, Vec<Entity>>, StoreError>;
 
+    /// Look up all entities matching `query` as of the latest block
+    fn get_derived(
+        &self,
+        query: &DerivedEntityQuery,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError>;
+
     fn input_schema(&self) -> Arc<Schema>;
 }
 
@@ -192,6 +198,13 @@ impl<T: ?Sized + ReadStore> ReadStore for Arc<T> {
         (**self).get_many(ids_for_type)
     }
 
+    fn get_derived(
+        &self,
+        query: &DerivedEntityQuery,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        (**self).get_derived(query)
+    }
+
     fn input_schema(&self) -> Arc<Schema> {
         (**self).input_schema()
     }
@@ -446,6 +459,21 @@ pub trait QueryStore: Send + Sync {
         query: EntityQuery,
     ) -> Result<(Vec<BTreeMap<Word, r::Value>>, Trace), QueryExecutionError>;
 
+    /// Count the entities that match `query.filter` and compute the
+    /// aggregates in `query.aggregates` over them
+    fn aggregate(
+        &self,
+        query: EntityAggregateQuery,
+    ) -> Result<(EntityAggregates, Trace), QueryExecutionError>;
+
+    /// Return the versions of the entity `query.id` that were valid at any
+    /// block in the interval of the query, ordered by the block at which
+    /// they became valid
+    fn history(
+        &self,
+        query: EntityHistoryQuery,
+    ) -> Result<(Vec<EntityVersion>, Trace), QueryExecutionError>;
+
     async fn is_deployment_synced(&self) -> Result<bool, Error>;
 
     async fn block_ptr(&self) -> Result<Option<BlockPtr>, StoreError>;
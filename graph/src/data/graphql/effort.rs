@@ -4,10 +4,10 @@ use prometheus::core::GenericCounter;
 use rand::{prelude::Rng, thread_rng};
 use std::collections::{HashMap, HashSet};
 use std::iter::FromIterator;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
 
-use crate::components::metrics::{Counter, Gauge, MetricsRegistry};
+use crate::components::metrics::{Counter, CounterVec, Gauge, MetricsRegistry};
 use crate::components::store::PoolWaitStats;
 use crate::data::graphql::shape_hash::shape_hash;
 use crate::data::query::{CacheStatus, QueryExecutionError};
@@ -60,6 +60,19 @@ impl QueryEffort {
         let query_effort = inner.effort.get(&shape_hash).map(|stats| stats.duration());
         (query_effort, total_effort)
     }
+
+    /// Return the average effort of one run of the query `shape_hash`. If
+    /// we have no data for the particular query, return the average effort
+    /// of all queries, and `ZERO_DURATION` if we have no measurements at all
+    pub fn average_effort(&self, shape_hash: u64) -> Duration {
+        let inner = self.inner.read().unwrap();
+        inner
+            .effort
+            .get(&shape_hash)
+            .and_then(MovingStats::average)
+            .or_else(|| inner.total.average())
+            .unwrap_or(Duration::ZERO)
+    }
 }
 
 impl QueryEffortInner {
@@ -186,6 +199,69 @@ impl Decision {
     }
 }
 
+/// The maximum number of clients for which we keep a `ClientBucket`
+const MAX_CLIENT_BUCKETS: usize = 10_000;
+
+/// A token bucket for the query effort of one client, measured in
+/// milliseconds. The bucket holds at most `capacity` and is refilled at
+/// `rate` per second. The effort of a query is only taken out of the bucket
+/// after the query has run, which can leave the bucket with a negative
+/// balance; the client then has to wait until the bucket is refilled
+/// enough to become positive again
+struct ClientBucket {
+    balance: f64,
+    updated: Instant,
+}
+
+impl ClientBucket {
+    fn new(capacity: f64, now: Instant) -> Self {
+        ClientBucket {
+            balance: capacity,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant, rate: f64, capacity: f64) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.balance = (self.balance + elapsed * rate).min(capacity);
+        self.updated = now;
+    }
+
+    /// How long the client has to wait until its balance is positive
+    fn wait_time(&self, rate: f64) -> Option<Duration> {
+        if self.balance > 0.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(-self.balance / rate))
+        }
+    }
+}
+
+/// Make sure that `buckets` has room for one more client. Clients whose
+/// buckets are full again are forgotten first since they are no different
+/// from clients we have never seen. If all clients are still in debt, we
+/// forget the one that is least in debt, so that clients can not grow the
+/// map without bounds by sending ever new client ids
+fn make_room(buckets: &mut HashMap<String, ClientBucket>, now: Instant, rate: f64, capacity: f64) {
+    if buckets.len() < MAX_CLIENT_BUCKETS {
+        return;
+    }
+    buckets.retain(|_, bucket| {
+        bucket.refill(now, rate, capacity);
+        bucket.balance < capacity
+    });
+    while buckets.len() >= MAX_CLIENT_BUCKETS {
+        let fullest = buckets
+            .iter()
+            .max_by(|(_, a), (_, b)| a.balance.total_cmp(&b.balance))
+            .map(|(client, _)| client.clone());
+        match fullest {
+            Some(client) => buckets.remove(&client),
+            None => break,
+        };
+    }
+}
+
 pub struct LoadManager {
     logger: Logger,
     effort: QueryEffort,
@@ -201,6 +277,15 @@ pub struct LoadManager {
     effort_gauge: Box<Gauge>,
     query_counters: HashMap<CacheStatus, Counter>,
     kill_rate_gauge: Box<Gauge>,
+    /// How much query effort each client can use per second, and in a
+    /// burst
+    client_rate: Duration,
+    client_burst: Duration,
+    /// The token buckets that limit how much query effort each client
+    /// can cause
+    client_buckets: Mutex<HashMap<String, ClientBucket>>,
+    client_effort: Box<CounterVec>,
+    client_rate_limited: Box<CounterVec>,
 }
 
 impl LoadManager {
@@ -251,6 +336,20 @@ impl LoadManager {
                 (*s, counter)
             })
             .collect::<HashMap<_, _>>();
+        let client_effort = registry
+            .new_counter_vec(
+                "query_client_effort_ms",
+                "Query effort charged to each client",
+                vec!["client".to_owned()],
+            )
+            .expect("failed to create `query_client_effort_ms` counter");
+        let client_rate_limited = registry
+            .new_counter_vec(
+                "query_client_rate_limited",
+                "Number of queries rejected because their client exceeded its rate limit",
+                vec!["client".to_owned()],
+            )
+            .expect("failed to create `query_client_rate_limited` counter");
 
         Self {
             logger,
@@ -261,9 +360,111 @@ impl LoadManager {
             effort_gauge,
             query_counters,
             kill_rate_gauge,
+            client_rate: ENV_VARS.load_client_rate,
+            client_burst: ENV_VARS.load_client_burst,
+            client_buckets: Mutex::new(HashMap::new()),
+            client_effort,
+            client_rate_limited,
+        }
+    }
+
+    /// Use `rate` and `burst` instead of [`ENV_VARS.load_client_rate`] and
+    /// [`ENV_VARS.load_client_burst`] for per-client rate limiting
+    pub fn with_client_limits(mut self, rate: Duration, burst: Duration) -> Self {
+        self.client_rate = rate;
+        self.client_burst = burst;
+        self
+    }
+
+    /// The rate at which client buckets are refilled and their capacity,
+    /// both in milliseconds
+    fn client_limits(&self) -> (f64, f64) {
+        (
+            self.client_rate.as_secs_f64() * 1000.0,
+            self.client_burst.as_secs_f64() * 1000.0,
+        )
+    }
+
+    /// The label under which metrics for `client` are recorded. To keep the
+    /// number of label values bounded, only the clients listed in
+    /// [`ENV_VARS.load_client_labels`] get a label of their own
+    fn client_label(client: &str) -> &str {
+        if ENV_VARS.load_client_labels.contains(client) {
+            client
+        } else {
+            "other"
         }
     }
 
+    /// Check whether `client` has used up its allowance for query effort,
+    /// and return an error if it has. Clients are allowed to use
+    /// [`ENV_VARS.load_client_rate`] of query effort per second, and can
+    /// use up to [`ENV_VARS.load_client_burst`] in a burst
+    pub fn check_client(&self, client: &str) -> Result<(), QueryExecutionError> {
+        if self.client_rate.is_zero() {
+            return Ok(());
+        }
+
+        let (rate, capacity) = self.client_limits();
+        let wait_time = {
+            let mut buckets = self.client_buckets.lock().unwrap();
+            // Clients we have no bucket for have a full bucket
+            match buckets.get_mut(client) {
+                Some(bucket) => {
+                    bucket.refill(Instant::now(), rate, capacity);
+                    bucket.wait_time(rate)
+                }
+                None => None,
+            }
+        };
+
+        match wait_time {
+            None => Ok(()),
+            Some(wait_time) => {
+                self.client_rate_limited
+                    .with_label_values(&[Self::client_label(client)])
+                    .inc();
+                if ENV_VARS.load_simulate {
+                    debug!(self.logger, "Rate limiting client";
+                        "client" => client,
+                        "wait_ms" => wait_time.as_millis());
+                    Ok(())
+                } else {
+                    Err(QueryExecutionError::RateLimited(
+                        client.to_string(),
+                        wait_time,
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Charge `client` for running the query `shape_hash` once. What the
+    /// query costs comes from the effort that we have recorded for queries
+    /// of that shape with `record_work`, not from how long this particular
+    /// run took, so that clients pay the same for the same query
+    pub fn charge_client(&self, client: &str, shape_hash: u64) {
+        let effort = self.effort.average_effort(shape_hash).as_secs_f64() * 1000.0;
+        self.client_effort
+            .with_label_values(&[Self::client_label(client)])
+            .inc_by(effort);
+        if self.client_rate.is_zero() {
+            return;
+        }
+
+        let (rate, capacity) = self.client_limits();
+        let now = Instant::now();
+        let mut buckets = self.client_buckets.lock().unwrap();
+        if !buckets.contains_key(client) {
+            make_room(&mut buckets, now, rate, capacity);
+        }
+        let bucket = buckets
+            .entry(client.to_string())
+            .or_insert_with(|| ClientBucket::new(capacity, now));
+        bucket.refill(now, rate, capacity);
+        bucket.balance -= effort;
+    }
+
     /// Record that we spent `duration` amount of work for the query
     /// `shape_hash`, where `cache_status` indicates whether the query
     /// was cached or had to actually run
@@ -271,7 +472,9 @@ impl LoadManager {
         self.query_counters
             .get(&cache_status)
             .map(GenericCounter::inc);
-        if !ENV_VARS.load_management_is_disabled() {
+        // Per-client rate limiting needs the effort even when load
+        // management is otherwise disabled
+        if !ENV_VARS.load_management_is_disabled() || !self.client_rate.is_zero() {
             self.effort.add(shape_hash, duration, &self.effort_gauge);
         }
     }
@@ -497,8 +700,64 @@ impl QueryLoadManager for LoadManager {
         self.query_counters
             .get(&cache_status)
             .map(|counter| counter.inc());
-        if !ENV_VARS.load_management_is_disabled() {
+        // Per-client rate limiting needs the effort even when load
+        // management is otherwise disabled
+        if !ENV_VARS.load_management_is_disabled() || !self.client_rate.is_zero() {
             self.effort.add(shape_hash, duration, &self.effort_gauge);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use std::collections::HashMap;
+
+    use super::{make_room, ClientBucket, MAX_CLIENT_BUCKETS};
+
+    #[test]
+    fn client_bucket() {
+        const RATE: f64 = 100.0;
+        const CAPACITY: f64 = 1000.0;
+
+        let start = Instant::now();
+        let mut bucket = ClientBucket::new(CAPACITY, start);
+        assert_eq!(None, bucket.wait_time(RATE));
+
+        // Going into debt makes the client wait until the debt is paid off
+        bucket.balance -= 1500.0;
+        assert_eq!(Some(Duration::from_secs(5)), bucket.wait_time(RATE));
+
+        bucket.refill(start + Duration::from_secs(2), RATE, CAPACITY);
+        assert_eq!(Some(Duration::from_secs(3)), bucket.wait_time(RATE));
+
+        bucket.refill(start + Duration::from_secs(6), RATE, CAPACITY);
+        assert_eq!(None, bucket.wait_time(RATE));
+
+        // The bucket never holds more than its capacity
+        bucket.refill(start + Duration::from_secs(600), RATE, CAPACITY);
+        assert_eq!(CAPACITY, bucket.balance);
+    }
+
+    #[test]
+    fn client_buckets_are_bounded() {
+        const RATE: f64 = 100.0;
+        const CAPACITY: f64 = 1000.0;
+
+        // Every client is in debt, the one with the highest number the least
+        let now = Instant::now();
+        let mut buckets: HashMap<_, _> = (0..MAX_CLIENT_BUCKETS)
+            .map(|i| {
+                let mut bucket = ClientBucket::new(CAPACITY, now);
+                bucket.balance = -1000.0 - (MAX_CLIENT_BUCKETS - i) as f64;
+                (format!("client{}", i), bucket)
+            })
+            .collect();
+
+        make_room(&mut buckets, now, RATE, CAPACITY);
+        assert_eq!(MAX_CLIENT_BUCKETS - 1, buckets.len());
+        assert!(!buckets.contains_key(&format!("client{}", MAX_CLIENT_BUCKETS - 1)));
+        assert!(buckets.contains_key("client0"));
+    }
+}
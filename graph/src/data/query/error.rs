@@ -7,9 +7,10 @@ use std::error::Error;
 use std::fmt;
 use std::string::FromUtf8Error;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::data::subgraph::*;
-use crate::prelude::q;
+use crate::prelude::{q, BlockNumber};
 use crate::{components::store::StoreError, prelude::CacheWeight};
 
 #[derive(Debug, Clone)]
@@ -75,6 +76,14 @@ pub enum QueryExecutionError {
     InvalidSubgraphManifest,
     ResultTooBig(usize, usize),
     DeploymentNotFound(String),
+    PersistedQueryNotFound,
+    PersistedQueryHashMismatch(String),
+    QueryNotAllowed(String),
+    RateLimited(String, Duration),
+    InvalidCursor(String),
+    AggregatesDisabled,
+    TooManyAggregateEntities(usize),
+    BlockBeforeEarliest(String, BlockNumber, BlockNumber), // (deployment, earliest_block, block)
 }
 
 impl QueryExecutionError {
@@ -109,7 +118,8 @@ impl QueryExecutionError {
             | CyclicalFragment(_)
             | UndefinedFragment(_)
             | FulltextQueryInvalidSyntax(_)
-            | FulltextQueryRequiresFilter => true,
+            | FulltextQueryRequiresFilter
+            | InvalidCursor(_) => true,
             ListValueError(_, _)
             | ResolveEntitiesError(_)
             | RangeArgumentsError(_, _, _)
@@ -131,7 +141,14 @@ impl QueryExecutionError {
             | InvalidSubgraphManifest
             | ValidationError(_, _)
             | ResultTooBig(_, _)
-            | DeploymentNotFound(_) => false,
+            | DeploymentNotFound(_)
+            | PersistedQueryNotFound
+            | PersistedQueryHashMismatch(_)
+            | QueryNotAllowed(_)
+            | RateLimited(_, _)
+            | AggregatesDisabled
+            | TooManyAggregateEntities(_)
+            | BlockBeforeEarliest(_, _, _) => false,
         }
     }
 }
@@ -275,7 +292,17 @@ impl fmt::Display for QueryExecutionError {
             SubgraphManifestResolveError(e) => write!(f, "failed to resolve subgraph manifest: {}", e),
             InvalidSubgraphManifest => write!(f, "invalid subgraph manifest file"),
             ResultTooBig(actual, limit) => write!(f, "the result size of {} is larger than the allowed limit of {}", actual, limit),
-            DeploymentNotFound(id_or_name) => write!(f, "deployment `{}` does not exist", id_or_name)
+            DeploymentNotFound(id_or_name) => write!(f, "deployment `{}` does not exist", id_or_name),
+            // Clients that use automatic persisted queries look for exactly
+            // this message to know that they need to send the query text
+            PersistedQueryNotFound => write!(f, "PersistedQueryNotFound"),
+            PersistedQueryHashMismatch(hash) => write!(f, "the query does not have the sha256 hash `{}`", hash),
+            QueryNotAllowed(hash) => write!(f, "the query with sha256 hash `{}` is not in the list of allowed queries", hash),
+            RateLimited(client, retry_after) => write!(f, "client `{}` has used up its allowance for running queries. Please try again in {} seconds", client, retry_after.as_secs().max(1)),
+            InvalidCursor(cursor) => write!(f, "invalid cursor `{}`; a cursor can only be used with the order of the query that returned it", cursor),
+            AggregatesDisabled => write!(f, "aggregate queries are disabled on this node"),
+            TooManyAggregateEntities(limit) => write!(f, "aggregates can only be computed over at most {} entities. Use `where` to aggregate over fewer entities", limit),
+            BlockBeforeEarliest(deployment, earliest_block, block) => write!(f, "subgraph {} only has data starting at block number {} and data for block number {} is therefore not available", deployment, earliest_block, block)
         }
     }
 }
@@ -132,6 +132,9 @@ pub struct Query {
     pub shape_hash: u64,
     pub query_text: Arc<String>,
     pub variables_text: Arc<String>,
+    /// Identifies the client that sent the query, for per-client rate
+    /// limiting and metrics
+    pub client: Option<String>,
     _force_use_of_new: (),
 }
 
@@ -158,6 +161,7 @@ impl Query {
             shape_hash,
             query_text: Arc::new(query_text),
             variables_text: Arc::new(variables_text),
+            client: None,
             _force_use_of_new: (),
         }
     }
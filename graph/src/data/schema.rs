@@ -3,7 +3,7 @@ use crate::components::store::{EntityKey, EntityType, SubgraphStore};
 use crate::data::graphql::ext::{DirectiveExt, DirectiveFinder, DocumentExt, TypeExt, ValueExt};
 use crate::data::graphql::ObjectTypeExt;
 use crate::data::store::{self, ValueType};
-use crate::data::subgraph::{DeploymentHash, SubgraphName};
+use crate::data::subgraph::{DeploymentHash, SubgraphName, SPEC_VERSION_0_0_8};
 use crate::prelude::{
     anyhow, lazy_static,
     q::Value,
@@ -14,6 +14,7 @@ use anyhow::{Context, Error};
 use graphql_parser::{self, Pos};
 use inflector::Inflector;
 use itertools::Itertools;
+use semver::Version;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -112,6 +113,10 @@ pub enum SchemaValidationError {
     FulltextIncludedFieldMissingRequiredProperty,
     #[error("Fulltext entity field, {0}, not found or not a string")]
     FulltextIncludedFieldInvalid(String),
+    #[error("Type `{0}` has an invalid index directive: {1}")]
+    InvalidIndex(String, String), // (type, reason)
+    #[error("Field `{1}` in type `{0}` has an invalid @noIndex directive: {2}")]
+    InvalidNoIndex(String, String, String), // (type, field, reason)
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -260,6 +265,86 @@ impl From<&s::Directive> for FulltextDefinition {
         }
     }
 }
+
+/// The index methods that can be used in an `@index` directive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexMethod {
+    BTree,
+    Gist,
+    Brin,
+}
+
+impl TryFrom<&str> for IndexMethod {
+    type Error = String;
+    fn try_from(method: &str) -> Result<Self, Self::Error> {
+        match method {
+            "btree" => Ok(IndexMethod::BTree),
+            "gist" => Ok(IndexMethod::Gist),
+            "brin" => Ok(IndexMethod::Brin),
+            invalid => Err(format!(
+                "the index method {} is invalid. It must be one of: btree, gist, brin",
+                invalid
+            )),
+        }
+    }
+}
+
+impl IndexMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IndexMethod::BTree => "btree",
+            IndexMethod::Gist => "gist",
+            IndexMethod::Brin => "brin",
+        }
+    }
+}
+
+/// An index that the subgraph declares for an entity type with a directive
+/// like `@index(fields: ["pool", "timestamp"], method: btree, where: {
+/// active: true })`. The `where` argument is optional and makes the index
+/// a partial index that only covers entities where the given fields have
+/// the given values
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexDefinition {
+    pub fields: Vec<String>,
+    pub method: IndexMethod,
+    pub filter: BTreeMap<String, Value>,
+}
+
+impl From<&s::Directive> for IndexDefinition {
+    // Assumes the input is an index directive that has already been
+    // validated because it makes liberal use of unwrap()
+    fn from(directive: &Directive) -> Self {
+        let fields = directive
+            .argument("fields")
+            .unwrap()
+            .as_list()
+            .unwrap()
+            .iter()
+            .map(|field| field.as_str().unwrap().to_string())
+            .collect();
+
+        let method = directive
+            .argument("method")
+            .map(|method| {
+                IndexMethod::try_from(method.as_enum().or_else(|| method.as_str()).unwrap())
+                    .unwrap()
+            })
+            .unwrap_or(IndexMethod::BTree);
+
+        let filter = directive
+            .argument("where")
+            .map(|filter| filter.as_object().unwrap().clone())
+            .unwrap_or_default();
+
+        IndexDefinition {
+            fields,
+            method,
+            filter,
+        }
+    }
+}
+
 #[derive(Debug, Error, PartialEq, Eq, Clone)]
 pub enum SchemaImportError {
     #[error("Schema for imported subgraph `{0}` was not found")]
@@ -644,6 +729,51 @@ impl Schema {
         self.immutable_types.contains(entity_type)
     }
 
+    /// Resolve the `@derivedFrom` field `field_name` of `entity_type` into
+    /// the entity types that hold the references and the name of the
+    /// attribute on those types that holds them. If the field has an
+    /// interface type, all entity types implementing it are returned
+    pub fn derived_field(
+        &self,
+        entity_type: &EntityType,
+        field_name: &str,
+    ) -> Result<(Vec<EntityType>, String), Error> {
+        let field = self
+            .document
+            .get_object_type_definition(entity_type.as_str())
+            .ok_or_else(|| anyhow!("unknown entity type `{}`", entity_type))?
+            .field(field_name)
+            .ok_or_else(|| {
+                anyhow!(
+                    "entity type `{}` has no field `{}`",
+                    entity_type,
+                    field_name
+                )
+            })?;
+
+        let target_field = match field
+            .find_directive("derivedFrom")
+            .and_then(|directive| directive.argument("field"))
+        {
+            Some(Value::String(target_field)) => target_field.clone(),
+            _ => {
+                return Err(anyhow!(
+                    "field `{}` of entity type `{}` is not a derived field",
+                    field_name,
+                    entity_type
+                ))
+            }
+        };
+
+        let base_type = EntityType::new(field.field_type.get_base_type().to_owned());
+        let entity_types = match self.types_for_interface.get(&base_type) {
+            Some(object_types) => object_types.iter().map(EntityType::from).collect(),
+            None => vec![base_type],
+        };
+
+        Ok((entity_types, target_field))
+    }
+
     pub fn resolve_schema_references<S: SubgraphStore>(
         &self,
         store: Arc<S>,
@@ -861,6 +991,7 @@ impl Schema {
 
     pub fn validate(
         &self,
+        spec_version: &Version,
         schemas: &HashMap<SchemaReference, Arc<Schema>>,
     ) -> Result<(), Vec<SchemaValidationError>> {
         let mut errors: Vec<SchemaValidationError> = [
@@ -868,7 +999,7 @@ impl Schema {
             self.validate_derived_from(),
             self.validate_schema_type_has_no_fields(),
             self.validate_directives_on_schema_type(),
-            self.validate_reserved_types_usage(),
+            self.validate_reserved_types_usage(spec_version),
             self.validate_interface_id_type(),
         ]
         .into_iter()
@@ -880,6 +1011,7 @@ impl Schema {
         errors.append(&mut self.validate_fields());
         errors.append(&mut self.validate_import_directives());
         errors.append(&mut self.validate_fulltext_directives());
+        errors.append(&mut self.validate_index_directives());
         errors.append(&mut self.validate_imported_types(schemas));
 
         if errors.is_empty() {
@@ -1180,6 +1312,159 @@ impl Schema {
         return vec![];
     }
 
+    /// Check the `@index` and `@noIndex` directives on object types and the
+    /// `@noIndex` directives on their fields
+    fn validate_index_directives(&self) -> Vec<SchemaValidationError> {
+        let enums: HashMap<&str, Vec<&str>> = self
+            .document
+            .get_enum_definitions()
+            .into_iter()
+            .map(|enum_type| {
+                let values = enum_type
+                    .values
+                    .iter()
+                    .map(|value| value.name.as_str())
+                    .collect();
+                (enum_type.name.as_str(), values)
+            })
+            .collect();
+
+        let mut errors = vec![];
+        for object_type in self.document.get_object_type_definitions() {
+            let is_entity = object_type.find_directive("entity").is_some();
+            let invalid = |reason: String| {
+                SchemaValidationError::InvalidIndex(object_type.name.clone(), reason)
+            };
+
+            for directive in object_type
+                .directives
+                .iter()
+                .filter(|directive| directive.name == "index")
+            {
+                if !is_entity {
+                    errors.push(invalid(
+                        "@index can only be used on entity types".to_string(),
+                    ));
+                } else if let Err(reason) =
+                    Self::validate_index_directive(object_type, directive, &enums)
+                {
+                    errors.push(invalid(reason));
+                }
+            }
+            if !is_entity && object_type.find_directive("noIndex").is_some() {
+                errors.push(invalid(
+                    "@noIndex can only be used on entity types".to_string(),
+                ));
+            }
+
+            for field in &object_type.fields {
+                if field.find_directive("noIndex").is_none() {
+                    continue;
+                }
+                let reason = if !is_entity {
+                    "@noIndex can only be used on fields of entity types"
+                } else if field.name == "id" {
+                    "the id of an entity is always indexed"
+                } else if field.is_derived() {
+                    "derived fields are not stored and therefore never indexed"
+                } else {
+                    continue;
+                };
+                errors.push(SchemaValidationError::InvalidNoIndex(
+                    object_type.name.clone(),
+                    field.name.clone(),
+                    reason.to_string(),
+                ));
+            }
+        }
+        errors
+    }
+
+    fn validate_index_directive(
+        object_type: &ObjectType,
+        directive: &Directive,
+        enums: &HashMap<&str, Vec<&str>>,
+    ) -> Result<(), String> {
+        if let Some((name, _)) = directive
+            .arguments
+            .iter()
+            .find(|(name, _)| !["fields", "method", "where"].contains(&name.as_str()))
+        {
+            return Err(format!("unknown argument `{}`", name));
+        }
+
+        let stored_field = |name: &str| {
+            object_type
+                .field(name)
+                .filter(|field| !field.is_derived())
+                .ok_or_else(|| format!("`{}` is not a stored field of the type", name))
+        };
+
+        let fields = match directive.argument("fields") {
+            Some(Value::List(fields)) if !fields.is_empty() => fields,
+            _ => return Err("`fields` must be a non-empty list of field names".to_string()),
+        };
+        let mut seen = HashSet::new();
+        let mut has_list = false;
+        for field in fields {
+            let name = field
+                .as_str()
+                .ok_or_else(|| "`fields` must be a non-empty list of field names".to_string())?;
+            if !seen.insert(name) {
+                return Err(format!("the field `{}` is listed more than once", name));
+            }
+            has_list |= stored_field(name)?.field_type.is_list();
+        }
+
+        let method = match directive.argument("method") {
+            Some(method) => method
+                .as_enum()
+                .or_else(|| method.as_str())
+                .ok_or_else(|| "`method` must be one of: btree, gist, brin".to_string())
+                .and_then(IndexMethod::try_from)?,
+            None => IndexMethod::BTree,
+        };
+        if has_list && method != IndexMethod::BTree {
+            return Err(format!(
+                "list fields can only be indexed with btree, not with {}",
+                method.as_str()
+            ));
+        }
+
+        if let Some(filter) = directive.argument("where") {
+            let filter = filter
+                .as_object()
+                .ok_or_else(|| "`where` must be an object of field values".to_string())?;
+            for (name, value) in filter {
+                let field = stored_field(name)?;
+                if field.field_type.is_list() {
+                    return Err(format!(
+                        "the list field `{}` can not be used in `where`",
+                        name
+                    ));
+                }
+                let base = field.field_type.get_base_type();
+                let valid = match (base, value) {
+                    (_, Value::Null) => true,
+                    ("Boolean", Value::Boolean(_)) => true,
+                    ("Int" | "Int8" | "BigInt" | "BigDecimal", Value::Int(_)) => true,
+                    ("String" | "ID", Value::String(_)) => true,
+                    (base, Value::Enum(variant) | Value::String(variant)) => enums
+                        .get(base)
+                        .map_or(false, |values| values.contains(&variant.as_str())),
+                    _ => false,
+                };
+                if !valid {
+                    return Err(format!(
+                        "the value for `{}` in `where` is not a valid {}",
+                        name, base
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn validate_import_directives(&self) -> Vec<SchemaValidationError> {
         self.subgraph_schema_object_type()
             .map_or(vec![], |subgraph_schema_type| {
@@ -1278,8 +1563,11 @@ impl Schema {
     }
 
     /// Checks if the schema is using types that are reserved
-    /// by `graph-node`
-    fn validate_reserved_types_usage(&self) -> Result<(), SchemaValidationError> {
+    /// by `graph-node` for the given `spec_version`
+    fn validate_reserved_types_usage(
+        &self,
+        spec_version: &Version,
+    ) -> Result<(), SchemaValidationError> {
         let document = &self.document;
         let object_types: Vec<_> = document
             .get_object_type_definitions()
@@ -1307,6 +1595,22 @@ impl Schema {
             .map(|type_name| format!("{}_orderBy", type_name))
             .collect();
 
+        // TYPE_NAME_aggregate* types for all object types
+        let mut aggregate_types: Vec<_> = object_types
+            .iter()
+            .flat_map(|type_name| {
+                ["", "Sum", "Avg", "MinMax"]
+                    .iter()
+                    .map(move |suffix| format!("{}_aggregate{}", type_name, suffix))
+            })
+            .collect();
+
+        // TYPE_NAME_version types for all object types
+        let mut version_types: Vec<String> = object_types
+            .iter()
+            .map(|type_name| format!("{}_version", type_name))
+            .collect();
+
         let mut reserved_types: Vec<String> = vec![
             // The built-in scalar types
             "Boolean".into(),
@@ -1316,13 +1620,24 @@ impl Schema {
             "String".into(),
             "Bytes".into(),
             "BigInt".into(),
+            // The `Int8` and `Timestamp` scalars are built into every schema,
+            // regardless of its spec version
+            store::INT8_SCALAR.into(),
+            store::TIMESTAMP_SCALAR.into(),
             // Reserved Query and Subscription types
             "Query".into(),
             "Subscription".into(),
         ];
 
+        // Subgraphs with an older spec version might have types of their
+        // own with the names of aggregate types that were added later
+        if spec_version >= &SPEC_VERSION_0_0_8 {
+            reserved_types.append(&mut aggregate_types);
+        }
+
         reserved_types.append(&mut filter_types);
         reserved_types.append(&mut order_by_types);
+        reserved_types.append(&mut version_types);
 
         // `reserved_types` will now only contain
         // the reserved types that the given schema *is* using.
@@ -1550,6 +1865,16 @@ impl Schema {
             .find(|object_type| object_type.name.eq(SCHEMA_TYPE_NAME))
     }
 
+    /// The indexes declared with `@index` directives on `object_type`
+    pub fn entity_index_definitions(object_type: &ObjectType) -> Vec<IndexDefinition> {
+        object_type
+            .directives
+            .iter()
+            .filter(|directive| directive.name == "index")
+            .map(IndexDefinition::from)
+            .collect()
+    }
+
     pub fn entity_fulltext_definitions(
         entity: &str,
         document: &Document,
@@ -1626,7 +1951,7 @@ fn interface_implementations_id_type() {
             }}"
         );
         let schema = Schema::parse(&schema, DeploymentHash::new("dummy").unwrap()).unwrap();
-        let res = schema.validate(&HashMap::new());
+        let res = schema.validate(&SPEC_VERSION_0_0_8, &HashMap::new());
         if ok {
             assert!(matches!(res, Ok(_)));
         } else {
@@ -1862,6 +2187,8 @@ fn test_reserved_types_validation() {
         "String",
         "Bytes",
         "BigInt",
+        "Int8",
+        "Timestamp",
         // Reserved keywords
         "Query",
         "Subscription",
@@ -1874,7 +2201,9 @@ fn test_reserved_types_validation() {
 
         let schema = Schema::parse(&schema, dummy_hash.clone()).unwrap();
 
-        let errors = schema.validate(&HashMap::new()).unwrap_err();
+        let errors = schema
+            .validate(&SPEC_VERSION_0_0_8, &HashMap::new())
+            .unwrap_err();
         for error in errors {
             assert!(matches!(
                 error,
@@ -1884,6 +2213,59 @@ fn test_reserved_types_validation() {
     }
 }
 
+#[test]
+fn test_reserved_scalars_for_every_spec_version() {
+    use crate::data::subgraph::SPEC_VERSION_0_0_7;
+
+    let dummy_hash = DeploymentHash::new("dummy").unwrap();
+
+    for reserved_type in ["Int8", "Timestamp"] {
+        let schema = format!("type {} @entity {{ id: ID! }}\n", reserved_type);
+        let schema = Schema::parse(&schema, dummy_hash.clone()).unwrap();
+
+        for spec_version in [&SPEC_VERSION_0_0_7, &SPEC_VERSION_0_0_8] {
+            assert!(matches!(
+                schema
+                    .validate(spec_version, &HashMap::new())
+                    .unwrap_err()
+                    .as_slice(),
+                [SchemaValidationError::UsageOfReservedTypes(_)]
+            ));
+        }
+    }
+}
+
+#[test]
+fn test_reserved_aggregate_types_depend_on_spec_version() {
+    use crate::data::subgraph::SPEC_VERSION_0_0_7;
+
+    const SCHEMA: &str = r#"
+    type Gravatar @entity {
+        id: ID!
+    }
+    type Gravatar_aggregate @entity {
+        id: ID!
+    }
+    "#;
+
+    let dummy_hash = DeploymentHash::new("dummy").unwrap();
+    let schema = Schema::parse(SCHEMA, dummy_hash).unwrap();
+
+    assert!(schema
+        .validate(&SPEC_VERSION_0_0_7, &HashMap::new())
+        .is_ok());
+    match schema
+        .validate(&SPEC_VERSION_0_0_8, &HashMap::new())
+        .unwrap_err()
+        .as_slice()
+    {
+        [SchemaValidationError::UsageOfReservedTypes(Strings(reserved_types))] => {
+            assert_eq!(reserved_types, &vec!["Gravatar_aggregate".to_string()]);
+        }
+        errors => panic!("unexpected validation errors {:?}", errors),
+    }
+}
+
 #[test]
 fn test_reserved_filter_and_group_by_types_validation() {
     const SCHEMA: &str = r#"
@@ -1902,7 +2284,9 @@ fn test_reserved_filter_and_group_by_types_validation() {
 
     let schema = Schema::parse(SCHEMA, dummy_hash).unwrap();
 
-    let errors = schema.validate(&HashMap::new()).unwrap_err();
+    let errors = schema
+        .validate(&SPEC_VERSION_0_0_8, &HashMap::new())
+        .unwrap_err();
 
     // The only problem in the schema is the usage of reserved types
     assert_eq!(errors.len(), 1);
@@ -1952,3 +2336,100 @@ type Gravatar @entity {
 
     assert_eq!(schema.validate_fulltext_directives(), vec![]);
 }
+
+#[test]
+fn test_index_directive_validation() {
+    fn validate(extra: &str) -> Vec<SchemaValidationError> {
+        let schema = format!(
+            r#"
+enum Status {{ OPEN, CLOSED }}
+type Pool @entity {{
+  id: ID!
+  swaps: [Swap!]! @derivedFrom(field: "pool")
+}}
+type Swap @entity {extra} {{
+  id: ID!
+  pool: Pool!
+  timestamp: BigInt!
+  active: Boolean!
+  status: Status
+  tags: [String!]!
+  note: String @noIndex
+}}"#
+        );
+        let document = graphql_parser::parse_schema(&schema).expect("Failed to parse schema");
+        let schema = Schema::new(DeploymentHash::new("id1").unwrap(), document).unwrap();
+        schema.validate_index_directives()
+    }
+
+    fn invalid(reason: &str) -> Vec<SchemaValidationError> {
+        vec![SchemaValidationError::InvalidIndex(
+            "Swap".to_string(),
+            reason.to_string(),
+        )]
+    }
+
+    assert_eq!(validate(""), vec![]);
+    assert_eq!(validate("@noIndex"), vec![]);
+    assert_eq!(validate(r#"@index(fields: ["pool", "timestamp"])"#), vec![]);
+    assert_eq!(
+        validate(r#"@index(fields: ["timestamp"], method: brin) @index(fields: ["tags"])"#),
+        vec![]
+    );
+    assert_eq!(
+        validate(r#"@index(fields: ["pool"], where: { active: true, status: OPEN, note: null })"#),
+        vec![]
+    );
+
+    assert_eq!(
+        validate("@index"),
+        invalid("`fields` must be a non-empty list of field names")
+    );
+    assert_eq!(
+        validate(r#"@index(fields: ["pool"], unique: true)"#),
+        invalid("unknown argument `unique`")
+    );
+    assert_eq!(
+        validate(r#"@index(fields: ["owner"])"#),
+        invalid("`owner` is not a stored field of the type")
+    );
+    assert_eq!(
+        validate(r#"@index(fields: ["pool", "pool"])"#),
+        invalid("the field `pool` is listed more than once")
+    );
+    assert_eq!(
+        validate(r#"@index(fields: ["pool"], method: hash)"#),
+        invalid("the index method hash is invalid. It must be one of: btree, gist, brin")
+    );
+    assert_eq!(
+        validate(r#"@index(fields: ["tags"], method: gist)"#),
+        invalid("list fields can only be indexed with btree, not with gist")
+    );
+    assert_eq!(
+        validate(r#"@index(fields: ["pool"], where: { active: 1 })"#),
+        invalid("the value for `active` in `where` is not a valid Boolean")
+    );
+    assert_eq!(
+        validate(r#"@index(fields: ["pool"], where: { status: PENDING })"#),
+        invalid("the value for `status` in `where` is not a valid Status")
+    );
+    assert_eq!(
+        validate(r#"@index(fields: ["pool"], where: { tags: "a" })"#),
+        invalid("the list field `tags` can not be used in `where`")
+    );
+
+    const NO_INDEX_ON_ID: &str = r#"
+type Thing @entity {
+  id: ID! @noIndex
+}"#;
+    let document = graphql_parser::parse_schema(NO_INDEX_ON_ID).expect("Failed to parse schema");
+    let schema = Schema::new(DeploymentHash::new("id1").unwrap(), document).unwrap();
+    assert_eq!(
+        schema.validate_index_directives(),
+        vec![SchemaValidationError::InvalidNoIndex(
+            "Thing".to_string(),
+            "id".to_string(),
+            "the id of an entity is always indexed".to_string()
+        )]
+    );
+}
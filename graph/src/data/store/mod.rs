@@ -133,6 +133,8 @@ pub const ID: &str = "ID";
 pub const BYTES_SCALAR: &str = "Bytes";
 pub const BIG_INT_SCALAR: &str = "BigInt";
 pub const BIG_DECIMAL_SCALAR: &str = "BigDecimal";
+pub const INT8_SCALAR: &str = "Int8";
+pub const TIMESTAMP_SCALAR: &str = "Timestamp";
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ValueType {
@@ -141,7 +143,9 @@ pub enum ValueType {
     Bytes,
     BigDecimal,
     Int,
+    Int8,
     String,
+    Timestamp,
 }
 
 impl FromStr for ValueType {
@@ -154,7 +158,9 @@ impl FromStr for ValueType {
             "Bytes" => Ok(ValueType::Bytes),
             "BigDecimal" => Ok(ValueType::BigDecimal),
             "Int" => Ok(ValueType::Int),
+            "Int8" => Ok(ValueType::Int8),
             "String" | "ID" => Ok(ValueType::String),
+            "Timestamp" => Ok(ValueType::Timestamp),
             s => Err(anyhow!("Type not available in this context: {}", s)),
         }
     }
@@ -165,6 +171,20 @@ impl ValueType {
     pub fn is_scalar(s: &str) -> bool {
         Self::from_str(s).is_ok()
     }
+
+    /// The name of the GraphQL scalar type for this value type
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            ValueType::Boolean => "Boolean",
+            ValueType::BigInt => "BigInt",
+            ValueType::Bytes => "Bytes",
+            ValueType::BigDecimal => "BigDecimal",
+            ValueType::Int => "Int",
+            ValueType::Int8 => "Int8",
+            ValueType::String => "String",
+            ValueType::Timestamp => "Timestamp",
+        }
+    }
 }
 
 // Note: Do not modify fields without also making a backward compatible change to the StableHash impl (below)
@@ -181,6 +201,8 @@ pub enum Value {
     Null,
     Bytes(scalar::Bytes),
     BigInt(scalar::BigInt),
+    Int8(i64),
+    Timestamp(scalar::Timestamp),
 }
 
 impl stable_hash_legacy::StableHash for Value {
@@ -225,6 +247,12 @@ impl stable_hash_legacy::StableHash for Value {
             BigInt(inner) => {
                 stable_hash_legacy::StableHash::stable_hash(inner, sequence_number, state)
             }
+            Int8(inner) => {
+                stable_hash_legacy::StableHash::stable_hash(inner, sequence_number, state)
+            }
+            Timestamp(inner) => {
+                stable_hash_legacy::StableHash::stable_hash(inner, sequence_number, state)
+            }
         }
     }
 }
@@ -268,6 +296,14 @@ impl StableHash for Value {
                 inner.stable_hash(field_address.child(0), state);
                 7
             }
+            Int8(inner) => {
+                inner.stable_hash(field_address.child(0), state);
+                8
+            }
+            Timestamp(inner) => {
+                inner.stable_hash(field_address.child(0), state);
+                9
+            }
         };
 
         state.write(field_address, &[variant])
@@ -303,9 +339,24 @@ impl Value {
                     BYTES_SCALAR => Value::Bytes(scalar::Bytes::from_str(s)?),
                     BIG_INT_SCALAR => Value::BigInt(scalar::BigInt::from_str(s)?),
                     BIG_DECIMAL_SCALAR => Value::BigDecimal(scalar::BigDecimal::from_str(s)?),
+                    INT8_SCALAR => {
+                        Value::Int8(s.parse::<i64>().map_err(|_| {
+                            QueryExecutionError::ValueParseError(n.clone(), s.clone())
+                        })?)
+                    }
+                    TIMESTAMP_SCALAR => {
+                        Value::Timestamp(scalar::Timestamp::from_str(s).map_err(|e| {
+                            QueryExecutionError::ValueParseError(n.clone(), e.to_string())
+                        })?)
+                    }
                     _ => Value::String(s.clone()),
                 }
             }
+            (r::Value::Int(i), NamedType(n)) if n == INT8_SCALAR => Value::Int8(*i),
+            (r::Value::Int(i), NamedType(n)) if n == TIMESTAMP_SCALAR => Value::Timestamp(
+                scalar::Timestamp::from_microseconds_since_epoch(*i)
+                    .map_err(|e| QueryExecutionError::ValueParseError(n.clone(), e.to_string()))?,
+            ),
             (r::Value::Int(i), _) => Value::Int(*i as i32),
             (r::Value::Boolean(b), _) => Value::Bool(b.to_owned()),
             (r::Value::Null, _) => Value::Null,
@@ -346,6 +397,22 @@ impl Value {
         }
     }
 
+    pub fn as_int8(&self) -> Option<i64> {
+        if let Value::Int8(i) = self {
+            Some(*i)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_timestamp(&self) -> Option<scalar::Timestamp> {
+        if let Value::Timestamp(ts) = self {
+            Some(*ts)
+        } else {
+            None
+        }
+    }
+
     pub fn as_big_decimal(self) -> Option<scalar::BigDecimal> {
         if let Value::BigDecimal(d) = self {
             Some(d)
@@ -394,6 +461,8 @@ impl Value {
             Value::Bool(_) => "Boolean".to_owned(),
             Value::Bytes(_) => "Bytes".to_owned(),
             Value::Int(_) => "Int".to_owned(),
+            Value::Int8(_) => "Int8".to_owned(),
+            Value::Timestamp(_) => "Timestamp".to_owned(),
             Value::List(values) => {
                 if let Some(v) = values.first() {
                     format!("[{}]", v.type_name())
@@ -414,6 +483,8 @@ impl Value {
             | (Value::Bool(_), ValueType::Boolean)
             | (Value::Bytes(_), ValueType::Bytes)
             | (Value::Int(_), ValueType::Int)
+            | (Value::Int8(_), ValueType::Int8)
+            | (Value::Timestamp(_), ValueType::Timestamp)
             | (Value::Null, _) => true,
             (Value::List(values), _) if is_list => values
                 .iter()
@@ -438,6 +509,8 @@ impl fmt::Display for Value {
                     format!("[{}]", values.iter().map(ToString::to_string).join(", ")),
                 Value::Bytes(ref bytes) => bytes.to_string(),
                 Value::BigInt(ref number) => number.to_string(),
+                Value::Int8(i) => i.to_string(),
+                Value::Timestamp(ts) => ts.to_string(),
             }
         )
     }
@@ -454,6 +527,8 @@ impl fmt::Debug for Value {
             Self::Null => write!(f, "Null"),
             Self::Bytes(bytes) => bytes.fmt(f),
             Self::BigInt(number) => number.fmt(f),
+            Self::Int8(i) => f.debug_tuple("Int8").field(i).finish(),
+            Self::Timestamp(ts) => f.debug_tuple("Timestamp").field(ts).finish(),
         }
     }
 }
@@ -471,6 +546,8 @@ impl From<Value> for q::Value {
             }
             Value::Bytes(bytes) => q::Value::String(bytes.to_string()),
             Value::BigInt(number) => q::Value::String(number.to_string()),
+            Value::Int8(i) => q::Value::String(i.to_string()),
+            Value::Timestamp(ts) => q::Value::String(ts.to_string()),
         }
     }
 }
@@ -488,6 +565,8 @@ impl From<Value> for r::Value {
             }
             Value::Bytes(bytes) => r::Value::String(bytes.to_string()),
             Value::BigInt(number) => r::Value::String(number.to_string()),
+            Value::Int8(i) => r::Value::String(i.to_string()),
+            Value::Timestamp(ts) => r::Value::String(ts.to_string()),
         }
     }
 }
@@ -528,6 +607,18 @@ impl From<i32> for Value {
     }
 }
 
+impl From<i64> for Value {
+    fn from(value: i64) -> Value {
+        Value::Int8(value)
+    }
+}
+
+impl From<scalar::Timestamp> for Value {
+    fn from(value: scalar::Timestamp) -> Value {
+        Value::Timestamp(value)
+    }
+}
+
 impl From<scalar::BigDecimal> for Value {
     fn from(value: scalar::BigDecimal) -> Value {
         Value::BigDecimal(value)
@@ -1020,3 +1111,29 @@ fn fmt_debug() {
     let bi = Value::BigInt(scalar::BigInt::from(-17i32));
     assert_eq!("BigInt(-17)", format!("{:?}", bi));
 }
+
+#[test]
+fn value_int8() {
+    let graphql_value = r::Value::String("9223372036854775807".to_owned());
+    let ty = q::Type::NamedType(INT8_SCALAR.to_owned());
+    let from_query = Value::from_query_value(&graphql_value, &ty).unwrap();
+    assert_eq!(from_query, Value::Int8(i64::MAX));
+    assert_eq!(r::Value::from(from_query), graphql_value);
+
+    let from_query = Value::from_query_value(&r::Value::Int(-17), &ty).unwrap();
+    assert_eq!(from_query, Value::Int8(-17));
+}
+
+#[test]
+fn value_timestamp() {
+    let graphql_value = r::Value::String("1690000000123456".to_owned());
+    let ty = q::Type::NamedType(TIMESTAMP_SCALAR.to_owned());
+    let from_query = Value::from_query_value(&graphql_value, &ty).unwrap();
+    assert_eq!(
+        from_query,
+        Value::Timestamp(
+            scalar::Timestamp::from_microseconds_since_epoch(1_690_000_000_123_456).unwrap()
+        )
+    );
+    assert_eq!(r::Value::from(from_query), graphql_value);
+}
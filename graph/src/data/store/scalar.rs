@@ -1,3 +1,4 @@
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
 use diesel::deserialize::FromSql;
 use diesel::serialize::ToSql;
 use diesel_derives::{AsExpression, FromSqlRow};
@@ -624,9 +625,99 @@ impl From<Vec<u8>> for Bytes {
     }
 }
 
+/// A point in time with microsecond precision. Timestamps are exchanged
+/// with mappings and GraphQL clients as the number of microseconds since
+/// the Unix epoch
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(DateTime<Utc>);
+
+#[derive(Error, Debug)]
+pub enum TimestampError {
+    #[error("Invalid timestamp string: {0}")]
+    StringParseError(String),
+    #[error("Timestamp {0} is out of range")]
+    OutOfRange(i64),
+}
+
+impl Timestamp {
+    pub fn from_microseconds_since_epoch(micros: i64) -> Result<Self, TimestampError> {
+        let secs = micros.div_euclid(1_000_000);
+        let nanos = (micros.rem_euclid(1_000_000) * 1000) as u32;
+        NaiveDateTime::from_timestamp_opt(secs, nanos)
+            .map(|naive| Timestamp(DateTime::from_utc(naive, Utc)))
+            .ok_or(TimestampError::OutOfRange(micros))
+    }
+
+    pub fn as_microseconds_since_epoch(&self) -> i64 {
+        self.0.timestamp_micros()
+    }
+
+    /// The timestamp in the RFC 3339 format that Postgres understands
+    pub fn to_rfc3339(&self) -> String {
+        self.0.to_rfc3339_opts(SecondsFormat::Micros, true)
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.as_microseconds_since_epoch())
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = TimestampError;
+
+    /// Parse either a number of microseconds since the epoch or a timestamp
+    /// in RFC 3339 format, which is what Postgres produces
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<i64>() {
+            Ok(micros) => Timestamp::from_microseconds_since_epoch(micros),
+            Err(_) => DateTime::parse_from_rfc3339(s)
+                .map(|ts| Timestamp(ts.with_timezone(&Utc)))
+                .map_err(|_| TimestampError::StringParseError(s.to_owned())),
+        }
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let s = <String>::deserialize(deserializer)?;
+        Timestamp::from_str(&s).map_err(D::Error::custom)
+    }
+}
+
+impl stable_hash_legacy::StableHash for Timestamp {
+    fn stable_hash<H: stable_hash_legacy::StableHasher>(
+        &self,
+        sequence_number: H::Seq,
+        state: &mut H,
+    ) {
+        stable_hash_legacy::StableHash::stable_hash(
+            &self.as_microseconds_since_epoch(),
+            sequence_number,
+            state,
+        )
+    }
+}
+
+impl StableHash for Timestamp {
+    fn stable_hash<H: stable_hash::StableHasher>(&self, field_address: H::Addr, state: &mut H) {
+        self.as_microseconds_since_epoch()
+            .stable_hash(field_address, state)
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{BigDecimal, BigInt, Bytes};
+    use super::{BigDecimal, BigInt, Bytes, Timestamp};
     use stable_hash_legacy::crypto::SetHasher;
     use stable_hash_legacy::prelude::*;
     use stable_hash_legacy::utils::stable_hash;
@@ -736,4 +827,20 @@ mod test {
         assert_eq!("BigDecimal(-0.17)", format!("{:?}", bd));
         assert_eq!("Bytes(0xdeadbeef)", format!("{:?}", bytes));
     }
+
+    #[test]
+    fn timestamp_to_from_string() {
+        for micros in [0, 1, -1, 1_690_000_000_123_456, -1_000_001] {
+            let ts = Timestamp::from_microseconds_since_epoch(micros).unwrap();
+            assert_eq!(micros, ts.as_microseconds_since_epoch());
+            assert_eq!(ts, Timestamp::from_str(&ts.to_string()).unwrap());
+            assert_eq!(ts, Timestamp::from_str(&ts.to_rfc3339()).unwrap());
+        }
+
+        // The format in which Postgres returns `timestamptz` values as JSON
+        let ts = Timestamp::from_str("2023-07-22T04:26:40.123456+00:00").unwrap();
+        assert_eq!(1_690_000_000_123_456, ts.as_microseconds_since_epoch());
+
+        assert!(Timestamp::from_str("yesterday").is_err());
+    }
 }
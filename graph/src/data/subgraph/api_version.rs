@@ -32,6 +32,10 @@ pub const SPEC_VERSION_0_0_6: Version = Version::new(0, 0, 6);
 /// Enables offchain data sources.
 pub const SPEC_VERSION_0_0_7: Version = Version::new(0, 0, 7);
 
+/// Reserves the names of the `<Type>_aggregate` types, which can't be used
+/// as type names in the schema from this version on.
+pub const SPEC_VERSION_0_0_8: Version = Version::new(0, 0, 8);
+
 pub const MIN_SPEC_VERSION: Version = Version::new(0, 0, 2);
 
 #[derive(Clone, PartialEq, Debug)]
@@ -28,7 +28,7 @@ use crate::{
     blockchain::{BlockPtr, Blockchain, DataSource as _},
     components::{
         link_resolver::LinkResolver,
-        store::{DeploymentLocator, StoreError, SubgraphStore},
+        store::{DeploymentLocator, StoreError, SubgraphStore, BLOCK_NUMBER_MAX},
     },
     data::{
         graphql::TryFromValue,
@@ -407,6 +407,8 @@ pub struct Source {
     pub abi: String,
     #[serde(rename = "startBlock", default)]
     pub start_block: BlockNumber,
+    #[serde(rename = "endBlock", default)]
+    pub end_block: Option<BlockNumber>,
 }
 
 pub fn calls_host_fn(runtime: &[u8], host_fn: &str) -> anyhow::Result<bool> {
@@ -478,6 +480,71 @@ impl Graft {
     }
 }
 
+/// Hints from the subgraph author to indexers about how to index a
+/// subgraph
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexerHints {
+    /// How much history of the subgraph's data to keep
+    pub prune: Option<Prune>,
+}
+
+impl IndexerHints {
+    /// The number of blocks of history that the subgraph should keep.
+    /// Subgraphs that do not ask for pruning keep all their history
+    pub fn history_blocks(&self) -> BlockNumber {
+        match self.prune {
+            None | Some(Prune::Never) => BLOCK_NUMBER_MAX,
+            Some(Prune::Auto) => ENV_VARS.min_history_blocks,
+            Some(Prune::Blocks(blocks)) => blocks.max(ENV_VARS.min_history_blocks),
+        }
+    }
+}
+
+/// How much history a subgraph keeps, written as `auto`, `never` or a
+/// number of blocks in the manifest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Prune {
+    /// Keep only the history that is needed to handle reorgs
+    Auto,
+    /// Keep all history
+    Never,
+    /// Keep the history for this many blocks
+    Blocks(BlockNumber),
+}
+
+impl<'de> de::Deserialize<'de> for Prune {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Blocks(BlockNumber),
+            Keyword(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Blocks(blocks) if blocks > 0 => Ok(Prune::Blocks(blocks)),
+            Raw::Blocks(blocks) => Err(D::Error::custom(format!(
+                "prune must keep a positive number of blocks, but is {}",
+                blocks
+            ))),
+            Raw::Keyword(keyword) => match keyword.as_str() {
+                "auto" => Ok(Prune::Auto),
+                "never" => Ok(Prune::Never),
+                _ => Err(D::Error::custom(format!(
+                    "prune must be `auto`, `never` or a number of blocks, but is `{}`",
+                    keyword
+                ))),
+            },
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BaseSubgraphManifest<C, S, D, T> {
@@ -492,6 +559,8 @@ pub struct BaseSubgraphManifest<C, S, D, T> {
     pub graft: Option<Graft>,
     #[serde(default)]
     pub templates: Vec<T>,
+    #[serde(default)]
+    pub indexer_hints: IndexerHints,
     #[serde(skip_serializing, default)]
     pub chain: PhantomData<C>,
 }
@@ -571,7 +640,7 @@ impl<C: Blockchain> UnvalidatedSubgraphManifest<C> {
 
         self.0
             .schema
-            .validate(&schemas)
+            .validate(&self.0.spec_version, &schemas)
             .err()
             .into_iter()
             .for_each(|schema_errors| {
@@ -644,6 +713,29 @@ impl<C: Blockchain> SubgraphManifest<C> {
             .collect()
     }
 
+    pub fn end_blocks(&self) -> BTreeSet<BlockNumber> {
+        self.data_sources
+            .iter()
+            .filter_map(|d| d.as_onchain()?.end_block())
+            .collect()
+    }
+
+    /// The block after which the subgraph has nothing left to index. This is only
+    /// known if every data source has an end block and no data sources can be
+    /// created from templates.
+    pub fn max_end_block(&self) -> Option<BlockNumber> {
+        if !self.templates.is_empty() {
+            return None;
+        }
+
+        let end_blocks = self
+            .data_sources
+            .iter()
+            .map(|d| d.end_block())
+            .collect::<Option<Vec<_>>>()?;
+        end_blocks.into_iter().max()
+    }
+
     pub fn api_versions(&self) -> impl Iterator<Item = semver::Version> + '_ {
         self.templates
             .iter()
@@ -708,6 +800,7 @@ impl<C: Blockchain> UnresolvedSubgraphManifest<C> {
             data_sources,
             graft,
             templates,
+            indexer_hints,
             chain,
         } = self;
 
@@ -779,6 +872,7 @@ impl<C: Blockchain> UnresolvedSubgraphManifest<C> {
             data_sources,
             graft,
             templates,
+            indexer_hints,
             chain,
         })
     }
@@ -812,19 +906,22 @@ impl DeploymentState {
         self.latest_block.number > 0
     }
 
-    pub fn block_queryable(&self, block: BlockNumber) -> Result<(), String> {
+    pub fn block_queryable(&self, block: BlockNumber) -> Result<(), QueryExecutionError> {
         if block > self.latest_block.number {
-            return Err(format!(
-                "subgraph {} has only indexed up to block number {} \
+            return Err(QueryExecutionError::ValueParseError(
+                "block.number".to_owned(),
+                format!(
+                    "subgraph {} has only indexed up to block number {} \
                         and data for block number {} is therefore not yet available",
-                self.id, self.latest_block.number, block
+                    self.id, self.latest_block.number, block
+                ),
             ));
         }
         if block < self.earliest_block_number {
-            return Err(format!(
-                "subgraph {} only has data starting at block number {} \
-                            and data for block number {} is therefore not available",
-                self.id, self.earliest_block_number, block
+            return Err(QueryExecutionError::BlockBeforeEarliest(
+                self.id.to_string(),
+                self.earliest_block_number,
+                block,
             ));
         }
         Ok(())
@@ -870,6 +967,32 @@ fn test_subgraph_name_validation() {
     assert!(SubgraphName::new("this-component-is-very-long-but-we-dont-care").is_ok());
 }
 
+#[test]
+fn test_block_queryable() {
+    let state = DeploymentState {
+        id: DeploymentHash::new("QmTest").unwrap(),
+        reorg_count: 0,
+        max_reorg_depth: 0,
+        latest_block: BlockPtr::from((web3::types::H256::zero(), 10u64)),
+        earliest_block_number: 5,
+    };
+
+    assert!(state.block_queryable(5).is_ok());
+    assert!(state.block_queryable(10).is_ok());
+    assert!(matches!(
+        state.block_queryable(11),
+        Err(QueryExecutionError::ValueParseError(_, _))
+    ));
+    match state.block_queryable(4) {
+        Err(e @ QueryExecutionError::BlockBeforeEarliest(_, 5, 4)) => assert_eq!(
+            "subgraph QmTest only has data starting at block number 5 \
+             and data for block number 4 is therefore not available",
+            e.to_string()
+        ),
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
 #[test]
 fn test_display_vector() {
     let manifest_validation_error = SubgraphRegistrarError::ManifestValidationError(vec![
@@ -165,6 +165,9 @@ pub struct SubgraphManifestEntity {
     pub features: Vec<String>,
     pub schema: String,
     pub raw_yaml: Option<String>,
+    /// The number of blocks of history the deployment keeps when it is
+    /// pruned automatically
+    pub history_blocks: BlockNumber,
 }
 
 impl SubgraphManifestEntity {
@@ -176,6 +179,7 @@ impl SubgraphManifestEntity {
             features: manifest.features.iter().map(|f| f.to_string()).collect(),
             schema: manifest.schema.document.clone().to_string(),
             raw_yaml: Some(raw_yaml),
+            history_blocks: manifest.indexer_hints.history_blocks(),
         }
     }
 
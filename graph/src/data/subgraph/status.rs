@@ -109,6 +109,14 @@ pub struct Info {
 
     pub entity_count: u64,
 
+    /// The number of blocks of history that the deployment keeps; queries
+    /// for blocks before that are not possible once the deployment is pruned
+    pub history_blocks: BlockNumber,
+
+    /// The earliest block that queries can use. It moves forward as the
+    /// deployment is pruned
+    pub earliest_block_number: BlockNumber,
+
     /// ID of the Graph Node that the subgraph is indexed by.
     pub node: Option<String>,
 }
@@ -120,6 +128,8 @@ impl IntoValue for Info {
             subgraph,
             chains,
             entity_count,
+            history_blocks,
+            earliest_block_number,
             fatal_error,
             health,
             node,
@@ -165,6 +175,8 @@ impl IntoValue for Info {
             nonFatalErrors: non_fatal_errors,
             chains: chains.into_iter().map(|chain| chain.into_value()).collect::<Vec<_>>(),
             entityCount: format!("{}", entity_count),
+            historyBlocks: history_blocks,
+            earliestQueryableBlock: earliest_block_number,
             node: node,
         }
     }
@@ -267,6 +267,13 @@ impl Value {
             ("Bytes", Value::String(s)) => Ok(Value::String(s)),
             ("BigInt", Value::String(s)) => Ok(Value::String(s)),
             ("BigInt", Value::Int(n)) => Ok(Value::String(n.to_string())),
+            ("Int8", Value::Int(n)) => Ok(Value::Int(n)),
+            ("Int8", Value::String(s)) => match s.parse::<i64>() {
+                Ok(n) => Ok(Value::Int(n)),
+                Err(_) => Err(Value::String(s)),
+            },
+            ("Timestamp", Value::String(s)) => Ok(Value::String(s)),
+            ("Timestamp", Value::Int(n)) => Ok(Value::Int(n)),
             ("JSONObject", Value::Object(obj)) => Ok(Value::Object(obj)),
             ("Date", Value::String(obj)) => Ok(Value::String(obj)),
             (_, v) => Err(v),
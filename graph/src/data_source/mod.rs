@@ -2,8 +2,8 @@ pub mod offchain;
 
 use crate::{
     blockchain::{
-        BlockPtr, Blockchain, DataSource as _, DataSourceTemplate as _, TriggerData as _,
-        UnresolvedDataSource as _, UnresolvedDataSourceTemplate as _,
+        Block as _, BlockPtr, Blockchain, DataSource as _, DataSourceTemplate as _,
+        TriggerData as _, UnresolvedDataSource as _, UnresolvedDataSourceTemplate as _,
     },
     components::{
         link_resolver::LinkResolver,
@@ -13,7 +13,7 @@ use crate::{
     data_source::offchain::OFFCHAIN_KINDS,
     prelude::{CheapClone as _, DataSourceContext},
 };
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use semver::Version;
 use serde::{de::IntoDeserializer as _, Deserialize, Deserializer};
 use slog::{Logger, SendSyncRefUnwindSafeKV};
@@ -88,6 +88,13 @@ impl<C: Blockchain> DataSource<C> {
         }
     }
 
+    pub fn end_block(&self) -> Option<BlockNumber> {
+        match self {
+            Self::Onchain(ds) => ds.end_block(),
+            Self::Offchain(_) => None,
+        }
+    }
+
     pub fn creation_block(&self) -> Option<BlockNumber> {
         match self {
             Self::Onchain(ds) => ds.creation_block(),
@@ -123,6 +130,9 @@ impl<C: Blockchain> DataSource<C> {
         logger: &Logger,
     ) -> Result<Option<TriggerWithHandler<MappingTrigger<C>>>, Error> {
         match (self, trigger) {
+            (Self::Onchain(ds), TriggerData::Onchain(_)) if ds.has_expired(block.number()) => {
+                Ok(None)
+            }
             (Self::Onchain(ds), TriggerData::Onchain(trigger)) => ds
                 .match_and_decode(trigger, block, logger)
                 .map(|t| t.map(|t| t.map(MappingTrigger::Onchain))),
@@ -170,7 +180,19 @@ impl<C: Blockchain> DataSource<C> {
 
     pub fn validate(&self) -> Vec<Error> {
         match self {
-            Self::Onchain(ds) => ds.validate(),
+            Self::Onchain(ds) => {
+                let mut errors = ds.validate();
+                if let Some(end_block) = ds.end_block() {
+                    if end_block < ds.start_block() {
+                        errors.push(anyhow!(
+                            "`endBlock` ({}) is lower than `startBlock` ({})",
+                            end_block,
+                            ds.start_block()
+                        ));
+                    }
+                }
+                errors
+            }
             Self::Offchain(_) => vec![],
         }
     }
@@ -54,6 +54,15 @@ pub struct EnvVarsGraphQl {
     /// Set by the environment variable `GRAPH_QUERY_CACHE_STALE_PERIOD`. The
     /// default value is 100.
     pub query_cache_stale_period: u64,
+    /// Whether to keep the results of queries against recent blocks in the
+    /// primary database, where all query nodes can use them, rather than in
+    /// the memory of each query node. The limits set by
+    /// `GRAPH_QUERY_CACHE_BLOCKS` and `GRAPH_QUERY_CACHE_MAX_MEM` apply to
+    /// the shared cache, too, except that it is not sharded.
+    ///
+    /// Set by the environment variable `GRAPH_QUERY_CACHE_SHARED`. The
+    /// default value is `false`.
+    pub query_cache_shared: bool,
     /// Set by the environment variable `GRAPH_GRAPHQL_QUERY_TIMEOUT` (expressed in
     /// seconds). No default value is provided.
     pub query_timeout: Option<Duration>,
@@ -69,6 +78,18 @@ pub struct EnvVarsGraphQl {
     /// Set by the environment variable `GRAPH_GRAPHQL_MAX_SKIP`. The default
     /// value is 4294967295 ([`u32::MAX`]).
     pub max_skip: u32,
+    /// Turns off the `<types>Aggregate` query fields; queries that use them
+    /// fail with an error.
+    ///
+    /// Set by the flag `GRAPH_GRAPHQL_DISABLE_AGGREGATES`. Off by default.
+    pub disable_aggregates: bool,
+    /// The maximum number of entities that one of the `<types>Aggregate`
+    /// query fields can count and aggregate over. Queries whose `where`
+    /// filter matches more entities fail with an error.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_GRAPHQL_MAX_AGGREGATE_ENTITIES`. The default value is 1000000.
+    pub max_aggregate_entities: usize,
     /// Allow skipping the check whether a deployment has changed while
     /// we were running a query. Once we are sure that the check mechanism
     /// is reliable, this variable should be removed.
@@ -84,6 +105,44 @@ pub struct EnvVarsGraphQl {
     /// Set by the flag `GRAPH_GRAPHQL_MAX_OPERATIONS_PER_CONNECTION`.
     /// Defaults to 1000.
     pub max_operations_per_connection: usize,
+    /// How long clients that use the `graphql-transport-ws` protocol have
+    /// to send a `connection_init` message after opening a WebSocket
+    /// connection before the connection is closed.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_GRAPHQL_WS_CONNECTION_INIT_TIMEOUT` (expressed in seconds). The
+    /// default value is 3 seconds.
+    pub ws_connection_init_timeout: Duration,
+    /// How often to send a `ping` to clients that use the
+    /// `graphql-transport-ws` protocol to keep their connection alive.
+    ///
+    /// Set by the environment variable `GRAPH_GRAPHQL_WS_KEEPALIVE_INTERVAL`
+    /// (expressed in seconds). The default value is 12 seconds.
+    pub ws_keepalive_interval: Duration,
+    /// Maximum total memory to be used by the cache of automatic persisted
+    /// queries in the HTTP server. Set to 0 to stop the server from
+    /// remembering queries that clients send along with their hash.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_GRAPHQL_PERSISTED_QUERY_CACHE_MAX_MEM` (expressed in MB). The
+    /// default value is 10MB.
+    pub persisted_query_cache_max_mem: usize,
+    /// Path to a JSON file that maps the sha256 hashes of queries to their
+    /// text. When it is set, the HTTP server only runs the queries in that
+    /// file.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_GRAPHQL_PERSISTED_QUERY_ALLOW_LIST`. No default value is
+    /// provided.
+    pub persisted_query_allow_list: Option<String>,
+    /// The name of an HTTP header that identifies the client sending a
+    /// query for per-client rate limiting. When the header is not set, or
+    /// a request does not have it, clients are identified by their IP
+    /// address.
+    ///
+    /// Set by the environment variable `GRAPH_GRAPHQL_CLIENT_ID_HEADER`. No
+    /// default value is provided.
+    pub client_id_header: Option<String>,
 }
 
 // This does not print any values avoid accidentally leaking any sensitive env vars
@@ -119,15 +178,23 @@ impl From<InnerGraphQl> for EnvVarsGraphQl {
             query_cache_blocks: x.query_cache_blocks,
             query_cache_max_mem: x.query_cache_max_mem_in_mb.0 * 1000 * 1000,
             query_cache_stale_period: x.query_cache_stale_period,
+            query_cache_shared: x.query_cache_shared.0,
             query_timeout: x.query_timeout_in_secs.map(Duration::from_secs),
             max_complexity: x.max_complexity.map(|x| x.0),
             max_depth: x.max_depth.0,
             max_first: x.max_first,
             max_skip: x.max_skip.0,
+            disable_aggregates: x.disable_aggregates.0,
+            max_aggregate_entities: x.max_aggregate_entities.0,
             allow_deployment_change: x.allow_deployment_change.0,
             warn_result_size: x.warn_result_size.0 .0,
             error_result_size: x.error_result_size.0 .0,
             max_operations_per_connection: x.max_operations_per_connection,
+            ws_connection_init_timeout: Duration::from_secs(x.ws_connection_init_timeout_in_secs),
+            ws_keepalive_interval: Duration::from_secs(x.ws_keepalive_interval_in_secs),
+            persisted_query_cache_max_mem: x.persisted_query_cache_max_mem_in_mb.0 * 1000 * 1000,
+            persisted_query_allow_list: x.persisted_query_allow_list,
+            client_id_header: x.client_id_header,
         }
     }
 }
@@ -155,6 +222,8 @@ pub struct InnerGraphQl {
     query_cache_max_mem_in_mb: NoUnderscores<usize>,
     #[envconfig(from = "GRAPH_QUERY_CACHE_STALE_PERIOD", default = "100")]
     query_cache_stale_period: u64,
+    #[envconfig(from = "GRAPH_QUERY_CACHE_SHARED", default = "false")]
+    query_cache_shared: EnvVarBoolean,
     #[envconfig(from = "GRAPH_GRAPHQL_QUERY_TIMEOUT")]
     query_timeout_in_secs: Option<u64>,
     #[envconfig(from = "GRAPH_GRAPHQL_MAX_COMPLEXITY")]
@@ -165,6 +234,10 @@ pub struct InnerGraphQl {
     max_first: u32,
     #[envconfig(from = "GRAPH_GRAPHQL_MAX_SKIP", default = "")]
     max_skip: WithDefaultUsize<u32, { u32::MAX as usize }>,
+    #[envconfig(from = "GRAPH_GRAPHQL_DISABLE_AGGREGATES", default = "false")]
+    disable_aggregates: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_GRAPHQL_MAX_AGGREGATE_ENTITIES", default = "1000000")]
+    max_aggregate_entities: NoUnderscores<usize>,
     #[envconfig(from = "GRAPHQL_ALLOW_DEPLOYMENT_CHANGE", default = "false")]
     allow_deployment_change: EnvVarBoolean,
     #[envconfig(from = "GRAPH_GRAPHQL_WARN_RESULT_SIZE", default = "")]
@@ -173,4 +246,14 @@ pub struct InnerGraphQl {
     error_result_size: WithDefaultUsize<NoUnderscores<usize>, { usize::MAX }>,
     #[envconfig(from = "GRAPH_GRAPHQL_MAX_OPERATIONS_PER_CONNECTION", default = "1000")]
     max_operations_per_connection: usize,
+    #[envconfig(from = "GRAPH_GRAPHQL_WS_CONNECTION_INIT_TIMEOUT", default = "3")]
+    ws_connection_init_timeout_in_secs: u64,
+    #[envconfig(from = "GRAPH_GRAPHQL_WS_KEEPALIVE_INTERVAL", default = "12")]
+    ws_keepalive_interval_in_secs: u64,
+    #[envconfig(from = "GRAPH_GRAPHQL_PERSISTED_QUERY_CACHE_MAX_MEM", default = "10")]
+    persisted_query_cache_max_mem_in_mb: NoUnderscores<usize>,
+    #[envconfig(from = "GRAPH_GRAPHQL_PERSISTED_QUERY_ALLOW_LIST")]
+    persisted_query_allow_list: Option<String>,
+    #[envconfig(from = "GRAPH_GRAPHQL_CLIENT_ID_HEADER")]
+    client_id_header: Option<String>,
 }
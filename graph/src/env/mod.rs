@@ -18,7 +18,8 @@ use self::graphql::*;
 use self::mappings::*;
 use self::store::*;
 use crate::{
-    components::subgraph::SubgraphVersionSwitchingMode, runtime::gas::CONST_MAX_GAS_PER_HANDLER,
+    components::{store::BlockNumber, subgraph::SubgraphVersionSwitchingMode},
+    runtime::gas::CONST_MAX_GAS_PER_HANDLER,
 };
 
 pub static UNSAFE_CONFIG: AtomicBool = AtomicBool::new(false);
@@ -104,13 +105,48 @@ pub struct EnvVars {
     ///
     /// Set by the flag `GRAPH_LOAD_SIMULATE`.
     pub load_simulate: bool,
+    /// How much query effort each client can use per second. Each query
+    /// costs the average time that queries of the same shape took recently.
+    /// Clients that use more than that will have their queries rejected
+    /// until they are back within their allowance. Setting this to 0
+    /// disables per-client rate limiting.
+    ///
+    /// Set by the environment variable `GRAPH_LOAD_CLIENT_RATE` (expressed
+    /// in milliseconds). The default value is 0.
+    pub load_client_rate: Duration,
+    /// How much query effort a client can use in a burst before it is
+    /// limited to `load_client_rate`.
+    ///
+    /// Set by the environment variable `GRAPH_LOAD_CLIENT_BURST` (expressed
+    /// in milliseconds). The default value is 10000.
+    pub load_client_burst: Duration,
+    /// The clients that get their own label in per-client query metrics.
+    /// All other clients are recorded under the label `other`.
+    ///
+    /// Set by the environment variable `GRAPH_LOAD_CLIENT_LABELS` (comma
+    /// separated). No clients get their own label by default.
+    pub load_client_labels: HashSet<String>,
+    /// The maximum number of blocks that a reorg can revert. This is the
+    /// same setting that Ethereum chains use; the store needs it to know
+    /// which blocks are final when it prunes deployments.
+    ///
+    /// Set by the environment variable `ETHEREUM_REORG_THRESHOLD`. The
+    /// default value is 250.
+    pub reorg_threshold: BlockNumber,
+    /// The least number of blocks of history that pruning keeps for a
+    /// deployment. Subgraphs that declare `prune: auto` keep exactly this
+    /// much history, and subgraphs that ask for less keep this much, too.
+    ///
+    /// Set by the environment variable `GRAPH_MIN_HISTORY_BLOCKS`. The
+    /// default value is twice the reorg threshold.
+    pub min_history_blocks: BlockNumber,
     /// Set by the flag `GRAPH_ALLOW_NON_DETERMINISTIC_FULLTEXT_SEARCH`, but
     /// enabled anyway (overridden) if [debug
     /// assertions](https://doc.rust-lang.org/reference/conditional-compilation.html#debug_assertions)
     /// are enabled.
     pub allow_non_deterministic_fulltext_search: bool,
     /// Set by the environment variable `GRAPH_MAX_SPEC_VERSION`. The default
-    /// value is `0.0.7`.
+    /// value is `0.0.8`.
     pub max_spec_version: Version,
     /// Set by the flag `GRAPH_DISABLE_GRAFTS`.
     pub disable_grafts: bool,
@@ -223,6 +259,18 @@ impl EnvVars {
             load_threshold: Duration::from_millis(inner.load_threshold_in_ms),
             load_jail_threshold: inner.load_jail_threshold,
             load_simulate: inner.load_simulate.0,
+            load_client_rate: Duration::from_millis(inner.load_client_rate_in_ms),
+            load_client_burst: Duration::from_millis(inner.load_client_burst_in_ms),
+            load_client_labels: inner
+                .load_client_labels
+                .split(',')
+                .filter(|client| !client.is_empty())
+                .map(str::to_string)
+                .collect(),
+            reorg_threshold: inner.reorg_threshold,
+            min_history_blocks: inner
+                .min_history_blocks
+                .unwrap_or(2 * inner.reorg_threshold),
             allow_non_deterministic_fulltext_search: inner
                 .allow_non_deterministic_fulltext_search
                 .0
@@ -309,12 +357,22 @@ struct Inner {
     load_jail_threshold: Option<f64>,
     #[envconfig(from = "GRAPH_LOAD_SIMULATE", default = "false")]
     load_simulate: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_LOAD_CLIENT_RATE", default = "0")]
+    load_client_rate_in_ms: u64,
+    #[envconfig(from = "GRAPH_LOAD_CLIENT_BURST", default = "10000")]
+    load_client_burst_in_ms: u64,
+    #[envconfig(from = "GRAPH_LOAD_CLIENT_LABELS", default = "")]
+    load_client_labels: String,
+    #[envconfig(from = "ETHEREUM_REORG_THRESHOLD", default = "250")]
+    reorg_threshold: BlockNumber,
+    #[envconfig(from = "GRAPH_MIN_HISTORY_BLOCKS")]
+    min_history_blocks: Option<BlockNumber>,
     #[envconfig(
         from = "GRAPH_ALLOW_NON_DETERMINISTIC_FULLTEXT_SEARCH",
         default = "false"
     )]
     allow_non_deterministic_fulltext_search: EnvVarBoolean,
-    #[envconfig(from = "GRAPH_MAX_SPEC_VERSION", default = "0.0.7")]
+    #[envconfig(from = "GRAPH_MAX_SPEC_VERSION", default = "0.0.8")]
     max_spec_version: Version,
     #[envconfig(from = "GRAPH_DISABLE_GRAFTS", default = "false")]
     disable_grafts: EnvVarBoolean,
@@ -86,11 +86,48 @@ pub struct EnvVarsStore {
     /// Setting this to `0` disables pipelined writes, and writes will be
     /// done synchronously.
     pub write_queue_size: usize,
+    /// The writer combines consecutive blocks in the write queue into one
+    /// transaction as long as they have at most this many entity
+    /// modifications in total. Setting this to `0` disables batching.
+    ///
+    /// Set by the environment variable `GRAPH_STORE_WRITE_BATCH_SIZE`. The
+    /// default value is 10000.
+    pub write_batch_size: usize,
+    /// When the write queue has room for more blocks, the writer waits up
+    /// to this long for more blocks to add to a batch that is smaller than
+    /// `write_batch_size`.
+    ///
+    /// Set by the environment variable `GRAPH_STORE_WRITE_BATCH_DURATION`
+    /// (expressed in milliseconds). The default value is 0, i.e., the
+    /// writer only batches blocks that are already queued.
+    pub write_batch_duration: Duration,
 
     /// This is just in case new behavior causes issues. This can be removed
     /// once the new behavior has run in the hosted service for a few days
     /// without issues.
     pub disable_error_for_toplevel_parents: bool,
+
+    /// How much history a deployment can accumulate beyond the history it
+    /// should keep before it gets pruned, as a multiple of that history.
+    /// Pruning after every block would be wasteful since each pass would
+    /// only remove very little data.
+    ///
+    /// Set by the environment variable `GRAPH_STORE_HISTORY_SLACK_FACTOR`.
+    /// The default value is 1.2.
+    pub history_slack_factor: f64,
+    /// Tables from which pruning removes at least this fraction of the
+    /// rows are pruned by copying the rows that are kept into a new table.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_STORE_HISTORY_REBUILD_THRESHOLD`. The default value is 0.5.
+    pub rebuild_threshold: f64,
+    /// Tables from which pruning removes at least this fraction of the
+    /// rows, but less than `rebuild_threshold`, are pruned by deleting
+    /// rows. Tables where pruning would remove even less are not pruned.
+    ///
+    /// Set by the environment variable
+    /// `GRAPH_STORE_HISTORY_DELETE_THRESHOLD`. The default value is 0.05.
+    pub delete_threshold: f64,
 }
 
 // This does not print any values avoid accidentally leaking any sensitive env vars
@@ -127,7 +164,12 @@ impl From<InnerStore> for EnvVarsStore {
             connection_min_idle: x.connection_min_idle,
             connection_idle_timeout: Duration::from_secs(x.connection_idle_timeout_in_secs),
             write_queue_size: x.write_queue_size,
+            write_batch_size: x.write_batch_size,
+            write_batch_duration: Duration::from_millis(x.write_batch_duration_in_millis),
             disable_error_for_toplevel_parents: x.disable_error_for_toplevel_parents.0,
+            history_slack_factor: x.history_slack_factor,
+            rebuild_threshold: x.rebuild_threshold,
+            delete_threshold: x.delete_threshold,
         }
     }
 }
@@ -171,6 +213,16 @@ pub struct InnerStore {
     connection_idle_timeout_in_secs: u64,
     #[envconfig(from = "GRAPH_STORE_WRITE_QUEUE", default = "5")]
     write_queue_size: usize,
+    #[envconfig(from = "GRAPH_STORE_WRITE_BATCH_SIZE", default = "10000")]
+    write_batch_size: usize,
+    #[envconfig(from = "GRAPH_STORE_WRITE_BATCH_DURATION", default = "0")]
+    write_batch_duration_in_millis: u64,
     #[envconfig(from = "GRAPH_DISABLE_ERROR_FOR_TOPLEVEL_PARENTS", default = "false")]
     disable_error_for_toplevel_parents: EnvVarBoolean,
+    #[envconfig(from = "GRAPH_STORE_HISTORY_SLACK_FACTOR", default = "1.2")]
+    history_slack_factor: f64,
+    #[envconfig(from = "GRAPH_STORE_HISTORY_REBUILD_THRESHOLD", default = "0.5")]
+    rebuild_threshold: f64,
+    #[envconfig(from = "GRAPH_STORE_HISTORY_DELETE_THRESHOLD", default = "0.05")]
+    delete_threshold: f64,
 }
@@ -119,12 +119,14 @@ pub mod prelude {
     pub use crate::components::server::query::GraphQLServer;
     pub use crate::components::server::subscription::SubscriptionServer;
     pub use crate::components::store::{
-        AttributeNames, BlockNumber, CachedEthereumCall, ChainStore, Child, ChildMultiplicity,
-        EntityCache, EntityChange, EntityChangeOperation, EntityCollection, EntityFilter,
-        EntityLink, EntityModification, EntityOperation, EntityOrder, EntityQuery, EntityRange,
-        EntityWindow, EthereumCallCache, ParentLink, PartialBlockPtr, PoolWaitStats, QueryStore,
-        QueryStoreManager, StoreError, StoreEvent, StoreEventStream, StoreEventStreamBox,
-        SubgraphStore, UnfailOutcome, WindowAttribute, BLOCK_NUMBER_MAX,
+        AggregateFunction, AttributeNames, BlockNumber, CachedEthereumCall, ChainStore, Child,
+        ChildMultiplicity, EntityAggregateQuery, EntityAggregates, EntityCache, EntityChange,
+        EntityChangeOperation, EntityCollection, EntityCursor, EntityFilter, EntityHistoryQuery,
+        EntityLink, EntityModification, EntityOperation, EntityOrder, EntityOrderByChild,
+        EntityQuery, EntityRange, EntityVersion, EntityWindow, EthereumCallCache, OrderDirection,
+        ParentLink, PartialBlockPtr, PoolWaitStats, QueryStore, QueryStoreManager, StoreError,
+        StoreEvent, StoreEventStream, StoreEventStreamBox, SubgraphStore, UnfailOutcome,
+        WindowAttribute, BLOCK_NUMBER_MAX,
     };
     pub use crate::components::subgraph::{
         BlockState, DataSourceTemplateInfo, HostMetrics, RuntimeHost, RuntimeHostBuilder,
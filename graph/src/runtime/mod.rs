@@ -260,10 +260,11 @@ pub enum IndexForAscTypeId {
     Log = 1001,
     ArrayH256 = 1002,
     ArrayLog = 1003,
+    ArrayTypedMapStringStoreValue = 1004,
     // Continue to add more Ethereum type IDs here.
     // e.g.:
-    // NextEthereumType = 1004,
-    // AnotherEthereumType = 1005,
+    // NextEthereumType = 1005,
+    // AnotherEthereumType = 1006,
     // ...
     // LastEthereumType = 1499,
 
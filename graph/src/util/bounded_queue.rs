@@ -86,6 +86,28 @@ impl<T: Clone> BoundedQueue<T> {
         item.clone()
     }
 
+    /// Return copies of the items at the front of the queue for as long as
+    /// `f` returns `true` for them. The first item is always returned, and
+    /// if the queue is currently empty, this method blocks until an item is
+    /// available.
+    ///
+    /// This method locks the queue while it is executing, and `f` should
+    /// therefore not do any slow work.
+    pub async fn peek_while<F>(&self, mut f: F) -> Vec<T>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let _permit = self.pop_semaphore.acquire().await.unwrap();
+        let queue = self.queue.lock().unwrap();
+        let mut items = queue.iter();
+        let first = items.next().expect("the queue is not empty");
+        let mut result = vec![first.clone()];
+        if f(first) {
+            result.extend(items.take_while(|item| f(item)).cloned());
+        }
+        result
+    }
+
     /// Push an item into the queue. If the queue is currently full this method
     /// blocks until an item is available
     pub async fn push(&self, item: T) {
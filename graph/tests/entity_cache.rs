@@ -9,7 +9,8 @@ use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use graph::components::store::{
-    EntityKey, EntityType, ReadStore, StoredDynamicDataSource, WritableStore,
+    DerivedEntityQuery, EntityKey, EntityType, LoadRelatedRequest, ReadStore,
+    StoredDynamicDataSource, WritableStore,
 };
 use graph::{
     components::store::{DeploymentId, DeploymentLocator},
@@ -29,6 +30,18 @@ lazy_static! {
                 founded: Int
                 label: String
             }
+
+            type Artist @entity {
+                id: ID!
+                name: String!
+                albums: [Album!]! @derivedFrom(field: \"artist\")
+            }
+
+            type Album @entity {
+                id: ID!
+                title: String!
+                artist: Artist!
+            }
             ",
             SUBGRAPH_ID.clone(),
         )
@@ -67,6 +80,26 @@ impl ReadStore for MockStore {
         Ok(self.get_many_res.clone())
     }
 
+    fn get_derived(
+        &self,
+        query: &DerivedEntityQuery,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        Ok(self
+            .get_many_res
+            .get(&query.entity_type)
+            .into_iter()
+            .flatten()
+            .filter(|entity| query.matches(entity))
+            .map(|entity| {
+                let key = EntityKey {
+                    entity_type: query.entity_type.clone(),
+                    entity_id: entity.id().unwrap().into(),
+                };
+                (key, entity.clone())
+            })
+            .collect())
+    }
+
     fn input_schema(&self) -> Arc<Schema> {
         SCHEMA.clone()
     }
@@ -348,3 +381,87 @@ fn consecutive_modifications() {
         },])
     );
 }
+
+fn make_album(id: &'static str, title: &str, artist: &str) -> (EntityKey, Entity) {
+    (
+        EntityKey {
+            entity_type: EntityType::new("Album".to_string()),
+            entity_id: id.into(),
+        },
+        Entity::from(vec![
+            ("id", id.into()),
+            ("title", title.into()),
+            ("artist", artist.into()),
+        ]),
+    )
+}
+
+#[test]
+fn load_related_merges_store_and_cache() {
+    let store = {
+        let entities = vec![
+            make_album("a1", "Young Team", "mogwai").1,
+            make_album("a2", "Come On Die Young", "mogwai").1,
+            make_album("a3", "Takk", "sigurros").1,
+        ];
+        MockStore::new(entity_version_map("Album", entities))
+    };
+    let mut cache = EntityCache::new(Arc::new(store));
+
+    // Remove one album, move another one to a different artist and add a
+    // new one; all of that needs to be visible when loading related albums
+    cache.remove(make_album("a1", "Young Team", "mogwai").0);
+    let (key, data) = make_album("a2", "Come On Die Young", "sigurros");
+    cache.set(key, data).unwrap();
+    let (key, data) = make_album("a4", "Rock Action", "mogwai");
+    cache.set(key, data.clone()).unwrap();
+
+    let request = LoadRelatedRequest {
+        entity_type: EntityType::new("Artist".to_string()),
+        entity_id: "mogwai".into(),
+        entity_field: "albums".into(),
+    };
+    assert_eq!(vec![data], cache.load_related(&request).unwrap());
+
+    let request = LoadRelatedRequest {
+        entity_type: EntityType::new("Artist".to_string()),
+        entity_id: "sigurros".into(),
+        entity_field: "albums".into(),
+    };
+    let mut titles = cache
+        .load_related(&request)
+        .unwrap()
+        .into_iter()
+        .map(|album| album.get("title").unwrap().to_string())
+        .collect::<Vec<_>>();
+    titles.sort();
+    assert_eq!(vec!["Come On Die Young", "Takk"], titles);
+
+    // Asking for a field that is not derived is an error
+    let request = LoadRelatedRequest {
+        entity_type: EntityType::new("Album".to_string()),
+        entity_id: "a3".into(),
+        entity_field: "artist".into(),
+    };
+    assert!(cache.load_related(&request).is_err());
+}
+
+#[test]
+fn get_in_block_only_sees_changes() {
+    let store = {
+        let entities = vec![make_album("a1", "Young Team", "mogwai").1];
+        MockStore::new(entity_version_map("Album", entities))
+    };
+    let mut cache = EntityCache::new(Arc::new(store));
+
+    // Entities that are only in the store are not visible
+    let (a1_key, _) = make_album("a1", "Young Team", "mogwai");
+    assert_eq!(None, cache.get_in_block(&a1_key));
+
+    let (a2_key, a2_data) = make_album("a2", "Takk", "sigurros");
+    cache.set(a2_key.clone(), a2_data.clone()).unwrap();
+    assert_eq!(Some(a2_data), cache.get_in_block(&a2_key));
+
+    cache.remove(a2_key.clone());
+    assert_eq!(None, cache.get_in_block(&a2_key));
+}
@@ -1,9 +1,10 @@
 use futures03::future::FutureExt;
 use futures03::future::Shared;
 use graph::{
-    prelude::{debug, futures03, BlockPtr, CheapClone, Logger, QueryResult},
+    prelude::{async_trait, debug, futures03, BlockPtr, CheapClone, Logger, QueryResult, ENV_VARS},
     util::timed_rw_lock::TimedMutex,
 };
+use lazy_static::lazy_static;
 use stable_hash_legacy::crypto::SetHasher;
 use stable_hash_legacy::prelude::*;
 use std::future::Future;
@@ -249,3 +250,103 @@ impl QueryBlockCache {
         None
     }
 }
+
+/// A place to keep the results of queries against recent blocks. Results
+/// are kept per network for the `max_blocks` most recent blocks of that
+/// network, and the results for each block can weigh at most `max_weight`.
+/// When a result for a new block is inserted, the results for the oldest
+/// block are dropped
+#[async_trait]
+pub trait QueryBlockCacheBackend: Send + Sync + 'static {
+    /// Get the result for the query with hash `key` at `block_ptr`
+    async fn get(
+        &self,
+        logger: &Logger,
+        network: &str,
+        block_ptr: &BlockPtr,
+        key: &QueryHash,
+    ) -> Option<Arc<QueryResult>>;
+
+    /// Remember `result` as the result of the query with hash `key` at
+    /// `block_ptr`. Returns `true` if the result was cached, and `false`
+    /// if there was no room for it or if `block_ptr` is too old
+    async fn insert(
+        &self,
+        logger: &Logger,
+        network: &str,
+        block_ptr: BlockPtr,
+        key: QueryHash,
+        result: Arc<QueryResult>,
+        weight: usize,
+    ) -> bool;
+}
+
+lazy_static! {
+    static ref QUERY_BLOCK_CACHE: Arc<InMemoryQueryBlockCache> = {
+        let shards = ENV_VARS.graphql.query_block_cache_shards;
+        let blocks = ENV_VARS.graphql.query_cache_blocks;
+
+        // The memory budget is evenly divided among blocks and their shards.
+        let max_weight = ENV_VARS.graphql.query_cache_max_mem / (blocks * shards as usize);
+        Arc::new(InMemoryQueryBlockCache::new(blocks, shards, max_weight))
+    };
+}
+
+/// A `QueryBlockCacheBackend` that keeps results in the memory of this
+/// process. The cache is sharded by query hash to reduce lock contention
+pub struct InMemoryQueryBlockCache {
+    shards: Vec<TimedMutex<QueryBlockCache>>,
+}
+
+impl InMemoryQueryBlockCache {
+    pub fn new(max_blocks: usize, shards: u8, max_weight: usize) -> Self {
+        let shards = (0..shards)
+            .map(|i| {
+                let id = format!("query_block_cache_{}", i);
+                TimedMutex::new(QueryBlockCache::new(max_blocks, i, max_weight), id)
+            })
+            .collect();
+        InMemoryQueryBlockCache { shards }
+    }
+
+    /// The cache for this process, configured from the environment
+    pub fn shared() -> Arc<Self> {
+        QUERY_BLOCK_CACHE.cheap_clone()
+    }
+
+    fn shard(&self, key: &QueryHash) -> &TimedMutex<QueryBlockCache> {
+        &self.shards[(key[0] as usize) % self.shards.len()]
+    }
+}
+
+#[async_trait]
+impl QueryBlockCacheBackend for InMemoryQueryBlockCache {
+    async fn get(
+        &self,
+        logger: &Logger,
+        network: &str,
+        block_ptr: &BlockPtr,
+        key: &QueryHash,
+    ) -> Option<Arc<QueryResult>> {
+        self.shard(key).lock(logger).get(network, block_ptr, key)
+    }
+
+    async fn insert(
+        &self,
+        logger: &Logger,
+        network: &str,
+        block_ptr: BlockPtr,
+        key: QueryHash,
+        result: Arc<QueryResult>,
+        weight: usize,
+    ) -> bool {
+        self.shard(&key).lock(logger).insert(
+            network,
+            block_ptr,
+            key,
+            result,
+            weight,
+            logger.cheap_clone(),
+        )
+    }
+}
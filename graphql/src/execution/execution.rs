@@ -1,4 +1,4 @@
-use super::cache::{QueryBlockCache, QueryCache};
+use super::cache::{QueryBlockCacheBackend, QueryCache};
 use async_recursion::async_recursion;
 use crossbeam::atomic::AtomicCell;
 use graph::{
@@ -24,21 +24,6 @@ use crate::prelude::*;
 use crate::schema::ast as sast;
 
 lazy_static! {
-    // Sharded query results cache for recent blocks by network.
-    // The `VecDeque` works as a ring buffer with a capacity of `QUERY_CACHE_BLOCKS`.
-    static ref QUERY_BLOCK_CACHE: Vec<TimedMutex<QueryBlockCache>> = {
-            let shards = ENV_VARS.graphql.query_block_cache_shards;
-            let blocks = ENV_VARS.graphql.query_cache_blocks;
-
-            // The memory budget is evenly divided among blocks and their shards.
-            let max_weight = ENV_VARS.graphql.query_cache_max_mem / (blocks * shards as usize);
-            let mut caches = Vec::new();
-            for i in 0..shards {
-                let id = format!("query_block_cache_{}", i);
-                caches.push(TimedMutex::new(QueryBlockCache::new(blocks, i, max_weight), id))
-            }
-            caches
-    };
     static ref QUERY_HERD_CACHE: QueryCache<Arc<QueryResult>> = QueryCache::new("query_herd_cache");
 }
 
@@ -72,8 +57,11 @@ struct HashableQuery<'a> {
 // we are converting items to a string inside here as a quick-and-dirty
 // implementation. This precludes the ability to add new fields (unlikely
 // anyway). So, this hash isn't really Stable in the way that the StableHash
-// crate defines it. Since hashes are only persisted for this process, we don't
-// need that property. The reason we are using StableHash is to get collision
+// crate defines it. Hashes are only persisted for this process, or shared
+// with other query nodes through a `QueryBlockCacheBackend`; nodes that run
+// a different version of graph-node might compute different hashes for the
+// same query, which only leads to cache misses, and we therefore don't need
+// that property. The reason we are using StableHash is to get collision
 // resistance and use it's foolproof API to prevent easy mistakes instead.
 //
 // This is also only as collision resistant insofar as the to_string impls are
@@ -300,6 +288,7 @@ pub(crate) async fn execute_root_selection_set<R: Resolver>(
     selection_set: Arc<a::SelectionSet>,
     root_type: sast::ObjectType,
     block_ptr: Option<BlockPtr>,
+    query_block_cache: Arc<dyn QueryBlockCacheBackend>,
 ) -> Arc<QueryResult> {
     // Cache the cache key to not have to calculate it twice - once for lookup
     // and once for insert.
@@ -321,17 +310,15 @@ pub(crate) async fn execute_root_selection_set<R: Resolver>(
             if block_ptr.number != BLOCK_NUMBER_MAX {
                 // Calculate the hash outside of the lock
                 let cache_key = cache_key(&ctx, &selection_set, block_ptr);
-                let shard = (cache_key[0] as usize) % QUERY_BLOCK_CACHE.len();
 
                 // Check if the response is cached, first in the recent blocks cache,
                 // and then in the LfuCache for historical queries
-                // The blocks are used to delimit how long locks need to be held
+                if let Some(result) = query_block_cache
+                    .get(&ctx.logger, network, block_ptr, &cache_key)
+                    .await
                 {
-                    let cache = QUERY_BLOCK_CACHE[shard].lock(&ctx.logger);
-                    if let Some(result) = cache.get(network, block_ptr, &cache_key) {
-                        ctx.cache_status.store(CacheStatus::Hit);
-                        return result;
-                    }
+                    ctx.cache_status.store(CacheStatus::Hit);
+                    return result;
                 }
                 if let Some(mut cache) = lfu_cache(&ctx.logger, &cache_key) {
                     if let Some(weighted) = cache.get(&cache_key) {
@@ -413,20 +400,21 @@ pub(crate) async fn execute_root_selection_set<R: Resolver>(
     {
         // Calculate the weight outside the lock.
         let weight = result.weight();
-        let shard = (key[0] as usize) % QUERY_BLOCK_CACHE.len();
-        let inserted = QUERY_BLOCK_CACHE[shard].lock(&ctx.logger).insert(
-            network,
-            block_ptr,
-            key,
-            result.cheap_clone(),
-            weight,
-            ctx.logger.cheap_clone(),
-        );
+        let inserted = query_block_cache
+            .insert(
+                &ctx.logger,
+                network,
+                block_ptr,
+                key,
+                result.cheap_clone(),
+                weight,
+            )
+            .await;
 
         if inserted {
             ctx.cache_status.store(CacheStatus::Insert);
         } else if let Some(mut cache) = lfu_cache(&ctx.logger, &key) {
-            // Results that are too old for the QUERY_BLOCK_CACHE go into the QUERY_LFU_CACHE
+            // Results that are too old for the block cache go into the QUERY_LFU_CACHE
             let max_mem = ENV_VARS.graphql.query_cache_max_mem
                 / ENV_VARS.graphql.query_lfu_cache_shards as usize;
 
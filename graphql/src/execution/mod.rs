@@ -10,8 +10,9 @@ pub mod ast;
 
 use stable_hash_legacy::{crypto::SetHasher, StableHasher};
 
+pub use self::cache::{InMemoryQueryBlockCache, QueryBlockCacheBackend};
 pub use self::execution::*;
 pub use self::query::Query;
 pub use self::resolver::Resolver;
 
-type QueryHash = <SetHasher as StableHasher>::Out;
+pub type QueryHash = <SetHasher as StableHasher>::Out;
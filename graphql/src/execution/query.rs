@@ -538,8 +538,13 @@ impl<'s> RawQuery<'s> {
                             visited_fragments,
                         )?;
 
-                        // Non-collection queries pass through.
-                        if !sast::is_list_or_non_null_list_field(&s_field) {
+                        // Non-collection queries pass through. Aggregates
+                        // look at all matching entities and count like a
+                        // collection query
+                        if !sast::is_list_or_non_null_list_field(&s_field)
+                            && sast::get_aggregate_entity_type(self.schema.document(), &s_field)
+                                .is_none()
+                        {
                             return Ok(total_complexity + field_complexity);
                         }
 
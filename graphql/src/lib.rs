@@ -29,7 +29,10 @@ mod metrics;
 
 /// Prelude that exports the most important traits and types.
 pub mod prelude {
-    pub use super::execution::{ast as a, ExecutionContext, Query, Resolver};
+    pub use super::execution::{
+        ast as a, ExecutionContext, InMemoryQueryBlockCache, Query, QueryBlockCacheBackend,
+        QueryHash, Resolver,
+    };
     pub use super::introspection::IntrospectionResolver;
     pub use super::query::{execute_query, ext::BlockConstraint, QueryExecutionOptions};
     pub use super::schema::{api_schema, APISchemaError};
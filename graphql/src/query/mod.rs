@@ -27,6 +27,9 @@ pub struct QueryExecutionOptions<R> {
     pub max_skip: u32,
 
     pub load_manager: Arc<LoadManager>,
+
+    /// Where to cache the results of queries against recent blocks
+    pub query_block_cache: Arc<dyn QueryBlockCacheBackend>,
 }
 
 /// Executes a query and returns a result.
@@ -68,6 +71,7 @@ where
         selection_set.cheap_clone(),
         query_type,
         block_ptr.clone(),
+        options.query_block_cache,
     )
     .await;
     let elapsed = start.elapsed();
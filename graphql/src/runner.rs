@@ -2,16 +2,19 @@ use std::sync::Arc;
 use std::time::Instant;
 
 use crate::metrics::GraphQLMetrics;
-use crate::prelude::{QueryExecutionOptions, StoreResolver, SubscriptionExecutionOptions};
+use crate::prelude::{
+    InMemoryQueryBlockCache, QueryBlockCacheBackend, QueryExecutionOptions, StoreResolver,
+    SubscriptionExecutionOptions,
+};
 use crate::query::execute_query;
 use crate::subscription::execute_prepared_subscription;
 use graph::prelude::MetricsRegistry;
 use graph::{
     components::store::SubscriptionManager,
     prelude::{
-        async_trait, o, CheapClone, DeploymentState, GraphQLMetrics as GraphQLMetricsTrait,
-        GraphQlRunner as GraphQlRunnerTrait, Logger, Query, QueryExecutionError, Subscription,
-        SubscriptionError, SubscriptionResult, ENV_VARS,
+        async_trait, futures03::StreamExt, o, CheapClone, DeploymentState,
+        GraphQLMetrics as GraphQLMetricsTrait, GraphQlRunner as GraphQlRunnerTrait, Logger, Query,
+        QueryExecutionError, Subscription, SubscriptionError, SubscriptionResult, ENV_VARS,
     },
 };
 use graph::{data::graphql::effort::LoadManager, prelude::QueryStoreManager};
@@ -27,6 +30,7 @@ pub struct GraphQlRunner<S, SM> {
     subscription_manager: Arc<SM>,
     load_manager: Arc<LoadManager>,
     graphql_metrics: Arc<GraphQLMetrics>,
+    query_block_cache: Arc<dyn QueryBlockCacheBackend>,
 }
 
 #[cfg(debug_assertions)]
@@ -56,9 +60,20 @@ where
             subscription_manager,
             load_manager,
             graphql_metrics,
+            query_block_cache: InMemoryQueryBlockCache::shared(),
         }
     }
 
+    /// Use `query_block_cache` to cache the results of queries against
+    /// recent blocks instead of the cache in the memory of this process
+    pub fn with_query_block_cache(
+        mut self,
+        query_block_cache: Arc<dyn QueryBlockCacheBackend>,
+    ) -> Self {
+        self.query_block_cache = query_block_cache;
+        self
+    }
+
     /// Check if the subgraph state differs from `state` now in a way that
     /// would affect a query that looked at data as fresh as `latest_block`.
     /// If the subgraph did change, return the `Err` that should be sent back
@@ -168,6 +183,7 @@ where
                     max_first: max_first.unwrap_or(ENV_VARS.graphql.max_first),
                     max_skip: max_skip.unwrap_or(ENV_VARS.graphql.max_skip),
                     load_manager: self.load_manager.clone(),
+                    query_block_cache: self.query_block_cache.cheap_clone(),
                 },
             )
             .await;
@@ -210,17 +226,31 @@ where
         max_first: Option<u32>,
         max_skip: Option<u32>,
     ) -> QueryResults {
-        self.execute(
-            query,
-            target,
-            max_complexity,
-            max_depth,
-            max_first,
-            max_skip,
-            self.graphql_metrics.clone(),
-        )
-        .await
-        .unwrap_or_else(|e| e)
+        let client = query.client.clone();
+        let shape_hash = query.shape_hash;
+        if let Some(client) = &client {
+            if let Err(e) = self.load_manager.check_client(client) {
+                return QueryResults::from(vec![e]);
+            }
+        }
+
+        let results = self
+            .execute(
+                query,
+                target,
+                max_complexity,
+                max_depth,
+                max_first,
+                max_skip,
+                self.graphql_metrics.clone(),
+            )
+            .await
+            .unwrap_or_else(|e| e);
+
+        if let Some(client) = &client {
+            self.load_manager.charge_client(client, shape_hash);
+        }
+        results
     }
 
     async fn run_subscription(
@@ -228,6 +258,13 @@ where
         subscription: Subscription,
         target: QueryTarget,
     ) -> Result<SubscriptionResult, SubscriptionError> {
+        let client = subscription.query.client.clone();
+        if let Some(client) = &client {
+            if let Err(err) = self.load_manager.check_client(client) {
+                return Err(SubscriptionError::GraphQLError(vec![err]));
+            }
+        }
+
         let store = self.store.query_store(target.clone(), true).await?;
         let schema = store.api_schema()?;
         let network = store.network_name().to_string();
@@ -254,7 +291,8 @@ where
             return Err(SubscriptionError::GraphQLError(vec![err]));
         }
 
-        execute_prepared_subscription(
+        let shape_hash = query.shape_hash;
+        let results = execute_prepared_subscription(
             query,
             SubscriptionExecutionOptions {
                 logger: self.logger.clone(),
@@ -267,7 +305,22 @@ where
                 max_skip: ENV_VARS.graphql.max_skip,
                 graphql_metrics: self.graphql_metrics.clone(),
             },
-        )
+        )?;
+
+        // Every result of the subscription runs its query once more, and
+        // the client pays for each of them like for a query
+        match client {
+            Some(client) => {
+                let load_manager = self.load_manager.cheap_clone();
+                Ok(results
+                    .map(move |result| {
+                        load_manager.charge_client(&client, shape_hash);
+                        result
+                    })
+                    .boxed())
+            }
+            None => Ok(results),
+        }
     }
 
     fn load_manager(&self) -> Arc<LoadManager> {
@@ -7,7 +7,7 @@ use lazy_static::lazy_static;
 use crate::schema::ast;
 
 use graph::data::{
-    graphql::ext::{DirectiveExt, DocumentExt, ValueExt},
+    graphql::ext::{DirectiveExt, DocumentExt, TypeExt, ValueExt},
     schema::{META_FIELD_NAME, META_FIELD_TYPE, SCHEMA_TYPE_NAME},
 };
 use graph::prelude::s::{Value, *};
@@ -86,6 +86,7 @@ pub fn api_schema(input_schema: &Document) -> Result<Document, APISchemaError> {
     add_meta_field_type(&mut schema);
     add_types_for_object_types(&mut schema, &object_types)?;
     add_types_for_interface_types(&mut schema, &interface_types)?;
+    add_cursor_fields(&mut schema, &object_types, &interface_types);
     add_field_arguments(&mut schema, input_schema)?;
     add_query_type(&mut schema, &object_types, &interface_types)?;
     add_subscription_type(&mut schema, &object_types, &interface_types)?;
@@ -123,8 +124,10 @@ fn add_types_for_object_types(
 ) -> Result<(), APISchemaError> {
     for object_type in object_types {
         if !object_type.name.eq(SCHEMA_TYPE_NAME) {
-            add_order_by_type(schema, &object_type.name, &object_type.fields)?;
+            add_order_by_type(schema, &object_type.name, &object_type.fields, true)?;
             add_filter_type(schema, &object_type.name, &object_type.fields)?;
+            add_aggregate_types(schema, &object_type.name, &object_type.fields)?;
+            add_version_type(schema, &object_type.name)?;
         }
     }
     Ok(())
@@ -136,17 +139,68 @@ fn add_types_for_interface_types(
     interface_types: &[&InterfaceType],
 ) -> Result<(), APISchemaError> {
     for interface_type in interface_types {
-        add_order_by_type(schema, &interface_type.name, &interface_type.fields)?;
+        add_order_by_type(schema, &interface_type.name, &interface_type.fields, false)?;
         add_filter_type(schema, &interface_type.name, &interface_type.fields)?;
     }
     Ok(())
 }
 
+/// Adds a `_cursor` field to the given object and interface types unless
+/// they already have a field with that name. Its value is only filled in
+/// for entities returned by toplevel collection fields
+fn add_cursor_fields(
+    schema: &mut Document,
+    object_types: &[&ObjectType],
+    interface_types: &[&InterfaceType],
+) {
+    let cursor_field = || Field {
+        position: Pos::default(),
+        description: Some(
+            "An opaque cursor for the `after` and `before` arguments of the toplevel \
+             collection that returned this entity. Null for other fields"
+                .to_owned(),
+        ),
+        name: ast::CURSOR_FIELD_NAME.to_owned(),
+        arguments: vec![],
+        field_type: Type::NamedType("String".to_owned()),
+        directives: vec![Directive {
+            position: Pos::default(),
+            name: "cursor".to_owned(),
+            arguments: vec![],
+        }],
+    };
+    let add_to = |fields: &mut Vec<Field>| {
+        if !fields
+            .iter()
+            .any(|field| field.name == ast::CURSOR_FIELD_NAME)
+        {
+            fields.push(cursor_field());
+        }
+    };
+
+    for object_type in object_types {
+        if !object_type.name.eq(SCHEMA_TYPE_NAME) {
+            let object_type = ast::get_object_type_mut(schema, &object_type.name)
+                .expect("object type from input schema is missing in API schema");
+            add_to(&mut object_type.fields);
+        }
+    }
+    for interface_type in interface_types {
+        let interface_type = ast::get_interface_type_mut(schema, &interface_type.name)
+            .expect("interface type from input schema is missing in API schema");
+        add_to(&mut interface_type.fields);
+    }
+}
+
 /// Adds a `<type_name>_orderBy` enum type for the given fields to the schema.
+/// Values for sorting by a field of a child entity are only added if
+/// `child_values` is set, since the store can not sort the entities of
+/// several types that make up an interface that way
 fn add_order_by_type(
     schema: &mut Document,
     type_name: &str,
     fields: &[Field],
+    child_values: bool,
 ) -> Result<(), APISchemaError> {
     let type_name = format!("{}_orderBy", type_name);
 
@@ -159,11 +213,11 @@ fn add_order_by_type(
                 directives: vec![],
                 values: fields
                     .iter()
-                    .map(|field| &field.name)
+                    .flat_map(|field| field_order_by_values(schema, field, child_values))
                     .map(|name| EnumValue {
                         position: Pos::default(),
                         description: None,
-                        name: name.to_owned(),
+                        name,
                         directives: vec![],
                     })
                     .collect(),
@@ -176,6 +230,182 @@ fn add_order_by_type(
     Ok(())
 }
 
+/// Adds the `<type_name>_aggregate` type that the `<type_names>Aggregate`
+/// query field returns. Its `count` is always present; `sum`, `avg`, `min`
+/// and `max` only when there are fields they can be computed for, and
+/// return a `<type_name>_aggregateSum`, `_aggregateAvg` or
+/// `_aggregateMinMax` object with one field for each such field
+fn add_aggregate_types(
+    schema: &mut Document,
+    type_name: &str,
+    fields: &[Field],
+) -> Result<(), APISchemaError> {
+    fn object_type(name: String, fields: Vec<Field>) -> Definition {
+        Definition::TypeDefinition(TypeDefinition::Object(ObjectType {
+            position: Pos::default(),
+            description: None,
+            name,
+            implements_interfaces: vec![],
+            directives: vec![],
+            fields,
+        }))
+    }
+
+    fn field(name: &str, field_type: Type) -> Field {
+        Field {
+            position: Pos::default(),
+            description: None,
+            name: name.to_owned(),
+            arguments: vec![],
+            field_type,
+            directives: vec![],
+        }
+    }
+
+    // Subgraphs with a spec version before 0.0.8 can have types of their
+    // own with these names. Their entity type does not get aggregates
+    let aggregate_type_name = format!("{}_aggregate", type_name);
+    if ["", "Sum", "Avg", "MinMax"].iter().any(|suffix| {
+        schema
+            .get_named_type(&format!("{}{}", aggregate_type_name, suffix))
+            .is_some()
+    }) {
+        return Ok(());
+    }
+
+    let mut aggregate_fields = vec![field(
+        "count",
+        Type::NonNullType(Box::new(Type::NamedType("Int".to_string()))),
+    )];
+    let kinds: [(&str, &[AggregateFunction]); 3] = [
+        ("Sum", &[AggregateFunction::Sum]),
+        ("Avg", &[AggregateFunction::Avg]),
+        ("MinMax", &[AggregateFunction::Min, AggregateFunction::Max]),
+    ];
+    for (suffix, functions) in kinds {
+        // All functions of one kind produce values of the same type
+        let value_fields: Vec<_> = fields
+            .iter()
+            .filter(|field| {
+                !field.field_type.is_list() && ast::get_derived_from_directive(field).is_none()
+            })
+            .filter_map(|value_field| {
+                ValueType::from_str(value_field.field_type.get_base_type())
+                    .ok()
+                    .and_then(|value_type| functions[0].value_type(value_type))
+                    .map(|value_type| {
+                        field(
+                            &value_field.name,
+                            Type::NamedType(value_type.to_str().to_string()),
+                        )
+                    })
+            })
+            .collect();
+        if value_fields.is_empty() {
+            continue;
+        }
+
+        let values_type_name = format!("{}_aggregate{}", type_name, suffix);
+        for function in functions {
+            aggregate_fields.push(field(
+                function.as_str(),
+                Type::NamedType(values_type_name.clone()),
+            ));
+        }
+        schema
+            .definitions
+            .push(object_type(values_type_name, value_fields));
+    }
+
+    schema
+        .definitions
+        .push(object_type(aggregate_type_name, aggregate_fields));
+    Ok(())
+}
+
+/// Adds the `<type_name>_version` type that the `<typeName>History` query
+/// field returns a list of. It wraps one version of an entity together with
+/// the blocks for which that version is valid
+fn add_version_type(schema: &mut Document, type_name: &str) -> Result<(), APISchemaError> {
+    let version_type_name = format!("{}_version", type_name);
+    if schema.get_named_type(&version_type_name).is_some() {
+        return Err(APISchemaError::TypeExists(version_type_name));
+    }
+
+    let field = |name: &str, description: &str, field_type: Type| Field {
+        position: Pos::default(),
+        description: Some(description.to_owned()),
+        name: name.to_owned(),
+        arguments: vec![],
+        field_type,
+        directives: vec![],
+    };
+
+    let typedef = TypeDefinition::Object(ObjectType {
+        position: Pos::default(),
+        description: None,
+        name: version_type_name,
+        implements_interfaces: vec![],
+        directives: vec![],
+        fields: vec![
+            field(
+                "validFrom",
+                "The first block at which this version is valid",
+                Type::NonNullType(Box::new(Type::NamedType("Int".to_string()))),
+            ),
+            field(
+                "validUntil",
+                "The first block at which this version is no longer valid; \
+                 null if this is the current version",
+                Type::NamedType("Int".to_string()),
+            ),
+            field(
+                "entity",
+                "The entity as of this version",
+                Type::NonNullType(Box::new(Type::NamedType(type_name.to_owned()))),
+            ),
+        ],
+    });
+    schema.definitions.push(Definition::TypeDefinition(typedef));
+    Ok(())
+}
+
+/// Generates the `*_orderBy` enum values for the given field. Besides the
+/// field itself, a single-valued, non-derived reference to another entity
+/// adds a `<field>__<child_field>` value for each scalar or enum field of
+/// the referenced type
+fn field_order_by_values(schema: &Document, field: &Field, child_values: bool) -> Vec<String> {
+    let mut values = vec![field.name.to_owned()];
+
+    if !child_values
+        || field.field_type.is_list()
+        || ast::get_derived_from_directive(field).is_some()
+    {
+        return values;
+    }
+
+    let child_fields = match schema.get_named_type(field.field_type.get_base_type()) {
+        Some(TypeDefinition::Object(object_type)) => &object_type.fields,
+        Some(TypeDefinition::Interface(interface_type)) => &interface_type.fields,
+        _ => return values,
+    };
+
+    values.extend(
+        child_fields
+            .iter()
+            .filter(|child_field| {
+                !child_field.field_type.is_list()
+                    && ast::get_derived_from_directive(child_field).is_none()
+                    && matches!(
+                        schema.get_named_type(child_field.field_type.get_base_type()),
+                        Some(TypeDefinition::Scalar(_)) | Some(TypeDefinition::Enum(_))
+                    )
+            })
+            .map(|child_field| format!("{}__{}", field.name, child_field.name)),
+    );
+    values
+}
+
 /// Adds a `<type_name>_filter` enum type for the given fields to the schema.
 fn add_filter_type(
     schema: &mut Document,
@@ -187,6 +417,10 @@ fn add_filter_type(
         None => {
             let mut generated_filter_fields = field_input_values(schema, fields)?;
             generated_filter_fields.push(block_changed_filter_argument());
+            generated_filter_fields.extend(logical_filter_arguments(
+                &filter_type_name,
+                &generated_filter_fields,
+            ));
 
             let typedef = TypeDefinition::InputObject(InputObjectType {
                 position: Pos::default(),
@@ -271,6 +505,8 @@ fn field_scalar_filter_input_values(
         "BigDecimal" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
         "ID" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
         "Int" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
+        "Int8" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
+        "Timestamp" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
         "String" => vec![
             "",
             "not",
@@ -442,6 +678,28 @@ fn add_query_type(
         .filter_map(|fulltext| query_field_for_fulltext(fulltext))
         .collect();
     fields.append(&mut fulltext_fields);
+    let mut aggregate_fields = object_types
+        .iter()
+        .map(|t| t.name.as_str())
+        .filter(|name| !name.eq(&SCHEMA_TYPE_NAME))
+        .map(aggregate_field_for_type)
+        .filter(|aggregate_field| {
+            // Leaves out the types that did not get aggregate types
+            ast::get_aggregate_entity_type(schema, aggregate_field).is_some()
+                && !fields
+                    .iter()
+                    .any(|field| field.name == aggregate_field.name)
+        })
+        .collect();
+    fields.append(&mut aggregate_fields);
+    let mut history_fields = object_types
+        .iter()
+        .map(|t| t.name.as_str())
+        .filter(|name| !name.eq(&SCHEMA_TYPE_NAME))
+        .map(history_field_for_type)
+        .filter(|history_field| !fields.iter().any(|field| field.name == history_field.name))
+        .collect();
+    fields.append(&mut history_fields);
     fields.push(meta_field());
 
     let typedef = TypeDefinition::Object(ObjectType {
@@ -566,6 +824,29 @@ fn block_argument() -> InputValue {
     }
 }
 
+/// The `after` and `before` arguments for keyset pagination of toplevel
+/// collections. Both take the `_cursor` of an entity from a previous page;
+/// nested collections do not support cursors
+fn cursor_arguments() -> Vec<InputValue> {
+    ["after", "before"]
+        .into_iter()
+        .map(|name| InputValue {
+            position: Pos::default(),
+            description: Some(format!(
+                "Only return entities that come {} the entity with this `{}` in the \
+                 requested order. The cursor must come from a query with the same \
+                 `orderBy` and `orderDirection`.",
+                name,
+                ast::CURSOR_FIELD_NAME
+            )),
+            name: name.to_string(),
+            value_type: Type::NamedType("String".to_string()),
+            default_value: None,
+            directives: vec![],
+        })
+        .collect()
+}
+
 fn block_changed_filter_argument() -> InputValue {
     InputValue {
         position: Pos::default(),
@@ -577,6 +858,24 @@ fn block_changed_filter_argument() -> InputValue {
     }
 }
 
+/// Generates the `and` and `or` input values of a `*_filter` type; both take
+/// a list of filters of the same type. An operator is omitted if one of the
+/// entity's own filter fields already uses its name
+fn logical_filter_arguments(filter_type_name: &str, fields: &[InputValue]) -> Vec<InputValue> {
+    ["and", "or"]
+        .into_iter()
+        .filter(|op| !fields.iter().any(|field| field.name == *op))
+        .map(|op| InputValue {
+            position: Pos::default(),
+            description: None,
+            name: op.to_string(),
+            value_type: Type::ListType(Box::new(Type::NamedType(filter_type_name.to_owned()))),
+            default_value: None,
+            directives: vec![],
+        })
+        .collect()
+}
+
 fn subgraph_error_argument() -> InputValue {
     InputValue {
         position: Pos::default(),
@@ -594,6 +893,7 @@ fn subgraph_error_argument() -> InputValue {
 /// Generates `Query` fields for the given type name (e.g. `users` and `user`).
 fn query_fields_for_type(type_name: &str) -> Vec<Field> {
     let mut collection_arguments = collection_arguments_for_named_type(type_name);
+    collection_arguments.extend(cursor_arguments());
     collection_arguments.push(block_argument());
 
     let mut by_id_arguments = vec![
@@ -633,6 +933,86 @@ fn query_fields_for_type(type_name: &str) -> Vec<Field> {
     ]
 }
 
+/// Generates the `<type_names>Aggregate` query field for the given type name
+fn aggregate_field_for_type(type_name: &str) -> Field {
+    Field {
+        position: Pos::default(),
+        description: Some(format!(
+            "Count the `{}` entities that match `where`, and aggregate their fields",
+            type_name
+        )),
+        name: format!("{}Aggregate", type_name.to_plural().to_camel_case()),
+        arguments: vec![
+            input_value(
+                &"where".to_string(),
+                "",
+                Type::NamedType(format!("{}_filter", type_name)),
+            ),
+            block_argument(),
+            subgraph_error_argument(),
+        ],
+        field_type: Type::NonNullType(Box::new(Type::NamedType(format!(
+            "{}_aggregate",
+            type_name
+        )))),
+        directives: vec![],
+    }
+}
+
+/// Generates the `<typeName>History` query field for the given type name
+fn history_field_for_type(type_name: &str) -> Field {
+    let block_input = |name: &str, description: &str| InputValue {
+        position: Pos::default(),
+        description: Some(description.to_owned()),
+        name: name.to_owned(),
+        value_type: Type::NamedType("Int".to_string()),
+        default_value: None,
+        directives: vec![],
+    };
+
+    let mut skip = input_value(&"skip".to_string(), "", Type::NamedType("Int".to_string()));
+    skip.default_value = Some(Value::Int(0.into()));
+
+    let mut first = input_value(&"first".to_string(), "", Type::NamedType("Int".to_string()));
+    first.default_value = Some(Value::Int(100.into()));
+
+    Field {
+        position: Pos::default(),
+        description: Some(format!(
+            "All versions of the `{}` with the given id that were valid at some block \
+             between `fromBlock` and `toBlock`, ordered by the block at which they became valid",
+            type_name
+        )),
+        name: format!("{}History", type_name.to_camel_case()),
+        arguments: vec![
+            InputValue {
+                position: Pos::default(),
+                description: None,
+                name: "id".to_string(),
+                value_type: Type::NonNullType(Box::new(Type::NamedType("ID".to_string()))),
+                default_value: None,
+                directives: vec![],
+            },
+            block_input(
+                "fromBlock",
+                "The first block of the interval. Defaults to the earliest block \
+                 for which the subgraph has data",
+            ),
+            block_input(
+                "toBlock",
+                "The last block of the interval. Defaults to the latest block",
+            ),
+            skip,
+            first,
+            subgraph_error_argument(),
+        ],
+        field_type: Type::NonNullType(Box::new(Type::ListType(Box::new(Type::NonNullType(
+            Box::new(Type::NamedType(format!("{}_version", type_name))),
+        ))))),
+        directives: vec![],
+    }
+}
+
 fn meta_field() -> Field {
     lazy_static! {
         static ref META_FIELD: Field = Field {
@@ -671,16 +1051,34 @@ fn collection_arguments_for_named_type(type_name: &str) -> Vec<InputValue> {
     let args = vec![
         skip,
         first,
-        input_value(
-            &"orderBy".to_string(),
-            "",
-            Type::NamedType(format!("{}_orderBy", type_name)),
-        ),
-        input_value(
-            &"orderDirection".to_string(),
-            "",
-            Type::NamedType("OrderDirection".to_string()),
-        ),
+        InputValue {
+            description: Some(
+                "The fields to order by. Later fields only decide the order of \
+                 entities that have the same value for all earlier ones"
+                    .to_owned(),
+            ),
+            ..input_value(
+                &"orderBy".to_string(),
+                "",
+                Type::ListType(Box::new(Type::NonNullType(Box::new(Type::NamedType(
+                    format!("{}_orderBy", type_name),
+                ))))),
+            )
+        },
+        InputValue {
+            description: Some(
+                "The direction for each field in `orderBy`. A single direction \
+                 applies to all of them"
+                    .to_owned(),
+            ),
+            ..input_value(
+                &"orderDirection".to_string(),
+                "",
+                Type::ListType(Box::new(Type::NonNullType(Box::new(Type::NamedType(
+                    "OrderDirection".to_string(),
+                ))))),
+            )
+        },
         input_value(
             &"where".to_string(),
             "",
@@ -766,6 +1164,7 @@ fn add_field_arguments(
 
 #[cfg(test)]
 mod tests {
+    use graph::data::graphql::ext::DirectiveFinder;
     use graph::data::graphql::DocumentExt;
     use graphql_parser::schema::*;
 
@@ -852,6 +1251,319 @@ mod tests {
         assert_eq!(values, ["id", "name"]);
     }
 
+    #[test]
+    fn api_schema_contains_child_field_order_by_enum() {
+        let input_schema = parse_schema(
+            r#"
+              interface Pet {
+                  id: ID!
+                  name: String!
+                  owner: User
+              }
+
+              type Dog implements Pet {
+                  id: ID!
+                  name: String!
+                  owner: User
+              }
+
+              type Group {
+                  id: ID!
+                  title: String!
+                  members: [User!]!
+              }
+
+              type User {
+                  id: ID!
+                  group: Group!
+                  pet: Pet
+                  pets: [Pet!]!
+                  bestFriend: User @derivedFrom(field: "id")
+              }
+            "#,
+        )
+        .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derived API schema");
+
+        let enum_type = match schema.get_named_type("User_orderBy") {
+            Some(TypeDefinition::Enum(t)) => t,
+            _ => panic!("User_orderBy type is missing or not an enum"),
+        };
+
+        let values: Vec<&str> = enum_type
+            .values
+            .iter()
+            .map(|value| value.name.as_str())
+            .collect();
+        assert_eq!(
+            values,
+            [
+                "id",
+                "group",
+                "group__id",
+                "group__title",
+                "pet",
+                "pet__id",
+                "pet__name",
+                "pets",
+                "bestFriend"
+            ]
+        );
+
+        // Interfaces can not be sorted by child fields
+        let enum_type = match schema.get_named_type("Pet_orderBy") {
+            Some(TypeDefinition::Enum(t)) => t,
+            _ => panic!("Pet_orderBy type is missing or not an enum"),
+        };
+        let values: Vec<&str> = enum_type
+            .values
+            .iter()
+            .map(|value| value.name.as_str())
+            .collect();
+        assert_eq!(values, ["id", "name", "owner"]);
+    }
+
+    #[test]
+    fn api_schema_contains_aggregate_types() {
+        let input_schema = parse_schema(
+            r#"
+              type Token {
+                  id: ID!
+                  name: String!
+                  decimals: Int!
+                  supply: BigInt!
+                  prices: [BigDecimal!]!
+                  createdAt: Timestamp!
+              }
+
+              type Tag {
+                  id: ID!
+                  name: String!
+              }
+            "#,
+        )
+        .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derived API schema");
+
+        let field_types = |type_name: &str| -> Vec<(String, String)> {
+            match schema.get_named_type(type_name) {
+                Some(TypeDefinition::Object(t)) => t
+                    .fields
+                    .iter()
+                    .map(|field| (field.name.clone(), field.field_type.to_string()))
+                    .collect(),
+                _ => panic!("{} type is missing or not an object", type_name),
+            }
+        };
+        let expected = |fields: &[(&str, &str)]| -> Vec<(String, String)> {
+            fields
+                .iter()
+                .map(|(name, field_type)| (name.to_string(), field_type.to_string()))
+                .collect()
+        };
+
+        assert_eq!(
+            field_types("Token_aggregate"),
+            expected(&[
+                ("count", "Int!"),
+                ("sum", "Token_aggregateSum"),
+                ("avg", "Token_aggregateAvg"),
+                ("min", "Token_aggregateMinMax"),
+                ("max", "Token_aggregateMinMax"),
+            ])
+        );
+        assert_eq!(
+            field_types("Token_aggregateSum"),
+            expected(&[("decimals", "Int8"), ("supply", "BigInt")])
+        );
+        assert_eq!(
+            field_types("Token_aggregateAvg"),
+            expected(&[("decimals", "BigDecimal"), ("supply", "BigDecimal")])
+        );
+        assert_eq!(
+            field_types("Token_aggregateMinMax"),
+            expected(&[
+                ("decimals", "Int"),
+                ("supply", "BigInt"),
+                ("createdAt", "Timestamp")
+            ])
+        );
+
+        // Types without numeric fields can only be counted
+        assert_eq!(field_types("Tag_aggregate"), expected(&[("count", "Int!")]));
+        assert!(schema.get_named_type("Tag_aggregateSum").is_none());
+
+        let query_type = match schema.get_named_type("Query") {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("Query type is missing or not an object"),
+        };
+        let aggregate_field = query_type
+            .fields
+            .iter()
+            .find(|field| field.name == "tokensAggregate")
+            .expect("tokensAggregate field is missing");
+        assert_eq!(aggregate_field.field_type.to_string(), "Token_aggregate!");
+        let arguments: Vec<&str> = aggregate_field
+            .arguments
+            .iter()
+            .map(|argument| argument.name.as_str())
+            .collect();
+        assert_eq!(arguments, ["where", "block", "subgraphError"]);
+    }
+
+    #[test]
+    fn api_schema_skips_aggregates_for_taken_type_names() {
+        // Subgraphs with a spec version before 0.0.8 can use the names of
+        // aggregate types for their own entity types
+        let input_schema = parse_schema(
+            r#"
+              type Token @entity {
+                  id: ID!
+                  supply: BigInt!
+              }
+
+              type Token_aggregate @entity {
+                  id: ID!
+                  count: Int!
+              }
+            "#,
+        )
+        .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derived API schema");
+
+        match schema.get_named_type("Token_aggregate") {
+            Some(TypeDefinition::Object(t)) => {
+                assert!(t.find_directive("entity").is_some());
+                let fields: Vec<&str> = t.fields.iter().map(|field| field.name.as_str()).collect();
+                assert_eq!(fields, ["id", "count"]);
+            }
+            _ => panic!("Token_aggregate type is missing or not an object"),
+        }
+        assert!(schema.get_named_type("Token_aggregateSum").is_none());
+
+        let query_type = match schema.get_named_type("Query") {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("Query type is missing or not an object"),
+        };
+        assert!(query_type
+            .fields
+            .iter()
+            .all(|field| field.name != "tokensAggregate"));
+        let field = query_type
+            .fields
+            .iter()
+            .find(|field| field.name == "tokenAggregates")
+            .expect("tokenAggregates field is missing");
+        assert!(ast::get_aggregate_entity_type(&schema, field).is_none());
+    }
+
+    #[test]
+    fn api_schema_contains_cursor_fields() {
+        let input_schema = parse_schema(
+            r#"
+              interface Named {
+                  id: ID!
+                  name: String!
+              }
+
+              type User implements Named {
+                  id: ID!
+                  name: String!
+              }
+
+              type Token {
+                  id: ID!
+                  _cursor: Int!
+              }
+            "#,
+        )
+        .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derived API schema");
+
+        let cursor_field = |type_name: &str| -> Field {
+            let fields = match schema.get_named_type(type_name) {
+                Some(TypeDefinition::Object(t)) => &t.fields,
+                Some(TypeDefinition::Interface(t)) => &t.fields,
+                _ => panic!("{} type is missing", type_name),
+            };
+            fields
+                .iter()
+                .find(|field| field.name == "_cursor")
+                .expect("_cursor field is missing")
+                .clone()
+        };
+
+        for type_name in ["User", "Named"] {
+            let field = cursor_field(type_name);
+            assert_eq!(field.field_type.to_string(), "String");
+            assert!(ast::is_cursor_field(&field));
+        }
+
+        // A `_cursor` field from the input schema takes precedence
+        let field = cursor_field("Token");
+        assert_eq!(field.field_type.to_string(), "Int!");
+        assert!(!ast::is_cursor_field(&field));
+    }
+
+    #[test]
+    fn api_schema_contains_history_fields() {
+        let input_schema = parse_schema(
+            r#"
+              type Token {
+                  id: ID!
+                  balance: BigInt!
+              }
+            "#,
+        )
+        .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derived API schema");
+
+        let version_type = match schema.get_named_type("Token_version") {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("Token_version type is missing or not an object"),
+        };
+        let fields: Vec<_> = version_type
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.field_type.to_string()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("validFrom", "Int!".to_string()),
+                ("validUntil", "Int".to_string()),
+                ("entity", "Token!".to_string())
+            ]
+        );
+
+        let query_type = match schema.get_named_type("Query") {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("Query type is missing or not an object"),
+        };
+        let history_field = query_type
+            .fields
+            .iter()
+            .find(|field| field.name == "tokenHistory")
+            .expect("tokenHistory field is missing");
+        assert_eq!(history_field.field_type.to_string(), "[Token_version!]!");
+        let arguments: Vec<&str> = history_field
+            .arguments
+            .iter()
+            .map(|argument| argument.name.as_str())
+            .collect();
+        assert_eq!(
+            arguments,
+            [
+                "id",
+                "fromBlock",
+                "toBlock",
+                "skip",
+                "first",
+                "subgraphError"
+            ]
+        );
+    }
+
     #[test]
     fn api_schema_contains_object_type_filter_enum() {
         let input_schema = parse_schema(
@@ -969,7 +1681,9 @@ mod tests {
                 "favoritePet_",
                 "leastFavoritePet_",
                 "mostFavoritePets_",
-                "_change_block"
+                "_change_block",
+                "and",
+                "or"
             ]
             .iter()
             .map(ToString::to_string)
@@ -987,6 +1701,17 @@ mod tests {
             String::from("Pet_filter")
         );
 
+        let or_field = user_filter_type
+            .fields
+            .iter()
+            .find(|field| field.name == "or")
+            .expect("or field is missing");
+
+        assert_eq!(
+            or_field.value_type.to_string(),
+            String::from("[User_filter]")
+        );
+
         let pet_filter = schema
             .get_named_type("Pet_filter")
             .expect("Pet_filter type is missing in derived API schema");
@@ -1046,7 +1771,9 @@ mod tests {
                 "mostLovedBy_not_contains",
                 "mostLovedBy_not_contains_nocase",
                 "mostLovedBy_",
-                "_change_block"
+                "_change_block",
+                "and",
+                "or"
             ]
             .iter()
             .map(ToString::to_string)
@@ -1170,7 +1897,9 @@ mod tests {
                 "favoritePet_not_ends_with",
                 "favoritePet_not_ends_with_nocase",
                 "favoritePet_",
-                "_change_block"
+                "_change_block",
+                "and",
+                "or"
             ]
             .iter()
             .map(ToString::to_string)
@@ -1257,6 +1986,8 @@ mod tests {
                 "orderBy",
                 "orderDirection",
                 "where",
+                "after",
+                "before",
                 "block",
                 "subgraphError",
             ]
@@ -1354,6 +2085,8 @@ mod tests {
                 "orderBy",
                 "orderDirection",
                 "where",
+                "after",
+                "before",
                 "block",
                 "subgraphError"
             ]
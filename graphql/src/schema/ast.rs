@@ -6,7 +6,7 @@ use std::str::FromStr;
 use std::sync::Arc;
 
 use graph::data::graphql::ext::DirectiveFinder;
-use graph::data::graphql::{DocumentExt, ObjectOrInterface};
+use graph::data::graphql::{DocumentExt, ObjectOrInterface, TypeExt};
 use graph::prelude::anyhow::anyhow;
 use graph::prelude::{s, Error, ValueType};
 
@@ -376,6 +376,16 @@ pub fn get_derived_from_directive(field_definition: &s::Field) -> Option<&s::Dir
     field_definition.find_directive("derivedFrom")
 }
 
+/// The name of the field that the API schema adds to entity types for the
+/// opaque cursor that the `after` and `before` arguments take
+pub const CURSOR_FIELD_NAME: &str = "_cursor";
+
+/// Whether `field_definition` is the cursor field that the API schema adds,
+/// rather than a field of the same name from the subgraph's schema
+pub fn is_cursor_field(field_definition: &s::Field) -> bool {
+    field_definition.find_directive("cursor").is_some()
+}
+
 pub fn get_derived_from_field<'a>(
     object_type: impl Into<ObjectOrInterface<'a>>,
     field_definition: &'a s::Field,
@@ -389,6 +399,41 @@ pub fn get_derived_from_field<'a>(
         .and_then(|derived_from_field_name| get_field(object_type, derived_from_field_name))
 }
 
+/// If `field_definition` is one of the generated `<types>Aggregate` query
+/// fields, return the object type whose entities it aggregates over
+pub fn get_aggregate_entity_type<'a>(
+    schema: &'a s::Document,
+    field_definition: &s::Field,
+) -> Option<&'a s::ObjectType> {
+    // `<type>_aggregate` is a reserved name for every object type `<type>`
+    // from spec version 0.0.8 on. Subgraphs with an older spec version can
+    // have entity types with such a name, but the generated types are never
+    // entity types
+    let aggregate_type_name = field_definition.field_type.get_base_type();
+    let aggregate_type = schema.get_object_type_definition(aggregate_type_name)?;
+    if aggregate_type.find_directive("entity").is_some() {
+        return None;
+    }
+    aggregate_type_name
+        .strip_suffix("_aggregate")
+        .and_then(|type_name| schema.get_object_type_definition(type_name))
+}
+
+/// If `field_definition` is one of the generated `<typeName>History` query
+/// fields, return the object type whose versions it returns
+pub fn get_history_entity_type<'a>(
+    schema: &'a s::Document,
+    field_definition: &s::Field,
+) -> Option<&'a s::ObjectType> {
+    // `<type>_version` is a reserved name for every object type `<type>`
+    // and can therefore only be one of the generated types
+    field_definition
+        .field_type
+        .get_base_type()
+        .strip_suffix("_version")
+        .and_then(|type_name| schema.get_object_type_definition(type_name))
+}
+
 pub fn is_list(field_type: &s::Type) -> bool {
     match field_type {
         s::Type::NamedType(_) => false,
@@ -17,8 +17,8 @@ use graph::{components::store::EntityType, data::graphql::*};
 use graph::{
     data::graphql::ext::DirectiveFinder,
     prelude::{
-        s, ApiSchema, AttributeNames, BlockNumber, ChildMultiplicity, EntityCollection,
-        EntityFilter, EntityLink, EntityOrder, EntityWindow, Logger, ParentLink,
+        s, AggregateFunction, ApiSchema, AttributeNames, BlockNumber, ChildMultiplicity,
+        EntityCollection, EntityFilter, EntityLink, EntityOrder, EntityWindow, Logger, ParentLink,
         QueryExecutionError, QueryStore, StoreError, Value as StoreValue, WindowAttribute,
         ENV_VARS,
     },
@@ -27,7 +27,7 @@ use graph::{
 use crate::execution::{ast as a, ExecutionContext, Resolver};
 use crate::metrics::GraphQLMetrics;
 use crate::schema::ast as sast;
-use crate::store::query::build_query;
+use crate::store::query::{build_aggregate_query, build_history_query, build_query, encode_cursor};
 use crate::store::StoreResolver;
 
 lazy_static! {
@@ -563,6 +563,32 @@ fn execute_selection_set<'a>(
             let field_type = object_type
                 .field(&field.name)
                 .expect("field names are valid");
+
+            if let Some(entity_type) =
+                sast::get_aggregate_entity_type(schema.document(), field_type)
+            {
+                match execute_aggregate_field(resolver, ctx, entity_type, field) {
+                    Ok((node, trace)) => {
+                        Join::perform(&mut parents, vec![node], field.response_key());
+                        parent_trace.push(field.response_key(), trace);
+                    }
+                    Err(e) => errors.push(e),
+                }
+                continue;
+            }
+
+            if let Some(entity_type) = sast::get_history_entity_type(schema.document(), field_type)
+            {
+                match execute_history_field(resolver, ctx, entity_type, field) {
+                    Ok((nodes, trace)) => {
+                        Join::perform(&mut parents, nodes, field.response_key());
+                        parent_trace.push(field.response_key(), trace);
+                    }
+                    Err(e) => errors.push(e),
+                }
+                continue;
+            }
+
             let child_type = schema
                 .object_or_interface(field_type.field_type.get_base_type())
                 .expect("we only collect fields that are objects or interfaces");
@@ -659,6 +685,146 @@ fn execute_field(
     .map_err(|e| vec![e])
 }
 
+/// Executes one of the `<types>Aggregate` fields. The resulting node holds
+/// the `count`, and has a child for each `sum`, `avg`, `min` and `max`
+/// field in the selection set with the values of the aggregates
+fn execute_aggregate_field(
+    resolver: &StoreResolver,
+    ctx: &ExecutionContext<impl Resolver>,
+    entity_type: &s::ObjectType,
+    field: &a::Field,
+) -> Result<(Node, Trace), QueryExecutionError> {
+    if ENV_VARS.graphql.disable_aggregates {
+        return Err(QueryExecutionError::AggregatesDisabled);
+    }
+
+    let mut query = build_aggregate_query(
+        entity_type,
+        resolver.block_number(),
+        field,
+        ctx.query.schema.as_ref(),
+    )?;
+    query.query_id = Some(ctx.query.query_id.clone());
+    query.logger = Some(ctx.logger.clone());
+
+    let keys = query.aggregates.clone();
+    let (aggregates, trace) = resolver.store.aggregate(query)?;
+    let values: BTreeMap<_, _> = keys.into_iter().zip(aggregates.values).collect();
+
+    let mut entity = BTreeMap::new();
+    entity.insert("count".into(), r::Value::Int(aggregates.count));
+    entity.insert(
+        "__typename".into(),
+        r::Value::String(format!("{}_aggregate", entity_type.name)),
+    );
+    let mut node = Node::from(entity);
+
+    for (_, aggregate_fields) in field.selection_set.fields() {
+        for aggregate_field in aggregate_fields {
+            let function = match AggregateFunction::ALL
+                .iter()
+                .find(|function| function.as_str() == aggregate_field.name)
+            {
+                Some(function) => *function,
+                None => continue,
+            };
+            let mut child = BTreeMap::new();
+            for (_, value_fields) in aggregate_field.selection_set.fields() {
+                for value_field in value_fields.filter(|f| !f.name.starts_with("__")) {
+                    let value = values
+                        .get(&(function, value_field.name.clone()))
+                        .cloned()
+                        .map(r::Value::from)
+                        .unwrap_or(r::Value::Null);
+                    child.insert(value_field.name.as_str().into(), value);
+                }
+            }
+            node.set_children(
+                aggregate_field.response_key().to_string(),
+                vec![Rc::new(Node::from(child))],
+            );
+        }
+    }
+    Ok((node, trace))
+}
+
+/// Executes one of the `<typeName>History` fields. There is one node for
+/// each version, and the `entity` of the version is its child. Fields of
+/// the entity that reference other entities can not be resolved, since
+/// each version belongs to a different block
+fn execute_history_field(
+    resolver: &StoreResolver,
+    ctx: &ExecutionContext<impl Resolver>,
+    entity_type: &s::ObjectType,
+    field: &a::Field,
+) -> Result<(Vec<Node>, Trace), QueryExecutionError> {
+    let schema = &ctx.query.schema;
+    let entity_fields: Vec<_> = field
+        .selection_set
+        .fields()
+        .flat_map(|(_, fields)| fields)
+        .filter(|version_field| version_field.name == "entity")
+        .collect();
+    for entity_field in &entity_fields {
+        for (object_type, fields) in entity_field.selection_set.fields() {
+            for attr in fields {
+                let is_reference = object_type
+                    .field(&attr.name)
+                    .and_then(|attr_type| {
+                        schema.object_or_interface(attr_type.field_type.get_base_type())
+                    })
+                    .is_some();
+                if is_reference {
+                    return Err(QueryExecutionError::NotSupported(format!(
+                        "field `{}` of `{}` can not be queried in its history \
+                         since it references other entities",
+                        attr.name, object_type.name
+                    )));
+                }
+            }
+        }
+    }
+
+    let mut query = build_history_query(
+        entity_type,
+        resolver.block_number(),
+        field,
+        ctx.max_first,
+        ctx.max_skip,
+    )?;
+    query.query_id = Some(ctx.query.query_id.clone());
+    query.logger = Some(ctx.logger.clone());
+
+    let (versions, trace) = resolver.store.history(query)?;
+    let typename = format!("{}_version", entity_type.name);
+    let nodes = versions
+        .into_iter()
+        .map(|version| {
+            let mut map = BTreeMap::new();
+            map.insert("validFrom".into(), r::Value::Int(version.valid_from.into()));
+            map.insert(
+                "validUntil".into(),
+                version
+                    .valid_until
+                    .map(|block| r::Value::Int(block.into()))
+                    .unwrap_or(r::Value::Null),
+            );
+            map.insert("__typename".into(), r::Value::String(typename.clone()));
+            let mut node = Node::from(map);
+
+            let entity = Rc::new(Node::from(version.entity));
+            for entity_field in &entity_fields {
+                node.set_children(
+                    entity_field.response_key().to_string(),
+                    vec![entity.clone()],
+                );
+            }
+            node
+        })
+        .collect();
+    Ok((nodes, trace))
+}
+
 /// Query child entities for `parents` from the store. The `join` indicates
 /// in which child field to look for the parent's id/join field. When
 /// `is_single` is `true`, there is at most one child per parent.
@@ -703,6 +869,9 @@ fn fetch(
         );
     }
 
+    // Cursors can only be used with toplevel collections, and we only
+    // compute them when the query asks for them
+    let mut cursor_order = None;
     if !is_root_node(parents.iter().map(|p| &**p)) {
         // For anything but the root node, restrict the children we select
         // by the parent list
@@ -711,15 +880,47 @@ fn fetch(
             return Ok((vec![], Trace::None));
         }
         query.collection = EntityCollection::Window(windows);
+    } else if selects_cursor(field) {
+        cursor_order = Some(query.order.clone());
     }
     store.find_query_values(query).map(|(values, trace)| {
         (
-            values.into_iter().map(|entity| entity.into()).collect(),
+            values
+                .into_iter()
+                .map(|mut entity| {
+                    if let Some(cursor) = cursor_order
+                        .as_ref()
+                        .and_then(|order| encode_cursor(order, &entity))
+                    {
+                        entity.insert(
+                            Word::from(sast::CURSOR_FIELD_NAME),
+                            r::Value::String(cursor),
+                        );
+                    }
+                    entity.into()
+                })
+                .collect(),
             trace,
         )
     })
 }
 
+/// Whether the selection set of `field` contains the generated `_cursor`
+/// field for any of the types it can return
+fn selects_cursor(field: &a::Field) -> bool {
+    field
+        .selection_set
+        .fields()
+        .any(|(object_type, mut fields)| {
+            fields.any(|field| {
+                field.name == sast::CURSOR_FIELD_NAME
+                    && sast::get_field(object_type, &field.name)
+                        .map(sast::is_cursor_field)
+                        .unwrap_or(false)
+            })
+        })
+}
+
 #[derive(Debug, Default, Clone)]
 pub(crate) struct SelectedAttributes(BTreeMap<String, AttributeNames>);
 
@@ -727,46 +928,65 @@ impl SelectedAttributes {
     /// Extract the attributes we should select from `selection_set`. In
     /// particular, disregard derived fields since they are not stored
     fn for_field(field: &a::Field) -> Result<SelectedAttributes, Vec<QueryExecutionError>> {
-        let mut map = BTreeMap::new();
-        for (object_type, fields) in field.selection_set.fields() {
-            let column_names = fields
-                .filter(|field| {
-                    // Keep fields that are not derived and for which we
-                    // can find the field type
-                    sast::get_field(object_type, &field.name)
-                        .map(|field_type| !field_type.is_derived())
-                        .unwrap_or(false)
-                })
-                .filter_map(|field| {
-                    if field.name.starts_with("__") {
-                        None
-                    } else {
-                        Some(field.name.clone())
-                    }
+        // We need to also select the fields we order by. Because of how the
+        // API Schema is set up, `orderBy` can only have an enum value or a
+        // list of them
+        let order_fields = match field.argument_value("orderBy") {
+            None | Some(r::Value::Null) => vec![],
+            Some(r::Value::Enum(e)) => vec![e.as_str()],
+            Some(r::Value::List(values)) => values
+                .iter()
+                .map(|value| match value {
+                    r::Value::Enum(e) => Ok(e.as_str()),
+                    v => Err(vec![constraint_violation!(
+                        "'orderBy' values must be enums but one is {:?}",
+                        v
+                    )
+                    .into()]),
                 })
-                .collect();
-            map.insert(
-                object_type.name().to_string(),
-                AttributeNames::Select(column_names),
-            );
-        }
-        // We need to also select the `orderBy` field if there is one.
-        // Because of how the API Schema is set up, `orderBy` can only have
-        // an enum value
-        match field.argument_value("orderBy") {
-            None => { /* nothing to do */ }
-            Some(r::Value::Enum(e)) => {
-                for columns in map.values_mut() {
-                    columns.add_str(e);
-                }
-            }
+                .collect::<Result<Vec<_>, _>>()?,
             Some(v) => {
                 return Err(vec![constraint_violation!(
-                    "'orderBy' attribute must be an enum but is {:?}",
+                    "'orderBy' attribute must be an enum or a list of enums but is {:?}",
                     v
                 )
                 .into()]);
             }
+        };
+
+        let mut map = BTreeMap::new();
+        for (object_type, fields) in field.selection_set.fields() {
+            let mut column_names = AttributeNames::Select(
+                fields
+                    .filter(|field| {
+                        // Keep fields that are not derived and for which we
+                        // can find the field type. The cursor is computed
+                        // from other fields and not stored
+                        sast::get_field(object_type, &field.name)
+                            .map(|field_type| {
+                                !field_type.is_derived() && !sast::is_cursor_field(field_type)
+                            })
+                            .unwrap_or(false)
+                    })
+                    .filter_map(|field| {
+                        if field.name.starts_with("__") {
+                            None
+                        } else {
+                            Some(field.name.clone())
+                        }
+                    })
+                    .collect(),
+            );
+            for name in &order_fields {
+                if sast::get_field(object_type, name).is_some() {
+                    column_names.add_str(name);
+                } else if let Some((join_field, _)) = name.split_once("__") {
+                    // Sorting by `<field>__<child_field>` only needs the
+                    // field that references the child
+                    column_names.add_str(join_field);
+                }
+            }
+            map.insert(object_type.name().to_string(), column_names);
         }
         Ok(SelectedAttributes(map))
     }
@@ -3,8 +3,8 @@ use std::mem::discriminant;
 
 use graph::data::graphql::ext::DirectiveFinder;
 use graph::data::graphql::TypeExt as _;
-use graph::data::value::Object;
 use graph::data::value::Value as DataValue;
+use graph::data::value::{Object, Word};
 use graph::prelude::*;
 use graph::{components::store::EntityType, data::graphql::ObjectOrInterface};
 
@@ -13,10 +13,13 @@ use crate::schema::ast as sast;
 
 use super::prefetch::SelectedAttributes;
 
+/// What an `orderBy` argument refers to
 #[derive(Debug)]
-enum OrderDirection {
-    Ascending,
-    Descending,
+enum OrderBy {
+    /// An attribute of the entity itself
+    Attribute(String, ValueType),
+    /// An attribute of a child entity, written as `<field>__<child_field>`
+    Child(EntityOrderByChild),
 }
 
 /// Builds a EntityQuery from GraphQL arguments.
@@ -47,27 +50,112 @@ pub(crate) fn build_query<'a>(
             })
             .collect(),
     });
-    let mut query = EntityQuery::new(parse_subgraph_id(entity)?, block, entity_types)
-        .range(build_range(field, max_first, max_skip)?);
+    let order = build_order(entity, field, schema)?;
+    let mut range = build_range(field, max_first, max_skip)?;
+    range.cursor = build_cursor(entity, field, &order)?;
+    let mut query = EntityQuery::new(parse_subgraph_id(entity)?, block, entity_types).range(range);
     if let Some(filter) = build_filter(entity, field, schema)? {
         query = query.filter(filter);
     }
-    let order = match (
-        build_order_by(entity, field)?,
-        build_order_direction(field)?,
-    ) {
-        (Some((attr, value_type)), OrderDirection::Ascending) => {
-            EntityOrder::Ascending(attr, value_type)
+    query = query.order(order);
+    Ok(query)
+}
+
+/// Builds an `EntityAggregateQuery` for one of the `<types>Aggregate`
+/// fields from its `where` argument and the `sum`, `avg`, `min` and `max`
+/// fields that its selection set asks for
+pub(crate) fn build_aggregate_query(
+    entity: &s::ObjectType,
+    block: BlockNumber,
+    field: &a::Field,
+    schema: &ApiSchema,
+) -> Result<EntityAggregateQuery, QueryExecutionError> {
+    let mut query = EntityAggregateQuery::new(parse_subgraph_id(entity)?, block, entity.into());
+    query.filter = build_filter(entity.into(), field, schema)?;
+
+    for (_, aggregate_fields) in field.selection_set.fields() {
+        for aggregate_field in aggregate_fields {
+            let function = match AggregateFunction::ALL
+                .iter()
+                .find(|function| function.as_str() == aggregate_field.name)
+            {
+                Some(function) => *function,
+                None => continue,
+            };
+            for (_, value_fields) in aggregate_field.selection_set.fields() {
+                for value_field in value_fields {
+                    if value_field.name.starts_with("__") {
+                        continue;
+                    }
+                    let aggregate = (function, value_field.name.clone());
+                    if !query.aggregates.contains(&aggregate) {
+                        query.aggregates.push(aggregate);
+                    }
+                }
+            }
         }
-        (Some((attr, value_type)), OrderDirection::Descending) => {
-            EntityOrder::Descending(attr, value_type)
+    }
+    Ok(query)
+}
+
+/// Builds an `EntityHistoryQuery` for one of the `<typeName>History` fields.
+/// The interval of the query can not extend past `block`, the block at
+/// which the rest of the query is run
+pub(crate) fn build_history_query(
+    entity: &s::ObjectType,
+    block: BlockNumber,
+    field: &a::Field,
+    max_first: u32,
+    max_skip: u32,
+) -> Result<EntityHistoryQuery, QueryExecutionError> {
+    let id = match field.argument_value("id") {
+        Some(r::Value::String(id)) => id.clone(),
+        _ => unreachable!("id is a required String argument"),
+    };
+
+    let block_argument = |name: &str| match field.argument_value(name) {
+        Some(r::Value::Int(n)) => BlockNumber::try_from(*n).map(Some).map_err(|_| {
+            QueryExecutionError::ValueParseError(
+                name.to_owned(),
+                format!("{} is not a valid block number", n),
+            )
+        }),
+        _ => Ok(None),
+    };
+    let from = block_argument("fromBlock")?;
+    let to = match block_argument("toBlock")? {
+        Some(to) if to > block => {
+            return Err(QueryExecutionError::ValueParseError(
+                "toBlock".to_owned(),
+                format!(
+                    "data for block number {} is not yet available, the latest block is {}",
+                    to, block
+                ),
+            ))
         }
-        (None, _) => EntityOrder::Default,
+        Some(to) => to,
+        None => block,
     };
-    query = query.order(order);
+
+    let mut query = EntityHistoryQuery::new(parse_subgraph_id(entity)?, entity.into(), id, to);
+    query.from = from;
+    query.range = build_range(field, max_first, max_skip)?;
     Ok(query)
 }
 
+fn entity_order(order_by: OrderBy, direction: OrderDirection) -> EntityOrder {
+    match (order_by, direction) {
+        (OrderBy::Attribute(attr, value_type), OrderDirection::Ascending) => {
+            EntityOrder::Ascending(attr, value_type)
+        }
+        (OrderBy::Attribute(attr, value_type), OrderDirection::Descending) => {
+            EntityOrder::Descending(attr, value_type)
+        }
+        (OrderBy::Child(child), OrderDirection::Ascending) => EntityOrder::ChildAscending(child),
+        (OrderBy::Child(child), OrderDirection::Descending) => EntityOrder::ChildDescending(child),
+    }
+}
+
 /// Parses GraphQL arguments into a EntityRange, if present.
 fn build_range(
     field: &a::Field,
@@ -107,9 +195,92 @@ fn build_range(
     Ok(EntityRange {
         first: Some(first),
         skip,
+        cursor: None,
     })
 }
 
+/// Parses the `after` or `before` argument into an `EntityCursor` for
+/// `order`, if present
+fn build_cursor(
+    entity: ObjectOrInterface,
+    field: &a::Field,
+    order: &EntityOrder,
+) -> Result<Option<EntityCursor>, QueryExecutionError> {
+    let (cursor, after) = match (
+        field.argument_value("after"),
+        field.argument_value("before"),
+    ) {
+        (Some(r::Value::String(_)), Some(r::Value::String(_))) => {
+            return Err(QueryExecutionError::ValidationError(
+                Some(field.position),
+                "only one of `after` and `before` can be used".to_string(),
+            ))
+        }
+        (Some(r::Value::String(cursor)), _) => (cursor, true),
+        (_, Some(r::Value::String(cursor))) => (cursor, false),
+        _ => return Ok(None),
+    };
+
+    let invalid = || QueryExecutionError::InvalidCursor(cursor.clone());
+    let attrs = order.cursor_attributes().ok_or_else(|| {
+        QueryExecutionError::NotSupported(
+            "cursors can not be used when ordering by a field of a child entity".to_string(),
+        )
+    })?;
+    let values = decode_cursor(cursor).ok_or_else(invalid)?;
+    if values.len() != attrs.len() {
+        return Err(invalid());
+    }
+    let values = attrs
+        .into_iter()
+        .zip(values)
+        .map(|(attr, (name, value))| {
+            if attr != name {
+                return Err(invalid());
+            }
+            let field = sast::get_field(entity, attr).ok_or_else(invalid)?;
+            Value::from_query_value(&value, &field.field_type).map_err(|_| invalid())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Some(match after {
+        true => EntityCursor::After(values),
+        false => EntityCursor::Before(values),
+    }))
+}
+
+/// Encodes the cursor of `entity` in `order`, or returns `None` if `order`
+/// does not support cursors. The cursor holds the name and value of each
+/// attribute from `EntityOrder::cursor_attributes`; it is hex-encoded to
+/// make it clear to clients that they should not rely on its contents
+pub(crate) fn encode_cursor(
+    order: &EntityOrder,
+    entity: &BTreeMap<Word, r::Value>,
+) -> Option<String> {
+    let pairs = order
+        .cursor_attributes()?
+        .into_iter()
+        .map(|attr| {
+            (
+                attr,
+                entity.get(&Word::from(attr)).unwrap_or(&r::Value::Null),
+            )
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_vec(&pairs).ok().map(hex::encode)
+}
+
+fn decode_cursor(cursor: &str) -> Option<Vec<(String, r::Value)>> {
+    let bytes = hex::decode(cursor).ok()?;
+    let pairs: Vec<(String, serde_json::Value)> = serde_json::from_slice(&bytes).ok()?;
+    Some(
+        pairs
+            .into_iter()
+            .map(|(name, value)| (name, r::Value::from(value)))
+            .collect(),
+    )
+}
+
 /// Parses GraphQL arguments into an EntityFilter, if present.
 fn build_filter(
     entity: ObjectOrInterface,
@@ -180,6 +351,17 @@ fn build_filter_from_object(
                     };
                 }
 
+                // The logical operators `and` and `or` take a list of filters
+                // for the same entity; an entity field with the same name
+                // takes precedence since no operator is generated for it
+                if (key == "and" || key == "or") && sast::get_field(entity, key).is_none() {
+                    let filters = build_list_filter_from_value(entity, value, schema)?;
+                    return Ok(match key {
+                        "and" => EntityFilter::And(filters),
+                        _ => EntityFilter::Or(filters),
+                    });
+                }
+
                 use self::sast::FilterOp::*;
                 let (field_name, op) = sast::parse_field_as_filter(key);
 
@@ -248,6 +430,24 @@ fn build_filter_from_object(
     }))
 }
 
+/// Parses the list of filter objects passed to an `and` or `or` operator
+fn build_list_filter_from_value(
+    entity: ObjectOrInterface,
+    value: &r::Value,
+    schema: &ApiSchema,
+) -> Result<Vec<EntityFilter>, QueryExecutionError> {
+    match value {
+        r::Value::List(values) => values
+            .iter()
+            .map(|value| match value {
+                r::Value::Object(object) => build_filter_from_object(entity, object, schema),
+                _ => Err(QueryExecutionError::InvalidFilterError),
+            })
+            .collect(),
+        _ => Err(QueryExecutionError::InvalidFilterError),
+    }
+}
+
 fn build_child_filter_from_object(
     entity: ObjectOrInterface,
     field_name: String,
@@ -360,41 +560,175 @@ fn list_values(value: Value, filter_type: &str) -> Result<Vec<Value>, QueryExecu
     }
 }
 
-/// Parses GraphQL arguments into an field name to order by, if present.
-fn build_order_by(
+/// Parses the `orderBy` and `orderDirection` arguments into an
+/// `EntityOrder`. Both take a list, but a single value is accepted for
+/// each, too. A single direction applies to all fields in `orderBy`,
+/// otherwise there must be one direction for each of them
+fn build_order(
     entity: ObjectOrInterface,
     field: &a::Field,
-) -> Result<Option<(String, ValueType)>, QueryExecutionError> {
-    match field.argument_value("orderBy") {
-        Some(r::Value::Enum(name)) => {
-            let field = sast::get_field(entity, name).ok_or_else(|| {
-                QueryExecutionError::EntityFieldError(entity.name().to_owned(), name.clone())
-            })?;
-            sast::get_field_value_type(&field.field_type)
-                .map(|value_type| Some((name.to_owned(), value_type)))
-                .map_err(|_| {
-                    QueryExecutionError::OrderByNotSupportedError(
-                        entity.name().to_owned(),
-                        name.clone(),
-                    )
-                })
-        }
-        _ => match field.argument_value("text") {
-            Some(r::Value::Object(filter)) => build_fulltext_order_by_from_object(filter),
-            None => Ok(None),
+    schema: &ApiSchema,
+) -> Result<EntityOrder, QueryExecutionError> {
+    let names = match field.argument_value("orderBy") {
+        Some(r::Value::Enum(name)) => vec![name],
+        Some(r::Value::List(values)) => values
+            .iter()
+            .map(|value| match value {
+                r::Value::Enum(name) => Ok(name),
+                _ => Err(QueryExecutionError::InvalidFilterError),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => vec![],
+    };
+    if names.is_empty() {
+        return match field.argument_value("text") {
+            Some(r::Value::Object(filter)) => Ok(entity_order(
+                build_fulltext_order_by_from_object(filter)?,
+                build_order_directions(field)
+                    .first()
+                    .copied()
+                    .unwrap_or(OrderDirection::Ascending),
+            )),
+            None => Ok(EntityOrder::Default),
             _ => Err(QueryExecutionError::InvalidFilterError),
+        };
+    }
+
+    let directions = match build_order_directions(field) {
+        directions if directions.is_empty() => vec![OrderDirection::Ascending; names.len()],
+        directions if directions.len() == 1 => vec![directions[0]; names.len()],
+        directions if directions.len() == names.len() => directions,
+        directions => {
+            return Err(QueryExecutionError::ValidationError(
+                Some(field.position),
+                format!(
+                    "`orderDirection` must have one direction or one for each of the {} \
+                     fields in `orderBy`, but it has {}",
+                    names.len(),
+                    directions.len()
+                ),
+            ))
+        }
+    };
+
+    let mut keys = names
+        .into_iter()
+        .zip(directions)
+        .map(|(name, direction)| Ok((name, order_by_from_name(entity, name, schema)?, direction)))
+        .collect::<Result<Vec<_>, QueryExecutionError>>()?;
+
+    if keys.len() == 1 {
+        let (_, order_by, direction) = keys.pop().unwrap();
+        return Ok(entity_order(order_by, direction));
+    }
+
+    keys.into_iter()
+        .map(|(name, order_by, direction)| match order_by {
+            OrderBy::Attribute(attr, value_type) => Ok((attr, value_type, direction)),
+            OrderBy::Child(_) => Err(QueryExecutionError::OrderByNotSupportedError(
+                entity.name().to_owned(),
+                name.clone(),
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(EntityOrder::Multi)
+}
+
+/// Resolves the name of a value of the `*_orderBy` enum
+fn order_by_from_name(
+    entity: ObjectOrInterface,
+    name: &String,
+    schema: &ApiSchema,
+) -> Result<OrderBy, QueryExecutionError> {
+    match sast::get_field(entity, name) {
+        Some(field) => sast::get_field_value_type(&field.field_type)
+            .map(|value_type| OrderBy::Attribute(name.to_owned(), value_type))
+            .map_err(|_| {
+                QueryExecutionError::OrderByNotSupportedError(
+                    entity.name().to_owned(),
+                    name.clone(),
+                )
+            }),
+        None => match name.split_once("__") {
+            Some((parent_field_name, child_field_name)) => {
+                build_child_order_by(entity, parent_field_name, child_field_name, schema)
+                    .map(OrderBy::Child)
+            }
+            None => Err(QueryExecutionError::EntityFieldError(
+                entity.name().to_owned(),
+                name.clone(),
+            )),
         },
     }
 }
 
-fn build_fulltext_order_by_from_object(
-    object: &Object,
-) -> Result<Option<(String, ValueType)>, QueryExecutionError> {
+/// Resolves an `orderBy: <field>__<child_field>` argument. The parent field
+/// must be a single-valued reference that is not derived, and the child
+/// field a scalar attribute of the referenced type
+fn build_child_order_by(
+    entity: ObjectOrInterface,
+    parent_field_name: &str,
+    child_field_name: &str,
+    schema: &ApiSchema,
+) -> Result<EntityOrderByChild, QueryExecutionError> {
+    let not_supported = || {
+        QueryExecutionError::OrderByNotSupportedError(
+            entity.name().to_owned(),
+            format!("{}__{}", parent_field_name, child_field_name),
+        )
+    };
+
+    let parent_field = sast::get_field(entity, parent_field_name).ok_or_else(|| {
+        QueryExecutionError::EntityFieldError(
+            entity.name().to_owned(),
+            parent_field_name.to_owned(),
+        )
+    })?;
+    if parent_field.field_type.is_list() || parent_field.is_derived() {
+        return Err(not_supported());
+    }
+
+    let child_entity = schema
+        .object_or_interface(parent_field.field_type.get_base_type())
+        .ok_or_else(not_supported)?;
+    let child_field = sast::get_field(child_entity, child_field_name).ok_or_else(|| {
+        QueryExecutionError::EntityFieldError(
+            child_entity.name().to_owned(),
+            child_field_name.to_owned(),
+        )
+    })?;
+    if child_field.field_type.is_list() || child_field.is_derived() {
+        return Err(not_supported());
+    }
+    sast::get_field_value_type(&child_field.field_type).map_err(|_| not_supported())?;
+
+    let child_types = match child_entity {
+        ObjectOrInterface::Object(object_type) => vec![EntityType::from(object_type)],
+        ObjectOrInterface::Interface(_) => child_entity
+            .object_types(schema.schema())
+            .ok_or_else(|| {
+                QueryExecutionError::AbstractTypeError(
+                    "Interface is not implemented by any types".to_string(),
+                )
+            })?
+            .iter()
+            .map(|object_type| EntityType::new(object_type.name.to_string()))
+            .collect(),
+    };
+
+    Ok(EntityOrderByChild {
+        join_attribute: parent_field_name.to_owned(),
+        sort_by_attribute: child_field_name.to_owned(),
+        child_types,
+    })
+}
+
+fn build_fulltext_order_by_from_object(object: &Object) -> Result<OrderBy, QueryExecutionError> {
     object.iter().next().map_or(
         Err(QueryExecutionError::FulltextQueryRequiresFilter),
         |(key, value)| {
             if let r::Value::String(_) = value {
-                Ok(Some((key.to_string(), ValueType::String)))
+                Ok(OrderBy::Attribute(key.to_string(), ValueType::String))
             } else {
                 Err(QueryExecutionError::FulltextQueryRequiresFilter)
             }
@@ -402,16 +736,17 @@ fn build_fulltext_order_by_from_object(
     )
 }
 
-/// Parses GraphQL arguments into a EntityOrder, if present.
-fn build_order_direction(field: &a::Field) -> Result<OrderDirection, QueryExecutionError> {
-    Ok(field
-        .argument_value("orderDirection")
-        .map(|value| match value {
-            r::Value::Enum(name) if name == "asc" => OrderDirection::Ascending,
-            r::Value::Enum(name) if name == "desc" => OrderDirection::Descending,
-            _ => OrderDirection::Ascending,
-        })
-        .unwrap_or(OrderDirection::Ascending))
+/// Parses the `orderDirection` argument into a list of directions
+fn build_order_directions(field: &a::Field) -> Vec<OrderDirection> {
+    let direction = |value: &r::Value| match value {
+        r::Value::Enum(name) if name == "desc" => OrderDirection::Descending,
+        _ => OrderDirection::Ascending,
+    };
+    match field.argument_value("orderDirection") {
+        Some(r::Value::List(values)) => values.iter().map(direction).collect(),
+        Some(r::Value::Null) | None => vec![],
+        Some(value) => vec![direction(value)],
+    }
 }
 
 /// Parses the subgraph ID from the ObjectType directives.
@@ -880,6 +1215,7 @@ mod tests {
             EntityRange {
                 first: Some(100),
                 skip: 50,
+                cursor: None,
             },
         );
     }
@@ -917,6 +1253,51 @@ mod tests {
         )
     }
 
+    #[test]
+    fn build_query_yields_or_filter() {
+        let schema = build_default_schema();
+        let name_filter = |name: &str| {
+            r::Value::Object(Object::from_iter(vec![(
+                "name".to_string(),
+                r::Value::String(name.to_string()),
+            )]))
+        };
+        let query_field = default_field_with(
+            "where",
+            r::Value::Object(Object::from_iter(vec![(
+                "or".to_string(),
+                r::Value::List(vec![name_filter("alice"), name_filter("bob")]),
+            )])),
+        );
+        assert_eq!(
+            build_query(
+                &ObjectType {
+                    fields: vec![field("name", Type::NamedType("string".to_owned()))],
+                    ..default_object()
+                },
+                BLOCK_NUMBER_MAX,
+                &query_field,
+                &BTreeMap::new(),
+                std::u32::MAX,
+                std::u32::MAX,
+                Default::default(),
+                &schema
+            )
+            .unwrap()
+            .filter,
+            Some(EntityFilter::And(vec![EntityFilter::Or(vec![
+                EntityFilter::And(vec![EntityFilter::Equal(
+                    "name".to_string(),
+                    Value::String("alice".to_string()),
+                )]),
+                EntityFilter::And(vec![EntityFilter::Equal(
+                    "name".to_string(),
+                    Value::String("bob".to_string()),
+                )]),
+            ])]))
+        )
+    }
+
     #[test]
     fn build_query_yields_block_change_gte_filter() {
         let schema = build_default_schema();
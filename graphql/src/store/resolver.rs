@@ -130,15 +130,6 @@ impl StoreResolver {
         bc: BlockConstraint,
         state: &DeploymentState,
     ) -> Result<BlockPtrTs, QueryExecutionError> {
-        fn block_queryable(
-            state: &DeploymentState,
-            block: BlockNumber,
-        ) -> Result<(), QueryExecutionError> {
-            state
-                .block_queryable(block)
-                .map_err(|msg| QueryExecutionError::ValueParseError("block.number".to_owned(), msg))
-        }
-
         async fn get_block_ts(
             store: &dyn QueryStore,
             ptr: &BlockPtr,
@@ -173,11 +164,11 @@ impl StoreResolver {
                             })
                     })?;
 
-                block_queryable(state, ptr.ptr.number)?;
+                state.block_queryable(ptr.ptr.number)?;
                 Ok(ptr)
             }
             BlockConstraint::Number(number) => {
-                block_queryable(state, number)?;
+                state.block_queryable(number)?;
                 // We don't have a way here to look the block hash up from
                 // the database, and even if we did, there is no guarantee
                 // that we have the block in our cache. We therefore
@@ -234,6 +234,7 @@ async fn execute_subscription_event(
         ctx.query.selection_set.cheap_clone(),
         subscription_type.into(),
         block_ptr,
+        InMemoryQueryBlockCache::shared(),
     )
     .await
 }
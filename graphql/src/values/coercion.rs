@@ -52,6 +52,15 @@ impl MaybeCoercible<ScalarType> for q::Value {
             ("BigInt", q::Value::Int(n)) => Ok(r::Value::String(
                 n.as_i64().ok_or(q::Value::Int(n))?.to_string(),
             )),
+            ("Int8", q::Value::Int(n)) => Ok(r::Value::Int(n.as_i64().ok_or(q::Value::Int(n))?)),
+            ("Int8", q::Value::String(s)) => match s.parse::<i64>() {
+                Ok(n) => Ok(r::Value::Int(n)),
+                Err(_) => Err(q::Value::String(s)),
+            },
+            ("Timestamp", q::Value::String(s)) => Ok(r::Value::String(s)),
+            ("Timestamp", q::Value::Int(n)) => {
+                Ok(r::Value::Int(n.as_i64().ok_or(q::Value::Int(n))?))
+            }
             (_, v) => Err(v),
         }
     }
@@ -195,18 +204,21 @@ pub(crate) fn coerce_value<'a>(
             Ok(r::Value::List(coerced_values))
         }
 
-        // Otherwise the list type is not coercible.
-        (Type::ListType(_), value) => Err(value),
+        // A single value is coerced into a list with just that value if it
+        // is coercible into the inner type, as the GraphQL spec requires
+        (Type::ListType(ty), value) => {
+            coerce_value(value, ty, resolver).map(|value| r::Value::List(vec![value]))
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use graph::prelude::r::Value;
-    use graphql_parser::schema::{EnumType, EnumValue, ScalarType, TypeDefinition};
+    use graphql_parser::schema::{EnumType, EnumValue, ScalarType, Type, TypeDefinition};
     use graphql_parser::Pos;
 
-    use super::coerce_to_definition;
+    use super::{coerce_to_definition, coerce_value};
 
     #[test]
     fn coercion_using_enum_type_definitions_is_correct() {
@@ -395,6 +407,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn coerce_int8_scalar() {
+        let int8_type = TypeDefinition::Scalar(ScalarType::new("Int8".to_string()));
+        let resolver = |_: &str| Some(&int8_type);
+
+        assert_eq!(
+            coerce_to_definition(Value::Int(1234.into()), "", &resolver),
+            Ok(Value::Int(1234))
+        );
+        // Values outside the i32 range can only be passed as strings
+        assert_eq!(
+            coerce_to_definition(Value::String(i64::MAX.to_string()), "", &resolver),
+            Ok(Value::Int(i64::MAX))
+        );
+        assert!(coerce_to_definition(Value::String("12a".to_string()), "", &resolver).is_err());
+        assert!(coerce_to_definition(Value::Float(1.5), "", &resolver).is_err());
+    }
+
     #[test]
     fn coerce_bytes_scalar() {
         let bytes_type = TypeDefinition::Scalar(ScalarType::new("Bytes".to_string()));
@@ -421,4 +451,27 @@ mod tests {
             Ok(Value::Int((-13289123 as i32).into()))
         );
     }
+
+    #[test]
+    fn coerce_single_value_to_list() {
+        let int_type = TypeDefinition::Scalar(ScalarType::new("Int".to_string()));
+        let resolver = |_: &str| Some(&int_type);
+        let list_type = Type::ListType(Box::new(Type::NonNullType(Box::new(Type::NamedType(
+            "Int".to_string(),
+        )))));
+
+        assert_eq!(
+            coerce_value(Value::Int(7), &list_type, &resolver),
+            Ok(Value::List(vec![Value::Int(7)]))
+        );
+        assert_eq!(
+            coerce_value(Value::List(vec![Value::Int(7)]), &list_type, &resolver),
+            Ok(Value::List(vec![Value::Int(7)]))
+        );
+        assert!(coerce_value(Value::Boolean(true), &list_type, &resolver).is_err());
+        assert_eq!(
+            coerce_value(Value::Null, &list_type, &resolver),
+            Ok(Value::Null)
+        );
+    }
 }
@@ -10,8 +10,8 @@ use graph::prelude::{
     QueryExecutionError, QueryResult, Schema,
 };
 use graph_graphql::prelude::{
-    a, api_schema, execute_query, ExecutionContext, Query as PreparedQuery, QueryExecutionOptions,
-    Resolver,
+    a, api_schema, execute_query, ExecutionContext, InMemoryQueryBlockCache,
+    Query as PreparedQuery, QueryExecutionOptions, Resolver,
 };
 use test_store::graphql_metrics;
 use test_store::LOAD_MANAGER;
@@ -570,6 +570,7 @@ async fn introspection_query(schema: Schema, query: &str) -> QueryResult {
         max_first: std::u32::MAX,
         max_skip: std::u32::MAX,
         load_manager: LOAD_MANAGER.clone(),
+        query_block_cache: InMemoryQueryBlockCache::shared(),
     };
 
     let schema = Arc::new(ApiSchema::from_api_schema(schema).unwrap());
@@ -3,6 +3,7 @@ extern crate pretty_assertions;
 
 use graph::components::store::{EntityKey, EntityType};
 use graph::data::subgraph::schema::DeploymentCreate;
+use graph::data::subgraph::IndexerHints;
 use graph::entity;
 use graph::prelude::SubscriptionResult;
 use graphql_parser::Pos;
@@ -17,7 +18,7 @@ use std::{
 
 use graph::{
     components::store::DeploymentLocator,
-    data::graphql::{object, object_value},
+    data::graphql::{effort::LoadManager, object, object_value},
     data::subgraph::schema::SubgraphError,
     data::{
         query::{QueryResults, QueryTarget},
@@ -130,6 +131,7 @@ async fn setup(
             data_sources: vec![],
             graft: None,
             templates: vec![],
+            indexer_hints: IndexerHints::default(),
             chain: PhantomData,
         };
 
@@ -737,6 +739,309 @@ fn can_query_with_child_filter_on_named_type_field() {
     })
 }
 
+#[test]
+fn can_query_with_or_filter() {
+    const QUERY: &str = "
+    query {
+        musicians(first: 100, orderBy: id, where: { or: [{ name: \"John\" }, { mainBand_: { name_contains: \"The Amateurs\" } }] }) {
+            name
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            musicians: vec![
+                object! { name: "John" },
+                object! { name: "Tom" },
+            ]
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
+#[test]
+fn can_query_with_nested_and_or_filter() {
+    const QUERY: &str = "
+    query {
+        musicians(first: 100, orderBy: id, where: { and: [{ or: [{ name: \"John\" }, { name: \"Lisa\" }] }, { writtenSongs_: { title_contains: \"Rock\" } }] }) {
+            name
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            musicians: vec![
+                object! { name: "Lisa" },
+            ]
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
+#[test]
+fn can_query_with_sorting_by_child_entity() {
+    const QUERY: &str = "
+    query {
+        musicians(first: 100, orderBy: mainBand__name, orderDirection: asc) {
+            name
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        // Musicians without a main band sort last
+        let exp = object! {
+            musicians: vec![
+                object! { name: "Tom" },
+                object! { name: "John" },
+                object! { name: "Lisa" },
+                object! { name: "Valerie" },
+            ]
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
+#[test]
+fn can_query_with_sorting_by_multiple_fields() {
+    const QUERY: &str = "
+    query {
+        musicians(first: 100, orderBy: [mainBand, name], orderDirection: [asc, desc]) {
+            name
+        }
+        sameDirection: musicians(first: 100, orderBy: [mainBand, name], orderDirection: desc) {
+            name
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            musicians: vec![
+                object! { name: "Lisa" },
+                object! { name: "John" },
+                object! { name: "Tom" },
+                object! { name: "Valerie" },
+            ],
+            sameDirection: vec![
+                object! { name: "Valerie" },
+                object! { name: "Tom" },
+                object! { name: "Lisa" },
+                object! { name: "John" },
+            ]
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
+#[test]
+fn order_direction_must_match_order_by() {
+    const QUERY: &str = "
+    query {
+        musicians(orderBy: [mainBand, name], orderDirection: [asc, desc, asc]) {
+            name
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        let errors = result.to_result().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .to_string()
+            .contains("`orderDirection` must have one direction"));
+    })
+}
+
+#[test]
+fn can_page_with_cursors() {
+    use serde_json::Value;
+
+    async fn page(deployment: &DeploymentLocator, args: &str) -> Vec<(String, String)> {
+        let query = format!(
+            "query {{ musicians({}, orderBy: [mainBand, name], orderDirection: [asc, desc]) \
+             {{ name _cursor }} }}",
+            args
+        );
+        let result = serde_json::to_value(&execute_query(deployment, &query).await).unwrap();
+        match &result["data"]["musicians"] {
+            Value::Array(musicians) => musicians
+                .iter()
+                .map(|musician| {
+                    (
+                        musician["name"].as_str().unwrap().to_owned(),
+                        musician["_cursor"].as_str().unwrap().to_owned(),
+                    )
+                })
+                .collect(),
+            _ => panic!("unexpected result {}", result),
+        }
+    }
+
+    fn names(page: &[(String, String)]) -> Vec<&str> {
+        page.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    run_test_sequentially(|store| async move {
+        let deployment = setup(
+            store.as_ref(),
+            IdType::String.deployment_id(),
+            BTreeSet::new(),
+            IdType::String,
+        )
+        .await;
+
+        let first = page(&deployment, "first: 2").await;
+        assert_eq!(names(&first), ["Lisa", "John"]);
+
+        let second = page(&deployment, &format!("first: 2, after: \"{}\"", first[1].1)).await;
+        assert_eq!(names(&second), ["Tom", "Valerie"]);
+
+        // Valerie does not have a `mainBand`; nothing comes after her
+        let last = page(&deployment, &format!("after: \"{}\"", second[1].1)).await;
+        assert!(last.is_empty());
+
+        // With `before`, we get the entities closest to the cursor, but still
+        // in the requested order
+        let closest = page(
+            &deployment,
+            &format!("first: 1, before: \"{}\"", second[0].1),
+        )
+        .await;
+        assert_eq!(names(&closest), ["John"]);
+        let all = page(&deployment, &format!("before: \"{}\"", second[1].1)).await;
+        assert_eq!(names(&all), ["Lisa", "John", "Tom"]);
+
+        // A cursor can only be used with the order it was made for
+        for query in [
+            format!(
+                "query {{ musicians(orderBy: name, after: \"{}\") {{ name }} }}",
+                first[1].1
+            ),
+            "query { musicians(after: \"not a cursor\") { name } }".to_owned(),
+        ] {
+            let result = execute_query(&deployment, &query).await;
+            let errors = result.to_result().unwrap_err();
+            assert_eq!(errors.len(), 1);
+            assert!(
+                errors[0].to_string().starts_with("invalid cursor"),
+                "unexpected error {}",
+                errors[0]
+            );
+        }
+    })
+}
+
+#[test]
+fn can_query_aggregates() {
+    const QUERY: &str = "
+    query {
+        songStatsAggregate {
+            count
+            sum { played }
+            avg { played }
+            min { played }
+            max { played }
+        }
+        filtered: songStatsAggregate(where: { played_gt: 10 }) {
+            count
+            total: sum { played }
+        }
+        none: songStatsAggregate(where: { played_gt: 100 }) {
+            count
+            max { played }
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            songStatsAggregate: object! {
+                count: 2,
+                sum: object! { played: "25" },
+                avg: object! { played: "12.5" },
+                min: object! { played: 10 },
+                max: object! { played: 15 },
+            },
+            filtered: object! {
+                count: 1,
+                total: object! { played: "15" },
+            },
+            none: object! {
+                count: 0,
+                max: object! { played: r::Value::Null },
+            },
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
+#[test]
+fn can_query_with_sorting_by_child_interface() {
+    const QUERY: &str = "
+    query {
+        users(first: 100, orderBy: latestReview__body, orderDirection: desc) {
+            name
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            users: vec![
+                object! { name: "Goodwill" },
+                object! { name: "Baden" },
+            ]
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
+#[test]
+fn can_query_nested_collection_with_sorting_by_child_entity() {
+    const QUERY: &str = "
+    query {
+        bands(first: 100, orderBy: id) {
+            name
+            members(first: 100, orderBy: mainBand__name, orderDirection: asc) {
+                name
+            }
+        }
+    }";
+
+    run_query(QUERY, |result, _| {
+        let exp = object! {
+            bands: vec![
+                object! {
+                    name: "The Musicians",
+                    members: vec![
+                        object! { name: "Tom" },
+                        object! { name: "John" },
+                        object! { name: "Lisa" },
+                    ]
+                },
+                object! {
+                    name: "The Amateurs",
+                    members: vec![
+                        object! { name: "Tom" },
+                        object! { name: "John" },
+                    ]
+                },
+            ]
+        };
+
+        let data = extract_data!(result).unwrap();
+        assert_eq!(data, exp);
+    })
+}
+
 #[test]
 fn can_query_with_child_filter_on_derived_named_type_field() {
     const QUERY: &str = "
@@ -1193,6 +1498,59 @@ fn query_complexity() {
     })
 }
 
+#[test]
+fn rate_limits_clients() {
+    run_test_sequentially(|store| async move {
+        let deployment = setup_readonly(store.as_ref()).await;
+        // The allowance is so small that a client is rate limited after a
+        // few queries
+        let load_manager = Arc::new(
+            LoadManager::new(&*LOGGER, vec![], METRICS_REGISTRY.clone())
+                .with_client_limits(Duration::from_millis(1), Duration::from_millis(1)),
+        );
+        let runner = Arc::new(GraphQlRunner::new(
+            &*LOGGER,
+            STORE.clone(),
+            SUBSCRIPTION_MANAGER.clone(),
+            load_manager,
+            METRICS_REGISTRY.clone(),
+        ));
+        let run = |client: &str| {
+            let mut query = Query::new(
+                graphql_parser::parse_query("query { musicians(first: 100) { name } }")
+                    .unwrap()
+                    .into_static(),
+                None,
+            );
+            query.client = Some(client.to_owned());
+            let target = QueryTarget::Deployment(deployment.hash.clone(), Default::default());
+            runner.clone().run_query(query, target)
+        };
+
+        let mut rate_limited = None;
+        for _ in 0..1000 {
+            let result = first_result(run("greedy").await).await;
+            if let Err(errors) = result.to_result() {
+                rate_limited = Some(errors);
+                break;
+            }
+        }
+        match rate_limited
+            .expect("the client was never rate limited")
+            .as_slice()
+        {
+            [QueryError::ExecutionError(QueryExecutionError::RateLimited(client, _))] => {
+                assert_eq!("greedy", client)
+            }
+            errors => panic!("unexpected errors {:?}", errors),
+        }
+
+        // Other clients are not affected
+        let result = first_result(run("modest").await).await;
+        assert!(result.to_result().is_ok());
+    })
+}
+
 #[test]
 fn query_complexity_subscriptions() {
     run_test_sequentially(|store| async move {
@@ -226,9 +226,23 @@ pub enum Command {
     Prune {
         /// The deployment to prune (see `help info`)
         deployment: DeploymentSearch,
-        /// Prune tables with a ratio of entities to entity versions lower than this
-        #[clap(long, short, default_value = "0.20")]
-        prune_ratio: f64,
+        /// Rebuild tables when pruning would remove at least this fraction
+        /// of their rows [default: GRAPH_STORE_HISTORY_REBUILD_THRESHOLD]
+        #[clap(long, short)]
+        rebuild_threshold: Option<f64>,
+        /// Delete rows from tables when pruning would remove at least this
+        /// fraction of their rows, but less than the rebuild threshold
+        /// [default: GRAPH_STORE_HISTORY_DELETE_THRESHOLD]
+        #[clap(long, short)]
+        delete_threshold: Option<f64>,
+        /// How to prune tables: `rebuild` copies the rows that are kept
+        /// into new tables, `delete` deletes outdated rows in batches, and
+        /// `auto` picks one of them for each table based on the thresholds
+        #[clap(
+            long, short, default_value = "auto",
+            possible_values = &["auto", "rebuild", "delete"]
+        )]
+        strategy: String,
         /// How much history to keep in blocks
         #[clap(long, short = 'y', default_value = "10000")]
         history: usize,
@@ -1171,10 +1185,21 @@ async fn main() -> anyhow::Result<()> {
         Prune {
             deployment,
             history,
-            prune_ratio,
+            rebuild_threshold,
+            delete_threshold,
+            strategy,
         } => {
             let (store, primary_pool) = ctx.store_and_primary();
-            commands::prune::run(store, primary_pool, deployment, history, prune_ratio).await
+            commands::prune::run(
+                store,
+                primary_pool,
+                deployment,
+                history,
+                rebuild_threshold,
+                delete_threshold,
+                strategy,
+            )
+            .await
         }
         Drop {
             deployment,
@@ -1,7 +1,8 @@
 use clap::Parser as _;
 use ethereum::chain::{EthereumAdapterSelector, EthereumStreamBuilder};
 use ethereum::{
-    BlockIngestor as EthereumBlockIngestor, EthereumAdapterTrait, EthereumNetworks, RuntimeAdapter,
+    BlockIngestor as EthereumBlockIngestor, DeclaredCallCache, EthereumAdapterTrait,
+    EthereumNetworks, RuntimeAdapter,
 };
 use git_testament::{git_testament, render_testament};
 use graph::blockchain::firehose_block_ingestor::FirehoseBlockIngestor;
@@ -37,7 +38,9 @@ use graph_server_index_node::IndexNodeServer;
 use graph_server_json_rpc::JsonRpcServer;
 use graph_server_metrics::PrometheusMetricsServer;
 use graph_server_websocket::SubscriptionServer as GraphQLSubscriptionServer;
-use graph_store_postgres::{register_jobs as register_store_jobs, ChainHeadUpdateListener, Store};
+use graph_store_postgres::{
+    register_jobs as register_store_jobs, ChainHeadUpdateListener, PostgresQueryBlockCache, Store,
+};
 use near::NearStreamBuilder;
 use std::collections::BTreeMap;
 use std::io::{BufRead, BufReader};
@@ -352,13 +355,19 @@ async fn main() {
             expensive_queries,
             metrics_registry.clone(),
         ));
-        let graphql_runner = Arc::new(GraphQlRunner::new(
+        let mut graphql_runner = GraphQlRunner::new(
             &logger,
             network_store.clone(),
             subscription_manager.clone(),
             load_manager,
             graphql_metrics_registry,
-        ));
+        );
+        if ENV_VARS.graphql.query_cache_shared {
+            graphql_runner = graphql_runner.with_query_block_cache(Arc::new(
+                PostgresQueryBlockCache::from_env(primary_pool.clone()),
+            ));
+        }
+        let graphql_runner = Arc::new(graphql_runner);
         let mut graphql_server =
             GraphQLQueryServer::new(&logger_factory, graphql_runner.clone(), node_id.clone());
         let subscription_server =
@@ -679,9 +688,11 @@ fn ethereum_networks_as_chains(
                 chain_store.clone(),
             );
 
+            let call_cache = Arc::new(DeclaredCallCache::new(chain_store.cheap_clone()));
+
             let runtime_adapter = Arc::new(RuntimeAdapter {
                 eth_adapters: Arc::new(eth_adapters.clone()),
-                call_cache: chain_store.cheap_clone(),
+                call_cache: call_cache.cheap_clone(),
             });
 
             let chain = ethereum::Chain::new(
@@ -689,8 +700,8 @@ fn ethereum_networks_as_chains(
                 network_name.clone(),
                 node_id.clone(),
                 registry.clone(),
-                chain_store.cheap_clone(),
                 chain_store,
+                call_cache,
                 firehose_endpoints.map_or_else(|| FirehoseEndpoints::new(), |v| v.clone()),
                 eth_adapters.clone(),
                 chain_head_update_listener.clone(),
@@ -6,7 +6,7 @@ use std::{
 };
 
 use graph::{
-    components::store::{PruneReporter, StatusStore},
+    components::store::{PruneReporter, PruneRequest, PruneStrategy, StatusStore},
     data::subgraph::status,
     prelude::{anyhow, BlockNumber},
 };
@@ -25,6 +25,8 @@ struct Progress {
     final_start: Instant,
     final_table_start: Instant,
     nonfinal_start: Instant,
+    delete_start: Instant,
+    delete_table_start: Instant,
 }
 
 impl Progress {
@@ -36,6 +38,8 @@ impl Progress {
             final_start: Instant::now(),
             final_table_start: Instant::now(),
             nonfinal_start: Instant::now(),
+            delete_start: Instant::now(),
+            delete_table_start: Instant::now(),
         }
     }
 }
@@ -125,6 +129,30 @@ impl PruneReporter for Progress {
         std::io::stdout().flush().ok();
     }
 
+    fn delete_start(&mut self, earliest_block: BlockNumber) {
+        println!("Delete outdated entities (versions that end before {earliest_block})");
+        print_copy_header();
+
+        self.delete_start = Instant::now();
+        self.delete_table_start = self.delete_start;
+    }
+
+    fn delete_batch(&mut self, table: &str, _rows: usize, total_rows: usize, finished: bool) {
+        print_copy_row(table, total_rows, self.delete_table_start.elapsed());
+        if finished {
+            println!("");
+            self.delete_table_start = Instant::now();
+        }
+        std::io::stdout().flush().ok();
+    }
+
+    fn delete_finish(&mut self) {
+        println!(
+            "Finished deleting outdated entity versions in {}s\n",
+            self.delete_start.elapsed().as_secs()
+        );
+    }
+
     fn finish_prune(&mut self) {
         println!("Finished pruning in {}s", self.start.elapsed().as_secs());
     }
@@ -135,7 +163,9 @@ pub async fn run(
     primary_pool: ConnectionPool,
     search: DeploymentSearch,
     history: usize,
-    prune_ratio: f64,
+    rebuild_threshold: Option<f64>,
+    delete_threshold: Option<f64>,
+    strategy: String,
 ) -> Result<(), anyhow::Error> {
     let history = history as BlockNumber;
     let deployment = search.locate_unique(&primary_pool)?;
@@ -158,25 +188,39 @@ pub async fn run(
         return Err(anyhow!("deployment {deployment} has only indexed up to block {latest} and we can't preserve {history} blocks of history"));
     }
 
+    let mut req = PruneRequest::new(
+        &deployment,
+        history,
+        // Using the setting for eth chains is a bit lazy; the value
+        // should really depend on the chain, but we don't have a
+        // convenient way to figure out how each chain deals with
+        // finality
+        ETH_ENV.reorg_threshold,
+        status.earliest_block_number,
+        latest,
+    )?;
+    if let Some(rebuild_threshold) = rebuild_threshold {
+        req.rebuild_threshold = rebuild_threshold;
+    }
+    if let Some(delete_threshold) = delete_threshold {
+        req.delete_threshold = delete_threshold;
+    }
+    req.forced_strategy = match strategy.as_str() {
+        "auto" => None,
+        "rebuild" => Some(PruneStrategy::Rebuild),
+        "delete" => Some(PruneStrategy::Delete),
+        _ => return Err(anyhow!("unknown pruning strategy `{strategy}`")),
+    };
+
     println!("prune {deployment}");
     println!("    latest: {latest}");
-    println!("     final: {}", latest - ETH_ENV.reorg_threshold);
-    println!("  earliest: {}\n", latest - history);
+    println!("     final: {}", req.final_block);
+    println!("  earliest: {}\n", req.earliest_block);
 
     let reporter = Box::new(Progress::new());
     store
         .subgraph_store()
-        .prune(
-            reporter,
-            &deployment,
-            latest - history,
-            // Using the setting for eth chains is a bit lazy; the value
-            // should really depend on the chain, but we don't have a
-            // convenient way to figure out how each chain deals with
-            // finality
-            ETH_ENV.reorg_threshold,
-            prune_ratio,
-        )
+        .prune(reporter, &deployment, req)
         .await?;
 
     Ok(())
@@ -11,7 +11,9 @@ use crate::manager::PanicSubscriptionManager;
 use crate::store_builder::StoreBuilder;
 use crate::MetricsContext;
 use ethereum::chain::{EthereumAdapterSelector, EthereumStreamBuilder};
-use ethereum::{ProviderEthRpcMetrics, RuntimeAdapter as EthereumRuntimeAdapter};
+use ethereum::{
+    DeclaredCallCache, ProviderEthRpcMetrics, RuntimeAdapter as EthereumRuntimeAdapter,
+};
 use graph::anyhow::{bail, format_err};
 use graph::blockchain::{BlockchainKind, BlockchainMap};
 use graph::cheap_clone::CheapClone;
@@ -116,13 +118,15 @@ pub async fn run(
         .chain_store(network_name.as_ref())
         .expect(format!("No chain store for {}", &network_name).as_ref());
 
+    let call_cache = Arc::new(DeclaredCallCache::new(chain_store.cheap_clone()));
+
     let chain = ethereum::Chain::new(
         logger_factory.clone(),
         network_name.clone(),
         node_id.clone(),
         metrics_registry.clone(),
         chain_store.cheap_clone(),
-        chain_store.cheap_clone(),
+        call_cache.cheap_clone(),
         firehose_endpoints.clone(),
         eth_adapters.clone(),
         chain_head_update_listener,
@@ -135,7 +139,7 @@ pub async fn run(
             chain_store.cheap_clone(),
         )),
         Arc::new(EthereumRuntimeAdapter {
-            call_cache: chain_store.cheap_clone(),
+            call_cache,
             eth_adapters: Arc::new(eth_adapters2),
         }),
         ethereum::ENV_VARS.reorg_threshold,
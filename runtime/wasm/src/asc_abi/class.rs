@@ -421,6 +421,12 @@ impl From<EnumPayload> for i32 {
     }
 }
 
+impl From<EnumPayload> for i64 {
+    fn from(payload: EnumPayload) -> i64 {
+        payload.0 as i64
+    }
+}
+
 impl From<EnumPayload> for f64 {
     fn from(payload: EnumPayload) -> f64 {
         f64::from_bits(payload.0)
@@ -545,6 +551,8 @@ pub enum StoreValueKind {
     Null,
     Bytes,
     BigInt,
+    Int8,
+    Timestamp,
 }
 
 impl StoreValueKind {
@@ -560,6 +568,8 @@ impl StoreValueKind {
             Value::Null => StoreValueKind::Null,
             Value::Bytes(_) => StoreValueKind::Bytes,
             Value::BigInt(_) => StoreValueKind::BigInt,
+            Value::Int8(_) => StoreValueKind::Int8,
+            Value::Timestamp(_) => StoreValueKind::Timestamp,
         }
     }
 }
@@ -618,6 +628,10 @@ impl AscIndexId for AscTypedMap<AscString, AscTypedMap<AscString, AscEnum<JsonVa
 }
 
 pub type AscEntity = AscTypedMap<AscString, AscEnum<StoreValueKind>>;
+
+impl AscIndexId for Array<AscPtr<AscEntity>> {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::ArrayTypedMapStringStoreValue;
+}
 pub(crate) type AscJson = AscTypedMap<AscString, AscEnum<JsonValueKind>>;
 
 #[repr(u32)]
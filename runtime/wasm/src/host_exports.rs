@@ -10,7 +10,7 @@ use web3::types::H160;
 
 use graph::blockchain::Blockchain;
 use graph::components::store::EnsLookup;
-use graph::components::store::{EntityKey, EntityType};
+use graph::components::store::{EntityKey, EntityType, LoadRelatedRequest};
 use graph::components::subgraph::{CausalityRegion, ProofOfIndexingEvent, SharedProofOfIndexing};
 use graph::data::store;
 use graph::data_source::{DataSource, DataSourceTemplate};
@@ -211,6 +211,46 @@ impl<C: Blockchain> HostExports<C> {
         Ok(result)
     }
 
+    pub(crate) fn store_get_in_block(
+        &self,
+        state: &BlockState<C>,
+        entity_type: String,
+        entity_id: String,
+        gas: &GasCounter,
+    ) -> Result<Option<Entity>, anyhow::Error> {
+        let store_key = EntityKey {
+            entity_type: EntityType::new(entity_type),
+            entity_id: entity_id.into(),
+        };
+
+        let result = state.entity_cache.get_in_block(&store_key);
+        gas.consume_host_fn(gas::STORE_GET.with_args(complexity::Linear, (&store_key, &result)))?;
+
+        Ok(result)
+    }
+
+    pub(crate) fn store_load_related(
+        &self,
+        state: &mut BlockState<C>,
+        entity_type: String,
+        entity_id: String,
+        entity_field: String,
+        gas: &GasCounter,
+    ) -> Result<Vec<Entity>, anyhow::Error> {
+        let request = LoadRelatedRequest {
+            entity_type: EntityType::new(entity_type),
+            entity_id: entity_id.into(),
+            entity_field: entity_field.into(),
+        };
+
+        let result = state.entity_cache.load_related(&request)?;
+        gas.consume_host_fn(
+            gas::STORE_GET.with_args(complexity::Linear, (&request.entity_type, &result)),
+        )?;
+
+        Ok(result)
+    }
+
     /// Prints the module of `n` in hex.
     /// Integers are encoded using the least amount of digits (no leading zero digits).
     /// Their encoding may be of uneven length. The number zero encodes as "0x0".
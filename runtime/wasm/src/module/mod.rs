@@ -507,6 +507,21 @@ impl<C: Blockchain> WasmInstance<C> {
         link!("abort", abort, message_ptr, file_name_ptr, line, column);
 
         link!("store.get", store_get, "host_export_store_get", entity, id);
+        link!(
+            "store.getInBlock",
+            store_get_in_block,
+            "host_export_store_get_in_block",
+            entity,
+            id
+        );
+        link!(
+            "store.loadRelated",
+            store_load_related,
+            "host_export_store_load_related",
+            entity,
+            id,
+            field
+        );
         link!(
             "store.set",
             store_set,
@@ -1015,6 +1030,59 @@ impl<C: Blockchain> WasmInstanceContext<C> {
         Ok(ret)
     }
 
+    /// function store.getInBlock(entity: string, id: string): Entity | null
+    pub fn store_get_in_block(
+        &mut self,
+        gas: &GasCounter,
+        entity_ptr: AscPtr<AscString>,
+        id_ptr: AscPtr<AscString>,
+    ) -> Result<AscPtr<AscEntity>, HostExportError> {
+        let entity_type: String = asc_get(self, entity_ptr, gas)?;
+        let id: String = asc_get(self, id_ptr, gas)?;
+        let entity_option =
+            self.ctx
+                .host_exports
+                .store_get_in_block(&self.ctx.state, entity_type, id, gas)?;
+
+        let ret = match entity_option {
+            Some(entity) => {
+                let _section = self
+                    .host_metrics
+                    .stopwatch
+                    .start_section("store_get_in_block_asc_new");
+                asc_new(self, &entity.sorted(), gas)?
+            }
+            None => AscPtr::null(),
+        };
+
+        Ok(ret)
+    }
+
+    /// function store.loadRelated(entity_type: string, id: string, field: string): Array<Entity>
+    pub fn store_load_related(
+        &mut self,
+        gas: &GasCounter,
+        entity_type_ptr: AscPtr<AscString>,
+        id_ptr: AscPtr<AscString>,
+        field_ptr: AscPtr<AscString>,
+    ) -> Result<AscPtr<Array<AscPtr<AscEntity>>>, HostExportError> {
+        let entity_type: String = asc_get(self, entity_type_ptr, gas)?;
+        let id: String = asc_get(self, id_ptr, gas)?;
+        let field: String = asc_get(self, field_ptr, gas)?;
+        let entities = self.ctx.host_exports.store_load_related(
+            &mut self.ctx.state,
+            entity_type,
+            id,
+            field,
+            gas,
+        )?;
+
+        let entities: Vec<Vec<(String, store::Value)>> =
+            entities.into_iter().map(|entity| entity.sorted()).collect();
+        let ret = asc_new(self, &*entities, gas)?;
+        Ok(ret)
+    }
+
     /// function typeConversion.bytesToString(bytes: Bytes): string
     pub fn bytes_to_string(
         &mut self,
@@ -268,6 +268,13 @@ impl FromAscObj<AscEnum<StoreValueKind>> for store::Value {
                 let array: Vec<u8> = asc_get(heap, ptr, gas)?;
                 Value::BigInt(store::scalar::BigInt::from_signed_bytes_le(&array))
             }
+            StoreValueKind::Int8 => Value::Int8(i64::from(payload)),
+            StoreValueKind::Timestamp => {
+                let micros = i64::from(payload);
+                let ts = store::scalar::Timestamp::from_microseconds_since_epoch(micros)
+                    .map_err(|e| DeterministicHostError::from(anyhow::Error::from(e)))?;
+                Value::Timestamp(ts)
+            }
         })
     }
 }
@@ -296,6 +303,8 @@ impl ToAscObj<AscEnum<StoreValueKind>> for store::Value {
                     asc_new(heap, &*big_int.to_signed_bytes_le(), gas)?;
                 bytes_obj.into()
             }
+            Value::Int8(n) => EnumPayload::from(*n),
+            Value::Timestamp(ts) => EnumPayload::from(ts.as_microseconds_since_epoch()),
         };
 
         Ok(AscEnum {
@@ -0,0 +1,110 @@
+//! Support for running GraphQL subscriptions over plain HTTP: results are
+//! streamed to the client as server-sent events, following the 'distinct
+//! connections mode' of the GraphQL over SSE protocol. Each result is sent
+//! as a `next` event, and a `complete` event signals the end of the stream
+use std::time::Duration;
+
+use graph::prelude::*;
+use http::header::ACCEPT;
+use hyper::body::{Bytes, Sender};
+use hyper::{Body, Request};
+
+/// How often we send a comment to the client while there are no results.
+/// That keeps proxies from closing idle connections, and it is how we
+/// notice that a client went away
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+const KEEPALIVE_EVENT: &[u8] = b":\n\n";
+const COMPLETE_EVENT: &[u8] = b"event: complete\ndata:\n\n";
+
+/// Whether the client asked for a response as server-sent events
+pub(crate) fn accepts_event_stream(request: &Request<Body>) -> bool {
+    request
+        .headers()
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| media_type.split(';').next().map(str::trim) == Some("text/event-stream"))
+}
+
+/// Whether `document` contains a subscription operation
+pub(crate) fn is_subscription(document: &q::Document) -> bool {
+    document.definitions.iter().any(|def| {
+        matches!(
+            def,
+            q::Definition::Operation(q::OperationDefinition::Subscription(_))
+        )
+    })
+}
+
+fn next_event(result: &QueryResult) -> Bytes {
+    let json = serde_json::to_string(result).expect("Failed to serialize GraphQL response to JSON");
+    Bytes::from(format!("event: next\ndata: {}\n\n", json))
+}
+
+/// Send the results of a subscription to the client until there are no
+/// more results or the client disconnects. Dropping `results` when we
+/// return unsubscribes from changes in the store
+pub(crate) async fn send_events(
+    logger: Logger,
+    mut results: QueryResultStream,
+    mut sender: Sender,
+) {
+    let mut keepalive = tokio::time::interval(KEEPALIVE_INTERVAL);
+    // The first tick completes immediately
+    keepalive.tick().await;
+
+    loop {
+        let event = tokio::select! {
+            result = results.next() => match result {
+                Some(result) => next_event(&result),
+                None => {
+                    let _ = sender.send_data(Bytes::from_static(COMPLETE_EVENT)).await;
+                    break;
+                }
+            },
+            _ = keepalive.tick() => Bytes::from_static(KEEPALIVE_EVENT),
+        };
+
+        if sender.send_data(event).await.is_err() {
+            debug!(logger, "Client closed subscription event stream");
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Body, Request};
+
+    use super::{accepts_event_stream, is_subscription};
+
+    #[test]
+    fn detects_event_stream_requests() {
+        let request = |accept: &str| {
+            Request::builder()
+                .header("Accept", accept)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        assert!(accepts_event_stream(&request("text/event-stream")));
+        assert!(accepts_event_stream(&request(
+            "application/json, text/event-stream;q=0.9"
+        )));
+        assert!(!accepts_event_stream(&request("application/json")));
+        assert!(!accepts_event_stream(
+            &Request::builder().body(Body::empty()).unwrap()
+        ));
+    }
+
+    #[test]
+    fn detects_subscriptions() {
+        let parse = |text| graphql_parser::parse_query(text).unwrap().into_static();
+
+        assert!(is_subscription(&parse("subscription { users { id } }")));
+        assert!(!is_subscription(&parse("{ users { id } }")));
+        assert!(!is_subscription(&parse("query { users { id } }")));
+    }
+}
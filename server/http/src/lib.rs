@@ -7,7 +7,10 @@ extern crate graphql_parser;
 extern crate http;
 extern crate hyper;
 extern crate serde;
+extern crate sha2;
 
+mod event_stream;
+mod persisted_queries;
 mod request;
 mod server;
 mod service;
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+
+use graph::prelude::*;
+use graph::util::cache_weight::CacheWeight;
+use graph::util::lfu_cache::LfuCache;
+use sha2::{Digest, Sha256};
+
+/// A parsed query in the cache of persisted queries. We use the length of
+/// the query text as an estimate of how much memory the parsed query uses
+#[derive(Debug, Default)]
+struct CachedDocument {
+    document: Option<q::Document>,
+    text_len: usize,
+}
+
+impl CacheWeight for CachedDocument {
+    fn indirect_weight(&self) -> usize {
+        self.text_len
+    }
+}
+
+/// Support for automatic persisted queries: clients send the sha256 hash
+/// of a query instead of its text, and we look the query up by its hash.
+/// Clients register a query by sending its text along with its hash once;
+/// the parsed query is then kept in a cache whose size is bounded by
+/// `GRAPH_GRAPHQL_PERSISTED_QUERY_CACHE_MAX_MEM`.
+///
+/// If the operator provides an allow-list with
+/// `GRAPH_GRAPHQL_PERSISTED_QUERY_ALLOW_LIST`, only queries in that list
+/// can be run, and they can always be referred to by their hash
+#[derive(Debug)]
+pub struct PersistedQueries {
+    cache: Mutex<LfuCache<String, CachedDocument>>,
+    max_weight: usize,
+    allow_list: Option<HashMap<String, q::Document>>,
+}
+
+impl PersistedQueries {
+    pub fn new(max_weight: usize, allow_list: Option<HashMap<String, q::Document>>) -> Self {
+        PersistedQueries {
+            cache: Mutex::new(LfuCache::new()),
+            max_weight,
+            allow_list,
+        }
+    }
+
+    /// Set up persisted queries as configured by the environment, loading
+    /// the allow-list if there is one
+    pub fn from_env() -> Result<Self, Error> {
+        let allow_list = ENV_VARS
+            .graphql
+            .persisted_query_allow_list
+            .as_ref()
+            .map(|path| Self::load_allow_list(path))
+            .transpose()?;
+        Ok(Self::new(
+            ENV_VARS.graphql.persisted_query_cache_max_mem,
+            allow_list,
+        ))
+    }
+
+    /// Read an allow-list from the JSON file at `path`. The file must
+    /// contain an object that maps the hash of each query to its text
+    fn load_allow_list(path: &str) -> Result<HashMap<String, q::Document>, Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| anyhow!("failed to read query allow-list `{}`: {}", path, e))?;
+        let queries: HashMap<String, String> = serde_json::from_str(&text)
+            .map_err(|e| anyhow!("query allow-list `{}` is not valid: {}", path, e))?;
+        queries
+            .into_iter()
+            .map(|(hash, query)| {
+                let hash = hash.to_lowercase();
+                if hash != sha256(&query) {
+                    return Err(anyhow!(
+                        "query allow-list `{}`: the query for `{}` has a different hash",
+                        path,
+                        hash
+                    ));
+                }
+                let document = graphql_parser::parse_query(&query)
+                    .map_err(|e| {
+                        anyhow!(
+                            "query allow-list `{}`: the query for `{}` is invalid: {}",
+                            path,
+                            hash,
+                            e
+                        )
+                    })?
+                    .into_static();
+                Ok((hash, document))
+            })
+            .collect()
+    }
+
+    /// Return the parsed query for a request that has the query text
+    /// `text`, the persisted query hash `hash` or both
+    pub fn document(
+        &self,
+        hash: Option<&str>,
+        text: Option<&str>,
+    ) -> Result<q::Document, QueryError> {
+        let hash = match (hash, text) {
+            (Some(hash), Some(text)) => {
+                let hash = hash.to_lowercase();
+                if hash != sha256(text) {
+                    return Err(QueryExecutionError::PersistedQueryHashMismatch(hash).into());
+                }
+                hash
+            }
+            (Some(hash), None) => hash.to_lowercase(),
+            (None, Some(text)) if self.allow_list.is_some() => sha256(text),
+            (None, Some(text)) => return parse(text),
+            (None, None) => unreachable!("requests have a query text, a hash, or both"),
+        };
+
+        if let Some(allow_list) = &self.allow_list {
+            return allow_list
+                .get(&hash)
+                .cloned()
+                .ok_or_else(|| QueryExecutionError::QueryNotAllowed(hash).into());
+        }
+
+        let cached = self
+            .cache
+            .lock()
+            .unwrap()
+            .get(&hash)
+            .and_then(|cached| cached.document.clone());
+        if let Some(document) = cached {
+            return Ok(document);
+        }
+
+        let text = text.ok_or(QueryExecutionError::PersistedQueryNotFound)?;
+        let document = parse(text)?;
+        if self.max_weight > 0 {
+            let cached = CachedDocument {
+                document: Some(document.clone()),
+                text_len: text.len(),
+            };
+            let mut cache = self.cache.lock().unwrap();
+            cache.insert(hash, cached);
+            cache.evict(self.max_weight);
+        }
+        Ok(document)
+    }
+}
+
+fn parse(text: &str) -> Result<q::Document, QueryError> {
+    graphql_parser::parse_query(text)
+        .map(|document| document.into_static())
+        .map_err(|e| QueryError::ParseError(Arc::new(e.into())))
+}
+
+/// The hex encoded sha256 hash of `text`
+fn sha256(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use graph::prelude::{QueryError, QueryExecutionError};
+
+    use super::{sha256, PersistedQueries};
+
+    const QUERY: &str = "{ users { name } }";
+
+    fn parse(text: &str) -> graph::prelude::q::Document {
+        graphql_parser::parse_query(text).unwrap().into_static()
+    }
+
+    fn is_error(result: Result<graph::prelude::q::Document, QueryError>, expected: &str) -> bool {
+        match result {
+            Err(QueryError::ExecutionError(e)) => e.to_string().starts_with(expected),
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn registers_and_finds_queries_by_hash() {
+        let queries = PersistedQueries::new(1_000_000, None);
+        let hash = sha256(QUERY);
+
+        assert!(is_error(
+            queries.document(Some(&hash), None),
+            "PersistedQueryNotFound"
+        ));
+        assert_eq!(
+            parse(QUERY),
+            queries.document(Some(&hash), Some(QUERY)).unwrap()
+        );
+        assert_eq!(parse(QUERY), queries.document(Some(&hash), None).unwrap());
+        assert_eq!(
+            parse(QUERY),
+            queries.document(Some(&hash.to_uppercase()), None).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_hash() {
+        let queries = PersistedQueries::new(1_000_000, None);
+        let hash = sha256("{ other }");
+
+        assert!(is_error(
+            queries.document(Some(&hash), Some(QUERY)),
+            "the query does not have the sha256 hash"
+        ));
+        assert!(is_error(
+            queries.document(Some(&hash), None),
+            "PersistedQueryNotFound"
+        ));
+    }
+
+    #[test]
+    fn does_not_remember_queries_without_cache() {
+        let queries = PersistedQueries::new(0, None);
+        let hash = sha256(QUERY);
+
+        assert_eq!(
+            parse(QUERY),
+            queries.document(Some(&hash), Some(QUERY)).unwrap()
+        );
+        assert!(is_error(
+            queries.document(Some(&hash), None),
+            "PersistedQueryNotFound"
+        ));
+    }
+
+    #[test]
+    fn only_allows_queries_in_allow_list() {
+        let hash = sha256(QUERY);
+        let allow_list = HashMap::from_iter([(hash.clone(), parse(QUERY))]);
+        let queries = PersistedQueries::new(1_000_000, Some(allow_list));
+
+        assert_eq!(parse(QUERY), queries.document(Some(&hash), None).unwrap());
+        assert_eq!(parse(QUERY), queries.document(None, Some(QUERY)).unwrap());
+
+        let other = "{ users { id } }";
+        assert!(is_error(
+            queries.document(None, Some(other)),
+            "the query with sha256 hash"
+        ));
+        assert!(is_error(
+            queries.document(Some(&sha256(other)), Some(other)),
+            "the query with sha256 hash"
+        ));
+        assert!(matches!(
+            queries.document(Some(&sha256(other)), None),
+            Err(QueryError::ExecutionError(
+                QueryExecutionError::QueryNotAllowed(_)
+            ))
+        ));
+    }
+}
@@ -4,7 +4,12 @@ use hyper::body::Bytes;
 use graph::components::server::query::GraphQLServerError;
 use graph::prelude::*;
 
-pub fn parse_graphql_request(body: &Bytes) -> Result<Query, GraphQLServerError> {
+use crate::persisted_queries::PersistedQueries;
+
+pub fn parse_graphql_request(
+    body: &Bytes,
+    persisted_queries: &PersistedQueries,
+) -> Result<Query, GraphQLServerError> {
     // Parse request body as JSON
     let json: serde_json::Value = serde_json::from_slice(body)
         .map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))?;
@@ -14,22 +19,47 @@ pub fn parse_graphql_request(body: &Bytes) -> Result<Query, GraphQLServerError>
         GraphQLServerError::ClientError(String::from("Request data is not an object"))
     })?;
 
-    // Ensure the JSON data has a "query" field
-    let query_value = obj.get("query").ok_or_else(|| {
-        GraphQLServerError::ClientError(String::from(
-            "The \"query\" field is missing in request data",
-        ))
-    })?;
+    // Get the hash of the query if this is a persisted query
+    let hash = match obj
+        .get("extensions")
+        .and_then(|extensions| extensions.get("persistedQuery"))
+    {
+        None | Some(serde_json::Value::Null) => None,
+        Some(persisted_query) => {
+            if persisted_query.get("version").and_then(|v| v.as_i64()) != Some(1) {
+                return Err(GraphQLServerError::ClientError(String::from(
+                    "Unsupported persisted query version",
+                )));
+            }
+            let hash = persisted_query
+                .get("sha256Hash")
+                .and_then(|hash| hash.as_str())
+                .ok_or_else(|| {
+                    GraphQLServerError::ClientError(String::from(
+                        "The \"sha256Hash\" field of the persisted query is missing or not a string",
+                    ))
+                })?;
+            Some(hash)
+        }
+    };
 
-    // Ensure the "query" field is a string
-    let query_string = query_value.as_str().ok_or_else(|| {
-        GraphQLServerError::ClientError(String::from("The \"query\" field is not a string"))
-    })?;
+    // Ensure the JSON data has a "query" field, unless the request is for
+    // a persisted query
+    let query_string = match (obj.get("query"), hash) {
+        (None, Some(_)) => None,
+        (None, None) => {
+            return Err(GraphQLServerError::ClientError(String::from(
+                "The \"query\" field is missing in request data",
+            )))
+        }
+        // Ensure the "query" field is a string
+        (Some(query_value), _) => Some(query_value.as_str().ok_or_else(|| {
+            GraphQLServerError::ClientError(String::from("The \"query\" field is not a string"))
+        })?),
+    };
 
-    // Parse the "query" field of the JSON body
-    let document = graphql_parser::parse_query(query_string)
-        .map_err(|e| GraphQLServerError::from(QueryError::ParseError(Arc::new(e.into()))))?
-        .into_static();
+    // Parse the "query" field of the JSON body, or look up the persisted query
+    let document = persisted_queries.document(hash, query_string)?;
 
     // Parse the "variables" field of the JSON body, if present
     let variables = match obj.get("variables") {
@@ -57,43 +87,52 @@ mod tests {
     };
 
     use super::parse_graphql_request;
+    use crate::persisted_queries::PersistedQueries;
 
     lazy_static! {
         static ref TARGET: QueryTarget = QueryTarget::Name(
             SubgraphName::new("test/request").unwrap(),
             Default::default()
         );
+        static ref PERSISTED_QUERIES: PersistedQueries = PersistedQueries::new(0, None);
     }
 
     #[test]
     fn rejects_invalid_json() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from("!@#)%"));
+        let request = parse_graphql_request(&hyper::body::Bytes::from("!@#)%"), &PERSISTED_QUERIES);
         request.expect_err("Should reject invalid JSON");
     }
 
     #[test]
     fn rejects_json_without_query_field() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from("{}"));
+        let request = parse_graphql_request(&hyper::body::Bytes::from("{}"), &PERSISTED_QUERIES);
         request.expect_err("Should reject JSON without query field");
     }
 
     #[test]
     fn rejects_json_with_non_string_query_field() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from("{\"query\": 5}"));
+        let request = parse_graphql_request(
+            &hyper::body::Bytes::from("{\"query\": 5}"),
+            &PERSISTED_QUERIES,
+        );
         request.expect_err("Should reject JSON with a non-string query field");
     }
 
     #[test]
     fn rejects_broken_queries() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from("{\"query\": \"foo\"}"));
+        let request = parse_graphql_request(
+            &hyper::body::Bytes::from("{\"query\": \"foo\"}"),
+            &PERSISTED_QUERIES,
+        );
         request.expect_err("Should reject broken queries");
     }
 
     #[test]
     fn accepts_valid_queries() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from(
-            "{\"query\": \"{ user { name } }\"}",
-        ));
+        let request = parse_graphql_request(
+            &hyper::body::Bytes::from("{\"query\": \"{ user { name } }\"}"),
+            &PERSISTED_QUERIES,
+        );
         let query = request.expect("Should accept valid queries");
         assert_eq!(
             query.document,
@@ -103,15 +142,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn rejects_unsupported_persisted_query_versions() {
+        let request = parse_graphql_request(
+            &hyper::body::Bytes::from(
+                "{\"extensions\": {\"persistedQuery\": {\"version\": 2, \"sha256Hash\": \"abc\"}}}",
+            ),
+            &PERSISTED_QUERIES,
+        );
+        request.expect_err("Should reject unsupported persisted query versions");
+    }
+
     #[test]
     fn accepts_null_variables() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from(
-            "\
+        let request = parse_graphql_request(
+            &hyper::body::Bytes::from(
+                "\
                  {\
                  \"query\": \"{ user { name } }\", \
                  \"variables\": null \
                  }",
-        ));
+            ),
+            &PERSISTED_QUERIES,
+        );
         let query = request.expect("Should accept null variables");
 
         let expected_query = graphql_parser::parse_query("{ user { name } }")
@@ -123,27 +176,33 @@ mod tests {
 
     #[test]
     fn rejects_non_map_variables() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from(
-            "\
+        let request = parse_graphql_request(
+            &hyper::body::Bytes::from(
+                "\
                  {\
                  \"query\": \"{ user { name } }\", \
                  \"variables\": 5 \
                  }",
-        ));
+            ),
+            &PERSISTED_QUERIES,
+        );
         request.expect_err("Should reject non-map variables");
     }
 
     #[test]
     fn parses_variables() {
-        let request = parse_graphql_request(&hyper::body::Bytes::from(
-            "\
+        let request = parse_graphql_request(
+            &hyper::body::Bytes::from(
+                "\
                  {\
                  \"query\": \"{ user { name } }\", \
                  \"variables\": { \
                  \"string\": \"s\", \"map\": {\"k\": \"v\"}, \"int\": 5 \
                  } \
                  }",
-        ));
+            ),
+            &PERSISTED_QUERIES,
+        );
         let query = request.expect("Should accept valid queries");
 
         let expected_query = graphql_parser::parse_query("{ user { name } }")
@@ -1,8 +1,10 @@
 use std::net::{Ipv4Addr, SocketAddrV4};
 
+use hyper::server::conn::AddrStream;
 use hyper::service::make_service_fn;
 use hyper::Server;
 
+use crate::persisted_queries::PersistedQueries;
 use crate::service::GraphQLService;
 use graph::prelude::{GraphQLServer as GraphQLServerTrait, *};
 use thiserror::Error;
@@ -12,6 +14,8 @@ use thiserror::Error;
 pub enum GraphQLServeError {
     #[error("Bind error: {0}")]
     BindError(#[from] hyper::Error),
+    #[error("Persisted queries error: {0}")]
+    PersistedQueriesError(String),
 }
 
 /// A GraphQL server based on Hyper.
@@ -58,6 +62,11 @@ where
             "Starting GraphQL HTTP server at: http://localhost:{}", port
         );
 
+        let persisted_queries = Arc::new(
+            PersistedQueries::from_env()
+                .map_err(|e| GraphQLServeError::PersistedQueriesError(e.to_string()))?,
+        );
+
         let addr = SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), port);
 
         // On every incoming request, launch a new GraphQL service that writes
@@ -65,10 +74,12 @@ where
         let logger_for_service = self.logger.clone();
         let graphql_runner = self.graphql_runner.clone();
         let node_id = self.node_id.clone();
-        let new_service = make_service_fn(move |_| {
+        let new_service = make_service_fn(move |conn: &AddrStream| {
             futures03::future::ok::<_, Error>(GraphQLService::new(
                 logger_for_service.clone(),
                 graphql_runner.clone(),
+                persisted_queries.clone(),
+                conn.remote_addr(),
                 ws_port,
                 node_id.clone(),
             ))
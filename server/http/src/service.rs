@@ -1,4 +1,5 @@
 use std::convert::TryFrom;
+use std::net::SocketAddr;
 use std::pin::Pin;
 use std::task::Context;
 use std::task::Poll;
@@ -10,11 +11,13 @@ use graph::{components::server::query::GraphQLServerError, data::query::QueryTar
 use http::header;
 use http::header::{
     ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
-    CONTENT_TYPE, LOCATION,
+    CACHE_CONTROL, CONTENT_TYPE, LOCATION,
 };
 use hyper::service::Service;
 use hyper::{Body, Method, Request, Response, StatusCode};
 
+use crate::event_stream::{accepts_event_stream, is_subscription, send_events};
+use crate::persisted_queries::PersistedQueries;
 use crate::request::parse_graphql_request;
 
 pub type GraphQLServiceResult = Result<Response<Body>, GraphQLServerError>;
@@ -27,6 +30,8 @@ pub type GraphQLServiceResponse =
 pub struct GraphQLService<Q> {
     logger: Logger,
     graphql_runner: Arc<Q>,
+    persisted_queries: Arc<PersistedQueries>,
+    remote_addr: SocketAddr,
     ws_port: u16,
     node_id: NodeId,
 }
@@ -36,6 +41,8 @@ impl<Q> Clone for GraphQLService<Q> {
         Self {
             logger: self.logger.clone(),
             graphql_runner: self.graphql_runner.clone(),
+            persisted_queries: self.persisted_queries.clone(),
+            remote_addr: self.remote_addr,
             ws_port: self.ws_port,
             node_id: self.node_id.clone(),
         }
@@ -47,10 +54,19 @@ where
     Q: GraphQlRunner,
 {
     /// Creates a new GraphQL service.
-    pub fn new(logger: Logger, graphql_runner: Arc<Q>, ws_port: u16, node_id: NodeId) -> Self {
+    pub fn new(
+        logger: Logger,
+        graphql_runner: Arc<Q>,
+        persisted_queries: Arc<PersistedQueries>,
+        remote_addr: SocketAddr,
+        ws_port: u16,
+        node_id: NodeId,
+    ) -> Self {
         GraphQLService {
             logger,
             graphql_runner,
+            persisted_queries,
+            remote_addr,
             ws_port,
             node_id,
         }
@@ -128,11 +144,8 @@ where
             GraphQLServerError::ClientError(format!("Invalid subgraph name {:?}", subgraph_name))
         })?;
 
-        self.handle_graphql_query(
-            QueryTarget::Name(subgraph_name, version),
-            request.into_body(),
-        )
-        .await
+        self.handle_graphql_query(QueryTarget::Name(subgraph_name, version), request)
+            .await
     }
 
     fn handle_graphql_query_by_id(
@@ -150,27 +163,49 @@ where
         match res {
             Err(_) => self.handle_not_found(),
             Ok((id, version)) => self
-                .handle_graphql_query(QueryTarget::Deployment(id, version), request.into_body())
+                .handle_graphql_query(QueryTarget::Deployment(id, version), request)
                 .boxed(),
         }
     }
 
+    /// Identify the client that sent `request` by the header configured
+    /// with `GRAPH_GRAPHQL_CLIENT_ID_HEADER` or, if there is none, by the
+    /// IP address of the connection
+    fn client(&self, request: &Request<Body>) -> String {
+        ENV_VARS
+            .graphql
+            .client_id_header
+            .as_ref()
+            .and_then(|name| request.headers().get(name.as_str()))
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+            .unwrap_or_else(|| self.remote_addr.ip().to_string())
+    }
+
     async fn handle_graphql_query(
         self,
         target: QueryTarget,
-        request_body: Body,
+        request: Request<Body>,
     ) -> GraphQLServiceResult {
         let service = self.clone();
+        let client = self.client(&request);
+        let event_stream = accepts_event_stream(&request);
 
         let start = Instant::now();
-        let body = hyper::body::to_bytes(request_body)
+        let body = hyper::body::to_bytes(request.into_body())
             .map_err(|_| GraphQLServerError::InternalError("Failed to read request body".into()))
             .await?;
-        let query = parse_graphql_request(&body);
+        let query = parse_graphql_request(&body, &self.persisted_queries);
         let query_parsing_time = start.elapsed();
 
         let result = match query {
-            Ok(query) => service.graphql_runner.run_query(query, target).await,
+            Ok(mut query) => {
+                query.client = Some(client);
+                if event_stream && is_subscription(&query.document) {
+                    return service.handle_graphql_subscription(query, target).await;
+                }
+                service.graphql_runner.run_query(query, target).await
+            }
             Err(GraphQLServerError::QueryError(e)) => QueryResult::from(e).into(),
             Err(e) => return Err(e),
         };
@@ -185,6 +220,38 @@ where
         Ok(result.as_http_response())
     }
 
+    /// Run a subscription and stream its results as server-sent events.
+    /// The subscription is stopped when the client disconnects
+    async fn handle_graphql_subscription(
+        self,
+        query: Query,
+        target: QueryTarget,
+    ) -> GraphQLServiceResult {
+        let subscription = Subscription { query };
+        let results = match self
+            .graphql_runner
+            .cheap_clone()
+            .run_subscription(subscription, target)
+            .await
+        {
+            Ok(results) => results,
+            Err(SubscriptionError::GraphQLError(e)) => {
+                return Ok(QueryResults::from(e).as_http_response())
+            }
+        };
+
+        let (sender, body) = Body::channel();
+        graph::spawn(send_events(self.logger.clone(), results, sender));
+
+        Ok(Response::builder()
+            .status(200)
+            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(body)
+            .unwrap())
+    }
+
     // Handles OPTIONS requests
     fn handle_graphql_options(&self, _request: Request<Body>) -> GraphQLServiceResponse {
         async {
@@ -353,6 +420,7 @@ mod tests {
     };
     use graph::prelude::*;
 
+    use crate::persisted_queries::PersistedQueries;
     use crate::test_utils;
 
     use super::GraphQLService;
@@ -401,7 +469,13 @@ mod tests {
             _subscription: Subscription,
             _target: QueryTarget,
         ) -> Result<SubscriptionResult, SubscriptionError> {
-            unreachable!();
+            let results = ["Jordi", "Jordan"].into_iter().map(|name| {
+                Arc::new(QueryResult::new(Object::from_iter(vec![(
+                    String::from("name"),
+                    r::Value::String(String::from(name)),
+                )])))
+            });
+            Ok(futures03::stream::iter(results).boxed())
         }
 
         fn load_manager(&self) -> Arc<LoadManager> {
@@ -420,7 +494,14 @@ mod tests {
         let graphql_runner = Arc::new(TestGraphQlRunner);
 
         let node_id = NodeId::new("test").unwrap();
-        let mut service = GraphQLService::new(logger, graphql_runner, 8001, node_id);
+        let mut service = GraphQLService::new(
+            logger,
+            graphql_runner,
+            Arc::new(PersistedQueries::new(0, None)),
+            "127.0.0.1:8000".parse().unwrap(),
+            8001,
+            node_id,
+        );
 
         let request = Request::builder()
             .method(Method::POST)
@@ -450,7 +531,14 @@ mod tests {
         let graphql_runner = Arc::new(TestGraphQlRunner);
 
         let node_id = NodeId::new("test").unwrap();
-        let mut service = GraphQLService::new(logger, graphql_runner, 8001, node_id);
+        let mut service = GraphQLService::new(
+            logger,
+            graphql_runner,
+            Arc::new(PersistedQueries::new(0, None)),
+            "127.0.0.1:8000".parse().unwrap(),
+            8001,
+            node_id,
+        );
 
         let request = Request::builder()
             .method(Method::POST)
@@ -476,4 +564,49 @@ mod tests {
             .expect("Query result field \"name\" is not a string");
         assert_eq!(name, "Jordi".to_string());
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn subscriptions_yield_event_stream() {
+        let logger = Logger::root(slog::Discard, o!());
+        let subgraph_id = USERS.clone();
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+
+        let node_id = NodeId::new("test").unwrap();
+        let mut service = GraphQLService::new(
+            logger,
+            graphql_runner,
+            Arc::new(PersistedQueries::new(0, None)),
+            "127.0.0.1:8000".parse().unwrap(),
+            8001,
+            node_id,
+        );
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!(
+                "http://localhost:8000/subgraphs/id/{}",
+                subgraph_id
+            ))
+            .header("Accept", "text/event-stream")
+            .body(Body::from("{\"query\": \"subscription { name }\"}"))
+            .unwrap();
+
+        let response = tokio::spawn(service.call(request))
+            .await
+            .unwrap()
+            .expect("Should return a response");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("Content-Type").unwrap(),
+            "text/event-stream"
+        );
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            String::from_utf8(body.to_vec()).unwrap(),
+            "event: next\ndata: {\"data\":{\"name\":\"Jordi\"}}\n\n\
+             event: next\ndata: {\"data\":{\"name\":\"Jordan\"}}\n\n\
+             event: complete\ndata:\n\n"
+        );
+    }
 }
@@ -351,6 +351,8 @@ impl<S: Store> IndexNodeResolver<S> {
                         },
                         contractAddress: &cached_call.contract_address[..],
                         returnValue: &cached_call.return_value[..],
+                        callData: cached_call.call_data.as_deref(),
+                        declared: cached_call.declared,
                     }
                 })
                 .collect::<Vec<r::Value>>(),
@@ -13,7 +13,9 @@ use std::task::Poll;
 use graph::components::{server::query::GraphQLServerError, store::Store};
 use graph::data::query::QueryResults;
 use graph::prelude::*;
-use graph_graphql::prelude::{execute_query, Query as PreparedQuery, QueryExecutionOptions};
+use graph_graphql::prelude::{
+    execute_query, InMemoryQueryBlockCache, Query as PreparedQuery, QueryExecutionOptions,
+};
 use graphql_parser;
 
 use crate::auth::bearer_token;
@@ -160,6 +162,7 @@ where
                 max_first: std::u32::MAX,
                 max_skip: std::u32::MAX,
                 load_manager,
+                query_block_cache: InMemoryQueryBlockCache::shared(),
             };
             let result = execute_query(query_clone.cheap_clone(), None, None, options).await;
             query_clone.log_execution(0);
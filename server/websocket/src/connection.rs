@@ -2,8 +2,13 @@ use futures::sync::mpsc;
 use futures03::stream::SplitStream;
 use graphql_parser::parse_query;
 use http::StatusCode;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::sync::{Mutex, Weak};
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
 use tokio_tungstenite::tungstenite::{Error as WsError, Message as WsMessage};
 use tokio_tungstenite::WebSocketStream;
 use uuid::Uuid;
@@ -18,7 +23,44 @@ struct StartPayload {
     operation_name: Option<String>,
 }
 
-/// GraphQL/WebSocket message received from a client.
+/// How long we give a client to acknowledge that we closed the connection
+/// before we drop it
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The GraphQL over WebSocket protocols we support. Clients pick one with
+/// the `Sec-WebSocket-Protocol` header when they open a connection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Protocol {
+    /// The protocol of the deprecated `subscriptions-transport-ws` library
+    GraphQlWs,
+    /// The protocol of the `graphql-ws` library
+    GraphQlTransportWs,
+}
+
+impl Protocol {
+    /// Pick the protocol for a connection from the comma-separated list of
+    /// protocols the client offered. Clients that don't ask for a protocol
+    /// we know get `graphql-ws` since that is all we used to support
+    pub(crate) fn negotiate(offered: Option<&str>) -> Self {
+        let transport_ws = Protocol::GraphQlTransportWs.name();
+        match offered {
+            Some(offered) if offered.split(',').any(|p| p.trim() == transport_ws) => {
+                Protocol::GraphQlTransportWs
+            }
+            _ => Protocol::GraphQlWs,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Protocol::GraphQlWs => "graphql-ws",
+            Protocol::GraphQlTransportWs => "graphql-transport-ws",
+        }
+    }
+}
+
+/// GraphQL/WebSocket message received from a client. Messages of the
+/// `graphql-transport-ws` protocol are translated into these
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 enum IncomingMessage {
@@ -34,21 +76,71 @@ enum IncomingMessage {
     Stop {
         id: String,
     },
+    #[serde(skip_deserializing)]
+    Ping {
+        payload: Option<serde_json::Value>,
+    },
+    #[serde(skip_deserializing)]
+    Pong,
 }
 
 impl IncomingMessage {
-    pub fn from_ws_message(msg: WsMessage) -> Result<Self, WsError> {
-        let text = msg.into_text()?;
-        serde_json::from_str(text.as_str()).map_err(|e| {
-            WsError::Http(http::Response::new(Some(format!(
-                "Invalid GraphQL over WebSocket message: {}: {}",
-                text, e
-            ))))
-        })
+    pub fn from_ws_message(msg: WsMessage, protocol: Protocol) -> Result<Self, String> {
+        let text = msg.into_text().map_err(|e| e.to_string())?;
+        let msg = match protocol {
+            Protocol::GraphQlWs => serde_json::from_str(text.as_str()),
+            Protocol::GraphQlTransportWs => {
+                serde_json::from_str::<TransportIncomingMessage>(text.as_str())
+                    .map(IncomingMessage::from)
+            }
+        };
+        msg.map_err(|e| format!("Invalid GraphQL over WebSocket message: {}: {}", text, e))
     }
 }
 
-/// GraphQL/WebSocket message to be sent to the client.
+/// GraphQL/WebSocket message received from a client that uses the
+/// `graphql-transport-ws` protocol
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TransportIncomingMessage {
+    ConnectionInit {
+        payload: Option<serde_json::Value>,
+    },
+    Ping {
+        payload: Option<serde_json::Value>,
+    },
+    Pong {
+        #[allow(dead_code)]
+        payload: Option<serde_json::Value>,
+    },
+    Subscribe {
+        id: String,
+        payload: StartPayload,
+    },
+    Complete {
+        id: String,
+    },
+}
+
+impl From<TransportIncomingMessage> for IncomingMessage {
+    fn from(msg: TransportIncomingMessage) -> Self {
+        match msg {
+            TransportIncomingMessage::ConnectionInit { payload } => {
+                IncomingMessage::ConnectionInit { payload }
+            }
+            TransportIncomingMessage::Ping { payload } => IncomingMessage::Ping { payload },
+            TransportIncomingMessage::Pong { payload: _ } => IncomingMessage::Pong,
+            TransportIncomingMessage::Subscribe { id, payload } => {
+                IncomingMessage::Start { id, payload }
+            }
+            TransportIncomingMessage::Complete { id } => IncomingMessage::Stop { id },
+        }
+    }
+}
+
+/// GraphQL/WebSocket message to be sent to the client. Messages are
+/// translated to the `graphql-transport-ws` protocol when the client uses
+/// that; `Ping` and `Pong` are only sent with that protocol
 #[derive(Debug, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 enum OutgoingMessage {
@@ -64,6 +156,10 @@ enum OutgoingMessage {
     Complete {
         id: String,
     },
+    Ping,
+    Pong {
+        payload: Option<serde_json::Value>,
+    },
 }
 
 impl OutgoingMessage {
@@ -79,78 +175,196 @@ impl OutgoingMessage {
     }
 }
 
-impl From<OutgoingMessage> for WsMessage {
+#[derive(Debug, Serialize)]
+struct TransportError {
+    message: String,
+}
+
+/// GraphQL/WebSocket message sent to a client that uses the
+/// `graphql-transport-ws` protocol
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TransportOutgoingMessage {
+    ConnectionAck,
+    Ping,
+    Pong {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        payload: Option<serde_json::Value>,
+    },
+    Next {
+        id: String,
+        payload: Arc<QueryResult>,
+    },
+    Error {
+        id: String,
+        payload: Vec<TransportError>,
+    },
+    Complete {
+        id: String,
+    },
+}
+
+impl From<OutgoingMessage> for TransportOutgoingMessage {
     fn from(msg: OutgoingMessage) -> Self {
-        WsMessage::text(serde_json::to_string(&msg).expect("invalid GraphQL/WebSocket message"))
+        match msg {
+            OutgoingMessage::ConnectionAck => TransportOutgoingMessage::ConnectionAck,
+            OutgoingMessage::Error { id, payload } => TransportOutgoingMessage::Error {
+                id,
+                payload: vec![TransportError { message: payload }],
+            },
+            OutgoingMessage::Data { id, payload } => TransportOutgoingMessage::Next { id, payload },
+            OutgoingMessage::Complete { id } => TransportOutgoingMessage::Complete { id },
+            OutgoingMessage::Ping => TransportOutgoingMessage::Ping,
+            OutgoingMessage::Pong { payload } => TransportOutgoingMessage::Pong { payload },
+        }
     }
 }
 
-/// Helper function to send outgoing messages.
-fn send_message(
-    sink: &mpsc::UnboundedSender<WsMessage>,
-    msg: OutgoingMessage,
-) -> Result<(), WsError> {
-    sink.unbounded_send(msg.into()).map_err(|_| {
-        let mut response = http::Response::new(None);
-        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
-        WsError::Http(response)
-    })
+/// Sends messages to the client in the protocol of the connection
+#[derive(Clone)]
+struct MessageSink {
+    sink: mpsc::UnboundedSender<WsMessage>,
+    protocol: Protocol,
 }
 
-/// Helper function to send error messages.
-fn send_error_string(
-    sink: &mpsc::UnboundedSender<WsMessage>,
-    operation_id: String,
-    error: String,
-) -> Result<(), WsError> {
-    sink.unbounded_send(OutgoingMessage::from_error_string(operation_id, error).into())
-        .map_err(|_| {
+impl MessageSink {
+    fn to_ws_message(&self, msg: OutgoingMessage) -> WsMessage {
+        let text = match self.protocol {
+            Protocol::GraphQlWs => serde_json::to_string(&msg),
+            Protocol::GraphQlTransportWs => {
+                serde_json::to_string(&TransportOutgoingMessage::from(msg))
+            }
+        };
+        WsMessage::text(text.expect("invalid GraphQL/WebSocket message"))
+    }
+
+    fn send_ws_message(&self, msg: WsMessage) -> Result<(), WsError> {
+        self.sink.unbounded_send(msg).map_err(|_| {
             let mut response = http::Response::new(None);
             *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
             WsError::Http(response)
         })
+    }
+
+    /// Helper function to send outgoing messages.
+    fn send(&self, msg: OutgoingMessage) -> Result<(), WsError> {
+        self.send_ws_message(self.to_ws_message(msg))
+    }
+
+    /// Helper function to send error messages.
+    fn send_error(&self, operation_id: String, error: String) -> Result<(), WsError> {
+        self.send(OutgoingMessage::from_error_string(operation_id, error))
+    }
+
+    /// Close the connection with the given close code. That is how the
+    /// `graphql-transport-ws` protocol reports errors that are not tied to
+    /// an operation
+    fn close(&self, code: u16, reason: impl Into<String>) -> Result<(), WsError> {
+        let frame = CloseFrame {
+            code: CloseCode::from(code),
+            reason: Cow::Owned(reason.into()),
+        };
+        self.send_ws_message(WsMessage::Close(Some(frame)))
+    }
+
+    /// Send a `ping` every `GRAPH_GRAPHQL_WS_KEEPALIVE_INTERVAL` until the
+    /// connection goes away
+    fn keep_alive(&self) {
+        let interval = ENV_VARS.graphql.ws_keepalive_interval;
+        if interval.is_zero() {
+            return;
+        }
+        let sink = self.clone();
+        graph::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            // The first tick completes immediately
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if sink.send(OutgoingMessage::Ping).is_err() {
+                    break;
+                }
+            }
+        });
+    }
 }
 
+/// The operations of a connection by their id. Each operation also has a
+/// sequence number so that an operation that finishes does not remove a
+/// later operation that reuses its id
+type OperationMap = HashMap<String, (u64, CancelGuard)>;
+
 /// Responsible for recording operation ids and stopping them.
 /// On drop, cancels all operations.
 struct Operations {
-    operations: HashMap<String, CancelGuard>,
-    msg_sink: mpsc::UnboundedSender<WsMessage>,
+    operations: Arc<Mutex<OperationMap>>,
+    next_seq: u64,
+    msg_sink: MessageSink,
 }
 
 impl Operations {
-    fn new(msg_sink: mpsc::UnboundedSender<WsMessage>) -> Self {
+    fn new(msg_sink: MessageSink) -> Self {
         Self {
-            operations: HashMap::new(),
+            operations: Arc::new(Mutex::new(HashMap::new())),
+            next_seq: 0,
             msg_sink,
         }
     }
 
     fn contains(&self, id: &str) -> bool {
-        self.operations.contains_key(id)
+        self.operations.lock().unwrap().contains_key(id)
     }
 
-    fn insert(&mut self, id: String, guard: CancelGuard) {
-        self.operations.insert(id, guard);
+    fn len(&self) -> usize {
+        self.operations.lock().unwrap().len()
+    }
+
+    /// Record the operation `id`. The returned `FinishedOperation` must be
+    /// used to forget the operation once it has no more results
+    fn insert(&mut self, id: String, guard: CancelGuard) -> FinishedOperation {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.operations
+            .lock()
+            .unwrap()
+            .insert(id.clone(), (seq, guard));
+        FinishedOperation {
+            operations: Arc::downgrade(&self.operations),
+            id,
+            seq,
+        }
     }
 
     fn stop(&mut self, operation_id: String) -> Result<(), WsError> {
         // Remove the operation with this ID from the known operations.
-        match self.operations.remove(&operation_id) {
+        let stopper = self
+            .operations
+            .lock()
+            .unwrap()
+            .remove(&operation_id)
+            .map(|(_, guard)| guard);
+
+        // With `graphql-transport-ws`, clients do not expect an answer when
+        // they complete an operation, and operations that we completed
+        // ourselves are not known anymore
+        if self.msg_sink.protocol == Protocol::GraphQlTransportWs {
+            if let Some(stopper) = stopper {
+                stopper.cancel();
+            }
+            return Ok(());
+        }
+
+        match stopper {
             Some(stopper) => {
                 // Cancel the subscription result stream.
                 stopper.cancel();
 
                 // Send a GQL_COMPLETE to indicate the operation is been completed.
-                send_message(
-                    &self.msg_sink,
-                    OutgoingMessage::Complete {
-                        id: operation_id.clone(),
-                    },
-                )
+                self.msg_sink.send(OutgoingMessage::Complete {
+                    id: operation_id.clone(),
+                })
             }
-            None => send_error_string(
-                &self.msg_sink,
+            None => self.msg_sink.send_error(
                 operation_id.clone(),
                 format!("Unknown operation ID: {}", operation_id),
             ),
@@ -160,7 +374,7 @@ impl Operations {
 
 impl Drop for Operations {
     fn drop(&mut self) {
-        let ids = Vec::from_iter(self.operations.keys().cloned());
+        let ids = Vec::from_iter(self.operations.lock().unwrap().keys().cloned());
         for id in ids {
             // Discard errors, the connection is being shutdown anyways.
             let _ = self.stop(id);
@@ -168,6 +382,29 @@ impl Drop for Operations {
     }
 }
 
+/// Removes an operation from the `Operations` of its connection when the
+/// server finished it, so that its id can be reused and it does not count
+/// towards the limit of operations per connection anymore
+#[derive(Clone)]
+struct FinishedOperation {
+    operations: Weak<Mutex<OperationMap>>,
+    id: String,
+    seq: u64,
+}
+
+impl FinishedOperation {
+    fn finish(&self) {
+        let operations = match self.operations.upgrade() {
+            Some(operations) => operations,
+            None => return,
+        };
+        let mut operations = operations.lock().unwrap();
+        if matches!(operations.get(&self.id), Some((seq, _)) if *seq == self.seq) {
+            operations.remove(&self.id);
+        }
+    }
+}
+
 /// A WebSocket connection implementing the GraphQL over WebSocket protocol.
 pub struct GraphQlConnection<Q, S> {
     id: String,
@@ -175,6 +412,8 @@ pub struct GraphQlConnection<Q, S> {
     graphql_runner: Arc<Q>,
     stream: WebSocketStream<S>,
     deployment: DeploymentHash,
+    client: String,
+    protocol: Protocol,
 }
 
 impl<Q, S> GraphQlConnection<Q, S>
@@ -188,6 +427,8 @@ where
         deployment: DeploymentHash,
         stream: WebSocketStream<S>,
         graphql_runner: Arc<Q>,
+        client: String,
+        protocol: Protocol,
     ) -> Self {
         GraphQlConnection {
             id: Uuid::new_v4().to_string(),
@@ -195,67 +436,160 @@ where
             graphql_runner,
             stream,
             deployment,
+            client,
+            protocol,
         }
     }
 
     async fn handle_incoming_messages(
         mut ws_stream: SplitStream<WebSocketStream<S>>,
-        mut msg_sink: mpsc::UnboundedSender<WsMessage>,
+        mut msg_sink: MessageSink,
         logger: Logger,
         connection_id: String,
         deployment: DeploymentHash,
         graphql_runner: Arc<Q>,
+        client: String,
     ) -> Result<(), WsError> {
+        let protocol = msg_sink.protocol;
         let mut operations = Operations::new(msg_sink.clone());
 
+        // Whether the client sent a `connection_init` message
+        let mut initialized = false;
+        // Whether we sent a close frame and are only waiting for the client
+        // to acknowledge that
+        let mut closing = false;
+        // When we stop waiting for the client. With `graphql-transport-ws`,
+        // the client has to initialize the connection in time, and after we
+        // closed the connection, it has to acknowledge that in time
+        let mut deadline = match protocol {
+            Protocol::GraphQlWs => None,
+            Protocol::GraphQlTransportWs => {
+                Some(Instant::now() + ENV_VARS.graphql.ws_connection_init_timeout)
+            }
+        };
+
+        // Close the connection because the client violated the
+        // `graphql-transport-ws` protocol
+        macro_rules! close {
+            ($code:expr, $reason:expr) => {{
+                msg_sink.close($code, $reason)?;
+                closing = true;
+                deadline = Some(Instant::now() + CLOSE_TIMEOUT);
+                continue;
+            }};
+        }
+
+        // Reject an operation. With `graphql-ws`, that also ends the
+        // connection
+        macro_rules! reject {
+            ($id:expr, $error:expr) => {{
+                msg_sink.send_error($id, $error)?;
+                match protocol {
+                    Protocol::GraphQlWs => return Ok(()),
+                    Protocol::GraphQlTransportWs => continue,
+                }
+            }};
+        }
+
         // Process incoming messages as long as the WebSocket is open
-        while let Some(ws_msg) = ws_stream.try_next().await? {
+        loop {
             use self::IncomingMessage::*;
             use self::OutgoingMessage::*;
 
+            let next = match deadline {
+                None => ws_stream.try_next().await?,
+                Some(until) => {
+                    let timeout = until.saturating_duration_since(Instant::now());
+                    match tokio::time::timeout(timeout, ws_stream.try_next()).await {
+                        Ok(next) => next?,
+                        Err(_) if closing => return Ok(()),
+                        Err(_) => close!(4408, "Connection initialisation timeout"),
+                    }
+                }
+            };
+            let ws_msg = match next {
+                Some(ws_msg) => ws_msg,
+                None => return Ok(()),
+            };
+
             debug!(logger, "Received message";
                    "connection" => &connection_id,
                    "msg" => format!("{}", ws_msg).as_str());
 
-            let msg = IncomingMessage::from_ws_message(ws_msg.clone())?;
+            match ws_msg {
+                WsMessage::Close(_) => return Ok(()),
+                WsMessage::Ping(_) | WsMessage::Pong(_) => continue,
+                _ if closing => continue,
+                _ => {}
+            }
+
+            let msg = match IncomingMessage::from_ws_message(ws_msg, protocol) {
+                Ok(msg) => msg,
+                Err(e) => match protocol {
+                    Protocol::GraphQlWs => return Err(WsError::Http(http::Response::new(Some(e)))),
+                    Protocol::GraphQlTransportWs => close!(4400, e),
+                },
+            };
 
             debug!(logger, "GraphQL/WebSocket message";
                    "connection" => &connection_id,
                    "msg" => format!("{:?}", msg).as_str());
 
             match msg {
-                // Always accept connection init requests
-                ConnectionInit { payload: _ } => send_message(&msg_sink, ConnectionAck),
+                // With `graphql-ws`, always accept connection init requests
+                ConnectionInit { payload: _ } => {
+                    if protocol == Protocol::GraphQlTransportWs {
+                        if initialized {
+                            close!(4429, "Too many initialisation requests");
+                        }
+                        deadline = None;
+                        msg_sink.keep_alive();
+                    }
+                    initialized = true;
+                    msg_sink.send(ConnectionAck)
+                }
 
                 // When receiving a connection termination request
                 ConnectionTerminate => {
                     // Close the message sink
-                    msg_sink.close().unwrap();
+                    msg_sink.sink.close().unwrap();
 
                     // Return an error here to terminate the connection
                     Err(WsError::ConnectionClosed)
                 }
 
+                IncomingMessage::Ping { payload } => {
+                    msg_sink.send(OutgoingMessage::Pong { payload })
+                }
+                IncomingMessage::Pong => Ok(()),
+
                 // When receiving a stop request
                 Stop { id } => operations.stop(id),
 
                 // When receiving a start request
                 Start { id, payload } => {
+                    if protocol == Protocol::GraphQlTransportWs && !initialized {
+                        close!(4401, "Unauthorized");
+                    }
+
                     // Respond with a GQL_ERROR if we already have an operation with this ID
                     if operations.contains(&id) {
-                        return send_error_string(
-                            &msg_sink,
-                            id.clone(),
-                            format!("Operation with ID already started: {}", id),
-                        );
+                        match protocol {
+                            Protocol::GraphQlWs => reject!(
+                                id.clone(),
+                                format!("Operation with ID already started: {}", id)
+                            ),
+                            Protocol::GraphQlTransportWs => {
+                                close!(4409, format!("Subscriber for {} already exists", id))
+                            }
+                        }
                     }
 
                     let max_ops = ENV_VARS.graphql.max_operations_per_connection;
-                    if operations.operations.len() >= max_ops {
-                        return send_error_string(
-                            &msg_sink,
+                    if operations.len() >= max_ops {
+                        reject!(
                             id,
-                            format!("Reached the limit of {} operations per connection", max_ops),
+                            format!("Reached the limit of {} operations per connection", max_ops)
                         );
                     }
 
@@ -263,13 +597,7 @@ where
                     // the query is invalid
                     let query = match parse_query(&payload.query) {
                         Ok(query) => query.into_static(),
-                        Err(e) => {
-                            return send_error_string(
-                                &msg_sink,
-                                id,
-                                format!("Invalid query: {}: {}", payload.query, e),
-                            );
-                        }
+                        Err(e) => reject!(id, format!("Invalid query: {}: {}", payload.query, e)),
                     };
 
                     // Parse the query variables, if present
@@ -278,40 +606,42 @@ where
                         Some(variables @ serde_json::Value::Object(_)) => {
                             match serde_json::from_value(variables.clone()) {
                                 Ok(variables) => Some(variables),
-                                Err(e) => {
-                                    return send_error_string(
-                                        &msg_sink,
-                                        id,
-                                        format!("Invalid variables provided: {}", e),
-                                    );
-                                }
+                                Err(e) => reject!(id, format!("Invalid variables provided: {}", e)),
                             }
                         }
-                        _ => {
-                            return send_error_string(
-                                &msg_sink,
-                                id,
-                                format!("Invalid variables provided (must be an object)"),
-                            );
-                        }
+                        _ => reject!(
+                            id,
+                            format!("Invalid variables provided (must be an object)")
+                        ),
                     };
 
                     // Construct a subscription
                     let target = QueryTarget::Deployment(deployment.clone(), Default::default());
+                    let mut query = Query::new(query, variables);
+                    query.client = Some(client.clone());
                     let subscription = Subscription {
                         // Subscriptions currently do not benefit from the generational cache
                         // anyways, so don't bother passing a network.
-                        query: Query::new(query, variables),
+                        query,
                     };
 
                     debug!(logger, "Start operation";
                            "connection" => &connection_id,
                            "id" => &id);
 
+                    // Record the operation and set up cancelation
+                    let guard = CancelGuard::new();
+                    let cancel_handle = guard.handle();
+                    let finished = operations.insert(id.clone(), guard);
+                    let complete_finished = finished.clone();
+
                     // Execute the GraphQL subscription
                     let error_sink = msg_sink.clone();
-                    let result_sink = msg_sink.clone();
+                    let result_sink = msg_sink.sink.clone();
+                    let result_format = msg_sink.clone();
+                    let complete_sink = msg_sink.clone();
                     let result_id = id.clone();
+                    let complete_id = id.clone();
                     let err_id = id.clone();
                     let err_connection_id = connection_id.clone();
                     let err_logger = logger.clone();
@@ -342,7 +672,7 @@ where
 
                                         // An error means the client closed the websocket, ignore
                                         // and let it be handled in the websocket loop above.
-                                        let _ = error_sink.unbounded_send(msg.into());
+                                        let _ = error_sink.send(msg);
                                     }
                                 }
                             };
@@ -353,33 +683,48 @@ where
                                 .map(move |result| {
                                     OutgoingMessage::from_query_result(result_id.clone(), result)
                                 })
-                                .map(WsMessage::from)
+                                .map(move |msg| result_format.to_ws_message(msg))
                                 .map(Ok)
                                 .compat()
                                 .forward(result_sink.sink_map_err(|_| ()))
-                                .map(|_| ())
+                                .map(move |_| {
+                                    // Forget the operation before telling the client, who
+                                    // may reuse its id right away
+                                    complete_finished.finish();
+
+                                    // `graphql-transport-ws` clients expect to be told
+                                    // when an operation has no more results
+                                    if complete_sink.protocol == Protocol::GraphQlTransportWs {
+                                        let _ = complete_sink.send(Complete { id: complete_id });
+                                    }
+                                })
                         });
 
                     // Setup cancelation.
-                    let guard = CancelGuard::new();
                     let logger = logger.clone();
                     let cancel_id = id.clone();
                     let connection_id = connection_id.clone();
                     let run_subscription =
-                        run_subscription.compat().cancelable(&guard, move || {
-                            debug!(logger, "Stopped operation";
+                        run_subscription
+                            .compat()
+                            .cancelable(&cancel_handle, move || {
+                                debug!(logger, "Stopped operation";
                                        "connection" => &connection_id,
                                        "id" => &cancel_id);
-                            Ok(())
-                        });
-                    operations.insert(id, guard);
+                                Ok(())
+                            });
+                    let run_subscription = async move {
+                        let result = run_subscription.await;
+                        // The operation is also finished when it failed
+                        finished.finish();
+                        result
+                    };
 
                     graph::spawn_allow_panic(run_subscription);
                     Ok(())
                 }
             }?
         }
-        Ok(())
     }
 }
 
@@ -400,6 +745,10 @@ where
 
         // Allocate a channel for writing
         let (msg_sink, msg_stream) = mpsc::unbounded();
+        let msg_sink = MessageSink {
+            sink: msg_sink,
+            protocol: self.protocol,
+        };
 
         // Handle incoming messages asynchronously
         let ws_reader = Self::handle_incoming_messages(
@@ -409,6 +758,7 @@ where
             self.id.clone(),
             self.deployment.clone(),
             self.graphql_runner.clone(),
+            self.client.clone(),
         );
 
         // Send outgoing messages asynchronously
@@ -431,3 +781,101 @@ where
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::serde_json::{self, json};
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+    use futures::sync::mpsc;
+    use graph::prelude::CancelGuard;
+
+    use super::{
+        IncomingMessage, MessageSink, Operations, OutgoingMessage, Protocol,
+        TransportOutgoingMessage,
+    };
+
+    #[test]
+    fn negotiate_protocol() {
+        assert_eq!(Protocol::GraphQlWs, Protocol::negotiate(None));
+        assert_eq!(Protocol::GraphQlWs, Protocol::negotiate(Some("graphql-ws")));
+        assert_eq!(Protocol::GraphQlWs, Protocol::negotiate(Some("other")));
+        assert_eq!(
+            Protocol::GraphQlTransportWs,
+            Protocol::negotiate(Some("graphql-transport-ws"))
+        );
+        assert_eq!(
+            Protocol::GraphQlTransportWs,
+            Protocol::negotiate(Some("graphql-ws, graphql-transport-ws"))
+        );
+    }
+
+    #[test]
+    fn parse_transport_messages() {
+        let parse = |msg: serde_json::Value, protocol| {
+            IncomingMessage::from_ws_message(WsMessage::text(msg.to_string()), protocol)
+        };
+
+        let subscribe = json!({
+            "id": "1",
+            "type": "subscribe",
+            "payload": { "query": "subscription { users { id } }" }
+        });
+        assert!(matches!(
+            parse(subscribe.clone(), Protocol::GraphQlTransportWs),
+            Ok(IncomingMessage::Start { id, .. }) if id == "1"
+        ));
+        assert!(parse(subscribe, Protocol::GraphQlWs).is_err());
+
+        let ping = json!({ "type": "ping" });
+        assert!(matches!(
+            parse(ping.clone(), Protocol::GraphQlTransportWs),
+            Ok(IncomingMessage::Ping { payload: None })
+        ));
+        assert!(parse(ping, Protocol::GraphQlWs).is_err());
+
+        assert!(matches!(
+            parse(json!({ "id": "1", "type": "complete" }), Protocol::GraphQlTransportWs),
+            Ok(IncomingMessage::Stop { id }) if id == "1"
+        ));
+        assert!(parse(json!({ "type": "start" }), Protocol::GraphQlTransportWs).is_err());
+    }
+
+    #[test]
+    fn serialize_transport_messages() {
+        let error = OutgoingMessage::from_error_string("1".to_string(), "oops".to_string());
+        assert_eq!(
+            json!({ "type": "error", "id": "1", "payload": [{ "message": "oops" }] }),
+            serde_json::to_value(TransportOutgoingMessage::from(error)).unwrap()
+        );
+
+        let pong = OutgoingMessage::Pong { payload: None };
+        assert_eq!(
+            json!({ "type": "pong" }),
+            serde_json::to_value(TransportOutgoingMessage::from(pong)).unwrap()
+        );
+    }
+
+    #[test]
+    fn finished_operations_are_forgotten() {
+        let (sink, _stream) = mpsc::unbounded();
+        let mut operations = Operations::new(MessageSink {
+            sink,
+            protocol: Protocol::GraphQlTransportWs,
+        });
+
+        let finished = operations.insert("1".to_string(), CancelGuard::new());
+        assert!(operations.contains("1"));
+        finished.finish();
+        assert!(!operations.contains("1"));
+        assert_eq!(0, operations.len());
+
+        // An operation that finishes late does not remove a later
+        // operation with the same id
+        let stale = operations.insert("2".to_string(), CancelGuard::new());
+        operations.stop("2".to_string()).unwrap();
+        let _current = operations.insert("2".to_string(), CancelGuard::new());
+        stale.finish();
+        assert!(operations.contains("2"));
+    }
+}
@@ -10,7 +10,7 @@ use tokio::net::TcpListener;
 use tokio_tungstenite::accept_hdr_async;
 use tokio_tungstenite::tungstenite::handshake::server::Request;
 
-use crate::connection::GraphQlConnection;
+use crate::connection::{GraphQlConnection, Protocol};
 
 /// A GraphQL subscription server based on Hyper / Websockets.
 pub struct SubscriptionServer<Q, S> {
@@ -111,8 +111,8 @@ where
             .expect("Failed to bind WebSocket port");
 
         loop {
-            let stream = match socket.accept().await {
-                Ok((stream, _)) => stream,
+            let (stream, remote_addr) = match socket.accept().await {
+                Ok(accepted) => accepted,
                 Err(e) => {
                     trace!(self.logger, "Connection error: {}", e);
                     continue;
@@ -127,6 +127,15 @@ where
             let subgraph_id = Arc::new(Mutex::new(None));
             let accept_subgraph_id = subgraph_id.clone();
 
+            // The client that opened the connection, identified by the header
+            // configured with `GRAPH_GRAPHQL_CLIENT_ID_HEADER` or its IP address
+            let client = Arc::new(Mutex::new(remote_addr.ip().to_string()));
+            let accept_client = client.clone();
+
+            // The GraphQL over WebSocket protocol the client asked for
+            let protocol = Arc::new(Mutex::new(Protocol::GraphQlWs));
+            let accept_protocol = protocol.clone();
+
             accept_hdr_async(stream, move |request: &Request, mut response: Response<()>| {
                 // Try to obtain the subgraph ID or name from the URL path.
                 // Return a 404 if the URL path contains no name/ID segment.
@@ -180,9 +189,25 @@ where
                     }
 
                 *accept_subgraph_id.lock().unwrap() = Some(state.id);
+                if let Some(id) = ENV_VARS
+                    .graphql
+                    .client_id_header
+                    .as_ref()
+                    .and_then(|name| request.headers().get(name.as_str()))
+                    .and_then(|value| value.to_str().ok())
+                {
+                    *accept_client.lock().unwrap() = id.to_owned();
+                }
+                let protocol = Protocol::negotiate(
+                    request
+                        .headers()
+                        .get("Sec-WebSocket-Protocol")
+                        .and_then(|value| value.to_str().ok()),
+                );
+                *accept_protocol.lock().unwrap() = protocol;
                 response.headers_mut().insert(
                     "Sec-WebSocket-Protocol",
-                    HeaderValue::from_static("graphql-ws"),
+                    HeaderValue::from_static(protocol.name()),
                 );
                 Ok(response)
             })
@@ -191,6 +216,8 @@ where
                     Ok(ws_stream) => {
                         // Obtain the subgraph ID or name that we resolved the request to
                         let subgraph_id = subgraph_id.lock().unwrap().clone().unwrap();
+                        let client = client.lock().unwrap().clone();
+                        let protocol = *protocol.lock().unwrap();
 
                         // Spawn a GraphQL over WebSocket connection
                         let service = GraphQlConnection::new(
@@ -198,6 +225,8 @@ where
                             subgraph_id,
                             ws_stream,
                             graphql_runner.clone(),
+                            client,
+                            protocol,
                         );
 
                         graph::spawn_allow_panic(service.into_future().compat());
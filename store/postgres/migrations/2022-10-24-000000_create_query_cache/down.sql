@@ -0,0 +1,2 @@
+drop table if exists query_cache_result;
+drop table if exists query_cache_block;
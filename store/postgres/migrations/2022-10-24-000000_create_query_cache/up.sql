@@ -0,0 +1,23 @@
+-- Tables for the query result cache that query nodes can share. They are
+-- unlogged since losing their contents only means cache misses
+create unlogged table if not exists query_cache_block(
+  network    text not null,
+  hash       bytea not null,
+  number     int4 not null,
+  weight     int8 not null,
+  primary key(network, hash)
+);
+
+create index if not exists query_cache_block_network_number
+    on query_cache_block(network, number);
+
+create unlogged table if not exists query_cache_result(
+  network    text not null,
+  block_hash bytea not null,
+  key        bytea not null,
+  deployment text not null,
+  result     text not null,
+  primary key(network, block_hash, key),
+  foreign key(network, block_hash)
+    references query_cache_block(network, hash) on delete cascade
+);
@@ -0,0 +1,2 @@
+alter table subgraphs.subgraph_manifest
+  add column history_blocks int4 not null default 2147483647;
@@ -5,7 +5,7 @@ use diesel::result::QueryResult;
 use diesel::serialize::{Output, ToSql};
 use diesel::sql_types::{Integer, Range};
 use std::io::Write;
-use std::ops::{Bound, RangeBounds, RangeFrom};
+use std::ops::{Bound, RangeBounds, RangeFrom, RangeInclusive};
 
 use graph::prelude::{BlockNumber, BlockPtr, BLOCK_NUMBER_MAX};
 
@@ -85,6 +85,18 @@ impl From<RangeFrom<BlockNumber>> for BlockRange {
     }
 }
 
+impl From<RangeInclusive<BlockNumber>> for BlockRange {
+    fn from(range: RangeInclusive<BlockNumber>) -> BlockRange {
+        // Postgres turns `[a, b]` into `[a, b+1)` which overflows when `b`
+        // is BLOCK_NUMBER_MAX; the range then really has no upper bound
+        let end = match range.end_bound() {
+            Bound::Included(&BLOCK_NUMBER_MAX) => Bound::Unbounded,
+            bound => clone_bound(bound),
+        };
+        BlockRange(clone_bound(range.start_bound()), end)
+    }
+}
+
 impl ToSql<Range<Integer>, Pg> for BlockRange {
     fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> diesel::serialize::Result {
         let pair = (self.0, self.1);
@@ -180,11 +192,11 @@ impl<'a> BlockRangeColumn<'a> {
                     // we generally only see BLOCK_NUMBER_MAX here for metadata
                     // queries where block ranges don't matter anyway
                     out.push_sql(" and coalesce(upper(");
-                    out.push_identifier(BLOCK_RANGE_COLUMN)?;
+                    self.name(out);
                     out.push_sql("), 2147483647) > ");
                     out.push_bind_param::<Integer, _>(block)?;
                     out.push_sql(" and lower(");
-                    out.push_identifier(BLOCK_RANGE_COLUMN)?;
+                    self.name(out);
                     out.push_sql(") <= ");
                     out.push_bind_param::<Integer, _>(block)
                 } else {
@@ -212,6 +224,64 @@ impl<'a> BlockRangeColumn<'a> {
         }
     }
 
+    /// Output SQL that matches rows whose block range has at least one
+    /// block in common with `range`
+    pub fn overlaps(&self, out: &mut AstPass<Pg>, range: &BlockRange) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        match self {
+            BlockRangeColumn::Mutable { .. } => {
+                self.name(out);
+                out.push_sql(" && ");
+                out.push_bind_param::<Range<Integer>, _>(range)
+            }
+            BlockRangeColumn::Immutable { .. } => {
+                // An immutable entity is valid from `block$` on forever
+                match range.1 {
+                    Bound::Included(block) => {
+                        self.name(out);
+                        out.push_sql(" <= ");
+                        out.push_bind_param::<Integer, _>(&block)
+                    }
+                    Bound::Excluded(block) => {
+                        self.name(out);
+                        out.push_sql(" < ");
+                        out.push_bind_param::<Integer, _>(&block)
+                    }
+                    Bound::Unbounded => {
+                        out.push_sql("true");
+                        Ok(())
+                    }
+                }
+            }
+        }
+    }
+
+    /// Output the first block at which a row is valid, and the first block
+    /// at which it is no longer valid as two columns `lower` and `upper`;
+    /// the upper bound is `null` for rows that are still current
+    pub fn bounds(&self, out: &mut AstPass<Pg>, lower: &str, upper: &str) {
+        match self {
+            BlockRangeColumn::Mutable { .. } => {
+                out.push_sql("lower(");
+                self.name(out);
+                out.push_sql(") as ");
+                out.push_sql(lower);
+                out.push_sql(", upper(");
+                self.name(out);
+                out.push_sql(") as ");
+                out.push_sql(upper);
+            }
+            BlockRangeColumn::Immutable { .. } => {
+                self.name(out);
+                out.push_sql(" as ");
+                out.push_sql(lower);
+                out.push_sql(", null::int4 as ");
+                out.push_sql(upper);
+            }
+        }
+    }
+
     /// Output the qualified name of the block range column
     pub fn name(&self, out: &mut AstPass<Pg>) {
         match self {
@@ -284,12 +354,12 @@ impl<'a> BlockRangeColumn<'a> {
         match self {
             BlockRangeColumn::Mutable { block, .. } => {
                 out.push_sql("lower(");
-                out.push_identifier(BLOCK_RANGE_COLUMN)?;
+                self.name(out);
                 out.push_sql(") >= ");
                 out.push_bind_param::<Integer, _>(block)
             }
             BlockRangeColumn::Immutable { block, .. } => {
-                out.push_identifier(BLOCK_COLUMN)?;
+                self.name(out);
                 out.push_sql(" >= ");
                 out.push_bind_param::<Integer, _>(block)
             }
@@ -1024,6 +1024,8 @@ mod data {
                     block_ptr: block_ptr.clone(),
                     contract_address: H160::from_slice(&row.2[..]),
                     return_value: row.1,
+                    call_data: None,
+                    declared: false,
                 })
                 .collect())
         }
@@ -1760,7 +1762,7 @@ impl EthereumCallCache for ChainStore {
         encoded_call: &[u8],
         block: BlockPtr,
     ) -> Result<Option<Vec<u8>>, Error> {
-        let id = contract_call_id(&contract_address, encoded_call, &block);
+        let id = CachedEthereumCall::id(&contract_address, encoded_call, &block);
         let conn = &*self.get_conn()?;
         if let Some(call_output) = conn.transaction::<_, Error, _>(|| {
             if let Some((return_value, update_accessed_at)) =
@@ -1793,7 +1795,7 @@ impl EthereumCallCache for ChainStore {
         block: BlockPtr,
         return_value: &[u8],
     ) -> Result<(), Error> {
-        let id = contract_call_id(&contract_address, encoded_call, &block);
+        let id = CachedEthereumCall::id(&contract_address, encoded_call, &block);
         let conn = &*self.get_conn()?;
         conn.transaction(|| {
             self.storage.set_call(
@@ -1806,18 +1808,3 @@ impl EthereumCallCache for ChainStore {
         })
     }
 }
-
-/// The id is the hashed encoded_call + contract_address + block hash to uniquely identify the call.
-/// 256 bits of output, and therefore 128 bits of security against collisions, are needed since this
-/// could be targeted by a birthday attack.
-fn contract_call_id(
-    contract_address: &ethabi::Address,
-    encoded_call: &[u8],
-    block: &BlockPtr,
-) -> [u8; 32] {
-    let mut hash = blake3::Hasher::new();
-    hash.update(encoded_call);
-    hash.update(contract_address.as_ref());
-    hash.update(block.hash_slice());
-    *hash.finalize().as_bytes()
-}
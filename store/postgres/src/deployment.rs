@@ -114,6 +114,8 @@ table! {
         start_block_number -> Nullable<Integer>,
         start_block_hash -> Nullable<Binary>,
         raw_yaml -> Nullable<Text>,
+        /// The number of blocks of history to keep when pruning
+        history_blocks -> Integer,
     }
 }
 
@@ -240,25 +242,35 @@ pub fn schema(conn: &PgConnection, site: &Site) -> Result<(Schema, bool), StoreE
 pub fn manifest_info(
     conn: &PgConnection,
     site: &Site,
-) -> Result<(Schema, Option<String>, Option<String>, String), StoreError> {
+) -> Result<(Schema, Option<String>, Option<String>, String, BlockNumber), StoreError> {
     use subgraph_manifest as sm;
-    let (s, description, repository, spec_version): (
+    let (s, description, repository, spec_version, history_blocks): (
         String,
         Option<String>,
         Option<String>,
         String,
+        BlockNumber,
     ) = sm::table
         .select((
             sm::schema,
             sm::description,
             sm::repository,
             sm::spec_version,
+            sm::history_blocks,
         ))
         .filter(sm::id.eq(site.id))
         .first(conn)?;
     Schema::parse(s.as_str(), site.deployment.clone())
         .map_err(|e| StoreError::Unknown(e))
-        .map(|schema| (schema, description, repository, spec_version))
+        .map(|schema| {
+            (
+                schema,
+                description,
+                repository,
+                spec_version,
+                history_blocks,
+            )
+        })
 }
 
 #[allow(dead_code)]
@@ -299,7 +311,7 @@ pub fn transact_block(
     firehose_cursor: &FirehoseCursor,
     full_count_query: &str,
     count: i32,
-) -> Result<(), StoreError> {
+) -> Result<BlockNumber, StoreError> {
     use crate::diesel::BoolExpressionMethods;
     use subgraph_deployment as d;
 
@@ -313,7 +325,7 @@ pub fn transact_block(
         entity_count_sql(full_count_query, count)
     };
 
-    let row_count = update(
+    let rows = update(
         d::table.filter(d::id.eq(site.id)).filter(
             // Asserts that the processing direction is forward.
             d::latest_ethereum_block_number
@@ -328,12 +340,13 @@ pub fn transact_block(
         d::entity_count.eq(sql(&count_sql)),
         d::current_reorg_depth.eq(0),
     ))
-    .execute(conn)
+    .returning(d::earliest_block_number)
+    .get_results::<BlockNumber>(conn)
     .map_err(StoreError::from)?;
 
-    match row_count {
+    match rows.len() {
         // Common case: A single row was updated.
-        1 => Ok(()),
+        1 => Ok(rows[0]),
 
         // No matching rows were found. This is an error. By the filter conditions, this can only be
         // due to a missing deployment (which `block_ptr` catches) or duplicate block processing.
@@ -911,6 +924,7 @@ pub fn create_deployment(
                 features,
                 schema,
                 raw_yaml,
+                history_blocks,
             },
         earliest_block,
         graft_base,
@@ -955,6 +969,7 @@ pub fn create_deployment(
         m::start_block_hash.eq(b(&earliest_block)),
         m::start_block_number.eq(earliest_block_number),
         m::raw_yaml.eq(raw_yaml),
+        m::history_blocks.eq(history_blocks),
     );
 
     if exists && replace {
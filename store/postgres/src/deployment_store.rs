@@ -5,7 +5,10 @@ use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
 use graph::anyhow::Context;
 use graph::blockchain::block_stream::FirehoseCursor;
-use graph::components::store::{EntityKey, EntityType, PruneReporter, StoredDynamicDataSource};
+use graph::components::store::{
+    DeploymentLocator, DerivedEntityQuery, EntityKey, EntityType, PruneReporter, PruneRequest,
+    StoredDynamicDataSource, VersionStats,
+};
 use graph::components::versions::VERSIONS;
 use graph::data::query::Trace;
 use graph::data::subgraph::{status, SPEC_VERSION_0_0_6};
@@ -17,7 +20,7 @@ use graph::semver::Version;
 use lru_time_cache::LruCache;
 use rand::{seq::SliceRandom, thread_rng};
 use std::borrow::Cow;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::Into;
 use std::iter::FromIterator;
 use std::ops::Bound;
@@ -32,9 +35,10 @@ use graph::constraint_violation;
 use graph::data::subgraph::schema::{DeploymentCreate, SubgraphError, POI_OBJECT};
 use graph::prelude::{
     anyhow, debug, info, o, warn, web3, ApiSchema, AttributeNames, BlockNumber, BlockPtr,
-    CheapClone, DeploymentHash, DeploymentState, Entity, EntityModification, EntityQuery, Error,
-    Logger, QueryExecutionError, Schema, StopwatchMetrics, StoreError, StoreEvent, UnfailOutcome,
-    Value, ENV_VARS,
+    CheapClone, DeploymentHash, DeploymentState, Entity, EntityAggregateQuery, EntityAggregates,
+    EntityHistoryQuery, EntityModification, EntityQuery, EntityVersion, Error, Logger,
+    QueryExecutionError, Schema, StopwatchMetrics, StoreError, StoreEvent, UnfailOutcome, Value,
+    ENV_VARS,
 };
 use graph_graphql::prelude::api_schema;
 use web3::types::Address;
@@ -47,7 +51,7 @@ use crate::dynds::DataSourcesTable;
 use crate::relational::{Layout, LayoutCache, SqlName, Table};
 use crate::relational_queries::FromEntityData;
 use crate::{connection_pool::ConnectionPool, detail};
-use crate::{dynds, primary::Site};
+use crate::{dynds, primary::DeploymentId, primary::Site};
 
 /// When connected to read replicas, this allows choosing which DB server to use for an operation.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -77,6 +81,21 @@ pub(crate) struct SubgraphInfo {
     pub(crate) description: Option<String>,
     pub(crate) repository: Option<String>,
     pub(crate) poi_version: ProofOfIndexingVersion,
+    /// The number of blocks of history the subgraph keeps; we prune it
+    /// automatically when it has much more than that
+    pub(crate) history_blocks: BlockNumber,
+}
+
+/// The changes that a subgraph made while processing one block
+pub(crate) struct BlockChanges<'a> {
+    /// The block at which we are writing the changes
+    pub(crate) block_ptr: &'a BlockPtr,
+    pub(crate) firehose_cursor: &'a FirehoseCursor,
+    pub(crate) mods: &'a [EntityModification],
+    pub(crate) data_sources: &'a [StoredDynamicDataSource],
+    pub(crate) deterministic_errors: &'a [SubgraphError],
+    pub(crate) manifest_idx_and_name: &'a [(u32, String)],
+    pub(crate) processed_data_sources: &'a [StoredDynamicDataSource],
 }
 
 pub struct StoreInner {
@@ -100,6 +119,11 @@ pub struct StoreInner {
     /// hosts this because it lives long enough, but it is managed from
     /// the entities module
     pub(crate) layout_cache: LayoutCache,
+
+    /// The deployments for which pruning is currently running in the
+    /// background. We never prune the same deployment twice at the same
+    /// time
+    pruning: Mutex<HashSet<DeploymentId>>,
 }
 
 /// Storage of the data for individual deployments. Each `DeploymentStore`
@@ -156,6 +180,7 @@ impl DeploymentStore {
             conn_round_robin_counter: AtomicUsize::new(0),
             subgraph_cache: Mutex::new(LruCache::with_capacity(100)),
             layout_cache: LayoutCache::new(ENV_VARS.store.query_stats_refresh_interval),
+            pruning: Mutex::new(HashSet::new()),
         };
 
         DeploymentStore(Arc::new(store))
@@ -250,6 +275,67 @@ impl DeploymentStore {
         )
     }
 
+    pub(crate) fn execute_aggregate(
+        &self,
+        conn: &PgConnection,
+        site: Arc<Site>,
+        query: EntityAggregateQuery,
+    ) -> Result<(EntityAggregates, Trace), QueryExecutionError> {
+        let layout = self.layout(conn, site)?;
+
+        let logger = query.logger.unwrap_or_else(|| self.logger.clone());
+        layout.aggregate(
+            &logger,
+            conn,
+            &query.entity_type,
+            query.filter,
+            query.aggregates,
+            query.block,
+            query.query_id,
+        )
+    }
+
+    pub(crate) fn execute_history(
+        &self,
+        conn: &PgConnection,
+        site: Arc<Site>,
+        query: EntityHistoryQuery,
+    ) -> Result<(Vec<EntityVersion>, Trace), QueryExecutionError> {
+        // Versions before the earliest block may have been removed by
+        // pruning, and we can't give a complete history for them
+        let state = deployment::state(conn, site.deployment.clone())?;
+        let from = query.from.unwrap_or(state.earliest_block_number);
+        if from < state.earliest_block_number {
+            return Err(QueryExecutionError::ValueParseError(
+                "fromBlock".to_owned(),
+                format!(
+                    "subgraph {} only has data starting at block number {} \
+                     and the history before block number {} is therefore not available",
+                    site.deployment, state.earliest_block_number, from
+                ),
+            ));
+        }
+        if from > query.to {
+            return Err(QueryExecutionError::ValueParseError(
+                "fromBlock".to_owned(),
+                format!("fromBlock {} must not be after toBlock {}", from, query.to),
+            ));
+        }
+
+        let layout = self.layout(conn, site)?;
+
+        let logger = query.logger.unwrap_or_else(|| self.logger.clone());
+        layout.history(
+            &logger,
+            conn,
+            &query.entity_type,
+            &query.id,
+            (from..=query.to).into(),
+            &query.range,
+            query.query_id,
+        )
+    }
+
     fn check_interface_entity_uniqueness(
         &self,
         conn: &PgConnection,
@@ -549,7 +635,7 @@ impl DeploymentStore {
             return Ok(info.clone());
         }
 
-        let (input_schema, description, repository, spec_version) =
+        let (input_schema, description, repository, spec_version, history_blocks) =
             deployment::manifest_info(conn, site)?;
 
         let graft_block =
@@ -585,6 +671,7 @@ impl DeploymentStore {
             description,
             repository,
             poi_version,
+            history_blocks,
         };
 
         // Insert the schema into the cache.
@@ -798,12 +885,10 @@ impl DeploymentStore {
     }
 
     pub(crate) async fn prune(
-        self: &Arc<Self>,
+        &self,
         mut reporter: Box<dyn PruneReporter>,
         site: Arc<Site>,
-        earliest_block: BlockNumber,
-        reorg_threshold: BlockNumber,
-        prune_ratio: f64,
+        req: PruneRequest,
     ) -> Result<Box<dyn PruneReporter>, StoreError> {
         let store = self.clone();
         self.with_conn(move |conn, cancel| {
@@ -811,42 +896,39 @@ impl DeploymentStore {
             cancel.check_cancel()?;
             let state = deployment::state(&conn, site.deployment.clone())?;
 
-            if state.latest_block.number <= reorg_threshold {
+            if state.latest_block.number <= req.history_blocks {
                 return Ok(reporter);
             }
 
-            if state.earliest_block_number > earliest_block {
-                return Err(constraint_violation!("earliest block can not move back from {} to {}", state.earliest_block_number, earliest_block).into());
-            }
-
-            let final_block = state.latest_block.number - reorg_threshold;
-            if final_block <= earliest_block {
-                return Err(constraint_violation!("the earliest block {} must be at least {} blocks before the current latest block {}", earliest_block, reorg_threshold, state.latest_block.number).into());
+            if state.earliest_block_number > req.earliest_block {
+                return Err(constraint_violation!(
+                    "earliest block can not move back from {} to {}",
+                    state.earliest_block_number,
+                    req.earliest_block
+                )
+                .into());
             }
 
             if let Some((_, graft)) = deployment::graft_point(conn, &site.deployment)? {
-                if graft.block_number() >= earliest_block {
-                    return Err(constraint_violation!("the earliest block {} must be after the graft point {}", earliest_block, graft.block_number()).into());
+                if graft.block_number() >= req.earliest_block {
+                    return Err(constraint_violation!(
+                        "the earliest block {} must be after the graft point {}",
+                        req.earliest_block,
+                        graft.block_number()
+                    )
+                    .into());
                 }
             }
 
             cancel.check_cancel()?;
 
             conn.transaction(|| {
-                deployment::set_earliest_block(conn, site.as_ref(), earliest_block)
+                deployment::set_earliest_block(conn, site.as_ref(), req.earliest_block)
             })?;
 
             cancel.check_cancel()?;
 
-            layout.prune_by_copying(
-                &store.logger,
-                reporter.as_mut(),
-                conn,
-                earliest_block,
-                final_block,
-                prune_ratio,
-                cancel,
-            )?;
+            layout.prune(&store.logger, reporter.as_mut(), conn, &req, cancel)?;
             Ok(reporter)
         })
         .await
@@ -1023,6 +1105,19 @@ impl DeploymentStore {
         layout.find_many(&conn, ids_for_type, block)
     }
 
+    pub(crate) fn get_derived(
+        &self,
+        site: Arc<Site>,
+        query: &DerivedEntityQuery,
+        block: BlockNumber,
+        excluded_keys: &[EntityKey],
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        let conn = self.get_conn()?;
+        let layout = self.layout(&conn, site)?;
+
+        layout.find_derived(&conn, query, block, excluded_keys)
+    }
+
     pub(crate) fn get_changes(
         &self,
         site: Arc<Site>,
@@ -1047,29 +1142,36 @@ impl DeploymentStore {
             .map(|(entities, _)| entities)
     }
 
+    /// Write the changes for one or more consecutive `blocks` in a single
+    /// transaction. The changes for each block are applied in order, so
+    /// that entity versions written for one block get closed correctly by
+    /// changes in later blocks; the subgraph head is only moved once, to
+    /// the last block
     pub(crate) fn transact_block_operations(
         &self,
         site: Arc<Site>,
-        block_ptr_to: &BlockPtr,
-        firehose_cursor: &FirehoseCursor,
-        mods: &[EntityModification],
+        blocks: &[BlockChanges<'_>],
         stopwatch: &StopwatchMetrics,
-        data_sources: &[StoredDynamicDataSource],
-        deterministic_errors: &[SubgraphError],
-        manifest_idx_and_name: &[(u32, String)],
-        processed_data_sources: &[StoredDynamicDataSource],
     ) -> Result<StoreEvent, StoreError> {
+        let last = match blocks.last() {
+            Some(last) => last,
+            None => return Err(constraint_violation!("there are no blocks to write")),
+        };
+
         let conn = {
             let _section = stopwatch.start_section("transact_blocks_get_conn");
             self.get_conn()?
         };
 
-        let event = conn.transaction(|| -> Result<_, StoreError> {
+        let (event, info, earliest_block) = conn.transaction(|| -> Result<_, StoreError> {
             // Emit a store event for the changes we are about to make. We
             // wait with sending it until we have done all our other work
             // so that we do not hold a lock on the notification queue
             // for longer than we have to
-            let event: StoreEvent = StoreEvent::from_mods(&site.deployment, mods);
+            let event: StoreEvent = StoreEvent::from_mods(
+                &site.deployment,
+                blocks.iter().flat_map(|block| block.mods.iter()),
+            );
 
             // Make the changes
             let layout = self.layout(&conn, site.clone())?;
@@ -1077,50 +1179,111 @@ impl DeploymentStore {
             //  see also: deployment-lock-for-update
             deployment::lock(&conn, &site)?;
 
-            let section = stopwatch.start_section("apply_entity_modifications");
-            let count = self.apply_entity_modifications(
-                &conn,
-                layout.as_ref(),
-                mods,
-                block_ptr_to,
-                stopwatch,
-            )?;
-            section.end();
-
-            dynds::insert(
-                &conn,
-                &site,
-                data_sources,
-                block_ptr_to,
-                manifest_idx_and_name,
-            )?;
-
-            dynds::update_offchain_status(&conn, &site, processed_data_sources)?;
+            let mut count = 0;
+            for block in blocks {
+                let section = stopwatch.start_section("apply_entity_modifications");
+                count += self.apply_entity_modifications(
+                    &conn,
+                    layout.as_ref(),
+                    block.mods,
+                    block.block_ptr,
+                    stopwatch,
+                )?;
+                section.end();
 
-            if !deterministic_errors.is_empty() {
-                deployment::insert_subgraph_errors(
+                dynds::insert(
                     &conn,
-                    &site.deployment,
-                    deterministic_errors,
-                    block_ptr_to.block_number(),
+                    &site,
+                    block.data_sources,
+                    block.block_ptr,
+                    block.manifest_idx_and_name,
                 )?;
+
+                dynds::update_offchain_status(&conn, &site, block.processed_data_sources)?;
+
+                if !block.deterministic_errors.is_empty() {
+                    deployment::insert_subgraph_errors(
+                        &conn,
+                        &site.deployment,
+                        block.deterministic_errors,
+                        block.block_ptr.block_number(),
+                    )?;
+                }
             }
 
-            deployment::transact_block(
+            let earliest_block = deployment::transact_block(
                 &conn,
                 &site,
-                block_ptr_to,
-                firehose_cursor,
+                last.block_ptr,
+                last.firehose_cursor,
                 layout.count_query.as_str(),
                 count,
             )?;
 
-            Ok(event)
+            let info = self.subgraph_info_with_conn(&conn, &site)?;
+
+            Ok((event, info, earliest_block))
         })?;
 
+        self.spawn_prune(site, &info, earliest_block, last.block_ptr.number);
+
         Ok(event)
     }
 
+    /// Start pruning the deployment in the background if it has
+    /// accumulated noticeably more history than the `history_blocks` it
+    /// needs to keep. Any problems with pruning are only logged since they
+    /// should not affect indexing
+    fn spawn_prune(
+        &self,
+        site: Arc<Site>,
+        info: &SubgraphInfo,
+        earliest_block: BlockNumber,
+        latest_block: BlockNumber,
+    ) {
+        let history_blocks = info.history_blocks;
+        let slack = history_blocks as f64 * ENV_VARS.store.history_slack_factor;
+        if ((latest_block - earliest_block) as f64) <= slack {
+            return;
+        }
+
+        let logger = self.logger.new(o!("sgd" => site.id.to_string(),
+                                        "subgraph_id" => site.deployment.to_string()));
+        let req = match PruneRequest::new(
+            &DeploymentLocator::from(site.as_ref()),
+            history_blocks,
+            ENV_VARS.reorg_threshold,
+            earliest_block,
+            latest_block,
+        ) {
+            Ok(req) => req,
+            Err(e) => {
+                debug!(logger, "Not pruning deployment"; "error" => e.to_string());
+                return;
+            }
+        };
+        if let Some(graft_block) = info.graft_block {
+            // We can only prune the blocks after the graft point
+            if graft_block >= req.earliest_block {
+                return;
+            }
+        }
+
+        if !self.pruning.lock().unwrap().insert(site.id) {
+            // Pruning for this deployment is already running
+            return;
+        }
+
+        let store = self.cheap_clone();
+        graph::spawn(async move {
+            let reporter = Box::new(PruneLogger::new(logger.clone()));
+            if let Err(e) = store.prune(reporter, site.cheap_clone(), req).await {
+                warn!(logger, "Pruning failed"; "error" => e.to_string());
+            }
+            store.pruning.lock().unwrap().remove(&site.id);
+        });
+    }
+
     fn rewind_with_conn(
         &self,
         conn: &PgConnection,
@@ -1610,6 +1773,55 @@ impl DeploymentStore {
     }
 }
 
+/// A `PruneReporter` for pruning that runs in the background which logs
+/// the main steps of pruning
+struct PruneLogger {
+    logger: Logger,
+    start: Instant,
+}
+
+impl PruneLogger {
+    fn new(logger: Logger) -> Self {
+        Self {
+            logger,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl PruneReporter for PruneLogger {
+    fn finish_analyze(&mut self, stats: &[VersionStats]) {
+        debug!(self.logger, "Analyzed tables before pruning";
+            "tables" => stats.len(),
+            "time_ms" => self.start.elapsed().as_millis());
+    }
+
+    fn copy_final_start(&mut self, earliest_block: BlockNumber, final_block: BlockNumber) {
+        info!(self.logger, "Start pruning";
+            "earliest_block" => earliest_block,
+            "final_block" => final_block);
+    }
+
+    fn copy_nonfinal_finish(&mut self, table: &str, rows: usize) {
+        debug!(self.logger, "Rebuilt table";
+            "table" => table,
+            "nonfinal_rows" => rows);
+    }
+
+    fn delete_batch(&mut self, table: &str, _rows: usize, total_rows: usize, finished: bool) {
+        if finished {
+            debug!(self.logger, "Deleted outdated entity versions";
+                "table" => table,
+                "rows" => total_rows);
+        }
+    }
+
+    fn finish_prune(&mut self) {
+        info!(self.logger, "Finished pruning";
+            "time_ms" => self.start.elapsed().as_millis());
+    }
+}
+
 /// Tries to fetch a [`Table`] either by its Entity name or its SQL name.
 ///
 /// Since we allow our input to be either camel-case or snake-case, we must retry the
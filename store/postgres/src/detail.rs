@@ -12,11 +12,12 @@ use git_testament::{git_testament, git_testament_macros};
 use graph::blockchain::BlockHash;
 use graph::data::subgraph::schema::{SubgraphError, SubgraphManifestEntity};
 use graph::prelude::{
-    bigdecimal::ToPrimitive, BigDecimal, BlockPtr, DeploymentHash, StoreError,
-    SubgraphDeploymentEntity,
+    bigdecimal::ToPrimitive, BigDecimal, BlockNumber, BlockPtr, DeploymentHash, StoreError,
+    SubgraphDeploymentEntity, BLOCK_NUMBER_MAX,
 };
 use graph::{constraint_violation, data::subgraph::status, prelude::web3::types::H256};
 use itertools::Itertools;
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::{ops::Bound, sync::Arc};
 
@@ -183,6 +184,7 @@ pub(crate) fn info_from_details(
     detail: DeploymentDetail,
     fatal: Option<ErrorDetail>,
     non_fatal: Vec<ErrorDetail>,
+    history_blocks: BlockNumber,
     sites: &[Arc<Site>],
 ) -> Result<status::Info, StoreError> {
     let DeploymentDetail {
@@ -246,6 +248,8 @@ pub(crate) fn info_from_details(
         non_fatal_errors,
         chains: vec![chain],
         entity_count,
+        history_blocks,
+        earliest_block_number,
         node: None,
     })
 }
@@ -274,6 +278,7 @@ pub(crate) fn deployment_statuses(
 ) -> Result<Vec<status::Info>, StoreError> {
     use subgraph_deployment as d;
     use subgraph_error as e;
+    use subgraph_manifest as m;
 
     // First, we fetch all deployment information along with any fatal errors.
     // Subsequently, we fetch non-fatal errors and we group them by deployment
@@ -314,11 +319,28 @@ pub(crate) fn deployment_statuses(
         .into_group_map()
     };
 
+    let history_blocks: HashMap<_, _> = if sites.is_empty() {
+        m::table
+            .select((m::id, m::history_blocks))
+            .load::<(DeploymentId, BlockNumber)>(conn)?
+    } else {
+        m::table
+            .filter(m::id.eq_any(sites.iter().map(|site| site.id)))
+            .select((m::id, m::history_blocks))
+            .load::<(DeploymentId, BlockNumber)>(conn)?
+    }
+    .into_iter()
+    .collect();
+
     details_with_fatal_error
         .into_iter()
         .map(|(detail, fatal)| {
             let non_fatal = non_fatal_errors.remove(&detail.id).unwrap_or(vec![]);
-            info_from_details(detail, fatal, non_fatal, sites)
+            let history_blocks = history_blocks
+                .get(&detail.id)
+                .copied()
+                .unwrap_or(BLOCK_NUMBER_MAX);
+            info_from_details(detail, fatal, non_fatal, history_blocks, sites)
         })
         .collect()
 }
@@ -341,6 +363,7 @@ struct StoredSubgraphManifest {
     start_block_number: Option<i32>,
     start_block_hash: Option<Bytes>,
     raw_yaml: Option<String>,
+    history_blocks: i32,
 }
 
 impl From<StoredSubgraphManifest> for SubgraphManifestEntity {
@@ -352,6 +375,7 @@ impl From<StoredSubgraphManifest> for SubgraphManifestEntity {
             features: value.features,
             schema: value.schema,
             raw_yaml: value.raw_yaml,
+            history_blocks: value.history_blocks,
         }
     }
 }
@@ -29,6 +29,7 @@ mod jobs;
 mod jsonb;
 mod notification_listener;
 mod primary;
+mod query_cache;
 pub mod query_store;
 mod relational;
 mod relational_queries;
@@ -60,6 +61,7 @@ pub use self::detail::DeploymentDetail;
 pub use self::jobs::register as register_jobs;
 pub use self::notification_listener::NotificationSender;
 pub use self::primary::{db_version, UnusedDeployment};
+pub use self::query_cache::PostgresQueryBlockCache;
 pub use self::store::Store;
 pub use self::store_events::SubscriptionManager;
 pub use self::subgraph_store::{unused, DeploymentPlacer, Shard, SubgraphStore, PRIMARY_SHARD};
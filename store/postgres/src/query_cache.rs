@@ -0,0 +1,311 @@
+//! A cache for the results of queries against recent blocks that is kept
+//! in unlogged tables in the primary so that all query nodes can share it.
+//! The tables are organized like the in-memory `QueryBlockCache`: for each
+//! network, `query_cache_block` has an entry for each of the most recent
+//! blocks with the total weight of the results cached for that block, and
+//! `query_cache_result` holds the results themselves. Removing a block
+//! from `query_cache_block` removes its results, too
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use diesel::sql_types::{BigInt, Binary, Integer, Text};
+use diesel::{sql_query, Connection, OptionalExtension, RunQueryDsl};
+
+use graph::constraint_violation;
+use graph::data::value::Object;
+use graph::prelude::serde::{Deserialize, Serialize};
+use graph::prelude::{
+    r, serde_json, warn, BlockPtr, DeploymentHash, Logger, QueryResult, StoreError, ENV_VARS,
+};
+use graph_graphql::prelude::{QueryBlockCacheBackend, QueryHash};
+
+use crate::connection_pool::ConnectionPool;
+
+/// A version of `r::Value` that we can serialize to JSON and read back
+/// without losing the order of the fields of objects or the difference
+/// between enums and strings
+#[derive(Serialize, Deserialize)]
+enum CachedValue {
+    Int(i64),
+    Float(f64),
+    String(String),
+    Boolean(bool),
+    Null,
+    Enum(String),
+    List(Vec<CachedValue>),
+    Object(Vec<(String, CachedValue)>),
+}
+
+impl From<&r::Value> for CachedValue {
+    fn from(value: &r::Value) -> Self {
+        match value {
+            r::Value::Int(i) => CachedValue::Int(*i),
+            r::Value::Float(f) => CachedValue::Float(*f),
+            r::Value::String(s) => CachedValue::String(s.clone()),
+            r::Value::Boolean(b) => CachedValue::Boolean(*b),
+            r::Value::Null => CachedValue::Null,
+            r::Value::Enum(s) => CachedValue::Enum(s.clone()),
+            r::Value::List(values) => {
+                CachedValue::List(values.iter().map(CachedValue::from).collect())
+            }
+            r::Value::Object(object) => CachedValue::from(object),
+        }
+    }
+}
+
+impl From<&Object> for CachedValue {
+    fn from(object: &Object) -> Self {
+        CachedValue::Object(
+            object
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), CachedValue::from(value)))
+                .collect(),
+        )
+    }
+}
+
+impl From<CachedValue> for r::Value {
+    fn from(value: CachedValue) -> Self {
+        match value {
+            CachedValue::Int(i) => r::Value::Int(i),
+            CachedValue::Float(f) => r::Value::Float(f),
+            CachedValue::String(s) => r::Value::String(s),
+            CachedValue::Boolean(b) => r::Value::Boolean(b),
+            CachedValue::Null => r::Value::Null,
+            CachedValue::Enum(s) => r::Value::Enum(s),
+            CachedValue::List(values) => {
+                r::Value::List(values.into_iter().map(r::Value::from).collect())
+            }
+            CachedValue::Object(entries) => r::Value::Object(Object::from_iter(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, r::Value::from(value))),
+            )),
+        }
+    }
+}
+
+#[derive(QueryableByName)]
+struct CachedResult {
+    #[sql_type = "Text"]
+    deployment: String,
+    #[sql_type = "Text"]
+    result: String,
+}
+
+impl CachedResult {
+    fn into_query_result(self) -> Result<QueryResult, StoreError> {
+        let value: CachedValue = serde_json::from_str(&self.result)?;
+        let data = match r::Value::from(value) {
+            r::Value::Object(data) => data,
+            _ => {
+                return Err(constraint_violation!(
+                    "cached query result for {} is not an object",
+                    self.deployment
+                ))
+            }
+        };
+        let deployment = DeploymentHash::new(self.deployment).map_err(|id| {
+            constraint_violation!("cached query result has invalid deployment `{}`", id)
+        })?;
+        let mut result = QueryResult::new(data);
+        result.deployment = Some(deployment);
+        Ok(result)
+    }
+}
+
+/// A `QueryBlockCacheBackend` that stores results in the primary. Like the
+/// in-memory cache, it keeps the results for the `max_blocks` most recent
+/// blocks of each network, and the results for one block can weigh at most
+/// `max_weight`. Since the cache is only an optimization, errors talking
+/// to the database are logged and otherwise treated like cache misses
+pub struct PostgresQueryBlockCache {
+    pool: ConnectionPool,
+    max_blocks: usize,
+    max_weight: usize,
+}
+
+impl PostgresQueryBlockCache {
+    pub fn new(pool: ConnectionPool, max_blocks: usize, max_weight: usize) -> Self {
+        PostgresQueryBlockCache {
+            pool,
+            max_blocks,
+            max_weight,
+        }
+    }
+
+    /// Create a cache with the limits configured in the environment. The
+    /// memory budget is divided evenly among blocks
+    pub fn from_env(pool: ConnectionPool) -> Self {
+        let max_blocks = ENV_VARS.graphql.query_cache_blocks;
+        let max_weight = ENV_VARS.graphql.query_cache_max_mem / max_blocks.max(1);
+        Self::new(pool, max_blocks, max_weight)
+    }
+
+    async fn find(
+        &self,
+        network: &str,
+        block_ptr: &BlockPtr,
+        key: &QueryHash,
+    ) -> Result<Option<QueryResult>, StoreError> {
+        let network = network.to_string();
+        let block_hash = block_ptr.hash_slice().to_vec();
+        let key = key.to_vec();
+
+        let cached = self
+            .pool
+            .with_conn(move |conn, _| {
+                let query = "select deployment, result from query_cache_result \
+                              where network = $1 and block_hash = $2 and key = $3";
+                sql_query(query)
+                    .bind::<Text, _>(network)
+                    .bind::<Binary, _>(block_hash)
+                    .bind::<Binary, _>(key)
+                    .get_result::<CachedResult>(conn)
+                    .optional()
+                    .map_err(|e| e.into())
+            })
+            .await?;
+        cached.map(CachedResult::into_query_result).transpose()
+    }
+
+    async fn add(
+        &self,
+        network: &str,
+        block_ptr: BlockPtr,
+        key: QueryHash,
+        result: Arc<QueryResult>,
+        weight: usize,
+    ) -> Result<bool, StoreError> {
+        // We never try to insert errors into this cache, and always resolve some value.
+        assert!(!result.has_errors());
+        let data = match result.data() {
+            Some(data) => serde_json::to_string(&CachedValue::from(data))?,
+            None => return Ok(false),
+        };
+        let deployment = match &result.deployment {
+            Some(deployment) => deployment.to_string(),
+            None => return Ok(false),
+        };
+        let network = network.to_string();
+        let max_blocks = self.max_blocks as i64;
+        let max_weight = self.max_weight as i64;
+        let weight = weight as i64;
+
+        self.pool
+            .with_conn(move |conn, _| {
+                conn.transaction::<_, StoreError, _>(|| {
+                    // Start a new block unless we already have a more
+                    // recent one, in which case this is a historical query
+                    // that does not belong in this cache
+                    let query = "insert into query_cache_block(network, hash, number, weight) \
+                                 select $1, $2, $3, 0 \
+                                  where not exists (select 1 from query_cache_block \
+                                                     where network = $1 and number > $3) \
+                                 on conflict(network, hash) do nothing";
+                    let new_block = sql_query(query)
+                        .bind::<Text, _>(network.as_str())
+                        .bind::<Binary, _>(block_ptr.hash_slice())
+                        .bind::<Integer, _>(block_ptr.number)
+                        .execute(conn)?
+                        > 0;
+
+                    if new_block {
+                        // Only keep the `max_blocks` most recent blocks
+                        let query = "delete from query_cache_block \
+                                      where network = $1 \
+                                        and hash not in (select hash from query_cache_block \
+                                                          where network = $1 \
+                                                          order by number desc \
+                                                          limit $2)";
+                        sql_query(query)
+                            .bind::<Text, _>(network.as_str())
+                            .bind::<BigInt, _>(max_blocks)
+                            .execute(conn)?;
+                    }
+
+                    // Reserve room for the result; this fails if the block
+                    // is not in the cache or if it is full
+                    let query = "update query_cache_block set weight = weight + $3 \
+                                  where network = $1 and hash = $2 and weight + $3 <= $4";
+                    let fits_in_cache = sql_query(query)
+                        .bind::<Text, _>(network.as_str())
+                        .bind::<Binary, _>(block_ptr.hash_slice())
+                        .bind::<BigInt, _>(weight)
+                        .bind::<BigInt, _>(max_weight)
+                        .execute(conn)?
+                        > 0;
+                    if !fits_in_cache {
+                        return Ok(false);
+                    }
+
+                    let query = "insert into query_cache_result(network, block_hash, key, \
+                                                                deployment, result) \
+                                 values ($1, $2, $3, $4, $5) \
+                                 on conflict(network, block_hash, key) do nothing";
+                    sql_query(query)
+                        .bind::<Text, _>(network.as_str())
+                        .bind::<Binary, _>(block_ptr.hash_slice())
+                        .bind::<Binary, _>(&key[..])
+                        .bind::<Text, _>(deployment.as_str())
+                        .bind::<Text, _>(data.as_str())
+                        .execute(conn)?;
+                    Ok(true)
+                })
+                .map_err(|e| e.into())
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl QueryBlockCacheBackend for PostgresQueryBlockCache {
+    async fn get(
+        &self,
+        logger: &Logger,
+        network: &str,
+        block_ptr: &BlockPtr,
+        key: &QueryHash,
+    ) -> Option<Arc<QueryResult>> {
+        if self.max_blocks == 0 {
+            return None;
+        }
+
+        match self.find(network, block_ptr, key).await {
+            Ok(result) => result.map(Arc::new),
+            Err(e) => {
+                warn!(logger, "Failed to look up query result in the shared query cache";
+                    "network" => network,
+                    "block" => block_ptr.number,
+                    "error" => e.to_string());
+                None
+            }
+        }
+    }
+
+    async fn insert(
+        &self,
+        logger: &Logger,
+        network: &str,
+        block_ptr: BlockPtr,
+        key: QueryHash,
+        result: Arc<QueryResult>,
+        weight: usize,
+    ) -> bool {
+        if self.max_blocks == 0 {
+            return false;
+        }
+
+        let number = block_ptr.number;
+        match self.add(network, block_ptr, key, result, weight).await {
+            Ok(inserted) => inserted,
+            Err(e) => {
+                warn!(logger, "Failed to add query result to the shared query cache";
+                    "network" => network,
+                    "block" => number,
+                    "error" => e.to_string());
+                false
+            }
+        }
+    }
+}
@@ -16,6 +16,8 @@ mod query_tests;
 
 mod prune;
 
+use diesel::pg::Pg;
+use diesel::query_builder::QueryFragment;
 use diesel::{connection::SimpleConnection, Connection};
 use diesel::{debug_query, OptionalExtension, PgConnection, RunQueryDsl};
 use graph::cheap_clone::CheapClone;
@@ -39,22 +41,26 @@ use crate::relational_queries::{FindChangesQuery, FindPossibleDeletionsQuery};
 use crate::{
     primary::{Namespace, Site},
     relational_queries::{
-        ClampRangeQuery, ConflictingEntityQuery, EntityData, EntityDeletion, FilterCollection,
-        FilterQuery, FindManyQuery, FindQuery, InsertQuery, RevertClampQuery, RevertRemoveQuery,
+        AggregateData, AggregateQuery, ClampRangeQuery, ConflictingEntityQuery, EntityData,
+        EntityDeletion, FilterCollection, FilterQuery, FindDerivedQuery, FindManyQuery, FindQuery,
+        HistoryData, HistoryQuery, InsertQuery, RevertClampQuery, RevertRemoveQuery,
     },
 };
-use graph::components::store::{EntityKey, EntityType};
+use graph::components::store::{DerivedEntityQuery, EntityKey, EntityType};
 use graph::data::graphql::ext::{DirectiveFinder, DocumentExt, ObjectTypeExt};
-use graph::data::schema::{FulltextConfig, FulltextDefinition, Schema, SCHEMA_TYPE_NAME};
+use graph::data::schema::{
+    FulltextConfig, FulltextDefinition, IndexDefinition, Schema, SCHEMA_TYPE_NAME,
+};
 use graph::data::store::BYTES_SCALAR;
 use graph::data::subgraph::schema::{POI_OBJECT, POI_TABLE};
 use graph::prelude::{
-    anyhow, info, BlockNumber, DeploymentHash, Entity, EntityChange, EntityCollection,
-    EntityFilter, EntityOperation, EntityOrder, EntityRange, Logger, QueryExecutionError,
-    StoreError, StoreEvent, ValueType, BLOCK_NUMBER_MAX,
+    anyhow, info, AggregateFunction, BlockNumber, DeploymentHash, Entity, EntityAggregates,
+    EntityChange, EntityCollection, EntityFilter, EntityOperation, EntityOrder, EntityRange,
+    EntityVersion, Logger, QueryExecutionError, StoreError, StoreEvent, ValueType,
+    BLOCK_NUMBER_MAX,
 };
 
-use crate::block_range::{BLOCK_COLUMN, BLOCK_RANGE_COLUMN};
+use crate::block_range::{BlockRange, BLOCK_COLUMN, BLOCK_RANGE_COLUMN};
 pub use crate::catalog::Catalog;
 use crate::connection_pool::ForeignServer;
 use crate::{catalog, deployment};
@@ -212,6 +218,41 @@ type IdTypeMap = HashMap<EntityType, IdType>;
 
 type EnumMap = BTreeMap<String, Arc<BTreeSet<String>>>;
 
+/// Log the SQL text and execution time of `query` if that is turned on,
+/// and return a trace for it
+fn log_query_timing(
+    logger: &Logger,
+    query: &impl QueryFragment<Pg>,
+    elapsed: Duration,
+    entity_count: usize,
+) -> Trace {
+    // 20kB
+    const MAXLEN: usize = 20_480;
+
+    if !ENV_VARS.log_sql_timing() {
+        return Trace::None;
+    }
+
+    let mut text = debug_query(query).to_string().replace("\n", "\t");
+    let trace = Trace::query(&text, elapsed, entity_count);
+
+    // If the query + bind variables is more than MAXLEN, truncate it;
+    // this will happen when queries have very large bind variables
+    // (e.g., long arrays of string ids)
+    if text.len() > MAXLEN {
+        text.truncate(MAXLEN);
+        text.push_str(" ...");
+    }
+    info!(
+        logger,
+        "Query timing (SQL)";
+        "query" => text,
+        "time_ms" => elapsed.as_millis(),
+        "entity_count" => entity_count
+    );
+    trace
+}
+
 #[derive(Debug, Clone)]
 pub struct Layout {
     /// Details of where the subgraph is stored
@@ -369,6 +410,7 @@ impl Layout {
                     fulltext_fields: None,
                     is_reference: false,
                     use_prefix_comparison: false,
+                    default_index: true,
                 },
                 Column {
                     name: SqlName::from(PRIMARY_KEY_COLUMN),
@@ -380,6 +422,7 @@ impl Layout {
                     fulltext_fields: None,
                     is_reference: false,
                     use_prefix_comparison: false,
+                    default_index: true,
                 },
             ],
             /// The position of this table in all the tables for this layout; this
@@ -388,6 +431,7 @@ impl Layout {
             position: position as u32,
             is_account_like: false,
             immutable: false,
+            indexes: vec![],
         }
     }
 
@@ -524,6 +568,36 @@ impl Layout {
         Ok(entities_for_type)
     }
 
+    pub fn find_derived(
+        &self,
+        conn: &PgConnection,
+        query: &DerivedEntityQuery,
+        block: BlockNumber,
+        excluded_keys: &[EntityKey],
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        let table = self.table_for_entity(&query.entity_type)?;
+        let column = table.column_for_field(query.entity_field.as_str())?;
+        let excluded_ids: Vec<&str> = excluded_keys
+            .iter()
+            .filter(|key| key.entity_type == query.entity_type)
+            .map(|key| key.entity_id.as_str())
+            .collect();
+        let query =
+            FindDerivedQuery::new(table, column, query.value.as_str(), &excluded_ids, block);
+
+        let mut entities = BTreeMap::new();
+        for data in query.load::<EntityData>(conn)? {
+            let entity_type = data.entity_type();
+            let entity: Entity = data.deserialize_with_layout(self, None, true)?;
+            let key = EntityKey {
+                entity_type,
+                entity_id: entity.id()?.into(),
+            };
+            entities.insert(key, entity);
+        }
+        Ok(entities)
+    }
+
     pub fn find_changes(
         &self,
         conn: &PgConnection,
@@ -632,39 +706,6 @@ impl Layout {
         block: BlockNumber,
         query_id: Option<String>,
     ) -> Result<(Vec<T>, Trace), QueryExecutionError> {
-        fn log_query_timing(
-            logger: &Logger,
-            query: &FilterQuery,
-            elapsed: Duration,
-            entity_count: usize,
-        ) -> Trace {
-            // 20kB
-            const MAXLEN: usize = 20_480;
-
-            if !ENV_VARS.log_sql_timing() {
-                return Trace::None;
-            }
-
-            let mut text = debug_query(&query).to_string().replace("\n", "\t");
-            let trace = Trace::query(&text, elapsed, entity_count);
-
-            // If the query + bind variables is more than MAXLEN, truncate it;
-            // this will happen when queries have very large bind variables
-            // (e.g., long arrays of string ids)
-            if text.len() > MAXLEN {
-                text.truncate(MAXLEN);
-                text.push_str(" ...");
-            }
-            info!(
-                logger,
-                "Query timing (SQL)";
-                "query" => text,
-                "time_ms" => elapsed.as_millis(),
-                "entity_count" => entity_count
-            );
-            trace
-        }
-
         let filter_collection = FilterCollection::new(self, collection, filter.as_ref(), block)?;
         let query = FilterQuery::new(
             &filter_collection,
@@ -674,6 +715,7 @@ impl Layout {
             block,
             query_id,
             &self.site,
+            self,
         )?;
         let query_clone = query.clone();
 
@@ -725,6 +767,100 @@ impl Layout {
             .map(|values| (values, trace))
     }
 
+    /// Count the entities of `entity_type` that match `filter` at `block`
+    /// and compute `aggregates` over them with a single SQL query
+    pub fn aggregate(
+        &self,
+        logger: &Logger,
+        conn: &PgConnection,
+        entity_type: &EntityType,
+        filter: Option<EntityFilter>,
+        aggregates: Vec<(AggregateFunction, String)>,
+        block: BlockNumber,
+        query_id: Option<String>,
+    ) -> Result<(EntityAggregates, Trace), QueryExecutionError> {
+        let table = self.table_for_entity(entity_type)?;
+        let query = AggregateQuery::new(
+            self,
+            table,
+            filter.as_ref(),
+            &aggregates,
+            block,
+            ENV_VARS.graphql.max_aggregate_entities,
+            query_id,
+            &self.site,
+        )?;
+
+        let start = Instant::now();
+        let data = conn
+            .transaction(|| {
+                if let Some(ref timeout_sql) = *STATEMENT_TIMEOUT {
+                    conn.batch_execute(timeout_sql)?;
+                }
+                query.clone().get_result::<AggregateData>(conn)
+            })
+            .map_err(|e| {
+                let mut query_text = String::new();
+                match write!(query_text, "{}", debug_query(&query)) {
+                    Ok(()) => (),
+                    Err(_) => {
+                        write!(query_text, "{query}").ok();
+                    }
+                };
+                QueryExecutionError::ResolveEntitiesError(format!("{e}, query = {query_text}"))
+            })?;
+        let trace = log_query_timing(logger, &query, start.elapsed(), 1);
+
+        let aggregates = query.aggregates(data)?;
+        let max_entities = ENV_VARS.graphql.max_aggregate_entities;
+        if aggregates.count as u64 > max_entities as u64 {
+            return Err(QueryExecutionError::TooManyAggregateEntities(max_entities));
+        }
+        Ok((aggregates, trace))
+    }
+
+    /// Find the versions of the entity `entity_type[id]` that are valid at
+    /// some block in `block_range`, ordered by the block at which they
+    /// became valid
+    pub fn history(
+        &self,
+        logger: &Logger,
+        conn: &PgConnection,
+        entity_type: &EntityType,
+        id: &str,
+        block_range: BlockRange,
+        range: &EntityRange,
+        query_id: Option<String>,
+    ) -> Result<(Vec<EntityVersion>, Trace), QueryExecutionError> {
+        let table = self.table_for_entity(entity_type)?;
+        let query = HistoryQuery::new(table, id, block_range, range, query_id, &self.site)?;
+
+        let start = Instant::now();
+        let rows = conn
+            .transaction(|| {
+                if let Some(ref timeout_sql) = *STATEMENT_TIMEOUT {
+                    conn.batch_execute(timeout_sql)?;
+                }
+                query.clone().load::<HistoryData>(conn)
+            })
+            .map_err(|e| {
+                let mut query_text = String::new();
+                match write!(query_text, "{}", debug_query(&query)) {
+                    Ok(()) => (),
+                    Err(_) => {
+                        write!(query_text, "{query}").ok();
+                    }
+                };
+                QueryExecutionError::ResolveEntitiesError(format!("{e}, query = {query_text}"))
+            })?;
+        let trace = log_query_timing(logger, &query, start.elapsed(), rows.len());
+
+        query
+            .versions(self, rows)
+            .map(|versions| (versions, trace))
+            .map_err(|e| e.into())
+    }
+
     pub fn update<'a>(
         &'a self,
         conn: &PgConnection,
@@ -938,7 +1074,9 @@ pub enum ColumnType {
     BigInt,
     Bytes,
     Int,
+    Int8,
     String,
+    Timestamp,
     TSVector(FulltextConfig),
     Enum(EnumType),
 }
@@ -995,7 +1133,9 @@ impl ColumnType {
             ValueType::BigInt => Ok(ColumnType::BigInt),
             ValueType::Bytes => Ok(ColumnType::Bytes),
             ValueType::Int => Ok(ColumnType::Int),
+            ValueType::Int8 => Ok(ColumnType::Int8),
             ValueType::String => Ok(ColumnType::String),
+            ValueType::Timestamp => Ok(ColumnType::Timestamp),
         }
     }
 
@@ -1006,7 +1146,9 @@ impl ColumnType {
             ColumnType::BigInt => "numeric",
             ColumnType::Bytes => "bytea",
             ColumnType::Int => "integer",
+            ColumnType::Int8 => "int8",
             ColumnType::String => "text",
+            ColumnType::Timestamp => "timestamptz",
             ColumnType::TSVector(_) => "tsvector",
             ColumnType::Enum(enum_type) => enum_type.name.as_str(),
         }
@@ -1037,6 +1179,9 @@ pub struct Column {
     /// Whether to use a prefix of the column for comparisons and index
     /// creation, or column values in their entirety
     pub use_prefix_comparison: bool,
+    /// Whether to create the default attribute index for this column.
+    /// Subgraphs can opt out of that with `@noIndex`
+    pub(crate) default_index: bool,
 }
 
 impl Column {
@@ -1092,6 +1237,7 @@ impl Column {
             fulltext_fields: None,
             is_reference,
             use_prefix_comparison,
+            default_index: field.find_directive("noIndex").is_none(),
         })
     }
 
@@ -1107,6 +1253,7 @@ impl Column {
             fulltext_fields: Some(def.included_fields.clone()),
             is_reference: false,
             use_prefix_comparison: false,
+            default_index: true,
         })
     }
 
@@ -1207,6 +1354,10 @@ pub struct Table {
     /// Entities in this table are immutable, i.e., will never be updated or
     /// deleted
     pub(crate) immutable: bool,
+
+    /// The indexes that the subgraph declared for this table with
+    /// `@index`. They are created in addition to the default indexes
+    pub(crate) indexes: Vec<IndexDefinition>,
 }
 
 impl Table {
@@ -1221,15 +1372,25 @@ impl Table {
         SqlName::check_valid_identifier(&*defn.name, "object")?;
 
         let table_name = SqlName::from(&*defn.name);
-        let columns = defn
+        let mut columns = defn
             .fields
             .iter()
             .filter(|field| !field.is_derived())
             .map(|field| Column::new(&table_name, field, catalog, enums, id_types))
             .chain(fulltexts.iter().map(Column::new_fulltext))
             .collect::<Result<Vec<Column>, StoreError>>()?;
+        if defn.find_directive("noIndex").is_some() {
+            // The id and fulltext columns are always indexed
+            for column in columns
+                .iter_mut()
+                .filter(|column| !column.is_primary_key() && !column.is_fulltext())
+            {
+                column.default_index = false;
+            }
+        }
         let qualified_name = SqlName::qualified_name(&catalog.site.namespace, &table_name);
         let immutable = defn.is_immutable();
+        let indexes = Schema::entity_index_definitions(defn);
 
         let table = Table {
             object: EntityType::from(defn),
@@ -1242,7 +1403,13 @@ impl Table {
             columns,
             position,
             immutable,
+            indexes,
         };
+        for index in &table.indexes {
+            for field in index.fields.iter().chain(index.filter.keys()) {
+                table.column_for_field(field)?;
+            }
+        }
         Ok(table)
     }
 
@@ -1257,6 +1424,7 @@ impl Table {
             is_account_like: self.is_account_like,
             position: self.position,
             immutable: self.immutable,
+            indexes: self.indexes.clone(),
         };
 
         Arc::new(other)
@@ -3,7 +3,7 @@ use std::{
     iter,
 };
 
-use graph::prelude::BLOCK_NUMBER_MAX;
+use graph::prelude::{q, BLOCK_NUMBER_MAX};
 
 use crate::relational::{
     Catalog, ColumnType, BLOCK_COLUMN, BLOCK_RANGE_COLUMN, BYTE_ARRAY_PREFIX_SIZE,
@@ -271,6 +271,10 @@ impl Table {
                 // and don't need an explicit attribute index
                 continue;
             }
+            if !column.default_index {
+                // The subgraph opted out of this index with `@noIndex`
+                continue;
+            }
 
             let (method, index_expr) = if column.is_reference() && !column.is_list() {
                 // For foreign keys, index the key together with the block range
@@ -284,27 +288,7 @@ impl Table {
                     ("gist", index_expr)
                 }
             } else {
-                // Attributes that are plain strings or bytes are
-                // indexed with a BTree; but they can be too large for
-                // Postgres' limit on values that can go into a BTree.
-                // For those attributes, only index the first
-                // STRING_PREFIX_SIZE or BYTE_ARRAY_PREFIX_SIZE characters
-                // see: attr-bytea-prefix
-                let index_expr = if column.use_prefix_comparison {
-                    match column.column_type {
-                        ColumnType::String => {
-                            format!("left({}, {})", column.name.quoted(), STRING_PREFIX_SIZE)
-                        }
-                        ColumnType::Bytes => format!(
-                            "substring({}, 1, {})",
-                            column.name.quoted(),
-                            BYTE_ARRAY_PREFIX_SIZE
-                        ),
-                        _ => unreachable!("only String and Bytes can have arbitrary size"),
-                    }
-                } else {
-                    column.name.quoted()
-                };
+                let index_expr = column.index_expr();
 
                 let method = if column.is_list() || column.is_fulltext() {
                     "gin"
@@ -326,9 +310,57 @@ impl Table {
             index_expr = index_expr,
         )?;
         }
+        self.create_declared_indexes(out, layout)?;
         writeln!(out)
     }
 
+    /// Create the indexes that the subgraph declared with `@index`
+    fn create_declared_indexes(&self, out: &mut String, layout: &Layout) -> fmt::Result {
+        for (i, index) in self.indexes.iter().enumerate() {
+            let mut index_exprs = Vec::new();
+            for field in &index.fields {
+                let column = self.column_for_field(field).map_err(|_| fmt::Error)?;
+                index_exprs.push(column.index_expr());
+            }
+
+            let mut conditions = Vec::new();
+            for (field, value) in &index.filter {
+                let column = self.column_for_field(field).map_err(|_| fmt::Error)?;
+                let condition = match value {
+                    q::Value::Null => format!("{} is null", column.name.quoted()),
+                    q::Value::Boolean(b) => format!("{} = {}", column.name.quoted(), b),
+                    q::Value::Int(n) => {
+                        let n = n.as_i64().ok_or(fmt::Error)?;
+                        format!("{} = {}", column.name.quoted(), n)
+                    }
+                    q::Value::String(s) | q::Value::Enum(s) => {
+                        format!("{} = '{}'", column.name.quoted(), s.replace('\'', "''"))
+                    }
+                    _ => unreachable!("index conditions are validated in the schema"),
+                };
+                conditions.push(condition);
+            }
+            let predicate = if conditions.is_empty() {
+                String::new()
+            } else {
+                format!("\n where {}", conditions.join(" and "))
+            };
+
+            write!(
+                out,
+                "create index idx_{table_index}_{index}_{table_name}\n    on {schema_name}.\"{table_name}\" using {method}({index_exprs}){predicate};\n",
+                table_index = self.position,
+                index = i,
+                table_name = self.name,
+                schema_name = layout.catalog.site.namespace,
+                method = index.method.as_str(),
+                index_exprs = index_exprs.join(", "),
+                predicate = predicate,
+            )?;
+        }
+        Ok(())
+    }
+
     /// Generate the DDL for one table, i.e. one `create table` statement
     /// and all `create index` statements for the table's columns
     ///
@@ -371,6 +403,30 @@ impl Table {
 }
 
 impl Column {
+    /// The expression to use when indexing this column. Attributes that
+    /// are plain strings or bytes are indexed with a BTree; but they can
+    /// be too large for Postgres' limit on values that can go into a
+    /// BTree. For those attributes, only index the first
+    /// STRING_PREFIX_SIZE or BYTE_ARRAY_PREFIX_SIZE characters
+    /// see: attr-bytea-prefix
+    fn index_expr(&self) -> String {
+        if self.use_prefix_comparison {
+            match self.column_type {
+                ColumnType::String => {
+                    format!("left({}, {})", self.name.quoted(), STRING_PREFIX_SIZE)
+                }
+                ColumnType::Bytes => format!(
+                    "substring({}, 1, {})",
+                    self.name.quoted(),
+                    BYTE_ARRAY_PREFIX_SIZE
+                ),
+                _ => unreachable!("only String and Bytes can have arbitrary size"),
+            }
+        } else {
+            self.name.quoted()
+        }
+    }
+
     /// Generate the DDL for one column, i.e. the part of a `create table`
     /// statement for this column.
     ///
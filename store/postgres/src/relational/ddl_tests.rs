@@ -74,6 +74,10 @@ fn generate_ddl() {
     let layout = test_layout(FORWARD_ENUM_GQL);
     let sql = layout.as_ddl().expect("Failed to generate DDL");
     check_eqv(FORWARD_ENUM_SQL, &sql);
+
+    let layout = test_layout(INDEX_GQL);
+    let sql = layout.as_ddl().expect("Failed to generate DDL");
+    check_eqv(INDEX_DDL, &sql);
 }
 
 #[test]
@@ -611,3 +615,69 @@ create index attr_0_1_thing_orientation
     on sgd0815.\"thing\" using btree(\"orientation\");
 
 ";
+
+const INDEX_GQL: &str = "
+type Pool @entity @noIndex {
+    id: ID!,
+    name: String!
+}
+
+type Swap @entity(immutable: true)
+    @index(fields: [\"pool\", \"timestamp\"])
+    @index(fields: [\"sender\"], where: { settled: false, memo: null }) {
+    id: ID!,
+    pool: Pool!,
+    timestamp: BigInt!,
+    sender: String!,
+    settled: Boolean!,
+    memo: String @noIndex
+}
+";
+
+const INDEX_DDL: &str = "create table sgd0815.\"pool\" (
+        vid                  bigserial primary key,
+        block_range          int4range not null,
+        \"id\"                 text not null,
+        \"name\"               text not null
+);
+alter table sgd0815.\"pool\"
+  add constraint pool_id_block_range_excl exclude using gist (id with =, block_range with &&);
+create index brin_pool
+    on sgd0815.pool
+ using brin(lower(block_range), coalesce(upper(block_range), 2147483647), vid);
+create index pool_block_range_closed
+    on sgd0815.pool(coalesce(upper(block_range), 2147483647))
+ where coalesce(upper(block_range), 2147483647) < 2147483647;
+create index attr_0_0_pool_id
+    on sgd0815.\"pool\" using btree(\"id\");
+
+create table sgd0815.\"swap\" (
+        vid                    bigserial primary key,
+        block$                 int not null,
+        \"id\"                 text not null,
+        \"pool\"               text not null,
+        \"timestamp\"          numeric not null,
+        \"sender\"             text not null,
+        \"settled\"            boolean not null,
+        \"memo\"               text,
+
+        unique(id)
+);
+create index brin_swap
+    on sgd0815.swap
+ using brin(block$, vid);
+create index attr_1_1_swap_pool
+    on sgd0815.\"swap\" using btree(\"pool\", block$);
+create index attr_1_2_swap_timestamp
+    on sgd0815.\"swap\" using btree(\"timestamp\");
+create index attr_1_3_swap_sender
+    on sgd0815.\"swap\" using btree(left(\"sender\", 256));
+create index attr_1_4_swap_settled
+    on sgd0815.\"swap\" using btree(\"settled\");
+create index idx_1_0_swap
+    on sgd0815.\"swap\" using btree(\"pool\", \"timestamp\");
+create index idx_1_1_swap
+    on sgd0815.\"swap\" using btree(left(\"sender\", 256))
+ where \"memo\" is null and \"settled\" = false;
+
+";
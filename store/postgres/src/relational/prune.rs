@@ -7,7 +7,7 @@ use diesel::{
     Connection, PgConnection, RunQueryDsl,
 };
 use graph::{
-    components::store::PruneReporter,
+    components::store::{PruneReporter, PruneRequest, PruneStrategy, VersionStats},
     prelude::{BlockNumber, CancelHandle, CancelToken, CancelableError, CheapClone, StoreError},
     slog::Logger,
 };
@@ -216,18 +216,132 @@ impl TablePair {
     }
 }
 
+/// Delete all entity versions from `table` that are not visible at
+/// `earliest_block` or any later block. Versions are deleted in batches in
+/// the order of their `vid` so that no single transaction runs for very
+/// long; the size of the batches adapts to how quickly we can delete them
+fn delete_outdated_entities(
+    conn: &PgConnection,
+    reporter: &mut dyn PruneReporter,
+    table: &Table,
+    earliest_block: BlockNumber,
+    cancel: &CancelHandle,
+) -> Result<usize, CancelableError<StoreError>> {
+    #[derive(QueryableByName)]
+    struct VidRange {
+        #[sql_type = "Nullable<BigInt>"]
+        min_vid: Option<i64>,
+        #[sql_type = "Nullable<BigInt>"]
+        max_vid: Option<i64>,
+    }
+
+    #[derive(QueryableByName)]
+    struct LastVid {
+        #[sql_type = "BigInt"]
+        rows: i64,
+        #[sql_type = "Nullable<BigInt>"]
+        last_vid: Option<i64>,
+    }
+
+    let (min_vid, max_vid) = match sql_query(&format!(
+        "select min(vid) as min_vid, max(vid) as max_vid from {qname} \
+          where coalesce(upper(block_range), 2147483647) <= $1",
+        qname = table.qualified_name
+    ))
+    .bind::<Integer, _>(earliest_block)
+    .get_result::<VidRange>(conn)?
+    {
+        VidRange {
+            min_vid: Some(min),
+            max_vid: Some(max),
+        } => (min, max),
+        _ => {
+            reporter.delete_batch(table.name.as_str(), 0, 0, true);
+            return Ok(0);
+        }
+    };
+    cancel.check_cancel()?;
+
+    let mut batch_size = AdaptiveBatchSize::new(table);
+    let mut next_vid = min_vid;
+    let mut total_rows: usize = 0;
+    loop {
+        let start = Instant::now();
+        let LastVid { last_vid, rows } = conn.transaction(|| {
+            sql_query(&format!(
+                "with dt as (delete from {qname} \
+                    where vid in (select vid from {qname} \
+                                   where coalesce(upper(block_range), 2147483647) <= $1 \
+                                     and vid >= $2 \
+                                     and vid <= $3 \
+                                   order by vid \
+                                   limit $4) \
+                    returning vid) \
+                 select max(dt.vid) as last_vid, count(*) as rows from dt",
+                qname = table.qualified_name
+            ))
+            .bind::<Integer, _>(earliest_block)
+            .bind::<BigInt, _>(next_vid)
+            .bind::<BigInt, _>(max_vid)
+            .bind::<BigInt, _>(&batch_size)
+            .get_result::<LastVid>(conn)
+        })?;
+        cancel.check_cancel()?;
+
+        total_rows += rows as usize;
+        let finished = last_vid.map_or(true, |last_vid| last_vid >= max_vid);
+        reporter.delete_batch(table.name.as_str(), rows as usize, total_rows, finished);
+
+        match last_vid {
+            Some(last_vid) if !finished => next_vid = last_vid + 1,
+            _ => break,
+        }
+        batch_size.adapt(start.elapsed());
+    }
+
+    Ok(total_rows)
+}
+
 impl Layout {
-    /// Remove all data from the underlying deployment that is not needed to
-    /// respond to queries before block `earliest_block`. The strategy
-    /// implemented here works well for situations in which pruning will
-    /// remove a large amount of data from the subgraph (at least 50%)
-    ///
-    /// Blocks before `final_block` are considered final and it is assumed
+    /// Analyze the `tables` and return statistics about them
+    fn analyze_tables(
+        &self,
+        conn: &PgConnection,
+        reporter: &mut dyn PruneReporter,
+        mut tables: Vec<&Arc<Table>>,
+        cancel: &CancelHandle,
+    ) -> Result<Vec<VersionStats>, CancelableError<StoreError>> {
+        reporter.start_analyze();
+        tables.sort_by_key(|table| table.name.as_str());
+        for table in &tables {
+            reporter.start_analyze_table(table.name.as_str());
+            table.analyze(conn)?;
+            reporter.finish_analyze_table(table.name.as_str());
+            cancel.check_cancel()?;
+        }
+        let stats = catalog::stats(conn, &self.site.namespace)?
+            .into_iter()
+            .filter(|s| {
+                tables
+                    .iter()
+                    .any(|table| table.name.as_str() == s.tablename)
+            })
+            .collect::<Vec<_>>();
+        reporter.finish_analyze(stats.as_slice());
+        Ok(stats)
+    }
+
+    /// Remove all data from the underlying deployment that is not needed
+    /// to respond to queries before block `req.earliest_block`. Blocks
+    /// before `req.final_block` are considered final and it is assumed
     /// that they will not be modified in any way while pruning is running.
-    /// Only tables where the ratio of entities to entity versions is below
-    /// `prune_ratio` will actually be pruned.
     ///
-    /// The strategy for `prune_by_copying` is to copy all data that is
+    /// For each table, `req.strategy` decides whether the table should be
+    /// pruned at all, and how. Tables from which pruning removes a large
+    /// amount of data (by default, at least 50%) are rebuilt; the other
+    /// tables are pruned by deleting outdated entity versions in batches.
+    ///
+    /// The strategy for rebuilding a table is to copy all data that is
     /// needed to respond to queries at block heights at or after
     /// `earliest_block` to a new table and then to replace the existing
     /// tables with these new tables atomically in a transaction. Copying
@@ -240,74 +354,77 @@ impl Layout {
     /// that they all have the same names as the original objects to ensure
     /// that pruning can be done again without risking name clashes.
     ///
-    /// The reason this strategy works well when a lot (or even the
-    /// majority) of the data needs to be removed is that in the more
-    /// straightforward strategy of simply deleting unneeded data, accessing
-    /// the remaining data becomes very inefficient since it is scattered
-    /// over a large number of pages, often with just one row per page. We
-    /// would therefore need to do a full vacuum of the tables after
-    /// deleting which effectively copies the remaining data into new
-    /// tables. But a full vacuum takes an `access exclusive` lock which
-    /// prevents both reads and writes to the table, which means it would
-    /// also block queries to the deployment, often for extended periods of
-    /// time. The `prune_by_copying` strategy never blocks reads, it only
-    /// ever blocks writes.
-    pub fn prune_by_copying(
+    /// The reason rebuilding works well when a lot (or even the majority)
+    /// of the data needs to be removed is that in the more straightforward
+    /// strategy of simply deleting unneeded data, accessing the remaining
+    /// data becomes very inefficient since it is scattered over a large
+    /// number of pages, often with just one row per page. We would
+    /// therefore need to do a full vacuum of the tables after deleting
+    /// which effectively copies the remaining data into new tables. But a
+    /// full vacuum takes an `access exclusive` lock which prevents both
+    /// reads and writes to the table, which means it would also block
+    /// queries to the deployment, often for extended periods of time.
+    /// Rebuilding never blocks reads, it only ever blocks writes. When
+    /// only a small fraction of a table needs to be removed, deleting is
+    /// much cheaper than copying the entire table, and the space that the
+    /// deleted rows used gets reused by later writes
+    pub fn prune(
         &self,
         _logger: &Logger,
         reporter: &mut dyn PruneReporter,
         conn: &PgConnection,
-        earliest_block: BlockNumber,
-        final_block: BlockNumber,
-        prune_ratio: f64,
+        req: &PruneRequest,
         cancel: &CancelHandle,
     ) -> Result<(), CancelableError<StoreError>> {
+        // Immutable entities never have more than one version, and pruning
+        // never removes anything from their tables
+        let tables: Vec<_> = self
+            .tables
+            .values()
+            .filter(|table| !table.immutable)
+            .collect();
+
         // Analyze all tables and get statistics for them
-        let mut tables: Vec<_> = self.tables.values().collect();
-        reporter.start_analyze();
-        tables.sort_by_key(|table| table.name.as_str());
-        for table in tables {
-            reporter.start_analyze_table(table.name.as_str());
-            table.analyze(conn)?;
-            reporter.finish_analyze_table(table.name.as_str());
-            cancel.check_cancel()?;
-        }
-        let stats = catalog::stats(conn, &self.site.namespace)?;
-        reporter.finish_analyze(stats.as_slice());
+        let stats = self.analyze_tables(conn, reporter, tables, cancel)?;
+
+        // Determine how to prune each table
+        let mut prunable_tables: Vec<_> = self
+            .tables
+            .values()
+            .filter(|table| !table.immutable)
+            .filter_map(|table| {
+                stats
+                    .iter()
+                    .find(|s| s.tablename == table.name.as_str())
+                    .and_then(|s| req.strategy(s))
+                    .map(|strategy| (table, strategy))
+            })
+            .collect();
+        prunable_tables.sort_by(|(a, _), (b, _)| a.name.as_str().cmp(b.name.as_str()));
 
-        // Determine which tables are prunable and create a shadow table for
-        // them via `TablePair::create`
-        let prunable_tables = {
-            let mut prunable_tables: Vec<TablePair> = self
-                .tables
-                .values()
-                .filter_map(|table| {
-                    stats
-                        .iter()
-                        .find(|s| s.tablename == table.name.as_str())
-                        .map(|s| (table, s))
-                })
-                .filter(|(_, stats)| stats.ratio <= prune_ratio)
-                .map(|(table, _)| TablePair::create(conn, self, table.cheap_clone()))
-                .collect::<Result<_, _>>()?;
-            prunable_tables.sort_by(|a, b| a.src.name.as_str().cmp(b.src.name.as_str()));
-            prunable_tables
-        };
+        // Create a shadow table for the tables we rebuild via
+        // `TablePair::create`
+        let rebuild_tables = prunable_tables
+            .iter()
+            .filter(|(_, strategy)| *strategy == PruneStrategy::Rebuild)
+            .map(|(table, _)| TablePair::create(conn, self, table.cheap_clone()))
+            .collect::<Result<Vec<_>, _>>()?;
         cancel.check_cancel()?;
 
         // Copy final entities. This can happen in parallel to indexing as
         // that part of the table will not change
-        reporter.copy_final_start(earliest_block, final_block);
-        for table in &prunable_tables {
-            table.copy_final_entities(conn, reporter, earliest_block, final_block, cancel)?;
+        reporter.copy_final_start(req.earliest_block, req.final_block);
+        for table in &rebuild_tables {
+            table.copy_final_entities(
+                conn,
+                reporter,
+                req.earliest_block,
+                req.final_block,
+                cancel,
+            )?;
         }
         reporter.copy_final_finish();
 
-        let prunable_src: Vec<_> = prunable_tables
-            .iter()
-            .map(|table| table.src.clone())
-            .collect();
-
         // Copy nonfinal entities, and replace the original `src` table with
         // the smaller `dst` table
         reporter.start_switch();
@@ -315,14 +432,14 @@ impl Layout {
             //  see also: deployment-lock-for-update
             deployment::lock(conn, &self.site)?;
 
-            for table in &prunable_tables {
+            for table in &rebuild_tables {
                 reporter.copy_nonfinal_start(table.src.name.as_str());
-                let rows = table.copy_nonfinal_entities(conn, final_block)?;
+                let rows = table.copy_nonfinal_entities(conn, req.final_block)?;
                 reporter.copy_nonfinal_finish(table.src.name.as_str(), rows);
                 cancel.check_cancel()?;
             }
 
-            for table in prunable_tables {
+            for table in rebuild_tables {
                 table.switch(conn, self)?;
                 cancel.check_cancel()?;
             }
@@ -331,24 +448,24 @@ impl Layout {
         })?;
         reporter.finish_switch();
 
-        // Analyze the new tables
-        reporter.start_analyze();
-        for table in &prunable_src {
-            reporter.start_analyze_table(table.name.as_str());
-            table.analyze(conn)?;
-            reporter.finish_analyze_table(table.name.as_str());
-            cancel.check_cancel()?;
+        // Delete outdated entity versions from the tables we do not
+        // rebuild. Since these versions are no longer visible to queries,
+        // deleting them does not need to block writes
+        reporter.delete_start(req.earliest_block);
+        for (table, _) in prunable_tables
+            .iter()
+            .filter(|(_, strategy)| *strategy == PruneStrategy::Delete)
+        {
+            delete_outdated_entities(conn, reporter, table, req.earliest_block, cancel)?;
         }
-        let stats: Vec<_> = catalog::stats(conn, &self.site.namespace)?
+        reporter.delete_finish();
+
+        // Analyze the pruned tables
+        let pruned_tables: Vec<_> = prunable_tables
             .into_iter()
-            .filter(|s| {
-                prunable_src
-                    .iter()
-                    .find(|table| table.name.as_str() == s.tablename)
-                    .is_some()
-            })
+            .map(|(table, _)| table)
             .collect();
-        reporter.finish_analyze(stats.as_slice());
+        self.analyze_tables(conn, reporter, pruned_tables, cancel)?;
 
         reporter.finish_prune();
 
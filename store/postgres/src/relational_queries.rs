@@ -9,15 +9,16 @@ use diesel::pg::{Pg, PgConnection};
 use diesel::query_builder::{AstPass, QueryFragment, QueryId};
 use diesel::query_dsl::{LoadQuery, RunQueryDsl};
 use diesel::result::{Error as DieselError, QueryResult};
-use diesel::sql_types::{Array, BigInt, Binary, Bool, Integer, Jsonb, Text};
+use diesel::sql_types::{Array, BigInt, Binary, Bool, Integer, Jsonb, Nullable, Text};
 use diesel::Connection;
 
 use graph::components::store::EntityKey;
 use graph::data::value::Word;
 use graph::prelude::{
-    anyhow, r, serde_json, Attribute, BlockNumber, ChildMultiplicity, Entity, EntityCollection,
-    EntityFilter, EntityLink, EntityOrder, EntityRange, EntityWindow, ParentLink,
-    QueryExecutionError, StoreError, Value, ENV_VARS,
+    anyhow, r, serde_json, AggregateFunction, Attribute, BlockNumber, ChildMultiplicity, Entity,
+    EntityAggregates, EntityCollection, EntityCursor, EntityFilter, EntityLink, EntityOrder,
+    EntityOrderByChild, EntityRange, EntityVersion, EntityWindow, OrderDirection, ParentLink,
+    QueryExecutionError, StoreError, Value, ValueType, BLOCK_NUMBER_MAX, ENV_VARS,
 };
 use graph::{
     components::store::{AttributeNames, EntityType},
@@ -38,8 +39,8 @@ use crate::relational::{
 use crate::sql_value::SqlValue;
 use crate::{
     block_range::{
-        BlockRangeColumn, BlockRangeLowerBoundClause, BlockRangeUpperBoundClause, BLOCK_COLUMN,
-        BLOCK_RANGE_COLUMN, BLOCK_RANGE_CURRENT,
+        BlockRange, BlockRangeColumn, BlockRangeLowerBoundClause, BlockRangeUpperBoundClause,
+        BLOCK_COLUMN, BLOCK_RANGE_COLUMN, BLOCK_RANGE_CURRENT,
     },
     primary::{Namespace, Site},
 };
@@ -281,6 +282,11 @@ pub trait FromColumnValue: Sized + std::fmt::Debug {
 
     fn from_i32(i: i32) -> Self;
 
+    fn from_i64(i: i64) -> Self;
+
+    // The string returned by the DB for a `timestamptz` column
+    fn from_timestamp(s: &str) -> Result<Self, StoreError>;
+
     fn from_big_decimal(d: scalar::BigDecimal) -> Self;
 
     fn from_big_int(i: serde_json::Number) -> Result<Self, StoreError>;
@@ -310,6 +316,13 @@ pub trait FromColumnValue: Sized + std::fmt::Debug {
                     number
                 ))),
             },
+            (j::Number(number), ColumnType::Int8) => match number.as_i64() {
+                Some(i) => Ok(Self::from_i64(i)),
+                None => Err(StoreError::Unknown(anyhow!(
+                    "failed to convert {} to Int8",
+                    number
+                ))),
+            },
             (j::Number(number), ColumnType::BigDecimal) => {
                 let s = number.to_string();
                 scalar::BigDecimal::from_str(s.as_str())
@@ -332,6 +345,7 @@ pub trait FromColumnValue: Sized + std::fmt::Debug {
                 Ok(Self::from_string(s))
             }
             (j::String(s), ColumnType::Bytes) => Self::from_bytes(s.trim_start_matches("\\x")),
+            (j::String(s), ColumnType::Timestamp) => Self::from_timestamp(&s),
             (j::String(s), column_type) => Err(StoreError::Unknown(anyhow!(
                 "can not convert string {} to {:?}",
                 s,
@@ -371,6 +385,18 @@ impl FromColumnValue for r::Value {
         r::Value::Int(i.into())
     }
 
+    fn from_i64(i: i64) -> Self {
+        r::Value::String(i.to_string())
+    }
+
+    fn from_timestamp(s: &str) -> Result<Self, StoreError> {
+        scalar::Timestamp::from_str(s)
+            .map(|ts| r::Value::String(ts.to_string()))
+            .map_err(|e| {
+                StoreError::Unknown(anyhow!("failed to convert {} to Timestamp: {}", s, e))
+            })
+    }
+
     fn from_big_decimal(d: scalar::BigDecimal) -> Self {
         r::Value::String(d.to_string())
     }
@@ -416,6 +442,18 @@ impl FromColumnValue for graph::prelude::Value {
         graph::prelude::Value::Int(i)
     }
 
+    fn from_i64(i: i64) -> Self {
+        graph::prelude::Value::Int8(i)
+    }
+
+    fn from_timestamp(s: &str) -> Result<Self, StoreError> {
+        scalar::Timestamp::from_str(s)
+            .map(graph::prelude::Value::Timestamp)
+            .map_err(|e| {
+                StoreError::Unknown(anyhow!("failed to convert {} to Timestamp: {}", s, e))
+            })
+    }
+
     fn from_big_decimal(d: scalar::BigDecimal) -> Self {
         graph::prelude::Value::BigDecimal(d)
     }
@@ -572,6 +610,12 @@ impl<'a> QueryFragment<Pg> for QueryValue<'a> {
                 ),
             },
             Value::Int(i) => out.push_bind_param::<Integer, _>(i),
+            Value::Int8(i) => out.push_bind_param::<BigInt, _>(i),
+            Value::Timestamp(ts) => {
+                out.push_bind_param::<Text, _>(&ts.to_rfc3339())?;
+                out.push_sql("::timestamptz");
+                Ok(())
+            }
             Value::BigDecimal(d) => {
                 out.push_bind_param::<Text, _>(&d.to_string())?;
                 out.push_sql("::numeric");
@@ -590,6 +634,19 @@ impl<'a> QueryFragment<Pg> for QueryValue<'a> {
                     ColumnType::Boolean => out.push_bind_param::<Array<Bool>, _>(&sql_values),
                     ColumnType::Bytes => out.push_bind_param::<Array<Binary>, _>(&sql_values),
                     ColumnType::Int => out.push_bind_param::<Array<Integer>, _>(&sql_values),
+                    ColumnType::Int8 => out.push_bind_param::<Array<BigInt>, _>(&sql_values),
+                    ColumnType::Timestamp => {
+                        let text_values: Vec<_> = values
+                            .iter()
+                            .map(|v| match v {
+                                Value::Timestamp(ts) => ts.to_rfc3339(),
+                                v => v.to_string(),
+                            })
+                            .collect();
+                        out.push_bind_param::<Array<Text>, _>(&text_values)?;
+                        out.push_sql("::timestamptz[]");
+                        Ok(())
+                    }
                     ColumnType::String => out.push_bind_param::<Array<Text>, _>(&sql_values),
                     ColumnType::Enum(enum_type) => {
                         out.push_bind_param::<Array<Text>, _>(&sql_values)?;
@@ -1169,6 +1226,8 @@ impl<'a> QueryFilter<'a> {
             Value::Null
             | Value::BigDecimal(_)
             | Value::Int(_)
+            | Value::Int8(_)
+            | Value::Timestamp(_)
             | Value::Bool(_)
             | Value::BigInt(_) => {
                 let filter = match negated {
@@ -1239,6 +1298,8 @@ impl<'a> QueryFilter<'a> {
                 | Value::Bytes(_)
                 | Value::BigDecimal(_)
                 | Value::Int(_)
+                | Value::Int8(_)
+                | Value::Timestamp(_)
                 | Value::String(_) => QueryValue(value, &column.column_type).walk_ast(out)?,
                 Value::Bool(_) | Value::List(_) | Value::Null => {
                     return Err(UnsupportedFilter {
@@ -1378,6 +1439,8 @@ impl<'a> QueryFilter<'a> {
             | Value::Bytes(_)
             | Value::BigDecimal(_)
             | Value::Int(_)
+            | Value::Int8(_)
+            | Value::Timestamp(_)
             | Value::List(_)
             | Value::Null => {
                 return Err(UnsupportedFilter {
@@ -1643,6 +1706,69 @@ impl<'a> LoadQuery<PgConnection, EntityData> for FindManyQuery<'a> {
 
 impl<'a, Conn> RunQueryDsl<Conn> for FindManyQuery<'a> {}
 
+/// Find the entities in `table` whose `column` references the entity with
+/// id `value`, leaving out the entities with ids in `excluded_ids`
+#[derive(Debug, Clone, Constructor)]
+pub struct FindDerivedQuery<'a> {
+    table: &'a Table,
+    column: &'a Column,
+    value: &'a str,
+    excluded_ids: &'a [&'a str],
+    block: BlockNumber,
+}
+
+impl<'a> QueryFragment<Pg> for FindDerivedQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        // Generate
+        //    select '..' as entity, to_jsonb(e.*) as data
+        //      from schema.table e
+        //     where e.column = $value (or $value = any(e.column) for lists)
+        //       and not exists (select 1 from unnest($excluded) ...)
+        //       and e.block_range @> $block
+        out.push_sql("select ");
+        out.push_bind_param::<Text, _>(&self.table.object.as_str())?;
+        out.push_sql(" as entity, to_jsonb(e.*) as data\n");
+        out.push_sql("  from ");
+        out.push_sql(self.table.qualified_name.as_str());
+        out.push_sql(" e\n where ");
+        if self.column.is_list() {
+            self.column.bind_id(self.value, &mut out)?;
+            out.push_sql(" = any(e.");
+            out.push_identifier(self.column.name.as_str())?;
+            out.push_sql(")");
+        } else {
+            out.push_sql("e.");
+            out.push_identifier(self.column.name.as_str())?;
+            out.push_sql(" = ");
+            self.column.bind_id(self.value, &mut out)?;
+        }
+        if !self.excluded_ids.is_empty() {
+            out.push_sql(" and not ");
+            self.table
+                .primary_key()
+                .is_in(self.excluded_ids, &mut out)?;
+        }
+        out.push_sql(" and ");
+        BlockRangeColumn::new(self.table, "e.", self.block).contains(&mut out)
+    }
+}
+
+impl<'a> QueryId for FindDerivedQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> LoadQuery<PgConnection, EntityData> for FindDerivedQuery<'a> {
+    fn internal_load(self, conn: &PgConnection) -> QueryResult<Vec<EntityData>> {
+        conn.query_by_name(&self)
+    }
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for FindDerivedQuery<'a> {}
+
 #[derive(Debug)]
 pub struct InsertQuery<'a> {
     table: &'a Table,
@@ -1950,6 +2076,14 @@ impl<'a> ParentLimit<'a> {
         }
     }
 
+    /// Join the tables that ranking by the sort key needs
+    fn join(&self, out: &mut AstPass<Pg>) -> QueryResult<()> {
+        if let ParentLimit::Ranked(sort_key, _) = self {
+            sort_key.join(out)?;
+        }
+        Ok(())
+    }
+
     fn restrict(&self, out: &mut AstPass<Pg>) -> QueryResult<()> {
         if let ParentLimit::Ranked(sort_key, range) = self {
             out.push_sql(" ");
@@ -2057,7 +2191,7 @@ impl<'a> FilterWindow<'a> {
         //      from unnest({parent_ids}) as p(id)
         //           cross join lateral
         //           (select {column names}
-        //              from children c
+        //              from children c {sort_key joins}
         //             where p.id = any(c.{parent_field})
         //               and .. other conditions on c ..
         //             order by c.{sort_key}
@@ -2070,7 +2204,9 @@ impl<'a> FilterWindow<'a> {
         write_column_names(&self.column_names, self.table, out)?;
         out.push_sql(" from ");
         out.push_sql(self.table.qualified_name.as_str());
-        out.push_sql(" c where ");
+        out.push_sql(" c");
+        limit.join(out)?;
+        out.push_sql("\n where ");
         BlockRangeColumn::new(self.table, "c.", block).contains(out)?;
         limit.filter(out);
         out.push_sql(" and p.id = any(c.");
@@ -2136,7 +2272,7 @@ impl<'a> FilterWindow<'a> {
         //      from unnest({parent_ids}) as p(id)
         //           cross join lateral
         //           (select {column names}
-        //              from children c
+        //              from children c {sort_key joins}
         //             where p.id = c.{parent_field}
         //               and .. other conditions on c ..
         //             order by c.{sort_key}
@@ -2149,7 +2285,9 @@ impl<'a> FilterWindow<'a> {
         write_column_names(&self.column_names, self.table, out)?;
         out.push_sql(" from ");
         out.push_sql(self.table.qualified_name.as_str());
-        out.push_sql(" c where ");
+        out.push_sql(" c");
+        limit.join(out)?;
+        out.push_sql("\n where ");
         BlockRangeColumn::new(self.table, "c.", block).contains(out)?;
         limit.filter(out);
         out.push_sql(" and p.id = c.");
@@ -2202,7 +2340,7 @@ impl<'a> FilterWindow<'a> {
         //                  as p(id, child_ids)
         //           cross join lateral
         //           (select {column names}
-        //              from children c
+        //              from children c {sort_key joins}
         //             where c.id = any(p.child_ids)
         //               and .. other conditions on c ..
         //             order by c.{sort_key}
@@ -2219,7 +2357,9 @@ impl<'a> FilterWindow<'a> {
         write_column_names(&self.column_names, self.table, out)?;
         out.push_sql(" from ");
         out.push_sql(self.table.qualified_name.as_str());
-        out.push_sql(" c where ");
+        out.push_sql(" c");
+        limit.join(out)?;
+        out.push_sql("\n where ");
         BlockRangeColumn::new(self.table, "c.", block).contains(out)?;
         limit.filter(out);
         out.push_sql(" and c.id = any(p.child_ids)");
@@ -2529,7 +2669,7 @@ impl<'a> FilterCollection<'a> {
 
 /// Convenience to pass the name of the column to order by around. If `name`
 /// is `None`, the sort key should be ignored
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub enum SortKey<'a> {
     None,
     /// Order by `id asc`
@@ -2542,6 +2682,21 @@ pub enum SortKey<'a> {
         value: Option<&'a str>,
         direction: &'static str,
     },
+    /// Order by a column of a child entity that `join_column` references.
+    /// There is one `(table, column)` pair for each table the child can be
+    /// stored in; the child tables are joined as `cc0`, `cc1`, ...
+    ChildKey {
+        join_column: &'a Column,
+        children: Vec<(&'a Table, &'a Column)>,
+        direction: &'static str,
+        block: BlockNumber,
+    },
+    /// Order by several columns in turn, none of which is `id`; ties are
+    /// broken by ordering by `id` in `id_direction`
+    Multi {
+        columns: Vec<(&'a Column, &'static str)>,
+        id_direction: &'static str,
+    },
 }
 
 /// String representation that is useful for debugging when `walk_ast` fails
@@ -2567,6 +2722,33 @@ impl<'a> fmt::Display for SortKey<'a> {
                 PRIMARY_KEY_COLUMN,
                 direction
             ),
+            ChildKey {
+                join_column,
+                children,
+                direction,
+                block: _,
+            } => write!(
+                f,
+                "{}.{} {}, {} {}",
+                join_column.name.as_str(),
+                children
+                    .iter()
+                    .map(|(_, column)| column.name.as_str())
+                    .unique()
+                    .join("|"),
+                direction,
+                PRIMARY_KEY_COLUMN,
+                direction
+            ),
+            Multi {
+                columns,
+                id_direction,
+            } => {
+                for (column, direction) in columns {
+                    write!(f, "{} {}, ", column.name.as_str(), direction)?;
+                }
+                write!(f, "{} {}", PRIMARY_KEY_COLUMN, id_direction)
+            }
         }
     }
 }
@@ -2577,6 +2759,7 @@ impl<'a> SortKey<'a> {
         collection: &'a FilterCollection,
         filter: Option<&'a EntityFilter>,
         block: BlockNumber,
+        layout: &'a Layout,
     ) -> Result<Self, QueryExecutionError> {
         const ASC: &str = "asc";
         const DESC: &str = "desc";
@@ -2617,6 +2800,79 @@ impl<'a> SortKey<'a> {
             }
         }
 
+        fn with_child_key<'a>(
+            layout: &'a Layout,
+            table: &'a Table,
+            child: EntityOrderByChild,
+            direction: &'static str,
+            block: BlockNumber,
+        ) -> Result<SortKey<'a>, QueryExecutionError> {
+            let join_column = table.column_for_field(&child.join_attribute)?;
+            let children = child
+                .child_types
+                .iter()
+                .map(|child_type| {
+                    let child_table = layout.table_for_entity(child_type)?.as_ref();
+                    let column = child_table.column_for_field(&child.sort_by_attribute)?;
+                    if column.is_fulltext() {
+                        return Err(QueryExecutionError::NotSupported(
+                            "sorting by a fulltext field of a child entity".to_string(),
+                        ));
+                    }
+                    Ok((child_table, column))
+                })
+                .collect::<Result<Vec<_>, QueryExecutionError>>()?;
+            Ok(SortKey::ChildKey {
+                join_column,
+                children,
+                direction,
+                block,
+            })
+        }
+
+        fn with_keys<'a>(
+            table: &'a Table,
+            keys: Vec<(String, OrderDirection)>,
+            br_column: Option<BlockRangeColumn<'a>>,
+        ) -> Result<SortKey<'a>, QueryExecutionError> {
+            let mut columns = Vec::new();
+            let mut id_direction = None;
+            for (attribute, direction) in keys {
+                let direction = match direction {
+                    OrderDirection::Ascending => ASC,
+                    OrderDirection::Descending => DESC,
+                };
+                let column = table.column_for_field(&attribute)?;
+                if column.is_fulltext() {
+                    return Err(QueryExecutionError::NotSupported(
+                        "sorting by a fulltext field together with other fields".to_string(),
+                    ));
+                }
+                if column.is_primary_key() {
+                    // `id` is unique, and any keys after it can not change
+                    // the order any more
+                    id_direction = Some(direction);
+                    break;
+                }
+                columns.push((column, direction));
+            }
+            let id_direction = id_direction
+                .or_else(|| columns.last().map(|(_, direction)| *direction))
+                .unwrap_or(ASC);
+
+            if columns.is_empty() {
+                match id_direction {
+                    ASC => Ok(SortKey::IdAsc(br_column)),
+                    _ => Ok(SortKey::IdDesc(br_column)),
+                }
+            } else {
+                Ok(SortKey::Multi {
+                    columns,
+                    id_direction,
+                })
+            }
+        }
+
         // If there is more than one table, we are querying an interface,
         // and the order is on an attribute in that interface so that all
         // tables have a column for that. It is therefore enough to just
@@ -2634,6 +2890,17 @@ impl<'a> SortKey<'a> {
         match order {
             EntityOrder::Ascending(attr, _) => with_key(table, attr, filter, ASC, br_column),
             EntityOrder::Descending(attr, _) => with_key(table, attr, filter, DESC, br_column),
+            EntityOrder::ChildAscending(child) => with_child_key(layout, table, child, ASC, block),
+            EntityOrder::ChildDescending(child) => {
+                with_child_key(layout, table, child, DESC, block)
+            }
+            EntityOrder::Multi(keys) => with_keys(
+                table,
+                keys.into_iter()
+                    .map(|(attr, _, direction)| (attr, direction))
+                    .collect(),
+                br_column,
+            ),
             EntityOrder::Default => Ok(SortKey::IdAsc(br_column)),
             EntityOrder::Unordered => Ok(SortKey::None),
         }
@@ -2662,7 +2929,46 @@ impl<'a> SortKey<'a> {
                 out.push_identifier(column.name.as_str())?;
                 Ok(())
             }
+            SortKey::ChildKey { .. } => Err(constraint_violation!(
+                "SortKey::ChildKey can only be used to sort a single entity type"
+            )),
+            SortKey::Multi { columns, .. } => {
+                for (column, _) in columns {
+                    out.push_sql(", c.");
+                    out.push_identifier(column.name.as_str())?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Generate the joins with the child tables that we need to sort by
+    ///   left join {child_table} cc{i} on cc{i}.id = c.{join_column}
+    ///                                and cc{i}.block_range @> $block
+    fn join(&self, out: &mut AstPass<Pg>) -> QueryResult<()> {
+        if let SortKey::ChildKey {
+            join_column,
+            children,
+            block,
+            ..
+        } = self
+        {
+            for (i, (child_table, _)) in children.iter().enumerate() {
+                let prefix = format!("cc{}.", i);
+                out.push_sql("\n  left join ");
+                out.push_sql(child_table.qualified_name.as_str());
+                out.push_sql(" cc");
+                out.push_sql(&i.to_string());
+                out.push_sql(" on ");
+                out.push_sql(&prefix);
+                out.push_identifier(PRIMARY_KEY_COLUMN)?;
+                out.push_sql(" = c.");
+                out.push_identifier(join_column.name.as_str())?;
+                out.push_sql(" and ");
+                BlockRangeColumn::new(child_table, &prefix, *block).contains(out)?;
+            }
         }
+        Ok(())
     }
 
     /// Generate
@@ -2698,6 +3004,21 @@ impl<'a> SortKey<'a> {
                 out.push_sql("order by ");
                 SortKey::sort_expr(column, value, direction, out)
             }
+            SortKey::ChildKey {
+                children,
+                direction,
+                ..
+            } => {
+                out.push_sql("order by ");
+                SortKey::child_sort_expr(children, direction, out)
+            }
+            SortKey::Multi {
+                columns,
+                id_direction,
+            } => {
+                out.push_sql("order by ");
+                SortKey::multi_sort_expr(columns, id_direction, out)
+            }
         }
     }
 
@@ -2724,9 +3045,139 @@ impl<'a> SortKey<'a> {
                 out.push_sql("order by g$parent_id, ");
                 SortKey::sort_expr(column, value, direction, out)
             }
+            SortKey::ChildKey {
+                children,
+                direction,
+                ..
+            } => {
+                out.push_sql("order by g$parent_id, ");
+                SortKey::child_sort_expr(children, direction, out)
+            }
+            SortKey::Multi {
+                columns,
+                id_direction,
+            } => {
+                out.push_sql("order by g$parent_id, ");
+                SortKey::multi_sort_expr(columns, id_direction, out)
+            }
+        }
+    }
+
+    /// Generate
+    ///   {column1} {direction1}, {column2} {direction2}, .., id {id_direction}
+    fn multi_sort_expr(
+        columns: &[(&Column, &str)],
+        id_direction: &str,
+        out: &mut AstPass<Pg>,
+    ) -> QueryResult<()> {
+        for (column, direction) in columns {
+            out.push_identifier(column.name.as_str())?;
+            out.push_sql(" ");
+            out.push_sql(direction);
+            out.push_sql(", ");
+        }
+        out.push_identifier(PRIMARY_KEY_COLUMN)?;
+        out.push_sql(" ");
+        out.push_sql(id_direction);
+        Ok(())
+    }
+
+    /// The sort key with all directions reversed. Sort keys that we can
+    /// not reverse are returned unchanged
+    fn reversed(&self) -> SortKey<'a> {
+        fn flip(direction: &str) -> &'static str {
+            if direction == "asc" {
+                "desc"
+            } else {
+                "asc"
+            }
+        }
+
+        match self {
+            SortKey::IdAsc(br_column) => SortKey::IdDesc(br_column.clone()),
+            SortKey::IdDesc(br_column) => SortKey::IdAsc(br_column.clone()),
+            SortKey::Key {
+                column,
+                value: None,
+                direction,
+            } => SortKey::Key {
+                column: *column,
+                value: None,
+                direction: flip(direction),
+            },
+            SortKey::Multi {
+                columns,
+                id_direction,
+            } => SortKey::Multi {
+                columns: columns
+                    .iter()
+                    .map(|(column, direction)| (*column, flip(direction)))
+                    .collect(),
+                id_direction: flip(id_direction),
+            },
+            SortKey::None | SortKey::Key { .. } | SortKey::ChildKey { .. } => self.clone(),
+        }
+    }
+
+    /// The columns, including `id` as the last one, and their directions
+    /// that determine the order of rows, or `None` if a cursor can not be
+    /// used with this sort key. They correspond to the attributes from
+    /// `EntityOrder::cursor_attributes`
+    fn cursor_columns<'b>(&'b self, table: &'b Table) -> Option<Vec<(&'b Column, &'static str)>> {
+        match self {
+            SortKey::IdAsc(_) => Some(vec![(table.primary_key(), "asc")]),
+            SortKey::IdDesc(_) => Some(vec![(table.primary_key(), "desc")]),
+            SortKey::Key {
+                column,
+                value: None,
+                direction,
+            } if !ENV_VARS.store.reversible_order_by_off => Some(vec![
+                (*column, *direction),
+                (table.primary_key(), *direction),
+            ]),
+            SortKey::Multi {
+                columns,
+                id_direction,
+            } => {
+                let mut keys = columns.clone();
+                keys.push((table.primary_key(), *id_direction));
+                Some(keys)
+            }
+            SortKey::None | SortKey::Key { .. } | SortKey::ChildKey { .. } => None,
         }
     }
 
+    /// Generate
+    ///   coalesce(cc0.{column}, cc1.{column}, ..) direction, c.id direction
+    fn child_sort_expr(
+        children: &[(&Table, &Column)],
+        direction: &str,
+        out: &mut AstPass<Pg>,
+    ) -> QueryResult<()> {
+        if children.len() > 1 {
+            out.push_sql("coalesce(");
+        }
+        for (i, (_, column)) in children.iter().enumerate() {
+            if i > 0 {
+                out.push_sql(", ");
+            }
+            out.push_sql("cc");
+            out.push_sql(&i.to_string());
+            out.push_sql(".");
+            out.push_identifier(column.name.as_str())?;
+        }
+        if children.len() > 1 {
+            out.push_sql(")");
+        }
+        out.push_sql(" ");
+        out.push_sql(direction);
+        out.push_sql(", c.");
+        out.push_identifier(PRIMARY_KEY_COLUMN)?;
+        out.push_sql(" ");
+        out.push_sql(direction);
+        Ok(())
+    }
+
     /// Generate
     ///   [name direction,] id
     fn sort_expr(
@@ -2853,8 +3304,57 @@ impl<'a> FilterQuery<'a> {
         block: BlockNumber,
         query_id: Option<String>,
         site: &'a Site,
+        layout: &'a Layout,
     ) -> Result<Self, QueryExecutionError> {
-        let sort_key = SortKey::new(order, collection, filter, block)?;
+        let sort_key = SortKey::new(order, collection, filter, block, layout)?;
+
+        if let SortKey::ChildKey { .. } = sort_key {
+            match collection {
+                FilterCollection::All(entities) if entities.len() == 1 => (),
+                FilterCollection::SingleWindow(_) => (),
+                _ => {
+                    return Err(QueryExecutionError::NotSupported(
+                        "sorting by a child attribute is only supported for \
+                         queries of a single entity type with a single parent type"
+                            .to_string(),
+                    ))
+                }
+            }
+        }
+
+        if let Some(cursor) = &range.cursor {
+            match collection {
+                FilterCollection::All(entities) if entities.len() == 1 => (),
+                _ => {
+                    return Err(QueryExecutionError::NotSupported(
+                        "cursors are only supported for toplevel queries of a \
+                         single entity type"
+                            .to_string(),
+                    ))
+                }
+            }
+            let table = collection
+                .first_table()
+                .expect("an entity query always contains at least one entity type/table");
+            match sort_key.cursor_columns(table) {
+                None => {
+                    return Err(QueryExecutionError::NotSupported(format!(
+                        "cursors can not be used when ordering by `{}`",
+                        sort_key
+                    )))
+                }
+                Some(columns) if columns.len() != cursor.values().len() => {
+                    return Err(graph::constraint_violation!(
+                        "cursor has {} values but sort key {} needs {}",
+                        cursor.values().len(),
+                        sort_key,
+                        columns.len()
+                    )
+                    .into())
+                }
+                Some(_) => (),
+            }
+        }
 
         Ok(FilterQuery {
             collection,
@@ -2881,6 +3381,7 @@ impl<'a> FilterQuery<'a> {
         out.push_sql("\n  from ");
         out.push_sql(table.qualified_name.as_str());
         out.push_sql(" c");
+        self.sort_key.join(&mut out)?;
 
         out.push_sql("\n where ");
         BlockRangeColumn::new(&table, "c.", self.block).contains(&mut out)?;
@@ -2888,10 +3389,116 @@ impl<'a> FilterQuery<'a> {
             out.push_sql(" and ");
             filter.walk_ast(out.reborrow())?;
         }
+        if let Some(cursor) = &self.range.0.cursor {
+            out.push_sql(" and ");
+            self.cursor_filter(table, cursor, &mut out)?;
+        }
         out.push_sql("\n");
         Ok(())
     }
 
+    /// The sort key that the rows of a page are retrieved in. When paging
+    /// backwards from a cursor, we take the rows closest to the cursor by
+    /// going through them in reverse order
+    fn page_sort_key(&self) -> Cow<SortKey<'a>> {
+        match &self.range.0.cursor {
+            Some(EntityCursor::Before(_)) => Cow::Owned(self.sort_key.reversed()),
+            Some(EntityCursor::After(_)) | None => Cow::Borrowed(&self.sort_key),
+        }
+    }
+
+    /// Generate a condition that restricts rows to the ones that come after
+    /// the position of the cursor in the order of `page_sort_key`. With
+    /// columns `k1, k2, ..` and cursor values `$v1, $v2, ..`
+    ///
+    ///   ((c.k1 > $v1)
+    ///     or (c.k1 = $v1 and c.k2 > $v2)
+    ///     or ..)
+    ///
+    /// where a null cursor value is compared with `is null` instead
+    fn cursor_filter(
+        &self,
+        table: &Table,
+        cursor: &EntityCursor,
+        out: &mut AstPass<Pg>,
+    ) -> QueryResult<()> {
+        let values = cursor.values();
+        let sort_key = self.page_sort_key();
+        let columns = sort_key
+            .cursor_columns(table)
+            .ok_or_else(|| constraint_violation!("sort key {} has no cursor", self.sort_key))?;
+
+        out.push_sql("(");
+        for i in 0..columns.len() {
+            if i > 0 {
+                out.push_sql(" or ");
+            }
+            out.push_sql("(");
+            for ((column, _), value) in columns[..i].iter().zip(values) {
+                out.push_sql("c.");
+                out.push_identifier(column.name.as_str())?;
+                match value {
+                    Value::Null => out.push_sql(" is null"),
+                    value => {
+                        out.push_sql(" = ");
+                        QueryValue(value, &column.column_type).walk_ast(out.reborrow())?;
+                    }
+                }
+                out.push_sql(" and ");
+            }
+            let (column, direction) = columns[i];
+            Self::comes_after(column, direction, &values[i], out)?;
+            out.push_sql(")");
+        }
+        out.push_sql(")");
+        Ok(())
+    }
+
+    /// Generate a condition that the value of `column` for `c` comes after
+    /// `value` when sorting in `direction`. Postgres sorts nulls last in
+    /// ascending and first in descending order
+    fn comes_after(
+        column: &Column,
+        direction: &str,
+        value: &Value,
+        out: &mut AstPass<Pg>,
+    ) -> QueryResult<()> {
+        let name = column.name.as_str();
+        match (direction, value) {
+            // Nothing comes after null in ascending order
+            ("asc", Value::Null) => out.push_sql("false"),
+            // Everything that is not null comes after null in descending
+            // order
+            (_, Value::Null) => {
+                out.push_sql("c.");
+                out.push_identifier(name)?;
+                out.push_sql(" is not null");
+            }
+            // asc:  (c.k is null or c.k > $v)
+            // desc: c.k < $v, which is never true for nulls
+            (direction, value) => {
+                let op = if direction == "asc" {
+                    if !column.is_primary_key() {
+                        out.push_sql("(c.");
+                        out.push_identifier(name)?;
+                        out.push_sql(" is null or ");
+                    }
+                    " > "
+                } else {
+                    " < "
+                };
+                out.push_sql("c.");
+                out.push_identifier(name)?;
+                out.push_sql(op);
+                QueryValue(value, &column.column_type).walk_ast(out.reborrow())?;
+                if direction == "asc" && !column.is_primary_key() {
+                    out.push_sql(")");
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn select_entity_and_data(table: &Table, out: &mut AstPass<Pg>) {
         out.push_sql("select '");
         out.push_sql(table.object.as_str());
@@ -2910,6 +3517,17 @@ impl<'a> FilterQuery<'a> {
     ///         where block_range @> $block
     ///           and filter
     ///         order by .. limit .. skip ..) c
+    ///
+    /// When paging backwards from a cursor, the inner query retrieves rows
+    /// in reverse order, and we restore the requested order with
+    ///
+    ///   select '..' as entity, to_jsonb(c.*) - 'g$cursor_pos' as data
+    ///     from
+    ///       (select {column names},
+    ///               row_number() over (order by {reversed}) as g$cursor_pos
+    ///          ..
+    ///         order by {reversed} limit .. skip ..) c
+    ///    order by g$cursor_pos desc
     fn query_no_window_one_entity(
         &self,
         table: &Table,
@@ -2917,14 +3535,31 @@ impl<'a> FilterQuery<'a> {
         mut out: AstPass<Pg>,
         column_names: &AttributeNames,
     ) -> QueryResult<()> {
-        Self::select_entity_and_data(table, &mut out);
+        let sort_key = self.page_sort_key();
+        let backwards = matches!(self.range.0.cursor, Some(EntityCursor::Before(_)));
+
+        if backwards {
+            out.push_sql("select '");
+            out.push_sql(table.object.as_str());
+            out.push_sql("' as entity, to_jsonb(c.*) - 'g$cursor_pos' as data");
+        } else {
+            Self::select_entity_and_data(table, &mut out);
+        }
         out.push_sql(" from (select ");
         write_column_names(column_names, table, &mut out)?;
+        if backwards {
+            out.push_sql(", row_number() over (");
+            sort_key.order_by(&mut out)?;
+            out.push_sql(") as g$cursor_pos");
+        }
         self.filtered_rows(table, filter, out.reborrow())?;
         out.push_sql("\n ");
-        self.sort_key.order_by(&mut out)?;
+        sort_key.order_by(&mut out)?;
         self.range.walk_ast(out.reborrow())?;
         out.push_sql(") c");
+        if backwards {
+            out.push_sql("\n order by g$cursor_pos desc");
+        }
         Ok(())
     }
 
@@ -2933,8 +3568,13 @@ impl<'a> FilterQuery<'a> {
     /// Generate a query
     ///   select '..' as entity, to_jsonb(e.*) as data
     ///     from (select c.*, p.id as g$parent_id from {window.children(...)}) c
+    ///          {sort_key joins}
     ///     order by c.g$parent_id, {sort_key}
     ///     limit {first} offset {skip}
+    ///
+    /// When sorting by a child attribute, the child tables are joined both
+    /// when ranking the children of each parent and here, since the rows
+    /// of `c` do not contain the attribute we sort by
     fn query_window_one_entity(
         &self,
         window: &FilterWindow,
@@ -2949,6 +3589,7 @@ impl<'a> FilterQuery<'a> {
             out.reborrow(),
         )?;
         out.push_sql(") c");
+        self.sort_key.join(&mut out)?;
         out.push_sql("\n ");
         self.sort_key.order_by_parent(&mut out)
     }
@@ -3176,6 +3817,342 @@ impl<'a> LoadQuery<PgConnection, EntityData> for FilterQuery<'a> {
 
 impl<'a, Conn> RunQueryDsl<Conn> for FilterQuery<'a> {}
 
+/// Helper struct for retrieving the result of an `AggregateQuery` as a
+/// Jsonb array that starts with the count, followed by one entry for each
+/// aggregate
+#[derive(QueryableByName, Debug)]
+pub struct AggregateData {
+    #[sql_type = "Jsonb"]
+    data: serde_json::Value,
+}
+
+/// Count the entities in one table that match a filter, and compute
+/// aggregates over some of their columns. The query looks at no more than
+/// `max_entities + 1` entities so that callers can tell that there are too
+/// many entities from the count without scanning all of them
+///
+///   select jsonb_build_array(count(*), {function}(c.{column}), ..) as data
+///     from (select c.*
+///             from {table} c
+///            where block_range @> $block
+///              and {filter}
+///            limit {max_entities + 1}) c
+#[derive(Debug, Clone)]
+pub struct AggregateQuery<'a> {
+    table: &'a Table,
+    filter: Option<QueryFilter<'a>>,
+    aggregates: Vec<(AggregateFunction, &'a Column)>,
+    block: BlockNumber,
+    max_entities: usize,
+    query_id: Option<String>,
+    site: &'a Site,
+}
+
+/// String representation that is useful for debugging when `walk_ast` fails
+impl<'a> fmt::Display for AggregateQuery<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "count")?;
+        for (function, column) in &self.aggregates {
+            write!(f, ", {}({})", function, column.name.as_str())?;
+        }
+        write!(f, " from {} at {}", self.table.qualified_name, self.block)?;
+        if let Some(query_id) = &self.query_id {
+            write!(f, " query_id {}", query_id)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> AggregateQuery<'a> {
+    pub fn new(
+        layout: &'a Layout,
+        table: &'a Table,
+        filter: Option<&'a EntityFilter>,
+        aggregates: &'a [(AggregateFunction, String)],
+        block: BlockNumber,
+        max_entities: usize,
+        query_id: Option<String>,
+        site: &'a Site,
+    ) -> Result<Self, QueryExecutionError> {
+        let filter = filter
+            .map(|filter| QueryFilter::new(filter, table, layout, block))
+            .transpose()?;
+        let aggregates = aggregates
+            .iter()
+            .map(|(function, attribute)| {
+                let column = table.column_for_field(attribute)?;
+                if Self::result_type(*function, column).is_none() {
+                    return Err(QueryExecutionError::NotSupported(format!(
+                        "`{}` can not be computed for attribute `{}` of `{}`",
+                        function, attribute, table.object
+                    )));
+                }
+                Ok((*function, column))
+            })
+            .collect::<Result<Vec<_>, QueryExecutionError>>()?;
+
+        Ok(AggregateQuery {
+            table,
+            filter,
+            aggregates,
+            block,
+            max_entities,
+            query_id,
+            site,
+        })
+    }
+
+    /// The type of the result of applying `function` to `column`, or `None`
+    /// if the function can not be applied to the column
+    fn result_type(function: AggregateFunction, column: &Column) -> Option<ColumnType> {
+        if column.is_list() {
+            return None;
+        }
+        let value_type = match column.column_type {
+            ColumnType::Int => ValueType::Int,
+            ColumnType::Int8 => ValueType::Int8,
+            ColumnType::BigInt => ValueType::BigInt,
+            ColumnType::BigDecimal => ValueType::BigDecimal,
+            ColumnType::Timestamp => ValueType::Timestamp,
+            _ => return None,
+        };
+        function
+            .value_type(value_type)
+            .map(|value_type| match value_type {
+                ValueType::Int => ColumnType::Int,
+                ValueType::Int8 => ColumnType::Int8,
+                ValueType::BigInt => ColumnType::BigInt,
+                ValueType::Timestamp => ColumnType::Timestamp,
+                _ => ColumnType::BigDecimal,
+            })
+    }
+
+    /// Convert the `data` that running this query produced into the count
+    /// and the values of the aggregates
+    pub fn aggregates(&self, data: AggregateData) -> Result<EntityAggregates, StoreError> {
+        let mut values = match data.data {
+            serde_json::Value::Array(values) if values.len() == self.aggregates.len() + 1 => {
+                values.into_iter()
+            }
+            data => {
+                return Err(StoreError::Unknown(anyhow!(
+                    "aggregate query returned unexpected data {}",
+                    data
+                )))
+            }
+        };
+
+        let count = match values.next() {
+            Some(serde_json::Value::Number(count)) => count.as_i64(),
+            _ => None,
+        }
+        .ok_or_else(|| StoreError::Unknown(anyhow!("aggregate query returned no count")))?;
+
+        let values = self
+            .aggregates
+            .iter()
+            .zip(values)
+            .map(|((function, column), json)| {
+                let column_type = Self::result_type(*function, column)
+                    .expect("we checked that the aggregate is valid in `new`");
+                Value::from_column_value(&column_type, json)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(EntityAggregates { count, values })
+    }
+}
+
+impl<'a> QueryFragment<Pg> for AggregateQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        if let Some(qid) = &self.query_id {
+            out.push_sql("/* controller='aggregate',application='");
+            out.push_sql(self.site.namespace.as_str());
+            out.push_sql("',route='");
+            out.push_sql(qid);
+            out.push_sql("',action='");
+            out.push_sql(&self.block.to_string());
+            out.push_sql("' */\n");
+        }
+
+        out.push_sql("select jsonb_build_array(count(*)");
+        for (function, column) in &self.aggregates {
+            out.push_sql(", ");
+            out.push_sql(function.as_str());
+            out.push_sql("(c.");
+            out.push_identifier(column.name.as_str())?;
+            out.push_sql(")");
+        }
+        out.push_sql(") as data\n  from (select c.* from ");
+        out.push_sql(self.table.qualified_name.as_str());
+        out.push_sql(" c\n where ");
+        BlockRangeColumn::new(self.table, "c.", self.block).contains(&mut out)?;
+        if let Some(filter) = &self.filter {
+            out.push_sql(" and ");
+            filter.walk_ast(out.reborrow())?;
+        }
+        // Postgres limits are `bigint`
+        let limit = (self.max_entities as u64)
+            .saturating_add(1)
+            .min(i64::MAX as u64);
+        out.push_sql("\n limit ");
+        out.push_sql(&limit.to_string());
+        out.push_sql(") c");
+        Ok(())
+    }
+}
+
+impl<'a> QueryId for AggregateQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> LoadQuery<PgConnection, AggregateData> for AggregateQuery<'a> {
+    fn internal_load(self, conn: &PgConnection) -> QueryResult<Vec<AggregateData>> {
+        conn.query_by_name(&self)
+    }
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for AggregateQuery<'a> {}
+
+/// Helper struct for retrieving the versions of an entity with the block
+/// range in which each version is valid
+#[derive(QueryableByName, Debug)]
+pub struct HistoryData {
+    #[sql_type = "Jsonb"]
+    data: serde_json::Value,
+    #[sql_type = "Integer"]
+    valid_from: BlockNumber,
+    #[sql_type = "Nullable<Integer>"]
+    valid_until: Option<BlockNumber>,
+}
+
+/// Find all versions of one entity whose block range overlaps with a
+/// given range of blocks
+///
+///   select to_jsonb(c.*) as data,
+///          lower(c.block_range) as valid_from,
+///          upper(c.block_range) as valid_until
+///     from {table} c
+///    where c.id = $id
+///      and c.block_range && $range
+///    order by valid_from
+///    limit {first} offset {skip}
+#[derive(Debug, Clone)]
+pub struct HistoryQuery<'a> {
+    table: &'a Table,
+    id: &'a str,
+    block_range: BlockRange,
+    range: FilterRange,
+    query_id: Option<String>,
+    site: &'a Site,
+}
+
+/// String representation that is useful for debugging when `walk_ast` fails
+impl<'a> fmt::Display for HistoryQuery<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "history of {}[{}] in {:?} {}",
+            self.table.object, self.id, self.block_range, self.range
+        )?;
+        if let Some(query_id) = &self.query_id {
+            write!(f, " query_id {}", query_id)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> HistoryQuery<'a> {
+    pub fn new(
+        table: &'a Table,
+        id: &'a str,
+        block_range: BlockRange,
+        range: &EntityRange,
+        query_id: Option<String>,
+        site: &'a Site,
+    ) -> Result<Self, QueryExecutionError> {
+        if range.cursor.is_some() {
+            return Err(QueryExecutionError::NotSupported(
+                "cursors can not be used when querying the history of an entity".to_string(),
+            ));
+        }
+        Ok(HistoryQuery {
+            table,
+            id,
+            block_range,
+            range: FilterRange(range.clone()),
+            query_id,
+            site,
+        })
+    }
+
+    /// Convert the rows that running this query produced into entity
+    /// versions
+    pub fn versions(
+        &self,
+        layout: &Layout,
+        rows: Vec<HistoryData>,
+    ) -> Result<Vec<EntityVersion>, StoreError> {
+        rows.into_iter()
+            .map(|row| {
+                let data = EntityData {
+                    entity: self.table.object.to_string(),
+                    data: row.data,
+                };
+                Ok(EntityVersion {
+                    entity: data.deserialize_with_layout(layout, None, false)?,
+                    valid_from: row.valid_from,
+                    valid_until: row.valid_until,
+                })
+            })
+            .collect()
+    }
+}
+
+impl<'a> QueryFragment<Pg> for HistoryQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        if let Some(qid) = &self.query_id {
+            out.push_sql("/* controller='history',application='");
+            out.push_sql(self.site.namespace.as_str());
+            out.push_sql("',route='");
+            out.push_sql(qid);
+            out.push_sql("' */\n");
+        }
+
+        // The block number is not used for any of the clauses we generate
+        let block_column = BlockRangeColumn::new(self.table, "c.", BLOCK_NUMBER_MAX);
+        out.push_sql("select to_jsonb(c.*) as data, ");
+        block_column.bounds(&mut out, "valid_from", "valid_until");
+        out.push_sql("\n  from ");
+        out.push_sql(self.table.qualified_name.as_str());
+        out.push_sql(" c\n where ");
+        self.table.primary_key().eq(self.id, &mut out)?;
+        out.push_sql(" and ");
+        block_column.overlaps(&mut out, &self.block_range)?;
+        out.push_sql("\n order by valid_from");
+        self.range.walk_ast(out.reborrow())
+    }
+}
+
+impl<'a> QueryId for HistoryQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> LoadQuery<PgConnection, HistoryData> for HistoryQuery<'a> {
+    fn internal_load(self, conn: &PgConnection) -> QueryResult<Vec<HistoryData>> {
+        conn.query_by_name(&self)
+    }
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for HistoryQuery<'a> {}
+
 /// Reduce the upper bound of the current entry's block range to `block` as
 /// long as that does not result in an empty block range
 #[derive(Debug)]
@@ -3553,10 +4530,11 @@ fn write_column_names(
     out: &mut AstPass<Pg>,
 ) -> QueryResult<()> {
     match column_names {
-        AttributeNames::All => out.push_sql(" * "),
+        AttributeNames::All => out.push_sql(" c.* "),
         AttributeNames::Select(column_names) => {
             let mut iterator = iter_column_names(column_names, table, true).peekable();
             while let Some(column_name) = iterator.next() {
+                out.push_sql("c.");
                 out.push_identifier(column_name)?;
                 if iterator.peek().is_some() {
                     out.push_sql(", ");
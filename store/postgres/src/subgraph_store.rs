@@ -17,7 +17,7 @@ use graph::{
         server::index_node::VersionInfo,
         store::{
             self, BlockStore, DeploymentLocator, DeploymentSchemaVersion,
-            EnsLookup as EnsLookupTrait, PruneReporter, SubgraphFork,
+            EnsLookup as EnsLookupTrait, PruneReporter, PruneRequest, SubgraphFork,
         },
     },
     constraint_violation,
@@ -1073,17 +1073,16 @@ impl SubgraphStoreInner {
     }
 
     /// Remove the history that is only needed to respond to queries before
-    /// block number `earliest_block` from the given deployment
+    /// block number `req.earliest_block` from the given deployment
     ///
-    /// Only tables with a ratio of entities to entity versions below
-    /// `prune_ratio` will be pruned; that ratio is determined by looking at
-    /// Postgres planner stats to avoid lengthy counting queries. It is
-    /// assumed that if the ratio is higher than `prune_ratio` that pruning
-    /// won't make much of a difference and will just cause unnecessary
-    /// work.
+    /// The ratio of entities to entity versions of each table, which is
+    /// determined by looking at Postgres planner stats to avoid lengthy
+    /// counting queries, determines whether a table is pruned by rebuilding
+    /// it, by deleting outdated versions, or not at all (see
+    /// `PruneRequest::strategy`)
     ///
-    /// The `reorg_threshold` is used to determine which blocks will not be
-    /// modified any more by the subgraph writer that may be running
+    /// The `req.reorg_threshold` is used to determine which blocks will not
+    /// be modified any more by the subgraph writer that may be running
     /// concurrently to reduce the amount of time that the writer needs to
     /// be locked out while pruning is happening.
     ///
@@ -1096,18 +1095,14 @@ impl SubgraphStoreInner {
         &self,
         reporter: Box<dyn PruneReporter>,
         deployment: &DeploymentLocator,
-        earliest_block: BlockNumber,
-        reorg_threshold: BlockNumber,
-        prune_ratio: f64,
+        req: PruneRequest,
     ) -> Result<Box<dyn PruneReporter>, StoreError> {
         // Find the store by the deployment id; otherwise, we could only
         // prune the active copy of the deployment with `deployment.hash`
         let site = self.find_site(deployment.id.into())?;
         let store = self.for_site(&site)?;
 
-        store
-            .prune(reporter, site, earliest_block, reorg_threshold, prune_ratio)
-            .await
+        store.prune(reporter, site, req).await
     }
 
     pub fn load_deployment(&self, site: &Site) -> Result<SubgraphDeploymentEntity, StoreError> {
@@ -1,22 +1,25 @@
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{collections::BTreeMap, sync::Arc};
 
 use graph::blockchain::block_stream::FirehoseCursor;
 use graph::components::store::EntityKey;
 use graph::components::store::ReadStore;
+use graph::constraint_violation;
 use graph::data::subgraph::schema;
 use graph::env::env_var;
 use graph::prelude::{
-    BlockNumber, Entity, MetricsRegistry, Schema, SubgraphDeploymentEntity, SubgraphStore as _,
-    BLOCK_NUMBER_MAX,
+    tokio, BlockNumber, Entity, MetricsRegistry, Schema, SubgraphDeploymentEntity,
+    SubgraphStore as _, BLOCK_NUMBER_MAX,
 };
 use graph::slog::info;
 use graph::util::bounded_queue::BoundedQueue;
 use graph::{
     cheap_clone::CheapClone,
-    components::store::{self, EntityType, WritableStore as WritableStoreTrait},
+    components::store::{
+        self, DerivedEntityQuery, EntityType, WritableStore as WritableStoreTrait,
+    },
     data::subgraph::schema::SubgraphError,
     prelude::{
         BlockPtr, DeploymentHash, EntityModification, Error, Logger, StopwatchMetrics, StoreError,
@@ -27,7 +30,7 @@ use graph::{
 };
 use store::StoredDynamicDataSource;
 
-use crate::deployment_store::DeploymentStore;
+use crate::deployment_store::{BlockChanges, DeploymentStore};
 use crate::{primary, primary::Site, relational::Layout, SubgraphStore};
 
 graph::prelude::lazy_static! {
@@ -254,27 +257,13 @@ impl SyncStore {
 
     fn transact_block_operations(
         &self,
-        block_ptr_to: &BlockPtr,
-        firehose_cursor: &FirehoseCursor,
-        mods: &[EntityModification],
+        blocks: &[BlockChanges<'_>],
         stopwatch: &StopwatchMetrics,
-        data_sources: &[StoredDynamicDataSource],
-        deterministic_errors: &[SubgraphError],
-        manifest_idx_and_name: &[(u32, String)],
-        processed_data_sources: &[StoredDynamicDataSource],
     ) -> Result<(), StoreError> {
         self.retry("transact_block_operations", move || {
-            let event = self.writable.transact_block_operations(
-                self.site.clone(),
-                block_ptr_to,
-                firehose_cursor,
-                mods,
-                stopwatch,
-                data_sources,
-                deterministic_errors,
-                manifest_idx_and_name,
-                processed_data_sources,
-            )?;
+            let event =
+                self.writable
+                    .transact_block_operations(self.site.clone(), blocks, stopwatch)?;
 
             let _section = stopwatch.start_section("send_store_event");
             self.try_send_store_event(event)?;
@@ -293,6 +282,18 @@ impl SyncStore {
         })
     }
 
+    fn get_derived(
+        &self,
+        query: &DerivedEntityQuery,
+        block: BlockNumber,
+        excluded_keys: &[EntityKey],
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        self.retry("get_derived", || {
+            self.writable
+                .get_derived(self.site.cheap_clone(), query, block, excluded_keys)
+        })
+    }
+
     async fn is_deployment_synced(&self) -> Result<bool, StoreError> {
         self.retry_async("is_deployment_synced", || async {
             self.writable
@@ -448,42 +449,66 @@ enum ExecResult {
 }
 
 impl Request {
-    fn execute(&self) -> Result<ExecResult, StoreError> {
+    /// The changes a `Write` request makes, or `None` for other requests
+    fn block_changes(&self) -> Option<BlockChanges<'_>> {
         match self {
             Request::Write {
-                store,
-                stopwatch,
-                block_ptr: block_ptr_to,
+                block_ptr,
                 firehose_cursor,
                 mods,
                 data_sources,
                 deterministic_errors,
                 manifest_idx_and_name,
                 processed_data_sources,
-            } => store
-                .transact_block_operations(
-                    block_ptr_to,
-                    firehose_cursor,
-                    mods,
-                    stopwatch,
-                    data_sources,
-                    deterministic_errors,
-                    manifest_idx_and_name,
-                    processed_data_sources,
-                )
-                .map(|()| ExecResult::Continue),
-            Request::RevertTo {
+                ..
+            } => Some(BlockChanges {
+                block_ptr,
+                firehose_cursor,
+                mods,
+                data_sources,
+                deterministic_errors,
+                manifest_idx_and_name,
+                processed_data_sources,
+            }),
+            Request::RevertTo { .. } | Request::Stop => None,
+        }
+    }
+
+    /// Execute a batch of requests as produced by `Queue::next_batch`.
+    /// A batch either consists of one or more `Write` requests, which are
+    /// written in one transaction, or of exactly one other request
+    fn execute_batch(batch: &[Arc<Request>]) -> Result<ExecResult, StoreError> {
+        match batch.first().map(|req| req.as_ref()) {
+            Some(Request::Write {
+                store, stopwatch, ..
+            }) => {
+                let blocks = batch
+                    .iter()
+                    .map(|req| req.block_changes())
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| {
+                        constraint_violation!("a batch of writes can only contain writes")
+                    })?;
+                store
+                    .transact_block_operations(&blocks, stopwatch)
+                    .map(|()| ExecResult::Continue)
+            }
+            Some(Request::RevertTo {
                 store,
                 block_ptr,
                 firehose_cursor,
-            } => store
+            }) => store
                 .revert_block_operations(block_ptr.clone(), firehose_cursor)
                 .map(|()| ExecResult::Continue),
-            Request::Stop => return Ok(ExecResult::Stop),
+            Some(Request::Stop) | None => Ok(ExecResult::Stop),
         }
     }
 }
 
+/// How often the writer checks whether more writes have been queued while
+/// it waits for a batch to fill up
+const BATCH_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 /// A queue that asynchronously writes requests queued with `push` to the
 /// underlying store and allows retrieving information that is a combination
 /// of queued changes and changes already committed to the store.
@@ -529,8 +554,9 @@ pub(crate) mod test_support {
         }
     }
 
-    /// Allow the writer to process `steps` requests. After calling this,
-    /// the writer will only process the number of requests it is allowed to
+    /// Allow the writer to process `steps` batches of requests. After
+    /// calling this, the writer will only process the number of batches it
+    /// is allowed to
     pub async fn allow_steps(steps: usize) {
         for _ in 0..steps {
             ALLOWED_STEPS.push(()).await
@@ -558,22 +584,25 @@ impl Queue {
                 // these methods would not be able to see that data until
                 // the write transaction commits, causing them to return
                 // incorrect results.
-                let req = {
+                let batch = {
                     let _section = queue.stopwatch.start_section("queue_wait");
-                    queue.queue.peek().await
+                    queue.next_batch().await
                 };
+                let batch_len = batch.len();
                 let res = {
                     let _section = queue.stopwatch.start_section("queue_execute");
-                    graph::spawn_blocking_allow_panic(move || req.execute()).await
+                    graph::spawn_blocking_allow_panic(move || Request::execute_batch(&batch)).await
                 };
 
                 let _section = queue.stopwatch.start_section("queue_pop");
                 use ExecResult::*;
                 match res {
                     Ok(Ok(Continue)) => {
-                        // The request has been handled. It's now safe to remove it
-                        // from the queue
-                        queue.queue.pop().await;
+                        // The requests have been handled. It's now safe to
+                        // remove them from the queue
+                        for _ in 0..batch_len {
+                            queue.queue.pop().await;
+                        }
                     }
                     Ok(Ok(Stop)) => {
                         // Graceful shutdown. We also handled the request
@@ -622,6 +651,53 @@ impl Queue {
         queue
     }
 
+    /// Return the requests at the front of the queue that the writer
+    /// should process next. Consecutive `Write` requests are combined into
+    /// one batch as long as they have at most
+    /// `GRAPH_STORE_WRITE_BATCH_SIZE` entity modifications in total; any
+    /// other request is processed on its own. If the batch could still
+    /// grow, wait up to `GRAPH_STORE_WRITE_BATCH_DURATION` for more
+    /// writes to be queued
+    async fn next_batch(&self) -> Vec<Arc<Request>> {
+        let max_mods = ENV_VARS.store.write_batch_size;
+        let max_wait = ENV_VARS.store.write_batch_duration;
+
+        let start = Instant::now();
+        loop {
+            let mut first = true;
+            let mut batch_mods = 0;
+            let batch = self
+                .queue
+                .peek_while(|req| {
+                    let fits = match req.as_ref() {
+                        Request::Write { mods, .. }
+                            if first || (max_mods > 0 && batch_mods + mods.len() <= max_mods) =>
+                        {
+                            batch_mods += mods.len();
+                            true
+                        }
+                        Request::Write { .. } | Request::RevertTo { .. } | Request::Stop => false,
+                    };
+                    first = false;
+                    fits
+                })
+                .await;
+
+            // The batch can not grow any more if it is not a batch of
+            // writes, or if we stopped adding requests to it before we
+            // reached the end of the queue
+            let complete = max_mods == 0
+                || !matches!(batch[0].as_ref(), Request::Write { .. })
+                || batch.len() < self.queue.len()
+                || self.queue.len() >= self.queue.capacity();
+            let waited = start.elapsed();
+            if complete || waited >= max_wait {
+                return batch;
+            }
+            tokio::time::sleep((max_wait - waited).min(BATCH_POLL_INTERVAL)).await;
+        }
+    }
+
     /// Add a write request to the queue
     async fn push(&self, req: Request) -> Result<(), StoreError> {
         self.check_err()?;
@@ -771,6 +847,56 @@ impl Queue {
         Ok(map)
     }
 
+    /// Get the entities matching `query` by looking at both the queue and
+    /// the store
+    fn get_derived(
+        &self,
+        query: &DerivedEntityQuery,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        // See the implementation of `get` for how we handle reverts
+        let mut tracker = BlockTracker::new();
+
+        // Collect the latest version of every entity of the right type that
+        // is modified by entries in the queue; going from newest to oldest
+        // entry means the first modification we see for a key wins
+        let queued = self.queue.fold(
+            BTreeMap::new(),
+            |mut map: BTreeMap<EntityKey, Option<Entity>>, req| {
+                tracker.update(req.as_ref());
+                match req.as_ref() {
+                    Request::Write {
+                        block_ptr, mods, ..
+                    } => {
+                        if tracker.visible(block_ptr) {
+                            for emod in mods {
+                                let key = emod.entity_ref();
+                                if key.entity_type == query.entity_type {
+                                    map.entry(key.clone())
+                                        .or_insert_with(|| emod.entity().cloned());
+                                }
+                            }
+                        }
+                    }
+                    Request::RevertTo { .. } | Request::Stop => { /* nothing to do */ }
+                }
+                map
+            },
+        );
+
+        // Entities that are modified in the queue must not be taken from the
+        // store since the queue has a newer version of them
+        let excluded_keys: Vec<_> = queued.keys().cloned().collect();
+        let mut entities = self
+            .store
+            .get_derived(query, tracker.query_block(), &excluded_keys)?;
+        entities.extend(queued.into_iter().filter_map(|(key, entity)| {
+            entity
+                .filter(|entity| query.matches(entity))
+                .map(|entity| (key, entity))
+        }));
+        Ok(entities)
+    }
+
     /// Load dynamic data sources by looking at both the queue and the store
     async fn load_dynamic_data_sources(
         &self,
@@ -860,14 +986,16 @@ impl Writer {
     ) -> Result<(), StoreError> {
         match self {
             Writer::Sync(store) => store.transact_block_operations(
-                &block_ptr_to,
-                &firehose_cursor,
-                &mods,
+                &[BlockChanges {
+                    block_ptr: &block_ptr_to,
+                    firehose_cursor: &firehose_cursor,
+                    mods: &mods,
+                    data_sources: &data_sources,
+                    deterministic_errors: &deterministic_errors,
+                    manifest_idx_and_name: &manifest_idx_and_name,
+                    processed_data_sources: &processed_data_sources,
+                }],
                 &stopwatch,
-                &data_sources,
-                &deterministic_errors,
-                &manifest_idx_and_name,
-                &processed_data_sources,
             ),
             Writer::Async(queue) => {
                 let req = Request::Write {
@@ -928,6 +1056,16 @@ impl Writer {
         }
     }
 
+    fn get_derived(
+        &self,
+        query: &DerivedEntityQuery,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        match self {
+            Writer::Sync(store) => store.get_derived(query, BLOCK_NUMBER_MAX, &[]),
+            Writer::Async(queue) => queue.get_derived(query),
+        }
+    }
+
     async fn load_dynamic_data_sources(
         &self,
         manifest_idx_and_name: Vec<(u32, String)>,
@@ -1010,6 +1148,13 @@ impl ReadStore for WritableStore {
         self.writer.get_many(ids_for_type)
     }
 
+    fn get_derived(
+        &self,
+        query: &DerivedEntityQuery,
+    ) -> Result<BTreeMap<EntityKey, Entity>, StoreError> {
+        self.writer.get_derived(query)
+    }
+
     fn input_schema(&self) -> Arc<Schema> {
         self.store.input_schema()
     }
@@ -5,6 +5,7 @@ use test_store::*;
 
 use graph::components::store::{
     DeploymentLocator, EntityKey, EntityOrder, EntityQuery, EntityType, PruneReporter,
+    PruneRequest, PruneStrategy,
 };
 use graph::data::store::scalar;
 use graph::data::subgraph::schema::*;
@@ -140,6 +141,7 @@ async fn insert_test_data(store: Arc<DieselSubgraphStore>) -> DeploymentLocator
         data_sources: vec![],
         graft: None,
         templates: vec![],
+        indexer_hints: IndexerHints::default(),
         chain: PhantomData,
     };
 
@@ -477,28 +479,31 @@ fn prune() {
         store: &DieselSubgraphStore,
         src: &DeploymentLocator,
         earliest_block: BlockNumber,
+        latest_block: BlockNumber,
+        strategy: PruneStrategy,
     ) -> Result<(), StoreError> {
         struct Progress;
         impl PruneReporter for Progress {}
         let reporter = Box::new(Progress);
 
-        store
-            .prune(reporter, &src, earliest_block, 1, 1.1)
-            .await
-            .map(|_| ())
+        let mut req = PruneRequest::new(&src, latest_block - earliest_block, 1, 0, latest_block)?;
+        // Make sure every table gets pruned with `strategy`
+        req.delete_threshold = 0.0;
+        req.forced_strategy = Some(strategy);
+        store.prune(reporter, &src, req).await.map(|_| ())
     }
 
     run_test(|store, src| async move {
         // The setup sets the subgraph pointer to block 2, we try to set
         // earliest block to 5
-        prune(&store, &src, 5)
+        prune(&store, &src, 5, 2, PruneStrategy::Rebuild)
             .await
             .expect_err("setting earliest block later than latest does not work");
 
         // Latest block 2 minus reorg threshold 1 means we need to copy
         // final blocks from block 1, but want earliest as block 2, i.e. no
         // final blocks which won't work
-        prune(&store, &src, 2)
+        prune(&store, &src, 2, 2, PruneStrategy::Rebuild)
             .await
             .expect_err("setting earliest block after last final block fails");
 
@@ -529,7 +534,9 @@ fn prune() {
             .await
             .unwrap();
         // Pruning only removes the [1,2) version of user 3
-        prune(&store, &src, 3).await.expect("pruning works");
+        prune(&store, &src, 3, 6, PruneStrategy::Rebuild)
+            .await
+            .expect("pruning works");
 
         // Check which versions exist at every block, even if they are
         // before the new earliest block, since we don't have a convenient
@@ -539,6 +546,24 @@ fn prune() {
         for block in 2..=5 {
             check_at_block(&store, &src, block, vec!["1", "2", "3"]);
         }
+
+        // Forward block ptr to block 7. Pruning by deleting removes the
+        // [1,5) version of user 2
+        transact_and_wait(&store, &src, BLOCKS[7].clone(), vec![])
+            .await
+            .unwrap();
+        prune(&store, &src, 5, 7, PruneStrategy::Delete)
+            .await
+            .expect("pruning by deleting works");
+
+        check_at_block(&store, &src, 0, vec!["1"]);
+        check_at_block(&store, &src, 1, vec!["1"]);
+        for block in 2..=4 {
+            check_at_block(&store, &src, block, vec!["1", "3"]);
+        }
+        for block in 5..=7 {
+            check_at_block(&store, &src, block, vec!["1", "2", "3"]);
+        }
         Ok(())
     })
 }
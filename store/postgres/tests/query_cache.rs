@@ -0,0 +1,116 @@
+//! Test the query result cache that query nodes share through the primary
+use std::future::Future;
+use std::sync::Arc;
+
+use diesel::{sql_query, RunQueryDsl};
+use graph::data::value::Object;
+use graph::prelude::{r, BlockPtr, DeploymentHash, QueryResult};
+use graph_graphql::prelude::{QueryBlockCacheBackend, QueryHash};
+use graph_store_postgres::PostgresQueryBlockCache;
+use test_store::*;
+
+const NETWORK: &str = "query_cache_network";
+const MAX_BLOCKS: usize = 2;
+const MAX_WEIGHT: usize = 100;
+
+fn run_test<F, R>(test: F)
+where
+    F: FnOnce(PostgresQueryBlockCache) -> R + Send + 'static,
+    R: Future<Output = ()> + Send + 'static,
+{
+    run_test_sequentially(|_| async move {
+        let pool = primary_pool();
+        let conn = pool.get().unwrap();
+        sql_query("delete from query_cache_block")
+            .execute(&conn)
+            .unwrap();
+
+        test(PostgresQueryBlockCache::new(pool, MAX_BLOCKS, MAX_WEIGHT)).await
+    })
+}
+
+fn result(n: i64) -> Arc<QueryResult> {
+    let data = Object::from_iter(vec![
+        ("value".to_string(), r::Value::Int(n)),
+        ("kind".to_string(), r::Value::Enum("Big".to_string())),
+        (
+            "items".to_string(),
+            r::Value::List(vec![r::Value::String("a".to_string()), r::Value::Null]),
+        ),
+    ]);
+    let mut result = QueryResult::new(data);
+    result.deployment = Some(DeploymentHash::new("QmQueryCache").unwrap());
+    Arc::new(result)
+}
+
+fn key(n: u8) -> QueryHash {
+    [n; 32]
+}
+
+async fn get(cache: &PostgresQueryBlockCache, block: &BlockPtr, n: u8) -> Option<Arc<QueryResult>> {
+    cache.get(&*LOGGER, NETWORK, block, &key(n)).await
+}
+
+async fn insert(cache: &PostgresQueryBlockCache, block: &BlockPtr, n: u8, weight: usize) -> bool {
+    cache
+        .insert(
+            &*LOGGER,
+            NETWORK,
+            block.clone(),
+            key(n),
+            result(n as i64),
+            weight,
+        )
+        .await
+}
+
+#[test]
+fn caches_results() {
+    run_test(|cache| async move {
+        assert!(get(&cache, &BLOCKS[1], 1).await.is_none());
+        assert!(insert(&cache, &BLOCKS[1], 1, 10).await);
+
+        let cached = get(&cache, &BLOCKS[1], 1).await.expect("result is cached");
+        assert_eq!(result(1).data(), cached.data());
+        assert_eq!(result(1).deployment, cached.deployment);
+
+        // The result is only found for the block it was cached for
+        assert!(get(&cache, &BLOCKS[2], 1).await.is_none());
+    })
+}
+
+#[test]
+fn does_not_cache_old_blocks() {
+    run_test(|cache| async move {
+        assert!(insert(&cache, &BLOCKS[2], 2, 10).await);
+        assert!(!insert(&cache, &BLOCKS[1], 1, 10).await);
+        assert!(get(&cache, &BLOCKS[1], 1).await.is_none());
+    })
+}
+
+#[test]
+fn respects_max_weight() {
+    run_test(|cache| async move {
+        assert!(insert(&cache, &BLOCKS[1], 1, 60).await);
+        assert!(!insert(&cache, &BLOCKS[1], 2, 60).await);
+        assert!(insert(&cache, &BLOCKS[1], 3, 40).await);
+
+        assert!(get(&cache, &BLOCKS[1], 1).await.is_some());
+        assert!(get(&cache, &BLOCKS[1], 2).await.is_none());
+        assert!(get(&cache, &BLOCKS[1], 3).await.is_some());
+    })
+}
+
+#[test]
+fn evicts_oldest_block() {
+    run_test(|cache| async move {
+        assert!(insert(&cache, &BLOCKS[1], 1, 10).await);
+        assert!(insert(&cache, &BLOCKS[2], 2, 10).await);
+        assert!(get(&cache, &BLOCKS[1], 1).await.is_some());
+
+        assert!(insert(&cache, &BLOCKS[3], 3, 10).await);
+        assert!(get(&cache, &BLOCKS[1], 1).await.is_none());
+        assert!(get(&cache, &BLOCKS[2], 2).await.is_some());
+        assert!(get(&cache, &BLOCKS[3], 3).await.is_some());
+    })
+}
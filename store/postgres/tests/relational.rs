@@ -1,12 +1,13 @@
 //! Test mapping of GraphQL schema to a relational schema
 use diesel::connection::SimpleConnection as _;
 use diesel::pg::PgConnection;
-use graph::components::store::EntityKey;
+use graph::components::store::{DerivedEntityQuery, EntityKey};
 use graph::data::store::scalar;
+use graph::data::value::Word;
 use graph::entity;
 use graph::prelude::BlockNumber;
 use graph::prelude::{
-    o, slog, tokio, web3::types::H256, DeploymentHash, Entity, EntityCollection, EntityFilter,
+    o, r, slog, tokio, web3::types::H256, DeploymentHash, Entity, EntityCollection, EntityFilter,
     EntityOrder, EntityQuery, EntityRange, Logger, Schema, StopwatchMetrics, Value, ValueType,
     BLOCK_NUMBER_MAX,
 };
@@ -29,7 +30,7 @@ use graph::{
 };
 use graph_store_postgres::{
     layout_for_tests::make_dummy_site,
-    layout_for_tests::{Layout, Namespace, STRING_PREFIX_SIZE},
+    layout_for_tests::{BlockRange, Layout, Namespace, STRING_PREFIX_SIZE},
 };
 
 use test_store::*;
@@ -124,6 +125,12 @@ const THINGS_GQL: &str = r#"
         drinks: [String!]
     }
 
+    type Tick @entity {
+        id: ID!,
+        count: Int8!,
+        at: Timestamp!
+    }
+
     type NullableStrings @entity {
         id: ID!,
         name: String,
@@ -518,6 +525,39 @@ fn find() {
     });
 }
 
+#[test]
+fn find_derived() {
+    run_test(|conn, layout| {
+        let things = vec![
+            entity! { id: "t0", bigThing: "t0" },
+            entity! { id: "t1", bigThing: "t0" },
+            entity! { id: "t2", bigThing: "t0" },
+            entity! { id: "t3", bigThing: "t1" },
+        ];
+        insert_entity(&conn, &layout, "Thing", things);
+
+        let query = DerivedEntityQuery {
+            entity_type: EntityType::from("Thing"),
+            entity_field: "bigThing".into(),
+            value: "t0".into(),
+        };
+        let ids = |excluded: &[EntityKey]| {
+            layout
+                .find_derived(conn, &query, BLOCK_NUMBER_MAX, excluded)
+                .expect("Failed to find derived entities")
+                .into_keys()
+                .map(|key| key.entity_id.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec!["t0", "t1", "t2"], ids(&[]));
+        assert_eq!(
+            vec!["t0", "t2"],
+            ids(&[EntityKey::data("Thing".to_owned(), "t1".to_owned())])
+        );
+    });
+}
+
 #[test]
 fn insert_null_fulltext_fields() {
     run_test(|conn, layout| {
@@ -704,6 +744,7 @@ fn count_scalar_entities(conn: &PgConnection, layout: &Layout) -> usize {
             EntityRange {
                 first: None,
                 skip: 0,
+                cursor: None,
             },
             BLOCK_NUMBER_MAX,
             None,
@@ -1137,6 +1178,79 @@ fn check_block_finds() {
     });
 }
 
+#[test]
+fn check_history() {
+    run_test(move |conn, layout| {
+        insert_users(conn, layout);
+        for (name, block) in [("Johnny", 3), ("Jonathan", 7)] {
+            update_user_entity(
+                conn,
+                layout,
+                "1",
+                "User",
+                name,
+                "tonofjohn@email.com",
+                67 as i32,
+                184.4,
+                false,
+                Some("yellow"),
+                None,
+                block,
+            );
+        }
+
+        let history = |block_range: BlockRange, first: u32| {
+            layout
+                .history(
+                    &*LOGGER,
+                    conn,
+                    &EntityType::from("User"),
+                    "1",
+                    block_range,
+                    &EntityRange::first(first),
+                    None,
+                )
+                .expect("layout.history failed to execute query")
+                .0
+                .into_iter()
+                .map(|version| match version.entity.get(&Word::from("name")) {
+                    Some(r::Value::String(name)) => {
+                        (name.clone(), version.valid_from, version.valid_until)
+                    }
+                    _ => panic!("layout.history returned a version without a name"),
+                })
+                .collect::<Vec<_>>()
+        };
+        let versions = |expected: &[(&str, BlockNumber, Option<BlockNumber>)]| {
+            expected
+                .iter()
+                .map(|(name, from, until)| (name.to_string(), *from, *until))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            versions(&[
+                ("Johnton", 0, Some(3)),
+                ("Johnny", 3, Some(7)),
+                ("Jonathan", 7, None)
+            ]),
+            history((0..=BLOCK_NUMBER_MAX).into(), 100)
+        );
+        assert_eq!(
+            versions(&[("Johnny", 3, Some(7))]),
+            history((4..=6).into(), 100)
+        );
+        assert_eq!(
+            versions(&[("Johnton", 0, Some(3)), ("Johnny", 3, Some(7))]),
+            history((2..=3).into(), 100)
+        );
+        assert_eq!(
+            versions(&[("Johnton", 0, Some(3))]),
+            history((0..=BLOCK_NUMBER_MAX).into(), 1)
+        );
+    });
+}
+
 #[test]
 fn check_find() {
     run_test(move |conn, layout| {
@@ -1820,3 +1934,81 @@ fn check_filters() {
             .check(vec![], filter_block_gte(BLOCK_NUMBER_MAX));
     });
 }
+
+#[test]
+fn int8_and_timestamp() {
+    fn tick(id: &str, count: i64, micros: i64) -> Entity {
+        let at = scalar::Timestamp::from_microseconds_since_epoch(micros).unwrap();
+        entity! {
+            id: id,
+            count: Value::Int8(count),
+            at: Value::Timestamp(at)
+        }
+    }
+
+    fn at(micros: i64) -> Value {
+        Value::Timestamp(scalar::Timestamp::from_microseconds_since_epoch(micros).unwrap())
+    }
+
+    run_test(|conn, layout| {
+        // Values that do not fit into an `Int` and timestamps before and
+        // after the epoch
+        let ticks = vec![
+            tick("1", std::i64::MAX, 1_690_000_000_123_456),
+            tick("2", 3_000_000_000, -1),
+            tick("3", -5, 0),
+        ];
+        insert_entity(conn, layout, "Tick", ticks.clone());
+
+        for tick in &ticks {
+            let id = tick.id().unwrap();
+            let entity = layout
+                .find(conn, &EntityType::from("Tick"), &id, BLOCK_NUMBER_MAX)
+                .expect("Failed to read Tick")
+                .unwrap();
+            assert_entity_eq!(scrub(tick), entity);
+        }
+
+        let checker = QueryChecker::new(conn, layout);
+        let ticks = || query(vec!["Tick"]);
+
+        checker
+            .check(
+                vec!["1", "2"],
+                ticks()
+                    .filter(EntityFilter::GreaterThan(
+                        "count".to_owned(),
+                        Value::Int8(std::i32::MAX as i64),
+                    ))
+                    .asc("id"),
+            )
+            .check(
+                vec!["1", "3"],
+                ticks()
+                    .filter(EntityFilter::In(
+                        "count".to_owned(),
+                        vec![Value::Int8(std::i64::MAX), Value::Int8(-5)],
+                    ))
+                    .asc("id"),
+            )
+            .check(
+                vec!["1", "3"],
+                ticks()
+                    .filter(EntityFilter::GreaterOrEqual("at".to_owned(), at(0)))
+                    .asc("id"),
+            )
+            .check(
+                vec!["2", "3"],
+                ticks()
+                    .filter(EntityFilter::In(
+                        "at".to_owned(),
+                        vec![at(-1), at(0), at(1)],
+                    ))
+                    .asc("id"),
+            )
+            .check(vec!["3", "2", "1"], ticks().asc("count"))
+            .check(vec!["1", "2", "3"], ticks().desc("count"))
+            .check(vec!["2", "3", "1"], ticks().asc("at"))
+            .check(vec!["1", "3", "2"], ticks().desc("at"));
+    });
+}
@@ -166,6 +166,7 @@ async fn insert_test_data(store: Arc<DieselSubgraphStore>) -> DeploymentLocator
         data_sources: vec![],
         graft: None,
         templates: vec![],
+        indexer_hints: IndexerHints::default(),
         chain: PhantomData,
     };
 
@@ -1293,6 +1294,7 @@ fn entity_changes_are_fired_and_forwarded_to_subscriptions() {
             data_sources: vec![],
             graft: None,
             templates: vec![],
+            indexer_hints: IndexerHints::default(),
             chain: PhantomData,
         };
 
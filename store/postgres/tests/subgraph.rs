@@ -6,6 +6,7 @@ use graph::{
     data::query::QueryTarget,
     data::subgraph::schema::SubgraphHealth,
     data::subgraph::schema::{DeploymentCreate, SubgraphError},
+    data::subgraph::IndexerHints,
     prelude::BlockPtr,
     prelude::EntityChange,
     prelude::EntityChangeOperation,
@@ -145,6 +146,7 @@ fn create_subgraph() {
             data_sources: vec![],
             graft: None,
             templates: vec![],
+            indexer_hints: IndexerHints::default(),
             chain: PhantomData,
         };
         let deployment = DeploymentCreate::new(String::new(), &manifest, None);
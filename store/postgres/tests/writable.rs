@@ -43,6 +43,7 @@ async fn insert_test_data(store: Arc<DieselSubgraphStore>) -> DeploymentLocator
         data_sources: vec![],
         graft: None,
         templates: vec![],
+        indexer_hints: IndexerHints::default(),
         chain: PhantomData,
     };
 
@@ -159,5 +160,14 @@ fn tracker() {
 
         resume_writer(&deployment, 1).await;
         assert_eq!(2, read_count());
+
+        // Test that consecutive writes are written in one step
+        pause_writer(&deployment).await;
+        for count in 3..6 {
+            insert_count(&subgraph_store, &deployment, count).await;
+        }
+        assert_eq!(5, read_count());
+        resume_writer(&deployment, 1).await;
+        assert_eq!(5, read_count());
     })
 }
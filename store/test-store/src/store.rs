@@ -3,6 +3,7 @@ use graph::data::graphql::effort::LoadManager;
 use graph::data::query::QueryResults;
 use graph::data::query::QueryTarget;
 use graph::data::subgraph::schema::{DeploymentCreate, SubgraphError};
+use graph::data::subgraph::IndexerHints;
 use graph::log;
 use graph::prelude::{QueryStoreManager as _, SubgraphStore as _, *};
 use graph::semver::Version;
@@ -13,7 +14,8 @@ use graph::{
     components::store::StoredDynamicDataSource, data::subgraph::status, prelude::NodeId,
 };
 use graph_graphql::prelude::{
-    execute_query, Query as PreparedQuery, QueryExecutionOptions, StoreResolver,
+    execute_query, InMemoryQueryBlockCache, Query as PreparedQuery, QueryExecutionOptions,
+    StoreResolver,
 };
 use graph_graphql::test_support::GraphQLMetrics;
 use graph_mock::MockMetricsRegistry;
@@ -164,6 +166,7 @@ pub async fn create_subgraph(
         data_sources: vec![],
         graft: None,
         templates: vec![],
+        indexer_hints: IndexerHints::default(),
         chain: PhantomData,
     };
 
@@ -492,6 +495,7 @@ async fn execute_subgraph_query_internal(
                     load_manager: LOAD_MANAGER.clone(),
                     max_first: std::u32::MAX,
                     max_skip: std::u32::MAX,
+                    query_block_cache: InMemoryQueryBlockCache::shared(),
                 },
             )
             .await,
@@ -573,6 +577,10 @@ fn build_store() -> (Arc<Store>, ConnectionPool, Config, Arc<SubscriptionManager
     .unwrap()
 }
 
+pub fn primary_pool() -> ConnectionPool {
+    PRIMARY_POOL.clone()
+}
+
 pub fn primary_connection() -> graph_store_postgres::layout_for_tests::Connection<'static> {
     let conn = PRIMARY_POOL.get().unwrap();
     graph_store_postgres::layout_for_tests::Connection::new(conn)